@@ -0,0 +1,132 @@
+//! Maintenance subcommands of the cloud_api binary (`cloud_api <command> ...`); without a command
+//! the binary starts the HTTP server.
+//!
+//!   cloud_api rebuild-read-model (--store <uuid> | --org <uuid>) [--scope orders|menu|all]
+
+use db::{list_store_ids_for_org, rebuild_store_read_model, DbPool, RebuildScope};
+use uuid::Uuid;
+
+const USAGE: &str =
+    "usage: cloud_api rebuild-read-model (--store <uuid> | --org <uuid>) [--scope orders|menu|all]";
+
+/// Run a subcommand and return the process exit code.
+pub async fn run(command: &str, args: &[String], database_url: &str) -> i32 {
+    match command {
+        "rebuild-read-model" => match RebuildArgs::parse(args) {
+            Ok(parsed) => rebuild_read_model(parsed, database_url).await,
+            Err(e) => {
+                eprintln!("{}\n{}", e, USAGE);
+                2
+            }
+        },
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            0
+        }
+        other => {
+            eprintln!("unknown command '{}'\n{}", other, USAGE);
+            2
+        }
+    }
+}
+
+enum Target {
+    Store(Uuid),
+    Org(Uuid),
+}
+
+struct RebuildArgs {
+    target: Target,
+    scope: RebuildScope,
+}
+
+impl RebuildArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut target = None;
+        let mut scope = RebuildScope::All;
+        let mut it = args.iter();
+        while let Some(flag) = it.next() {
+            let value = it
+                .next()
+                .ok_or_else(|| format!("missing value for {}", flag))?;
+            match flag.as_str() {
+                "--store" | "--org" if target.is_some() => {
+                    return Err("pass only one of --store or --org".to_string())
+                }
+                "--store" => target = Some(Target::Store(parse_uuid(flag, value)?)),
+                "--org" => target = Some(Target::Org(parse_uuid(flag, value)?)),
+                "--scope" => scope = value.parse()?,
+                other => return Err(format!("unknown option '{}'", other)),
+            }
+        }
+        let target = target.ok_or_else(|| "one of --store or --org is required".to_string())?;
+        Ok(Self { target, scope })
+    }
+}
+
+fn parse_uuid(flag: &str, value: &str) -> Result<Uuid, String> {
+    Uuid::parse_str(value).map_err(|_| format!("{} expects a uuid, got '{}'", flag, value))
+}
+
+async fn rebuild_read_model(args: RebuildArgs, database_url: &str) -> i32 {
+    let pool: DbPool = match db::connect(database_url).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            eprintln!("database connection failed: {}", e);
+            return 1;
+        }
+    };
+    if let Err(e) = db::run_migrations(&pool).await {
+        eprintln!("migrations failed: {}", e);
+        return 1;
+    }
+
+    let store_ids = match args.target {
+        Target::Store(id) => vec![id],
+        Target::Org(org_id) => match list_store_ids_for_org(&pool, org_id).await {
+            Ok(ids) => ids,
+            Err(e) => {
+                eprintln!("listing stores failed: {}", e);
+                return 1;
+            }
+        },
+    };
+    if store_ids.is_empty() {
+        eprintln!("no stores found");
+        return 1;
+    }
+
+    let mut failed_events = 0u64;
+    for store_id in store_ids {
+        println!("store {}: rebuilding ({:?})", store_id, args.scope);
+        let mut progress = |done: u64, total: u64| {
+            println!("store {}: {}/{} events replayed", store_id, done, total);
+        };
+        match rebuild_store_read_model(&pool, store_id, args.scope, &mut progress).await {
+            Ok(report) => {
+                println!(
+                    "store {}: done — {} device(s), {} event(s), {} projected, {} failed; {} order(s), {} menu item(s)",
+                    store_id,
+                    report.devices,
+                    report.events_total,
+                    report.events_projected,
+                    report.events_failed,
+                    report.orders,
+                    report.menu_items
+                );
+                failed_events += report.events_failed;
+            }
+            Err(e) => {
+                eprintln!("store {}: rebuild failed (run it again to finish): {}", store_id, e);
+                return 1;
+            }
+        }
+    }
+    if failed_events > 0 {
+        eprintln!(
+            "{} event(s) failed to project and were recorded in projection_dead_letters",
+            failed_events
+        );
+    }
+    0
+}
@@ -0,0 +1,75 @@
+//! In-process notifier for device_command_queue inserts. Whoever enqueues a command calls
+//! `notify` with the target device after the insert is committed; long-poll and SSE requests for
+//! that device wake up and re-read the queue. Notifications are hints only: waiters always
+//! re-query the database and also wake on a timeout, so commands enqueued by another instance
+//! are still delivered (just not instantly).
+
+use std::time::Duration;
+
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Notifications buffered per subscriber; a lagging subscriber simply re-reads the queue.
+const CHANNEL_CAPACITY: usize = 1024;
+
+pub struct CommandNotifier {
+    tx: broadcast::Sender<Uuid>,
+}
+
+impl Default for CommandNotifier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CommandNotifier {
+    pub fn new() -> Self {
+        let (tx, _) = broadcast::channel(CHANNEL_CAPACITY);
+        Self { tx }
+    }
+
+    /// A command was queued for this device.
+    pub fn notify(&self, device_id: Uuid) {
+        // Err only means nobody is listening.
+        let _ = self.tx.send(device_id);
+    }
+
+    pub fn notify_all(&self, device_ids: &[Uuid]) {
+        for device_id in device_ids {
+            self.notify(*device_id);
+        }
+    }
+
+    /// Subscribe before reading the queue so an insert between the read and the wait is not missed.
+    pub fn subscribe(&self, device_id: Uuid) -> CommandSubscription {
+        CommandSubscription {
+            device_id,
+            rx: self.tx.subscribe(),
+        }
+    }
+}
+
+pub struct CommandSubscription {
+    device_id: Uuid,
+    rx: broadcast::Receiver<Uuid>,
+}
+
+impl CommandSubscription {
+    /// Wait until a command is queued for the device or `timeout` elapses. Returns true if woken
+    /// by a notification (or if notifications were dropped, in which case one may be ours).
+    pub async fn wait(&mut self, timeout: Duration) -> bool {
+        let device_id = self.device_id;
+        let rx = &mut self.rx;
+        let woken = async move {
+            loop {
+                match rx.recv().await {
+                    Ok(id) if id == device_id => return true,
+                    Ok(_) => continue,
+                    Err(broadcast::error::RecvError::Lagged(_)) => return true,
+                    Err(broadcast::error::RecvError::Closed) => return false,
+                }
+            }
+        };
+        tokio::time::timeout(timeout, woken).await.unwrap_or(false)
+    }
+}
@@ -0,0 +1,28 @@
+//! Background task that expires stale device commands (see db::expire_stale_commands).
+
+use std::time::Duration;
+
+use db::{expire_stale_commands, DbPool};
+
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn spawn(pool: DbPool) {
+    tokio::spawn(async move {
+        tracing::info!("command sweeper started");
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            match expire_stale_commands(&pool).await {
+                Ok(expired) if expired.ttl_elapsed + expired.max_attempts_reached > 0 => {
+                    tracing::info!(
+                        "expired {} command(s) past TTL, {} out of delivery attempts",
+                        expired.ttl_elapsed,
+                        expired.max_attempts_reached
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("command sweeper pass failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,139 @@
+//! File downloads for portal reports: CSV and plain-text PDF.
+
+use axum::{
+    http::header,
+    response::{IntoResponse, Response},
+};
+
+/// CSV download with a header row. Fields containing commas, quotes or newlines are quoted.
+pub fn csv_response(filename: &str, headers: &[&str], rows: Vec<Vec<String>>) -> Response {
+    let mut body = String::new();
+    let header_row: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    for row in std::iter::once(&header_row).chain(rows.iter()) {
+        let fields: Vec<String> = row.iter().map(|f| csv_field(f)).collect();
+        body.push_str(&fields.join(","));
+        body.push_str("\r\n");
+    }
+    download(filename, "text/csv; charset=utf-8", body.into_bytes())
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// PDF download of a title and lines of text (A4, Courier so columns line up).
+pub fn pdf_response(filename: &str, title: &str, lines: &[String]) -> Response {
+    download(filename, "application/pdf", text_pdf(title, lines))
+}
+
+fn download(filename: &str, content_type: &str, body: Vec<u8>) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+const PAGE_WIDTH: u32 = 595;
+const PAGE_HEIGHT: u32 = 842;
+const MARGIN: u32 = 50;
+const LEADING: u32 = 12;
+const LINES_PER_PAGE: usize = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize - 2;
+
+/// A minimal PDF 1.4 document: the title in bold on the first page, then the lines, paginated.
+/// Characters outside ASCII are replaced with '?'.
+fn text_pdf(title: &str, lines: &[String]) -> Vec<u8> {
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(LINES_PER_PAGE).collect()
+    };
+
+    // Objects: 1 catalog, 2 page tree, 3 regular font, 4 bold font, then a page and its content
+    // stream for each page.
+    let mut objects: Vec<Vec<u8>> = Vec::new();
+    objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+    let kids: Vec<String> = (0..pages.len()).map(|i| format!("{} 0 R", 5 + 2 * i)).collect();
+    objects.push(
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            kids.join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+    );
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier /Encoding /WinAnsiEncoding >>".to_vec());
+    objects.push(b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier-Bold /Encoding /WinAnsiEncoding >>".to_vec());
+    for (i, page_lines) in pages.iter().enumerate() {
+        let mut content = format!("BT /F1 10 Tf {} TL {} {} Td\n", LEADING, MARGIN, PAGE_HEIGHT - MARGIN);
+        if i == 0 {
+            content.push_str(&format!("/F2 14 Tf ({}) Tj T* T* /F1 10 Tf\n", pdf_text(title)));
+        }
+        for line in page_lines.iter() {
+            content.push_str(&format!("({}) Tj T*\n", pdf_text(line)));
+        }
+        content.push_str("ET");
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                6 + 2 * i
+            )
+            .into_bytes(),
+        );
+        let mut stream = format!("<< /Length {} >>\nstream\n", content.len()).into_bytes();
+        stream.extend_from_slice(content.as_bytes());
+        stream.extend_from_slice(b"\nendstream");
+        objects.push(stream);
+    }
+
+    let mut out = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(out.len());
+        out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        out.extend_from_slice(object);
+        out.extend_from_slice(b"\nendobj\n");
+    }
+    let xref_at = out.len();
+    out.extend_from_slice(format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes());
+    for offset in offsets {
+        out.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    out.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_at
+        )
+        .as_bytes(),
+    );
+    out
+}
+
+/// Escape a line for a PDF string literal.
+fn pdf_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '(' | ')' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            _ => out.push('?'),
+        }
+    }
+    out
+}
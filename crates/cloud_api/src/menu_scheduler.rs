@@ -0,0 +1,34 @@
+//! Background task that makes scheduled menu changes live and re-sends store menus at daypart
+//! boundaries (see db::run_due_menu_schedules).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use db::{run_due_menu_schedules, DbPool};
+
+use crate::command_notify::CommandNotifier;
+
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+pub fn spawn(pool: DbPool, notifier: Arc<CommandNotifier>) {
+    tokio::spawn(async move {
+        tracing::info!("menu scheduler started");
+        let mut interval = tokio::time::interval(SCHEDULE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match run_due_menu_schedules(&pool).await {
+                Ok(run) if run.applied + run.failed + run.daypart_switches > 0 => {
+                    notifier.notify_all(&run.device_ids);
+                    tracing::info!(
+                        "applied {} scheduled menu change(s), {} failed; {} store menu(s) re-sent for dayparts",
+                        run.applied,
+                        run.failed,
+                        run.daypart_switches
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("menu scheduler pass failed: {}", e),
+            }
+        }
+    });
+}
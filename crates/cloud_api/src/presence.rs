@@ -0,0 +1,42 @@
+//! Open sync WebSocket connections per device on this instance. A device may briefly hold two
+//! sockets (reconnect before the old one times out); it only goes offline when the last closes.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct DevicePresence {
+    connections: Mutex<HashMap<Uuid, usize>>,
+}
+
+impl DevicePresence {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a connection. Returns true if it is the device's first on this instance.
+    pub fn connect(&self, device_id: Uuid) -> bool {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        let count = connections.entry(device_id).or_insert(0);
+        *count += 1;
+        *count == 1
+    }
+
+    /// Unregister a connection. Returns true if it was the device's last on this instance.
+    pub fn disconnect(&self, device_id: Uuid) -> bool {
+        let mut connections = self.connections.lock().unwrap_or_else(|e| e.into_inner());
+        match connections.get_mut(&device_id) {
+            Some(count) if *count > 1 => {
+                *count -= 1;
+                false
+            }
+            Some(_) => {
+                connections.remove(&device_id);
+                true
+            }
+            None => false,
+        }
+    }
+}
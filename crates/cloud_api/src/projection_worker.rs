@@ -0,0 +1,50 @@
+//! Background worker that projects device_event_log into the read models (see db::projection).
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use db::{list_devices_pending_projection, project_pending_for_device, DbPool};
+use tokio::sync::Notify;
+
+/// Fallback poll interval when nothing wakes the worker (also picks up retries whose backoff expired).
+const IDLE_POLL: Duration = Duration::from_secs(5);
+/// Devices handled per pass and events projected per device per transaction.
+const DEVICES_PER_PASS: i64 = 50;
+const EVENTS_PER_DEVICE: i64 = 200;
+
+pub fn spawn(pool: DbPool, notify: Arc<Notify>) {
+    tokio::spawn(async move {
+        tracing::info!("projection worker started");
+        loop {
+            let busy = match run_pass(&pool).await {
+                Ok(busy) => busy,
+                Err(e) => {
+                    tracing::warn!("projection worker pass failed: {}", e);
+                    false
+                }
+            };
+            if !busy {
+                let _ = tokio::time::timeout(IDLE_POLL, notify.notified()).await;
+            }
+        }
+    });
+}
+
+/// One pass over due devices. Returns true if any event was projected (so the caller loops again
+/// immediately to drain large backlogs).
+async fn run_pass(pool: &DbPool) -> Result<bool, sqlx::Error> {
+    let devices = list_devices_pending_projection(pool, DEVICES_PER_PASS).await?;
+    let mut busy = false;
+    for device_id in devices {
+        let pass = project_pending_for_device(pool, device_id, EVENTS_PER_DEVICE).await?;
+        if pass.dead_lettered > 0 {
+            tracing::error!(
+                "device {}: {} event(s) moved to projection_dead_letters",
+                device_id,
+                pass.dead_lettered
+            );
+        }
+        busy |= pass.projected > 0 || pass.dead_lettered > 0;
+    }
+    Ok(busy)
+}
@@ -661,16 +661,25 @@ async fn handle_provider_webhook(
         received_at: normalized.received_at.unwrap_or_else(Utc::now),
     };
 
-    let _saved = insert_delivery_order(db, order)
+    let saved = insert_delivery_order(db, order)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     // Emit POS-facing command using normalized payload.
     let pos_payload = serde_json::to_value(&normalized)
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    enqueue_delivery_order_command(db, org_id, store_id, &pos_payload)
+    if let Some(device_id) = enqueue_delivery_order_command(
+        db,
+        org_id,
+        store_id,
+        Uuid::parse_str(&saved.id).ok(),
+        &pos_payload,
+    )
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+    {
+        state.command_notifier.notify(device_id);
+    }
 
     db::touch_integration_last_sync(db, &integration.id)
         .await
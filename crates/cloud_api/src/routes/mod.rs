@@ -7,17 +7,27 @@ pub mod auth_login;
 pub mod device_activate;
 pub mod billing;
 pub mod portal_blogs;
+pub mod portal_commands;
 pub mod portal_dashboard;
 pub mod portal_docs;
 pub mod portal_me;
+pub mod portal_menu_schedule;
+pub mod portal_menu_templates;
+pub mod portal_menu_versions;
 pub mod portal_orgs;
+pub mod portal_kitchen;
 pub mod portal_store;
 pub mod portal_orders;
+pub mod portal_order_search;
+pub mod portal_projection;
+pub mod portal_reports;
 pub mod portal_super_admin;
+pub mod portal_z_reports;
 pub mod delivery_webhooks;
 pub mod sync_commands;
 pub mod sync_events;
 pub mod sync_menu;
+pub mod sync_ws;
 
 /// Build the application router (public + authenticated + device sync endpoints).
 /// Returns Router<AppState>; state is applied once in main via .with_state(state).
@@ -29,14 +39,24 @@ pub fn router(state: AppState) -> Router<AppState> {
         .merge(sync_events::router(state.clone()))
         .merge(sync_commands::router(state.clone()))
         .merge(sync_menu::router(state.clone()))
+        .merge(sync_ws::router(state.clone()))
         .merge(admin_activation_keys::router(state.clone()))
         .merge(portal_dashboard::router(state.clone()))
         .merge(portal_me::router(state.clone()))
         .merge(portal_orgs::router(state.clone()))
         .merge(portal_store::router(state.clone()))
         .merge(portal_orders::router(state.clone()))
+        .merge(portal_order_search::router(state.clone()))
+        .merge(portal_commands::router(state.clone()))
+        .merge(portal_projection::router(state.clone()))
+        .merge(portal_reports::router(state.clone()))
         .merge(portal_blogs::router(state.clone()))
         .merge(portal_docs::router(state.clone()))
         .merge(portal_super_admin::router(state.clone()))
+        .merge(portal_z_reports::router(state.clone()))
+        .merge(portal_kitchen::router(state.clone()))
+        .merge(portal_menu_templates::router(state.clone()))
+        .merge(portal_menu_versions::router(state.clone()))
+        .merge(portal_menu_schedule::router(state.clone()))
         .merge(delivery_webhooks::router(state))
 }
@@ -0,0 +1,353 @@
+//! Portal command center: send registered command types (domain::command_types) to one device,
+//! every device in a store, or every store in a franchise; approve or reject sensitive commands
+//! (two-person rule, see db::approvals).
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{
+    apply_menu_command_body, decide_command, get_command_scope, get_store_org_and_franchise,
+    list_active_device_ids_for_store, list_store_ids_for_franchise, user_can_approve_commands,
+    ApprovalDecision, CommandDecisionError, CommandEntity, CommandDecisionOutcome, MenuPublish,
+    MenuVersionSource, NewDeviceCommand,
+};
+use domain::{command_types, find_command_type, CommandTargeting, CommandTypeSpec};
+
+#[derive(Debug, Deserialize)]
+pub struct CommandPathParams {
+    pub command_id: String,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct DecisionBody {
+    /// Optional note shown in the approval trail.
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+/// Which devices receive the command, relative to the store in the path.
+#[derive(Debug, Deserialize)]
+#[serde(tag = "scope", rename_all = "snake_case")]
+pub enum CommandTarget {
+    /// One device of the store.
+    Device { device_id: Uuid },
+    /// Every active device in the store.
+    Store,
+    /// Every active device in every active store of the store's franchise.
+    Franchise,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SendCommandRequest {
+    pub command_type: String,
+    #[serde(default = "empty_body")]
+    pub command_body: serde_json::Value,
+    pub target: CommandTarget,
+    /// Optional: overrides the command type's default TTL.
+    pub expires_in_seconds: Option<u32>,
+}
+
+fn empty_body() -> serde_json::Value {
+    serde_json::json!({})
+}
+
+#[derive(Debug, Serialize)]
+pub struct QueuedCommand {
+    pub command_id: String,
+    pub store_id: String,
+    pub device_id: String,
+    pub status: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SendCommandResponse {
+    pub commands: Vec<QueuedCommand>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CommandTypesResponse {
+    pub command_types: Vec<CommandTypeSpec>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/portal/command-types", get(get_command_types))
+        .route("/portal/stores/:store_id/commands", post(post_store_command))
+        .route("/portal/commands/:command_id/approve", post(post_approve_command))
+        .route("/portal/commands/:command_id/reject", post(post_reject_command))
+}
+
+async fn get_command_types(_user: CurrentUser) -> Json<CommandTypesResponse> {
+    Json(CommandTypesResponse {
+        command_types: command_types(),
+    })
+}
+
+async fn post_store_command(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Json(req): Json<SendCommandRequest>,
+) -> Result<(StatusCode, Json<SendCommandResponse>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = Uuid::parse_str(&store_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid store_id".to_string()))?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+
+    let spec = find_command_type(&req.command_type).ok_or_else(|| {
+        (
+            StatusCode::BAD_REQUEST,
+            format!("unknown command_type '{}' (see GET /portal/command-types)", req.command_type),
+        )
+    })?;
+    if spec.targeting == CommandTargeting::Order {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("{} is sent from the order: use POST /portal/orders/:order_id/commands", spec.command_type),
+        ));
+    }
+    validate_command_body(&spec, &req.command_body)?;
+
+    let (org_id, franchise_id) = get_store_org_and_franchise(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "store not found".to_string()))?;
+
+    // (store, devices) pairs to queue for.
+    let mut targets: Vec<(Uuid, Vec<Uuid>)> = Vec::new();
+    match req.target {
+        CommandTarget::Device { device_id } => {
+            let devices = list_active_device_ids_for_store(db, store_uuid).await.map_err(internal)?;
+            if !devices.contains(&device_id) {
+                return Err((
+                    StatusCode::NOT_FOUND,
+                    "device not found or not active in this store".to_string(),
+                ));
+            }
+            targets.push((store_uuid, vec![device_id]));
+        }
+        CommandTarget::Store => {
+            let devices = list_active_device_ids_for_store(db, store_uuid).await.map_err(internal)?;
+            targets.push((store_uuid, devices));
+        }
+        CommandTarget::Franchise => {
+            let franchise_id = franchise_id.ok_or((
+                StatusCode::BAD_REQUEST,
+                "store is not part of a franchise".to_string(),
+            ))?;
+            // Franchise-wide sends need access to the whole org, not just this store.
+            let org_allowed = db::user_can_access_org(db, &user.0, org_id)
+                .await
+                .map_err(internal)?;
+            if !org_allowed {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    "franchise-wide commands need organization access".to_string(),
+                ));
+            }
+            for store in list_store_ids_for_franchise(db, franchise_id).await.map_err(internal)? {
+                let devices = list_active_device_ids_for_store(db, store).await.map_err(internal)?;
+                targets.push((store, devices));
+            }
+        }
+    }
+    if targets.iter().all(|(_, devices)| devices.is_empty()) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "no active devices for this target".to_string(),
+        ));
+    }
+
+    let expires_at = req
+        .expires_in_seconds
+        .map(|secs| chrono::Utc::now() + chrono::Duration::seconds(i64::from(secs)));
+    let command_publish = MenuPublish::new(MenuVersionSource::Command, Some(&user.0));
+    // Build every body first so the whole fan-out is queued in one transaction.
+    let mut bodies = Vec::new();
+    for (store, devices) in targets {
+        if devices.is_empty() {
+            continue;
+        }
+        let body = if spec.command_type == "apply_menu" {
+            match apply_menu_command_body(db, store, &command_publish)
+                .await
+                .map_err(internal)? {
+                Some(body) => body,
+                None => continue,
+            }
+        } else {
+            req.command_body.clone()
+        };
+        bodies.push((store, devices, body));
+    }
+    let mut new_commands = Vec::new();
+    for (store, devices, body) in &bodies {
+        for &device_id in devices {
+            new_commands.push(NewDeviceCommand {
+                org_id,
+                store_id: *store,
+                device_id,
+                command_type: spec.command_type,
+                command_body: body,
+                sensitive: spec.sensitive,
+                expires_at,
+                target: (spec.command_type == "apply_menu").then_some(CommandEntity::Menu(*store)),
+                requested_by_user_id: Some(&user.0),
+            });
+        }
+    }
+    let command_ids = db::enqueue_device_commands(db, &new_commands)
+        .await
+        .map_err(internal)?;
+    let mut commands = Vec::with_capacity(command_ids.len());
+    for (command, command_id) in new_commands.iter().zip(command_ids) {
+        if !spec.sensitive {
+            state.command_notifier.notify(command.device_id);
+        }
+        commands.push(QueuedCommand {
+            command_id: command_id.to_string(),
+            store_id: command.store_id.to_string(),
+            device_id: command.device_id.to_string(),
+            status: if spec.sensitive { "pending_approval" } else { "queued" }.to_string(),
+        });
+    }
+
+    tracing::info!(
+        "user {} queued {} {} command(s) from store {}",
+        user.0,
+        commands.len(),
+        spec.command_type,
+        store_uuid
+    );
+    Ok((StatusCode::CREATED, Json(SendCommandResponse { commands })))
+}
+
+/// Check command_body against the type's JSON Schema; 400 with every violation otherwise.
+fn validate_command_body(spec: &CommandTypeSpec, body: &serde_json::Value) -> Result<(), (StatusCode, String)> {
+    let schema = jsonschema::JSONSchema::compile(&spec.body_schema)
+        .map_err(|e| internal(format!("invalid schema for {}: {}", spec.command_type, e)))?;
+    let result = schema.validate(body);
+    if let Err(errors) = result {
+        let messages: Vec<String> = errors
+            .map(|e| {
+                let path = e.instance_path.to_string();
+                if path.is_empty() {
+                    e.to_string()
+                } else {
+                    format!("{}: {}", path, e)
+                }
+            })
+            .collect();
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("invalid command_body for {}: {}", spec.command_type, messages.join("; ")),
+        ));
+    }
+    Ok(())
+}
+
+async fn post_approve_command(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(CommandPathParams { command_id }): Path<CommandPathParams>,
+    body: Option<Json<DecisionBody>>,
+) -> Result<Json<CommandDecisionOutcome>, (StatusCode, String)> {
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    decide(&state, &user, &command_id, ApprovalDecision::Approve, body.comment).await
+}
+
+async fn post_reject_command(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(CommandPathParams { command_id }): Path<CommandPathParams>,
+    body: Option<Json<DecisionBody>>,
+) -> Result<Json<CommandDecisionOutcome>, (StatusCode, String)> {
+    let body = body.map(|Json(b)| b).unwrap_or_default();
+    decide(&state, &user, &command_id, ApprovalDecision::Reject, body.comment).await
+}
+
+async fn decide(
+    state: &AppState,
+    user: &CurrentUser,
+    command_id: &str,
+    decision: ApprovalDecision,
+    comment: Option<String>,
+) -> Result<Json<CommandDecisionOutcome>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let command_uuid = Uuid::parse_str(command_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid command_id".to_string()))?;
+
+    let scope = get_command_scope(db, command_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "command not found".to_string()))?;
+    let allowed = db::user_can_access_store(db, &user.0, scope.store_id)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    // Anyone with an approver role may decide; the requester may additionally withdraw their own.
+    let is_requester = scope.requested_by_user_id.as_deref() == Some(user.0.as_str());
+    let can_approve = user_can_approve_commands(db, &user.0, scope.org_id, scope.store_id)
+        .await
+        .map_err(internal)?;
+    let withdrawing = is_requester && decision == ApprovalDecision::Reject;
+    if !(can_approve || withdrawing) {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "your role cannot approve sensitive commands".to_string(),
+        ));
+    }
+
+    let comment = comment.map(|c| c.trim().to_string()).filter(|c| !c.is_empty());
+    let outcome = decide_command(db, command_uuid, &user.0, decision, comment.as_deref())
+        .await
+        .map_err(|e| match e {
+            CommandDecisionError::NotFound => (StatusCode::NOT_FOUND, e.to_string()),
+            CommandDecisionError::NotPending(_) | CommandDecisionError::AlreadyDecided => {
+                (StatusCode::CONFLICT, e.to_string())
+            }
+            CommandDecisionError::SelfApproval => (StatusCode::FORBIDDEN, e.to_string()),
+            CommandDecisionError::Db(e) => internal(e),
+        })?;
+
+    tracing::info!(
+        "command {} {:?} by user {} -> {}",
+        command_uuid,
+        decision,
+        user.0,
+        outcome.status
+    );
+    if outcome.status == "queued" {
+        state.command_notifier.notify(scope.device_id);
+    }
+    Ok(Json(outcome))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
@@ -1,4 +1,7 @@
+use std::collections::HashMap;
+
 use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use domain::StoreClock;
 use serde::Serialize;
 use sqlx::Row;
 
@@ -8,6 +11,7 @@ use crate::state::AppState;
 #[derive(Debug, Serialize)]
 pub struct DashboardSummary {
     pub total_orders: i64,
+    /// Orders in each store's current trading day (store timezone and cutoff).
     pub today_orders: i64,
     pub total_revenue_cents: i64,
     pub device_count: i64,
@@ -21,7 +25,10 @@ pub struct RecentOrder {
     pub store_name: String,
     pub status: String,
     pub total_cents: Option<i64>,
+    /// Store-local time with offset.
     pub occurred_at: String,
+    /// Business day in the store's timezone (YYYY-MM-DD).
+    pub trading_day: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -51,14 +58,31 @@ async fn get_summary(
         .map_err(internal)?
         .get::<i64, _>("c");
 
-    // Today's orders (by occurred_at in UTC date)
-    let today_orders: i64 = sqlx::query(
-        "SELECT COUNT(*) AS c FROM orders WHERE DATE(occurred_at) = CURDATE()",
-    )
-    .fetch_one(db)
-    .await
-    .map_err(internal)?
-    .get::<i64, _>("c");
+    // Today's orders: each store's current trading day, one query per distinct store clock.
+    let store_clocks = db::list_store_clocks(db).await.map_err(internal)?;
+    let mut stores_by_clock: HashMap<StoreClock, Vec<String>> = HashMap::new();
+    for (store_id, clock) in store_clocks {
+        stores_by_clock.entry(clock).or_default().push(store_id);
+    }
+    let mut today_orders: i64 = 0;
+    for (clock, store_ids) in stores_by_clock {
+        let (start, end) = clock.day_bounds(clock.today());
+        let sql = format!(
+            "SELECT COUNT(*) AS c FROM orders WHERE store_id IN ({}) AND occurred_at >= ? AND occurred_at < ?",
+            vec!["?"; store_ids.len()].join(", ")
+        );
+        let mut query = sqlx::query(&sql);
+        for id in &store_ids {
+            query = query.bind(id);
+        }
+        today_orders += query
+            .bind(start.naive_utc())
+            .bind(end.naive_utc())
+            .fetch_one(db)
+            .await
+            .map_err(internal)?
+            .get::<i64, _>("c");
+    }
 
     // Total revenue from transactions
     let total_revenue_cents: i64 =
@@ -88,7 +112,7 @@ async fn get_summary(
         today_orders,
         total_revenue_cents,
         device_count,
-        last_event_at: last_event_at.map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+        last_event_at: last_event_at.map(|dt| dt.and_utc().to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
     }))
 }
 
@@ -107,6 +131,8 @@ async fn get_recent_orders(
           o.id,
           o.local_order_id,
           s.name AS store_name,
+          s.timezone,
+          s.trading_day_cutoff,
           o.status,
           o.total_cents,
           o.occurred_at
@@ -122,16 +148,21 @@ async fn get_recent_orders(
 
     let orders = rows
         .into_iter()
-        .map(|row| RecentOrder {
-            id: row.get::<String, _>("id"),
-            local_order_id: row.get::<String, _>("local_order_id"),
-            store_name: row.get::<String, _>("store_name"),
-            status: row.get::<String, _>("status"),
-            total_cents: row.get::<Option<i64>, _>("total_cents"),
-            occurred_at: row
-                .get::<chrono::NaiveDateTime, _>("occurred_at")
-                .format("%Y-%m-%dT%H:%M:%S")
-                .to_string(),
+        .map(|row| {
+            let clock = StoreClock::new(
+                &row.get::<String, _>("timezone"),
+                row.get::<chrono::NaiveTime, _>("trading_day_cutoff"),
+            );
+            let occurred_at = row.get::<chrono::NaiveDateTime, _>("occurred_at").and_utc();
+            RecentOrder {
+                id: row.get::<String, _>("id"),
+                local_order_id: row.get::<String, _>("local_order_id"),
+                store_name: row.get::<String, _>("store_name"),
+                status: row.get::<String, _>("status"),
+                total_cents: row.get::<Option<i64>, _>("total_cents"),
+                occurred_at: clock.format_local(occurred_at),
+                trading_day: clock.trading_day(occurred_at).to_string(),
+            }
         })
         .collect();
 
@@ -0,0 +1,378 @@
+//! Portal kitchen display timings per store: ticket times (sent to ready), prep time per item
+//! (started to bumped) and per hour of the day over a range of business days, and the live open
+//! tickets. Projected from order_sent_to_kitchen, item_started, item_bumped and order_ready; see
+//! db::kitchen.
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{NaiveDate, NaiveDateTime, Timelike, Utc};
+use domain::StoreClock;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{KitchenItem, KitchenTicket};
+
+/// Longest range a single report may cover.
+const MAX_REPORT_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KitchenReportQuery {
+    /// First and last business day, inclusive (YYYY-MM-DD); both default to the store's current
+    /// trading day.
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KitchenSummary {
+    pub ticket_count: i64,
+    /// Tickets marked ready.
+    pub ready_count: i64,
+    pub avg_ticket_seconds: Option<i64>,
+    pub max_ticket_seconds: Option<i64>,
+    pub item_count: i64,
+    pub avg_prep_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KitchenTicketTime {
+    pub order_id: String,
+    pub local_order_id: String,
+    /// Store-local times with offset.
+    pub sent_at: String,
+    pub ready_at: Option<String>,
+    /// Sent to ready; null until the ticket is ready.
+    pub ticket_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KitchenItemTimes {
+    pub item: String,
+    /// Lines bumped.
+    pub count: i64,
+    /// Started to bumped (sent to bumped for lines never started).
+    pub avg_prep_seconds: Option<i64>,
+    /// Sent to bumped.
+    pub avg_total_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KitchenHour {
+    /// Store-local hour the ticket was sent (0-23).
+    pub hour: u32,
+    pub ticket_count: i64,
+    pub avg_ticket_seconds: Option<i64>,
+    pub item_count: i64,
+    pub avg_prep_seconds: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct KitchenReportResponse {
+    pub store_id: String,
+    pub store_name: String,
+    pub from: String,
+    pub to: String,
+    pub summary: KitchenSummary,
+    /// Oldest first.
+    pub tickets: Vec<KitchenTicketTime>,
+    /// Slowest average prep first.
+    pub items: Vec<KitchenItemTimes>,
+    /// Hours with tickets, in hour order.
+    pub hours: Vec<KitchenHour>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenTicketItem {
+    pub local_item_id: String,
+    pub item: String,
+    pub station: Option<String>,
+    pub quantity: f64,
+    /// `queued`, `started` or `bumped`.
+    pub state: &'static str,
+    pub started_at: Option<String>,
+    pub bumped_at: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenTicket {
+    pub order_id: String,
+    pub local_order_id: String,
+    pub sent_at: String,
+    pub age_seconds: i64,
+    pub items: Vec<OpenTicketItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OpenTicketsResponse {
+    pub store_id: String,
+    pub store_name: String,
+    pub as_of: String,
+    /// Oldest first.
+    pub tickets: Vec<OpenTicket>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/portal/stores/:store_id/kitchen/report", get(kitchen_report))
+        .route("/portal/stores/:store_id/kitchen/open", get(open_tickets))
+}
+
+/// Name and clock of a store the user can access.
+async fn authorize_store(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<(Uuid, String, StoreClock), (StatusCode, String)> {
+    let store_uuid = Uuid::parse_str(store_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid store_id".to_string()))?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    let row = sqlx::query("SELECT name, timezone, trading_day_cutoff FROM stores WHERE id = ?")
+        .bind(store_uuid.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "store not found".to_string()))?;
+    let clock = StoreClock::new(
+        &row.get::<String, _>("timezone"),
+        row.get::<chrono::NaiveTime, _>("trading_day_cutoff"),
+    );
+    Ok((store_uuid, row.get::<String, _>("name"), clock))
+}
+
+async fn kitchen_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Query(q): Query<KitchenReportQuery>,
+) -> Result<Json<KitchenReportResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let (store_uuid, store_name, clock) = authorize_store(db, &user, &store_id).await?;
+
+    let to = match q.to.as_deref() {
+        Some(d) => parse_day(d, "to")?,
+        None => clock.today(),
+    };
+    let from = match q.from.as_deref() {
+        Some(d) => parse_day(d, "from")?,
+        None => to,
+    };
+    if from > to {
+        return Err((StatusCode::BAD_REQUEST, "from must not be after to".to_string()));
+    }
+    if (to - from).num_days() >= MAX_REPORT_DAYS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("reports cover at most {} days", MAX_REPORT_DAYS),
+        ));
+    }
+
+    let (start, end) = clock.range_bounds(from, to);
+    let (start, end) = (start.naive_utc(), end.naive_utc());
+    let tickets = db::list_kitchen_tickets(db, store_uuid, start, end, false)
+        .await
+        .map_err(internal)?;
+    let items = db::list_kitchen_items(db, store_uuid, start, end, false)
+        .await
+        .map_err(internal)?;
+
+    Ok(Json(KitchenReportResponse {
+        store_id: store_uuid.to_string(),
+        store_name,
+        from: from.to_string(),
+        to: to.to_string(),
+        summary: summarize(&tickets, &items),
+        hours: by_hour(&clock, &tickets, &items),
+        items: by_item(&items),
+        tickets: tickets
+            .iter()
+            .map(|t| KitchenTicketTime {
+                order_id: t.order_id.clone(),
+                local_order_id: t.local_order_id.clone(),
+                sent_at: clock.format_local_naive(t.sent_at),
+                ready_at: t.ready_at.map(|at| clock.format_local_naive(at)),
+                ticket_seconds: ticket_seconds(t),
+            })
+            .collect(),
+    }))
+}
+
+async fn open_tickets(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<OpenTicketsResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let (store_uuid, store_name, clock) = authorize_store(db, &user, &store_id).await?;
+
+    // Tickets sent since the previous trading day started, so one sent just before the cutoff
+    // stays on the board; older tickets never marked ready are left off.
+    let today = clock.today();
+    let (start, end) = clock.range_bounds(today - chrono::Duration::days(1), today);
+    let (start, end) = (start.naive_utc(), end.naive_utc());
+    let tickets = db::list_kitchen_tickets(db, store_uuid, start, end, true)
+        .await
+        .map_err(internal)?;
+    let items = db::list_kitchen_items(db, store_uuid, start, end, true)
+        .await
+        .map_err(internal)?;
+
+    let mut items_by_order: HashMap<String, Vec<OpenTicketItem>> = HashMap::new();
+    for i in items {
+        let state = if i.bumped_at.is_some() {
+            "bumped"
+        } else if i.started_at.is_some() {
+            "started"
+        } else {
+            "queued"
+        };
+        items_by_order.entry(i.order_id).or_default().push(OpenTicketItem {
+            local_item_id: i.local_item_id,
+            item: i.item,
+            station: i.station,
+            quantity: i.quantity,
+            state,
+            started_at: i.started_at.map(|at| clock.format_local_naive(at)),
+            bumped_at: i.bumped_at.map(|at| clock.format_local_naive(at)),
+        });
+    }
+
+    let now = Utc::now();
+    Ok(Json(OpenTicketsResponse {
+        store_id: store_uuid.to_string(),
+        store_name,
+        as_of: clock.format_local(now),
+        tickets: tickets
+            .into_iter()
+            .map(|t| OpenTicket {
+                items: items_by_order.remove(&t.order_id).unwrap_or_default(),
+                sent_at: clock.format_local_naive(t.sent_at),
+                age_seconds: (now.naive_utc() - t.sent_at).num_seconds().max(0),
+                order_id: t.order_id,
+                local_order_id: t.local_order_id,
+            })
+            .collect(),
+    }))
+}
+
+fn summarize(tickets: &[KitchenTicket], items: &[KitchenItem]) -> KitchenSummary {
+    let ticket_times: Vec<i64> = tickets.iter().filter_map(ticket_seconds).collect();
+    let prep_times: Vec<i64> = items.iter().filter_map(prep_seconds).collect();
+    KitchenSummary {
+        ticket_count: tickets.len() as i64,
+        ready_count: ticket_times.len() as i64,
+        avg_ticket_seconds: average(&ticket_times),
+        max_ticket_seconds: ticket_times.iter().copied().max(),
+        item_count: items.len() as i64,
+        avg_prep_seconds: average(&prep_times),
+    }
+}
+
+fn by_item(items: &[KitchenItem]) -> Vec<KitchenItemTimes> {
+    let mut grouped: BTreeMap<&str, (Vec<i64>, Vec<i64>)> = BTreeMap::new();
+    for i in items {
+        let Some(prep) = prep_seconds(i) else {
+            continue;
+        };
+        let (preps, totals) = grouped.entry(i.item.as_str()).or_default();
+        preps.push(prep);
+        if let Some(total) = seconds_between(i.sent_at, i.bumped_at) {
+            totals.push(total);
+        }
+    }
+    let mut rows: Vec<KitchenItemTimes> = grouped
+        .into_iter()
+        .map(|(item, (preps, totals))| KitchenItemTimes {
+            item: item.to_string(),
+            count: preps.len() as i64,
+            avg_prep_seconds: average(&preps),
+            avg_total_seconds: average(&totals),
+        })
+        .collect();
+    rows.sort_by_key(|row| std::cmp::Reverse(row.avg_prep_seconds));
+    rows
+}
+
+fn by_hour(clock: &StoreClock, tickets: &[KitchenTicket], items: &[KitchenItem]) -> Vec<KitchenHour> {
+    let local_hour = |at: NaiveDateTime| at.and_utc().with_timezone(&clock.timezone).hour();
+    let mut ticket_hours: HashMap<&str, u32> = HashMap::new();
+    let mut hours: BTreeMap<u32, (i64, Vec<i64>, i64, Vec<i64>)> = BTreeMap::new();
+    for t in tickets {
+        let hour = local_hour(t.sent_at);
+        ticket_hours.insert(t.order_id.as_str(), hour);
+        let (count, times, _, _) = hours.entry(hour).or_default();
+        *count += 1;
+        times.extend(ticket_seconds(t));
+    }
+    for i in items {
+        let Some(&hour) = ticket_hours.get(i.order_id.as_str()) else {
+            continue;
+        };
+        let (_, _, count, preps) = hours.entry(hour).or_default();
+        *count += 1;
+        preps.extend(prep_seconds(i));
+    }
+    hours
+        .into_iter()
+        .map(|(hour, (ticket_count, times, item_count, preps))| KitchenHour {
+            hour,
+            ticket_count,
+            avg_ticket_seconds: average(&times),
+            item_count,
+            avg_prep_seconds: average(&preps),
+        })
+        .collect()
+}
+
+fn ticket_seconds(ticket: &KitchenTicket) -> Option<i64> {
+    seconds_between(Some(ticket.sent_at), ticket.ready_at)
+}
+
+/// Started to bumped, or sent to bumped for a line bumped without being started.
+fn prep_seconds(item: &KitchenItem) -> Option<i64> {
+    seconds_between(item.started_at.or(item.sent_at), item.bumped_at)
+}
+
+fn seconds_between(from: Option<NaiveDateTime>, to: Option<NaiveDateTime>) -> Option<i64> {
+    Some((to? - from?).num_seconds().max(0))
+}
+
+fn average(values: &[i64]) -> Option<i64> {
+    if values.is_empty() {
+        return None;
+    }
+    Some(values.iter().sum::<i64>() / values.len() as i64)
+}
+
+fn parse_day(value: &str, field: &str) -> Result<NaiveDate, (StatusCode, String)> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("{} must be YYYY-MM-DD", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
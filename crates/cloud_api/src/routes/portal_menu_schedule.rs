@@ -0,0 +1,483 @@
+//! Portal menu scheduling: menu versions or price changes scheduled to go live at a store-local
+//! time, and dayparts (store-local windows that categories and items are only available in). The
+//! menu scheduler applies both and queues apply_menu. See db::menu_schedule.
+
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    routing::{delete, get, put},
+    Json, Router,
+};
+use chrono::{NaiveDateTime, NaiveTime, Utc};
+use domain::{parse_weekday, weekday_str, StoreClock};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{
+    MenuDaypart, MenuPublish, MenuVersionSource, NewMenuDaypart, NewScheduledMenuChange,
+    ScheduledMenuChange, ScheduledPrice,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduledChangeBody {
+    /// Store-local time, YYYY-MM-DDTHH:MM[:SS].
+    pub run_at: String,
+    /// Menu version to make live.
+    pub version: Option<i32>,
+    /// Or new prices for some items.
+    pub prices: Option<Vec<ScheduledPrice>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DaypartBody {
+    pub name: String,
+    /// HH:MM store-local.
+    pub start_time: String,
+    /// HH:MM; before start_time runs past midnight, equal is all day.
+    pub end_time: String,
+    /// mon..sun; empty or omitted = every day.
+    #[serde(default)]
+    pub days: Vec<String>,
+    #[serde(default)]
+    pub local_category_ids: Vec<String>,
+    #[serde(default)]
+    pub local_item_ids: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledChangeResponse {
+    pub id: String,
+    /// Store-local time it goes live.
+    pub run_at: String,
+    /// The same moment with the store's current UTC offset.
+    pub due_at: String,
+    pub version: Option<i32>,
+    pub prices: Option<Vec<ScheduledPrice>>,
+    pub status: String,
+    pub error: Option<String>,
+    pub applied_at: Option<String>,
+    /// Menu version sent when it was applied.
+    pub applied_version: Option<i32>,
+    pub created_by_user_id: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ScheduledChangesResponse {
+    pub store_id: String,
+    pub timezone: String,
+    pub changes: Vec<ScheduledChangeResponse>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DaypartResponse {
+    pub id: String,
+    pub name: String,
+    pub start_time: String,
+    pub end_time: String,
+    pub days: Vec<&'static str>,
+    pub local_category_ids: Vec<String>,
+    pub local_item_ids: Vec<String>,
+    /// Whether the window is open now.
+    pub open: bool,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DaypartsResponse {
+    pub store_id: String,
+    pub timezone: String,
+    /// Store-local time `open` was worked out at.
+    pub as_of: String,
+    pub dayparts: Vec<DaypartResponse>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/portal/stores/:store_id/menu/scheduled",
+            get(list_scheduled).post(create_scheduled),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/scheduled/:change_id",
+            delete(cancel_scheduled),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/dayparts",
+            get(list_dayparts).post(create_daypart),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/dayparts/:daypart_id",
+            put(put_daypart).delete(delete_daypart),
+        )
+}
+
+async fn list_scheduled(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<ScheduledChangesResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let clock = store_clock(db, store_uuid).await?;
+    let changes = db::list_scheduled_menu_changes(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(ScheduledChangesResponse {
+        store_id: store_uuid.to_string(),
+        timezone: clock.timezone.name().to_string(),
+        changes: changes.into_iter().map(|c| scheduled_change(c, &clock)).collect(),
+    }))
+}
+
+async fn create_scheduled(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Json(body): Json<CreateScheduledChangeBody>,
+) -> Result<(StatusCode, Json<ScheduledChangeResponse>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let clock = store_clock(db, store_uuid).await?;
+    let run_at = parse_local_datetime(&body.run_at)?;
+    if clock.local_to_utc(run_at) <= Utc::now() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "run_at must be in the future (store-local time)".to_string(),
+        ));
+    }
+    match (body.version, body.prices.as_deref()) {
+        (Some(version), None) => {
+            db::get_menu_version(db, store_uuid, version)
+                .await
+                .map_err(internal)?
+                .ok_or((StatusCode::NOT_FOUND, "menu version not found".to_string()))?;
+        }
+        (None, Some(prices)) => {
+            if prices.is_empty() {
+                return Err((StatusCode::BAD_REQUEST, "prices must not be empty".to_string()));
+            }
+            if prices.iter().any(|p| p.price_pence < 0) {
+                return Err((StatusCode::BAD_REQUEST, "price_pence must not be negative".to_string()));
+            }
+            let (_, items) = db::get_store_menu_for_sync(db, store_uuid)
+                .await
+                .map_err(internal)?
+                .unwrap_or_default();
+            if let Some(unknown) = prices
+                .iter()
+                .find(|p| !items.iter().any(|i| i.local_item_id == p.local_item_id))
+            {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("item {} is not on this store's menu", unknown.local_item_id),
+                ));
+            }
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "give either version or prices".to_string(),
+            ))
+        }
+    }
+    let change_id = db::create_scheduled_menu_change(
+        db,
+        store_uuid,
+        &NewScheduledMenuChange {
+            run_at,
+            version: body.version,
+            prices: body.prices.as_deref(),
+            created_by_user_id: &user.0,
+        },
+    )
+    .await
+    .map_err(internal)?;
+    let change = db::get_scheduled_menu_change(db, store_uuid, change_id)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "scheduled change not found".to_string()))?;
+    Ok((StatusCode::CREATED, Json(scheduled_change(change, &clock))))
+}
+
+async fn cancel_scheduled(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, change_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let change_uuid = parse_uuid(&change_id, "change_id")?;
+    let change = db::get_scheduled_menu_change(db, store_uuid, change_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "scheduled change not found".to_string()))?;
+    let cancelled = db::cancel_scheduled_menu_change(db, store_uuid, change_uuid)
+        .await
+        .map_err(internal)?;
+    if !cancelled {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("scheduled change is already {}", change.status),
+        ));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn list_dayparts(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<DaypartsResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let clock = store_clock(db, store_uuid).await?;
+    let dayparts = db::list_menu_dayparts(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    let now = clock.local_time(Utc::now());
+    Ok(Json(DaypartsResponse {
+        store_id: store_uuid.to_string(),
+        timezone: clock.timezone.name().to_string(),
+        as_of: clock.format_local(Utc::now()),
+        dayparts: dayparts.into_iter().map(|d| daypart(d, now)).collect(),
+    }))
+}
+
+async fn create_daypart(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Json(body): Json<DaypartBody>,
+) -> Result<(StatusCode, Json<DaypartResponse>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let (start_time, end_time, days) = parse_daypart_window(&body)?;
+    let daypart_id = db::create_menu_daypart(
+        db,
+        store_uuid,
+        &NewMenuDaypart {
+            name: body.name.trim(),
+            start_time,
+            end_time,
+            days: &days,
+            local_category_ids: &body.local_category_ids,
+            local_item_ids: &body.local_item_ids,
+        },
+    )
+    .await
+    .map_err(internal)?;
+    send_menu(&state, db, store_uuid, &user).await?;
+    let created = find_daypart(db, store_uuid, daypart_id).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+async fn put_daypart(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, daypart_id)): Path<(String, String)>,
+    Json(body): Json<DaypartBody>,
+) -> Result<Json<DaypartResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let daypart_uuid = parse_uuid(&daypart_id, "daypart_id")?;
+    let (start_time, end_time, days) = parse_daypart_window(&body)?;
+    let updated = db::update_menu_daypart(
+        db,
+        store_uuid,
+        daypart_uuid,
+        &NewMenuDaypart {
+            name: body.name.trim(),
+            start_time,
+            end_time,
+            days: &days,
+            local_category_ids: &body.local_category_ids,
+            local_item_ids: &body.local_item_ids,
+        },
+    )
+    .await
+    .map_err(internal)?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "daypart not found".to_string()));
+    }
+    send_menu(&state, db, store_uuid, &user).await?;
+    Ok(Json(find_daypart(db, store_uuid, daypart_uuid).await?))
+}
+
+async fn delete_daypart(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, daypart_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let daypart_uuid = parse_uuid(&daypart_id, "daypart_id")?;
+    let deleted = db::delete_menu_daypart(db, store_uuid, daypart_uuid)
+        .await
+        .map_err(internal)?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "daypart not found".to_string()));
+    }
+    send_menu(&state, db, store_uuid, &user).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// After a daypart change: record the open dayparts and send the store's menu again.
+async fn send_menu(
+    state: &AppState,
+    db: &sqlx::MySqlPool,
+    store_uuid: Uuid,
+    user: &CurrentUser,
+) -> Result<(), (StatusCode, String)> {
+    db::update_daypart_state(db, store_uuid, Utc::now())
+        .await
+        .map_err(internal)?;
+    let publish = MenuPublish::new(MenuVersionSource::Daypart, Some(&user.0));
+    if let Ok(devices) = db::enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
+    Ok(())
+}
+
+async fn find_daypart(
+    db: &sqlx::MySqlPool,
+    store_uuid: Uuid,
+    daypart_id: Uuid,
+) -> Result<DaypartResponse, (StatusCode, String)> {
+    let clock = store_clock(db, store_uuid).await?;
+    let now = clock.local_time(Utc::now());
+    db::list_menu_dayparts(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .into_iter()
+        .find(|d| d.id == daypart_id.to_string())
+        .map(|d| daypart(d, now))
+        .ok_or((StatusCode::NOT_FOUND, "daypart not found".to_string()))
+}
+
+fn parse_daypart_window(
+    body: &DaypartBody,
+) -> Result<(NaiveTime, NaiveTime, Vec<chrono::Weekday>), (StatusCode, String)> {
+    if body.name.trim().is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name is required".to_string()));
+    }
+    let start_time = NaiveTime::parse_from_str(body.start_time.trim(), "%H:%M")
+        .map_err(|_| (StatusCode::BAD_REQUEST, "start_time must be HH:MM".to_string()))?;
+    let end_time = NaiveTime::parse_from_str(body.end_time.trim(), "%H:%M")
+        .map_err(|_| (StatusCode::BAD_REQUEST, "end_time must be HH:MM".to_string()))?;
+    let mut days = Vec::with_capacity(body.days.len());
+    for day in &body.days {
+        let day = parse_weekday(day)
+            .ok_or((StatusCode::BAD_REQUEST, format!("unknown day {} (use mon..sun)", day)))?;
+        if !days.contains(&day) {
+            days.push(day);
+        }
+    }
+    if body.local_category_ids.is_empty() && body.local_item_ids.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "give at least one local_category_id or local_item_id".to_string(),
+        ));
+    }
+    Ok((start_time, end_time, days))
+}
+
+fn parse_local_datetime(value: &str) -> Result<NaiveDateTime, (StatusCode, String)> {
+    let value = value.trim();
+    NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M:%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M"))
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                "run_at must be YYYY-MM-DDTHH:MM (store-local)".to_string(),
+            )
+        })
+}
+
+async fn store_clock(db: &sqlx::MySqlPool, store_uuid: Uuid) -> Result<StoreClock, (StatusCode, String)> {
+    Ok(db::get_store_clock(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .unwrap_or_default())
+}
+
+async fn authorize_store(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<Uuid, (StatusCode, String)> {
+    let store_uuid = parse_uuid(store_id, "store_id")?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    Ok(store_uuid)
+}
+
+fn scheduled_change(c: ScheduledMenuChange, clock: &StoreClock) -> ScheduledChangeResponse {
+    ScheduledChangeResponse {
+        id: c.id,
+        run_at: c.run_at.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        due_at: clock.format_local(clock.local_to_utc(c.run_at)),
+        version: c.version,
+        prices: c.prices.map(|p| p.0),
+        status: c.status,
+        error: c.error,
+        applied_at: c.applied_at.map(|at| clock.format_local_naive(at)),
+        applied_version: c.applied_version,
+        created_by_user_id: c.created_by_user_id,
+        created_at: clock.format_local_naive(c.created_at),
+    }
+}
+
+fn daypart(d: MenuDaypart, now: NaiveDateTime) -> DaypartResponse {
+    DaypartResponse {
+        open: d.window.is_open(now),
+        id: d.id,
+        name: d.name,
+        start_time: d.window.start.format("%H:%M").to_string(),
+        end_time: d.window.end.format("%H:%M").to_string(),
+        days: d.window.days.iter().map(|day| weekday_str(*day)).collect(),
+        local_category_ids: d.local_category_ids,
+        local_item_ids: d.local_item_ids,
+        updated_at: d.updated_at.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    }
+}
+
+fn parse_uuid(value: &str, field: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {}", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
@@ -0,0 +1,986 @@
+//! Portal head-office menu templates: an org- or franchise-level master menu edited in the portal,
+//! published to selected stores (apply_menu to every device in them), with per-store price and
+//! availability overrides and a preview of the menu each store gets. See db::menu_templates.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{delete, get, patch, post, put},
+    Json, Router,
+};
+use domain::VatRate;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{
+    MenuTemplate, MenuTemplateItemPatch, NewMenuTemplateItem, PublishedMenu, StoreMenuOverride,
+    SyncMenuCategory, SyncMenuItem,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct OrgPathParams {
+    pub org_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TemplatePathParams {
+    pub template_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateMenuTemplateBody {
+    pub name: String,
+    /// Makes a franchise template, publishable only to that franchise's stores.
+    pub franchise_id: Option<String>,
+    /// Start from this store's current menu.
+    pub copy_from_store_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchMenuTemplateBody {
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateCategoryBody {
+    pub name: String,
+    #[serde(default)]
+    pub position: i32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchTemplateCategoryBody {
+    pub name: Option<String>,
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateTemplateItemBody {
+    pub name: String,
+    pub description: Option<String>,
+    pub price_pence: Option<i64>,
+    /// Template category id.
+    pub category_id: Option<String>,
+    #[serde(default = "default_true")]
+    pub active: bool,
+    #[serde(default)]
+    pub customer_editable: bool,
+    /// "standard", "reduced" or "zero".
+    pub vat_rate: Option<String>,
+    pub takeaway_vat_rate: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchTemplateItemBody {
+    pub name: Option<String>,
+    pub description: Option<Option<String>>,
+    pub price_pence: Option<Option<i64>>,
+    /// Template category id.
+    pub category_id: Option<Option<String>>,
+    pub active: Option<bool>,
+    pub customer_editable: Option<bool>,
+    pub vat_rate: Option<Option<String>>,
+    pub takeaway_vat_rate: Option<Option<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PublishBody {
+    pub store_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PreviewQuery {
+    /// Layer this store's overrides on the preview.
+    pub store_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PutOverrideBody {
+    /// Store price; null = the template price.
+    pub price_pence: Option<i64>,
+    /// Store availability; null = the template's.
+    pub active: Option<bool>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuTemplateSummary {
+    pub id: String,
+    pub org_id: String,
+    pub franchise_id: Option<String>,
+    pub name: String,
+    pub category_count: i64,
+    pub item_count: i64,
+    pub store_count: i64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuTemplateListResponse {
+    pub templates: Vec<MenuTemplateSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateCategory {
+    pub id: String,
+    pub local_category_id: String,
+    pub name: String,
+    pub position: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateItem {
+    pub id: String,
+    pub local_item_id: String,
+    pub category_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub price_pence: Option<i64>,
+    pub vat_rate: Option<String>,
+    pub takeaway_vat_rate: Option<String>,
+    pub active: bool,
+    pub image_path: Option<String>,
+    pub customer_editable: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TemplateStore {
+    pub store_id: String,
+    pub store_name: String,
+    pub published_at: String,
+    pub published_by_user_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuTemplateDetail {
+    #[serde(flatten)]
+    pub template: MenuTemplateSummary,
+    pub categories: Vec<TemplateCategory>,
+    pub items: Vec<TemplateItem>,
+    /// Stores on this template, with when each was last published.
+    pub stores: Vec<TemplateStore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishedStore {
+    pub store_id: String,
+    /// Devices apply_menu was queued for.
+    pub device_count: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PublishResponse {
+    pub stores: Vec<PublishedStore>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuPreview {
+    pub categories: Vec<SyncMenuCategory>,
+    pub items: Vec<SyncMenuItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuOverride {
+    pub local_item_id: String,
+    pub price_pence: Option<i64>,
+    pub active: Option<bool>,
+    pub updated_by_user_id: Option<String>,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EffectiveMenuResponse {
+    pub store_id: String,
+    /// `template` (head-office menu plus overrides), `device` (the canonical device's menu) or
+    /// `none` (no menu yet).
+    pub source: &'static str,
+    pub template_id: Option<String>,
+    pub template_name: Option<String>,
+    pub published_at: Option<String>,
+    pub overrides: Vec<MenuOverride>,
+    pub categories: Vec<SyncMenuCategory>,
+    pub items: Vec<SyncMenuItem>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/portal/orgs/:org_id/menu-templates",
+            get(list_templates).post(create_template),
+        )
+        .route(
+            "/portal/menu-templates/:template_id",
+            get(get_template).patch(patch_template),
+        )
+        .route(
+            "/portal/menu-templates/:template_id/categories",
+            post(create_category),
+        )
+        .route(
+            "/portal/menu-templates/:template_id/categories/:category_id",
+            patch(patch_category).delete(delete_category),
+        )
+        .route("/portal/menu-templates/:template_id/items", post(create_item))
+        .route(
+            "/portal/menu-templates/:template_id/items/:item_id",
+            patch(patch_item).delete(delete_item),
+        )
+        .route(
+            "/portal/menu-templates/:template_id/publish",
+            post(publish_template),
+        )
+        .route(
+            "/portal/menu-templates/:template_id/stores/:store_id",
+            delete(remove_store),
+        )
+        .route(
+            "/portal/menu-templates/:template_id/preview",
+            get(preview_template),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/effective",
+            get(get_effective_menu),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/overrides",
+            get(list_overrides),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/overrides/:local_item_id",
+            put(put_override).delete(delete_override),
+        )
+}
+
+async fn list_templates(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(OrgPathParams { org_id }): Path<OrgPathParams>,
+) -> Result<Json<MenuTemplateListResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let org_uuid = parse_uuid(&org_id, "org_id")?;
+    authorize_org(db, &user, org_uuid).await?;
+    let templates = db::list_menu_templates(db, org_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(MenuTemplateListResponse {
+        templates: templates.into_iter().map(summary).collect(),
+    }))
+}
+
+async fn create_template(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(OrgPathParams { org_id }): Path<OrgPathParams>,
+    Json(body): Json<CreateMenuTemplateBody>,
+) -> Result<(StatusCode, Json<MenuTemplateDetail>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let org_uuid = parse_uuid(&org_id, "org_id")?;
+    authorize_org(db, &user, org_uuid).await?;
+    let name = non_empty_name(&body.name)?;
+    let franchise_id = body
+        .franchise_id
+        .as_deref()
+        .map(|id| parse_uuid(id, "franchise_id"))
+        .transpose()?;
+    if let Some(franchise_id) = franchise_id {
+        let franchise_org = db::get_franchise_org_id(db, franchise_id)
+            .await
+            .map_err(internal)?;
+        if franchise_org != Some(org_uuid) {
+            return Err((StatusCode::NOT_FOUND, "franchise not found in this org".to_string()));
+        }
+    }
+    let copy_from = body
+        .copy_from_store_id
+        .as_deref()
+        .map(|id| parse_uuid(id, "copy_from_store_id"))
+        .transpose()?;
+    if let Some(store_id) = copy_from {
+        check_store_in_scope(db, &user, store_id, org_uuid, franchise_id).await?;
+    }
+
+    let template_id = db::create_menu_template(db, org_uuid, franchise_id, name, &user.0)
+        .await
+        .map_err(internal)?;
+    if let Some(store_id) = copy_from {
+        db::copy_store_menu_into_template(db, template_id, store_id)
+            .await
+            .map_err(internal)?;
+    }
+    let template = db::get_menu_template(db, template_id)
+        .await
+        .map_err(internal)?
+        .ok_or_else(|| internal("template not found after insert"))?;
+    Ok((StatusCode::CREATED, Json(template_detail(db, template).await?)))
+}
+
+async fn get_template(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+) -> Result<Json<MenuTemplateDetail>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    Ok(Json(template_detail(db, template).await?))
+}
+
+async fn patch_template(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+    Json(body): Json<PatchMenuTemplateBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    if let Some(name) = body.name.as_deref() {
+        db::rename_menu_template(db, template_uuid(&template)?, non_empty_name(name)?)
+            .await
+            .map_err(internal)?;
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_category(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+    Json(body): Json<CreateTemplateCategoryBody>,
+) -> Result<(StatusCode, Json<TemplateCategory>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let name = non_empty_name(&body.name)?;
+    let (id, local_category_id) =
+        db::create_menu_template_category(db, template_uuid(&template)?, name, body.position)
+            .await
+            .map_err(internal)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(TemplateCategory {
+            id: id.to_string(),
+            local_category_id,
+            name: name.to_string(),
+            position: body.position,
+        }),
+    ))
+}
+
+async fn patch_category(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((template_id, category_id)): Path<(String, String)>,
+    Json(body): Json<PatchTemplateCategoryBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let category_uuid = parse_uuid(&category_id, "category_id")?;
+    let name = body.name.as_deref().map(non_empty_name).transpose()?;
+    let updated = db::update_menu_template_category(
+        db,
+        template_uuid(&template)?,
+        category_uuid,
+        name,
+        body.position,
+    )
+    .await
+    .map_err(internal)?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "category not found in this template".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_category(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((template_id, category_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let category_uuid = parse_uuid(&category_id, "category_id")?;
+    let deleted = db::delete_menu_template_category(db, template_uuid(&template)?, category_uuid)
+        .await
+        .map_err(internal)?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "category not found in this template".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn create_item(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+    Json(body): Json<CreateTemplateItemBody>,
+) -> Result<(StatusCode, Json<TemplateItem>), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let template_uuid = template_uuid(&template)?;
+    let name = non_empty_name(&body.name)?;
+    let local_category_id = match body.category_id.as_deref() {
+        Some(id) => Some(category_local_id(db, template_uuid, id).await?),
+        None => None,
+    };
+    let vat_rate = parse_vat_rate(body.vat_rate.as_deref(), "vat_rate")?;
+    let takeaway_vat_rate = parse_vat_rate(body.takeaway_vat_rate.as_deref(), "takeaway_vat_rate")?;
+
+    let (id, local_item_id) = db::create_menu_template_item(
+        db,
+        template_uuid,
+        &NewMenuTemplateItem {
+            local_category_id: local_category_id.as_deref(),
+            name,
+            description: body.description.as_deref(),
+            price_pence: body.price_pence,
+            vat_rate,
+            takeaway_vat_rate,
+            active: body.active,
+            customer_editable: body.customer_editable,
+        },
+    )
+    .await
+    .map_err(internal)?;
+    Ok((
+        StatusCode::CREATED,
+        Json(TemplateItem {
+            id: id.to_string(),
+            local_item_id,
+            category_id: body.category_id.clone(),
+            name: name.to_string(),
+            description: body.description.clone(),
+            price_pence: body.price_pence,
+            vat_rate: vat_rate.map(|r| r.as_str().to_string()),
+            takeaway_vat_rate: takeaway_vat_rate.map(|r| r.as_str().to_string()),
+            active: body.active,
+            image_path: None,
+            customer_editable: body.customer_editable,
+        }),
+    ))
+}
+
+async fn patch_item(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((template_id, item_id)): Path<(String, String)>,
+    Json(body): Json<PatchTemplateItemBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let template_uuid = template_uuid(&template)?;
+    let item_uuid = parse_uuid(&item_id, "item_id")?;
+    let name = body.name.as_deref().map(non_empty_name).transpose()?;
+    let local_category_id = match &body.category_id {
+        Some(Some(id)) => Some(Some(category_local_id(db, template_uuid, id).await?)),
+        Some(None) => Some(None),
+        None => None,
+    };
+    let vat_rate = body
+        .vat_rate
+        .as_ref()
+        .map(|r| parse_vat_rate(r.as_deref(), "vat_rate"))
+        .transpose()?;
+    let takeaway_vat_rate = body
+        .takeaway_vat_rate
+        .as_ref()
+        .map(|r| parse_vat_rate(r.as_deref(), "takeaway_vat_rate"))
+        .transpose()?;
+
+    let patch = MenuTemplateItemPatch {
+        local_category_id: local_category_id.as_ref().map(|c| c.as_deref()),
+        name,
+        description: body.description.as_ref().map(|d| d.as_deref()),
+        price_pence: body.price_pence,
+        vat_rate,
+        takeaway_vat_rate,
+        active: body.active,
+        customer_editable: body.customer_editable,
+    };
+    let updated = db::update_menu_template_item(db, template_uuid, item_uuid, &patch)
+        .await
+        .map_err(internal)?;
+    if !updated {
+        return Err((StatusCode::NOT_FOUND, "item not found in this template".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_item(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((template_id, item_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let item_uuid = parse_uuid(&item_id, "item_id")?;
+    let deleted = db::delete_menu_template_item(db, template_uuid(&template)?, item_uuid)
+        .await
+        .map_err(internal)?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "item not found in this template".to_string()));
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn publish_template(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+    Json(body): Json<PublishBody>,
+) -> Result<Json<PublishResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let template_uuid = template_uuid(&template)?;
+    let (org_id, franchise_id) = template_scope(&template)?;
+    if body.store_ids.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "store_ids must not be empty".to_string()));
+    }
+    let mut store_ids = Vec::with_capacity(body.store_ids.len());
+    for id in &body.store_ids {
+        let store_id = parse_uuid(id, "store_id")?;
+        check_store_in_scope(db, &user, store_id, org_id, franchise_id).await?;
+        if !store_ids.contains(&store_id) {
+            store_ids.push(store_id);
+        }
+    }
+
+    db::publish_menu_template(db, template_uuid, &store_ids, &user.0)
+        .await
+        .map_err(internal)?;
+    let mut stores = Vec::with_capacity(store_ids.len());
+    for store_id in store_ids {
+        let devices = db::enqueue_apply_menu_for_store(
+            db,
+            store_id,
+            &db::MenuPublish::new(db::MenuVersionSource::TemplatePublish, Some(&user.0)),
+        )
+            .await
+            .map_err(internal)?;
+        state.command_notifier.notify_all(&devices);
+        stores.push(PublishedStore {
+            store_id: store_id.to_string(),
+            device_count: devices.len(),
+        });
+    }
+    Ok(Json(PublishResponse { stores }))
+}
+
+async fn remove_store(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((template_id, store_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let store_uuid = parse_uuid(&store_id, "store_id")?;
+    let removed = db::remove_store_from_menu_template(db, template_uuid(&template)?, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !removed {
+        return Err((StatusCode::NOT_FOUND, "store is not on this template".to_string()));
+    }
+    // Back to the canonical device's menu.
+    let publish = db::MenuPublish::new(db::MenuVersionSource::TemplateRemoved, Some(&user.0));
+    if let Ok(devices) = db::enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn preview_template(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(TemplatePathParams { template_id }): Path<TemplatePathParams>,
+    Query(q): Query<PreviewQuery>,
+) -> Result<Json<MenuPreview>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let template = authorize_template(db, &user, &template_id).await?;
+    let mut menu = db::build_menu_template(db, template_uuid(&template)?)
+        .await
+        .map_err(internal)?;
+    if let Some(store_id) = q.store_id.as_deref() {
+        let store_uuid = parse_uuid(store_id, "store_id")?;
+        let (org_id, franchise_id) = template_scope(&template)?;
+        check_store_in_scope(db, &user, store_uuid, org_id, franchise_id).await?;
+        let overrides = db::list_store_menu_overrides(db, store_uuid)
+            .await
+            .map_err(internal)?;
+        let local_store_id = db::get_store_local_id(db, store_uuid)
+            .await
+            .map_err(internal)?;
+        db::apply_store_menu_overrides(&mut menu, &overrides, &local_store_id);
+    }
+    let PublishedMenu { categories, items } = menu;
+    Ok(Json(MenuPreview { categories, items }))
+}
+
+async fn get_effective_menu(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<EffectiveMenuResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let template = db::get_store_menu_template(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    let overrides = db::list_store_menu_overrides(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    let menu = db::get_store_menu_for_sync(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    let source = match (&template, &menu) {
+        (Some(_), _) => "template",
+        (None, Some(_)) => "device",
+        (None, None) => "none",
+    };
+    let (categories, mut items) = menu.unwrap_or_default();
+    db::apply_menu_dayparts(db, store_uuid, &mut items, chrono::Utc::now())
+        .await
+        .map_err(internal)?;
+    Ok(Json(EffectiveMenuResponse {
+        store_id: store_uuid.to_string(),
+        source,
+        template_id: template.as_ref().map(|t| t.template_id.clone()),
+        template_name: template.as_ref().map(|t| t.template_name.clone()),
+        published_at: template.as_ref().map(|t| format_time(t.published_at)),
+        overrides: overrides.into_iter().map(menu_override).collect(),
+        categories,
+        items,
+    }))
+}
+
+async fn list_overrides(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<Vec<MenuOverride>>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let overrides = db::list_store_menu_overrides(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(overrides.into_iter().map(menu_override).collect()))
+}
+
+async fn put_override(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, local_item_id)): Path<(String, String)>,
+    Json(body): Json<PutOverrideBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let template = db::get_store_menu_template(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((
+            StatusCode::CONFLICT,
+            "store is not on a head-office menu; edit its menu directly".to_string(),
+        ))?;
+    if !template.menu.items.iter().any(|i| i.local_item_id == local_item_id) {
+        return Err((StatusCode::NOT_FOUND, "item not on this store's menu".to_string()));
+    }
+    if body.price_pence.is_some_and(|p| p < 0) {
+        return Err((StatusCode::BAD_REQUEST, "price_pence must not be negative".to_string()));
+    }
+    db::set_store_menu_override(db, store_uuid, &local_item_id, body.price_pence, body.active, &user.0)
+        .await
+        .map_err(internal)?;
+    let publish = db::MenuPublish::new(db::MenuVersionSource::StoreOverride, Some(&user.0));
+    if let Ok(devices) = db::enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_override(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, local_item_id)): Path<(String, String)>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let deleted = db::delete_store_menu_override(db, store_uuid, &local_item_id)
+        .await
+        .map_err(internal)?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "no override for this item".to_string()));
+    }
+    let publish = db::MenuPublish::new(db::MenuVersionSource::StoreOverride, Some(&user.0));
+    if let Ok(devices) = db::enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn authorize_org(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    org_id: Uuid,
+) -> Result<(), (StatusCode, String)> {
+    let allowed = db::user_can_access_org(db, &user.0, org_id)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "not in your account".to_string()));
+    }
+    Ok(())
+}
+
+async fn authorize_store(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<Uuid, (StatusCode, String)> {
+    let store_uuid = parse_uuid(store_id, "store_id")?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    Ok(store_uuid)
+}
+
+/// A template in an org the user can access.
+async fn authorize_template(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    template_id: &str,
+) -> Result<MenuTemplate, (StatusCode, String)> {
+    let template_uuid = parse_uuid(template_id, "template_id")?;
+    let template = db::get_menu_template(db, template_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "menu template not found".to_string()))?;
+    let org_id = parse_uuid(&template.org_id, "org_id")?;
+    authorize_org(db, user, org_id).await?;
+    Ok(template)
+}
+
+/// A store the user can access, in the template's org (and franchise, for a franchise template).
+async fn check_store_in_scope(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: Uuid,
+    org_id: Uuid,
+    franchise_id: Option<Uuid>,
+) -> Result<(), (StatusCode, String)> {
+    let (store_org, store_franchise) = db::get_store_org_and_franchise(db, store_id)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, format!("store {} not found", store_id)))?;
+    if store_org != org_id || franchise_id.is_some_and(|f| store_franchise != Some(f)) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("store {} is not covered by this menu template", store_id),
+        ));
+    }
+    let allowed = db::user_can_access_store(db, &user.0, store_id)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    Ok(())
+}
+
+async fn template_detail(
+    db: &sqlx::MySqlPool,
+    template: MenuTemplate,
+) -> Result<MenuTemplateDetail, (StatusCode, String)> {
+    let template_uuid = template_uuid(&template)?;
+    let categories = db::list_menu_template_categories(db, template_uuid)
+        .await
+        .map_err(internal)?;
+    let items = db::list_menu_template_items(db, template_uuid)
+        .await
+        .map_err(internal)?;
+    let stores = db::list_menu_template_stores(db, template_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(MenuTemplateDetail {
+        template: summary(template),
+        categories: categories
+            .into_iter()
+            .map(|c| TemplateCategory {
+                id: c.id,
+                local_category_id: c.local_category_id,
+                name: c.name,
+                position: c.position,
+            })
+            .collect(),
+        items: items
+            .into_iter()
+            .map(|i| TemplateItem {
+                id: i.id,
+                local_item_id: i.local_item_id,
+                category_id: i.category_id,
+                name: i.name,
+                description: i.description,
+                price_pence: i.price_pence,
+                vat_rate: i.vat_rate,
+                takeaway_vat_rate: i.takeaway_vat_rate,
+                active: i.active,
+                image_path: i.image_path,
+                customer_editable: i.customer_editable,
+            })
+            .collect(),
+        stores: stores
+            .into_iter()
+            .map(|s| TemplateStore {
+                store_id: s.store_id,
+                store_name: s.store_name,
+                published_at: format_time(s.published_at),
+                published_by_user_id: s.published_by_user_id,
+            })
+            .collect(),
+    })
+}
+
+/// Local id of a template category given its row id.
+async fn category_local_id(
+    db: &sqlx::MySqlPool,
+    template_id: Uuid,
+    category_id: &str,
+) -> Result<String, (StatusCode, String)> {
+    let category_uuid = parse_uuid(category_id, "category_id")?;
+    db::get_menu_template_category_local_id(db, template_id, category_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "category not found in this template".to_string()))
+}
+
+fn summary(t: MenuTemplate) -> MenuTemplateSummary {
+    MenuTemplateSummary {
+        id: t.id,
+        org_id: t.org_id,
+        franchise_id: t.franchise_id,
+        name: t.name,
+        category_count: t.category_count,
+        item_count: t.item_count,
+        store_count: t.store_count,
+        created_at: format_time(t.created_at),
+        updated_at: format_time(t.updated_at),
+    }
+}
+
+fn menu_override(o: StoreMenuOverride) -> MenuOverride {
+    MenuOverride {
+        local_item_id: o.local_item_id,
+        price_pence: o.price_pence,
+        active: o.active,
+        updated_by_user_id: o.updated_by_user_id,
+        updated_at: format_time(o.updated_at),
+    }
+}
+
+fn template_uuid(template: &MenuTemplate) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(&template.id).map_err(internal)
+}
+
+/// Org and franchise the template may be published in.
+fn template_scope(template: &MenuTemplate) -> Result<(Uuid, Option<Uuid>), (StatusCode, String)> {
+    let org_id = Uuid::parse_str(&template.org_id).map_err(internal)?;
+    let franchise_id = template
+        .franchise_id
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(internal)?;
+    Ok((org_id, franchise_id))
+}
+
+fn non_empty_name(name: &str) -> Result<&str, (StatusCode, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "name must not be empty".to_string()));
+    }
+    Ok(name)
+}
+
+/// A VAT rate band from a request body; None (or null) clears it.
+fn parse_vat_rate(value: Option<&str>, field: &str) -> Result<Option<VatRate>, (StatusCode, String)> {
+    value
+        .map(|v| {
+            VatRate::parse(v).ok_or((
+                StatusCode::BAD_REQUEST,
+                format!("{} must be standard, reduced or zero", field),
+            ))
+        })
+        .transpose()
+}
+
+fn format_time(at: chrono::NaiveDateTime) -> String {
+    at.format("%Y-%m-%dT%H:%M:%S").to_string()
+}
+
+fn parse_uuid(value: &str, field: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {}", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
@@ -0,0 +1,229 @@
+//! Portal menu versions: every menu published to a store's devices is kept as a numbered version.
+//! List and view versions, diff two of them, and roll back to an earlier one (restored and
+//! published again as a new version). See db::menu_versions.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{
+    MenuDiff, MenuPublish, MenuRestoreError, MenuVersion, MenuVersionSource, SyncMenuCategory,
+    SyncMenuItem,
+};
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DiffQuery {
+    pub from: i32,
+    pub to: i32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuVersionSummary {
+    pub version: i32,
+    /// portal_edit, template_publish, template_removed, store_override, rollback, command,
+    /// scheduled or daypart.
+    pub source: String,
+    /// For a rollback, the version restored.
+    pub restored_version: Option<i32>,
+    pub template_id: Option<String>,
+    pub published_by_user_id: Option<String>,
+    pub published_by: Option<String>,
+    pub published_at: String,
+    pub category_count: i64,
+    pub item_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuVersionResponse {
+    #[serde(flatten)]
+    pub version: MenuVersionSummary,
+    pub categories: Vec<SyncMenuCategory>,
+    pub items: Vec<SyncMenuItem>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MenuDiffResponse {
+    pub from: i32,
+    pub to: i32,
+    #[serde(flatten)]
+    pub diff: MenuDiff,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RollbackResponse {
+    pub restored_version: i32,
+    /// The new version the restored menu was published as.
+    pub version: Option<i32>,
+    /// Devices apply_menu was queued for.
+    pub device_count: usize,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/portal/stores/:store_id/menu/versions",
+            get(list_versions),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/versions/:version",
+            get(get_version),
+        )
+        .route(
+            "/portal/stores/:store_id/menu/versions/:version/rollback",
+            post(rollback_version),
+        )
+        .route("/portal/stores/:store_id/menu/diff", get(diff_versions))
+}
+
+async fn list_versions(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+) -> Result<Json<Vec<MenuVersionSummary>>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let versions = db::list_menu_versions(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(versions.into_iter().map(summary).collect()))
+}
+
+async fn get_version(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, version)): Path<(String, i32)>,
+) -> Result<Json<MenuVersionResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let detail = db::get_menu_version(db, store_uuid, version)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "menu version not found".to_string()))?;
+    Ok(Json(MenuVersionResponse {
+        version: summary(detail.version),
+        categories: detail.menu.categories,
+        items: detail.menu.items,
+    }))
+}
+
+async fn diff_versions(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Query(q): Query<DiffQuery>,
+) -> Result<Json<MenuDiffResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    let mut menus = Vec::with_capacity(2);
+    for version in [q.from, q.to] {
+        let detail = db::get_menu_version(db, store_uuid, version)
+            .await
+            .map_err(internal)?
+            .ok_or((
+                StatusCode::NOT_FOUND,
+                format!("menu version {} not found", version),
+            ))?;
+        menus.push(detail.menu);
+    }
+    Ok(Json(MenuDiffResponse {
+        from: q.from,
+        to: q.to,
+        diff: db::diff_menus(&menus[0], &menus[1]),
+    }))
+}
+
+async fn rollback_version(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, version)): Path<(String, i32)>,
+) -> Result<Json<RollbackResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = authorize_store(db, &user, &store_id).await?;
+    db::restore_menu_version(db, store_uuid, version, &user.0)
+        .await
+        .map_err(|e| match e {
+            MenuRestoreError::NotFound => (StatusCode::NOT_FOUND, e.to_string()),
+            MenuRestoreError::TemplateGone | MenuRestoreError::NoDevice => {
+                (StatusCode::CONFLICT, e.to_string())
+            }
+            MenuRestoreError::Db(e) => internal(e),
+        })?;
+    let publish = MenuPublish {
+        source: MenuVersionSource::Rollback,
+        published_by_user_id: Some(&user.0),
+        restored_version: Some(version),
+    };
+    let devices = db::enqueue_apply_menu_for_store(db, store_uuid, &publish)
+        .await
+        .map_err(internal)?;
+    state.command_notifier.notify_all(&devices);
+    let latest = db::latest_menu_version(db, store_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(RollbackResponse {
+        restored_version: version,
+        version: latest,
+        device_count: devices.len(),
+    }))
+}
+
+async fn authorize_store(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<Uuid, (StatusCode, String)> {
+    let store_uuid = parse_uuid(store_id, "store_id")?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    Ok(store_uuid)
+}
+
+fn summary(v: MenuVersion) -> MenuVersionSummary {
+    MenuVersionSummary {
+        version: v.version,
+        source: v.source,
+        restored_version: v.restored_version,
+        template_id: v.template_id,
+        published_by_user_id: v.published_by_user_id,
+        published_by: v.published_by,
+        published_at: v.published_at.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        category_count: v.category_count,
+        item_count: v.item_count,
+    }
+}
+
+fn parse_uuid(value: &str, field: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {}", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
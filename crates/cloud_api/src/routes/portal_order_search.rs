@@ -0,0 +1,285 @@
+//! Portal order search across every store the user can access, or one org, franchise or store,
+//! with filters, sorting and cursor pagination.
+
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::NaiveDate;
+use domain::StoreClock;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{OrderSearch, OrderSearchCursor, OrderSearchWindow, OrderSort, ReportScope};
+
+const DEFAULT_LIMIT: u32 = 50;
+const MAX_LIMIT: u32 = 200;
+
+#[derive(Debug, Deserialize)]
+pub struct OrderSearchQuery {
+    /// At most one of org_id, franchise_id, store_id; every accessible store when none is given.
+    pub org_id: Option<String>,
+    pub franchise_id: Option<String>,
+    pub store_id: Option<String>,
+    /// First and last business day, inclusive (YYYY-MM-DD), each in the store's own timezone.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub min_total_cents: Option<i64>,
+    pub max_total_cents: Option<i64>,
+    /// Comma-separated statuses, e.g. "closed,partially_refunded".
+    pub status: Option<String>,
+    /// Transaction kind, e.g. "card" or "cash".
+    pub payment_kind: Option<String>,
+    pub device_id: Option<String>,
+    /// Delivery platform, e.g. "uber_eats".
+    pub delivery_provider: Option<String>,
+    /// Part of a product name.
+    pub product: Option<String>,
+    /// Cloud or global order id, any device's POS order id, receipt id or delivery platform
+    /// order id (exact).
+    pub q: Option<String>,
+    /// newest (default), oldest, total_desc or total_asc.
+    pub sort: Option<String>,
+    /// next_cursor from the previous page.
+    pub cursor: Option<String>,
+    /// Page size, default 50, at most 200.
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderSearchResult {
+    pub id: String,
+    pub store_id: String,
+    pub store_name: String,
+    pub device_id: String,
+    pub device_label: Option<String>,
+    pub local_order_id: String,
+    pub global_order_id: String,
+    pub status: String,
+    pub service_type: Option<String>,
+    pub delivery_provider: Option<String>,
+    pub external_order_id: Option<String>,
+    pub total_cents: i64,
+    pub refunded_cents: i64,
+    pub item_count: f64,
+    /// Store-local time with offset.
+    pub occurred_at: String,
+    /// Business day in the store's timezone (YYYY-MM-DD).
+    pub trading_day: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderSearchResponse {
+    pub orders: Vec<OrderSearchResult>,
+    /// Pass as `cursor` for the next page; null on the last page.
+    pub next_cursor: Option<String>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new().route("/portal/orders/search", get(search_orders))
+}
+
+async fn search_orders(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<OrderSearchQuery>,
+) -> Result<Json<OrderSearchResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+
+    let from = q.from.as_deref().map(|d| parse_day(d, "from")).transpose()?;
+    let to = q.to.as_deref().map(|d| parse_day(d, "to")).transpose()?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err((StatusCode::BAD_REQUEST, "from must not be after to".to_string()));
+        }
+    }
+    if let (Some(min), Some(max)) = (q.min_total_cents, q.max_total_cents) {
+        if min > max {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "min_total_cents must not be above max_total_cents".to_string(),
+            ));
+        }
+    }
+    let sort = match q.sort.as_deref() {
+        None => OrderSort::default(),
+        Some(s) => OrderSort::parse(s).ok_or((
+            StatusCode::BAD_REQUEST,
+            "sort must be newest, oldest, total_desc or total_asc".to_string(),
+        ))?,
+    };
+    let after = q
+        .cursor
+        .as_deref()
+        .map(|c| {
+            OrderSearchCursor::decode(c)
+                .ok_or((StatusCode::BAD_REQUEST, "invalid cursor".to_string()))
+        })
+        .transpose()?;
+    let limit = q.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+    let device_id = q
+        .device_id
+        .as_deref()
+        .map(|id| parse_uuid(id, "device_id"))
+        .transpose()?;
+
+    let clocks = resolve_stores(db, &user, &q).await?;
+    let mut stores_by_clock: HashMap<StoreClock, Vec<String>> = HashMap::new();
+    for (store_id, clock) in &clocks {
+        stores_by_clock.entry(*clock).or_default().push(store_id.clone());
+    }
+    let windows = stores_by_clock
+        .into_iter()
+        .map(|(clock, store_ids)| OrderSearchWindow {
+            store_ids,
+            start: from.map(|d| clock.day_bounds(d).0.naive_utc()),
+            end: to.map(|d| clock.day_bounds(d).1.naive_utc()),
+        })
+        .collect();
+
+    let search = OrderSearch {
+        windows,
+        min_total_cents: q.min_total_cents,
+        max_total_cents: q.max_total_cents,
+        statuses: q
+            .status
+            .as_deref()
+            .map(|s| {
+                s.split(',')
+                    .map(str::trim)
+                    .filter(|s| !s.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .unwrap_or_default(),
+        payment_kind: non_empty(q.payment_kind.as_deref()),
+        device_id,
+        delivery_provider: non_empty(q.delivery_provider.as_deref()),
+        product: non_empty(q.product.as_deref()),
+        reference: non_empty(q.q.as_deref()),
+        sort,
+        after,
+        // One extra row tells us whether there is another page.
+        limit: limit + 1,
+    };
+    let mut rows = db::search_orders(db, &search).await.map_err(internal)?;
+    let next_cursor = if rows.len() > limit as usize {
+        rows.truncate(limit as usize);
+        rows.last().map(|row| row.cursor().encode())
+    } else {
+        None
+    };
+
+    let orders = rows
+        .into_iter()
+        .map(|row| {
+            let clock = clocks.get(&row.store_id).copied().unwrap_or_default();
+            let occurred_at = row.occurred_at.and_utc();
+            OrderSearchResult {
+                occurred_at: clock.format_local(occurred_at),
+                trading_day: clock.trading_day(occurred_at).to_string(),
+                id: row.id,
+                store_id: row.store_id,
+                store_name: row.store_name,
+                device_id: row.device_id,
+                device_label: row.device_label,
+                local_order_id: row.local_order_id,
+                global_order_id: row.global_order_id,
+                status: row.status,
+                service_type: row.service_type,
+                delivery_provider: row.delivery_provider,
+                external_order_id: row.external_order_id,
+                total_cents: row.total_cents,
+                refunded_cents: row.refunded_cents,
+                item_count: row.item_count,
+            }
+        })
+        .collect();
+
+    Ok(Json(OrderSearchResponse { orders, next_cursor }))
+}
+
+/// Stores to search, with their clocks, after access checks.
+async fn resolve_stores(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    q: &OrderSearchQuery,
+) -> Result<HashMap<String, StoreClock>, (StatusCode, String)> {
+    let scope = match (q.org_id.as_deref(), q.franchise_id.as_deref(), q.store_id.as_deref()) {
+        (None, None, None) => None,
+        (Some(id), None, None) => Some(ReportScope::Org(parse_uuid(id, "org_id")?)),
+        (None, Some(id), None) => Some(ReportScope::Franchise(parse_uuid(id, "franchise_id")?)),
+        (None, None, Some(id)) => Some(ReportScope::Store(parse_uuid(id, "store_id")?)),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "at most one of org_id, franchise_id or store_id".to_string(),
+            ))
+        }
+    };
+    let Some(scope) = scope else {
+        let store_ids = db::list_accessible_store_ids(db, &user.0)
+            .await
+            .map_err(internal)?;
+        let mut clocks = db::list_store_clocks(db).await.map_err(internal)?;
+        return Ok(store_ids
+            .into_iter()
+            .map(|id| {
+                let clock = clocks.remove(&id).unwrap_or_default();
+                (id, clock)
+            })
+            .collect());
+    };
+
+    let allowed = match scope {
+        ReportScope::Org(org_id) => db::user_can_access_org(db, &user.0, org_id)
+            .await
+            .map_err(internal)?,
+        ReportScope::Franchise(franchise_id) => {
+            let org_id = db::get_franchise_org_id(db, franchise_id)
+                .await
+                .map_err(internal)?
+                .ok_or((StatusCode::NOT_FOUND, "franchise not found".to_string()))?;
+            db::user_can_access_org(db, &user.0, org_id)
+                .await
+                .map_err(internal)?
+        }
+        ReportScope::Store(store_id) => db::user_can_access_store(db, &user.0, store_id)
+            .await
+            .map_err(internal)?,
+    };
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "not in your account".to_string()));
+    }
+    let stores = db::list_report_stores(db, scope).await.map_err(internal)?;
+    if matches!(scope, ReportScope::Store(_)) && stores.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "store not found".to_string()));
+    }
+    Ok(stores.into_iter().collect())
+}
+
+fn non_empty(value: Option<&str>) -> Option<String> {
+    value.map(str::trim).filter(|v| !v.is_empty()).map(str::to_string)
+}
+
+fn parse_day(value: &str, field: &str) -> Result<NaiveDate, (StatusCode, String)> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("{} must be YYYY-MM-DD", field)))
+}
+
+fn parse_uuid(value: &str, field: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {}", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
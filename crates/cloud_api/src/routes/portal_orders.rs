@@ -1,15 +1,19 @@
+use std::collections::HashMap;
+
 use axum::{
     extract::{Path, State},
     http::StatusCode,
     routing::{get, post},
     Json, Router,
 };
+use domain::StoreClock;
 use serde::{Deserialize, Serialize};
 use sqlx::Row;
 use uuid::Uuid;
 
 use crate::session::CurrentUser;
 use crate::state::AppState;
+use db::{CommandApproval, CommandEntity, CommandStatusChange};
 
 #[derive(Debug, Deserialize)]
 pub struct OrderPathParams {
@@ -23,6 +27,37 @@ pub struct OrderItemRow {
     pub quantity: f64,
     pub unit_price_cents: Option<i64>,
     pub line_total_cents: Option<i64>,
+    pub modifiers: Vec<OrderItemModifierRow>,
+    pub discounts: Vec<OrderDiscountRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderItemModifierRow {
+    pub name: String,
+    pub quantity: f64,
+    pub price_delta_cents: i64,
+    /// The modifier's current menu price change; null when it is not on the store's menu.
+    pub menu_price_delta_cents: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderDiscountRow {
+    pub local_discount_id: Option<String>,
+    pub name: Option<String>,
+    pub code: Option<String>,
+    pub amount_cents: i64,
+    pub percent: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderTaxRow {
+    pub name: Option<String>,
+    pub rate_percent: f64,
+    pub net_cents: Option<i64>,
+    pub tax_cents: i64,
+    pub included: bool,
+    /// `pos` (sent by the POS) or `computed` (from the menu items' VAT rates).
+    pub source: String,
 }
 
 #[derive(Debug, Serialize)]
@@ -33,6 +68,33 @@ pub struct TransactionRow {
     pub occurred_at: String,
 }
 
+/// A refund against the order, with who made it and why.
+#[derive(Debug, Serialize)]
+pub struct RefundRow {
+    pub id: String,
+    pub local_refund_id: String,
+    pub kind: String,
+    /// Positive amount refunded.
+    pub amount_cents: i64,
+    pub reason: Option<String>,
+    pub staff_id: Option<String>,
+    pub staff_name: Option<String>,
+    /// The payment refunded, when the refund was against one payment.
+    pub refunded_transaction_id: Option<String>,
+    pub occurred_at: String,
+}
+
+/// A device that has sent events for the order, under its own local order id.
+#[derive(Debug, Serialize)]
+pub struct OrderDeviceRow {
+    pub device_id: String,
+    pub device_label: Option<String>,
+    pub local_order_id: String,
+    /// The order's owning device (void and refund commands go to it).
+    pub is_owner: bool,
+    pub first_seen_at: String,
+}
+
 #[derive(Debug, Serialize)]
 pub struct ReceiptRow {
     pub id: String,
@@ -40,29 +102,82 @@ pub struct ReceiptRow {
     pub occurred_at: String,
 }
 
+/// A command issued for the order, with its approval trail, status timeline and device outcome.
+#[derive(Debug, Serialize)]
+pub struct OrderCommandRow {
+    pub command_id: String,
+    pub command_type: String,
+    pub status: String,
+    pub requested_by: Option<String>,
+    pub created_at: String,
+    pub required_approvals: u32,
+    pub approved_at: Option<String>,
+    pub rejected_at: Option<String>,
+    pub delivered_at: Option<String>,
+    pub acked_at: Option<String>,
+    pub failed_at: Option<String>,
+    pub expired_reason: Option<String>,
+    /// Result the device sent with its ack or failure.
+    pub ack_result: Option<serde_json::Value>,
+    pub approvals: Vec<CommandApproval>,
+    /// Every status change, oldest first.
+    pub timeline: Vec<CommandStatusChange>,
+    /// The current user may approve or reject this command now.
+    pub can_decide: bool,
+}
+
 #[derive(Debug, Serialize)]
 pub struct OrderDetailResponse {
     pub id: String,
     pub org_id: String,
     pub store_id: String,
+    /// Owning device: the one whose order_created came first.
     pub device_id: String,
     pub local_order_id: String,
+    /// Store-scoped identity shared by every device that touches the order.
+    pub global_order_id: String,
     pub status: String,
+    /// eat_in, takeaway or delivery, when the POS sent it.
+    pub service_type: Option<String>,
     pub total_cents: Option<i64>,
+    /// Sum of refunds; the order is `partially_refunded` until it reaches the total.
+    pub refunded_cents: i64,
+    pub tip_cents: Option<i64>,
+    pub service_charge_cents: Option<i64>,
+    /// Store-local time with offset.
     pub occurred_at: String,
+    pub voided_at: Option<String>,
+    pub void_reason: Option<String>,
+    pub voided_by_staff_id: Option<String>,
+    pub voided_by_staff_name: Option<String>,
+    /// Business day (YYYY-MM-DD) in the store's timezone, after the trading-day cutoff.
+    pub trading_day: String,
     pub items: Vec<OrderItemRow>,
+    /// Order-level discounts (line discounts are on their items).
+    pub discounts: Vec<OrderDiscountRow>,
+    pub taxes: Vec<OrderTaxRow>,
+    /// Payments and refunds (negative amounts).
     pub transactions: Vec<TransactionRow>,
+    pub refunds: Vec<RefundRow>,
     pub receipts: Vec<ReceiptRow>,
+    /// Every device that has sent events for the order.
+    pub devices: Vec<OrderDeviceRow>,
+    pub commands: Vec<OrderCommandRow>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct EnqueueCommandRequest {
     pub command_type: String, // "void_order" | "refund_order"
+    /// Optional: seconds until the command expires if the device has not acked it. Defaults to
+    /// the command type's TTL.
+    pub expires_in_seconds: Option<u32>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct EnqueueCommandResponse {
     pub command_id: String,
+    /// `pending_approval`: a second user must approve before the device receives it.
+    pub status: String,
 }
 
 pub fn router(_state: AppState) -> Router<AppState> {
@@ -88,7 +203,8 @@ async fn get_order_detail(
 
     let order_row = sqlx::query(
         r#"
-        SELECT id, org_id, store_id, device_id, local_order_id, status, total_cents, occurred_at
+        SELECT id, org_id, store_id, device_id, local_order_id, global_order_id, status, service_type, total_cents, refunded_cents,
+               tip_cents, service_charge_cents, occurred_at, voided_at, void_reason, voided_by_staff_id, voided_by_staff_name
         FROM orders
         WHERE id = ?
         "#,
@@ -112,10 +228,15 @@ async fn get_order_detail(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    let clock = db::get_store_clock(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .unwrap_or_default();
 
     let item_rows = sqlx::query(
         r#"
         SELECT
+          id,
           local_item_id,
           product_ref,
           CAST(quantity AS DOUBLE) AS quantity,
@@ -131,14 +252,107 @@ async fn get_order_detail(
     .await
     .map_err(internal)?;
 
+    // Modifiers matched to the store's menu device by the line's product_ref and name, whichever
+    // till took the order (only the canonical device's menu is projected).
+    let modifier_rows = sqlx::query(
+        r#"
+        SELECT m.order_item_id, m.name, CAST(m.quantity AS DOUBLE) AS quantity, m.price_delta_cents,
+               (SELECT CAST(pm.price_delta_pence AS SIGNED)
+                FROM store_menu_devices md
+                JOIN pos_menu_item_modifiers pm ON pm.device_id = md.device_id
+                WHERE md.store_id = o.store_id AND pm.local_menu_item_id = oi.product_ref AND pm.name = m.name
+                LIMIT 1) AS menu_price_delta_cents
+        FROM order_item_modifiers m
+        JOIN order_items oi ON oi.id = m.order_item_id
+        JOIN orders o ON o.id = m.order_id
+        WHERE m.order_id = ?
+        ORDER BY m.position
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+
+    let discount_rows = sqlx::query(
+        r#"
+        SELECT order_item_id, local_discount_id, name, code, amount_cents, CAST(percent AS DOUBLE) AS percent
+        FROM order_discounts
+        WHERE order_id = ?
+        ORDER BY position
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+
+    let mut modifiers_by_item: HashMap<String, Vec<OrderItemModifierRow>> = HashMap::new();
+    for row in modifier_rows {
+        modifiers_by_item
+            .entry(row.get::<String, _>("order_item_id"))
+            .or_default()
+            .push(OrderItemModifierRow {
+                name: row.get::<String, _>("name"),
+                quantity: row.get::<f64, _>("quantity"),
+                price_delta_cents: row.get::<i64, _>("price_delta_cents"),
+                menu_price_delta_cents: row.get::<Option<i64>, _>("menu_price_delta_cents"),
+            });
+    }
+    let mut discounts = Vec::new();
+    let mut discounts_by_item: HashMap<String, Vec<OrderDiscountRow>> = HashMap::new();
+    for row in discount_rows {
+        let discount = OrderDiscountRow {
+            local_discount_id: row.get::<Option<String>, _>("local_discount_id"),
+            name: row.get::<Option<String>, _>("name"),
+            code: row.get::<Option<String>, _>("code"),
+            amount_cents: row.get::<i64, _>("amount_cents"),
+            percent: row.get::<Option<f64>, _>("percent"),
+        };
+        match row.get::<Option<String>, _>("order_item_id") {
+            Some(item_id) => discounts_by_item.entry(item_id).or_default().push(discount),
+            None => discounts.push(discount),
+        }
+    }
+
     let items = item_rows
         .into_iter()
-        .map(|row| OrderItemRow {
-            local_item_id: row.get::<Option<String>, _>("local_item_id"),
-            product_ref: row.get::<Option<String>, _>("product_ref"),
-            quantity: row.get::<f64, _>("quantity"),
-            unit_price_cents: row.get::<Option<i64>, _>("unit_price_cents"),
-            line_total_cents: row.get::<Option<i64>, _>("line_total_cents"),
+        .map(|row| {
+            let id = row.get::<String, _>("id");
+            OrderItemRow {
+                local_item_id: row.get::<Option<String>, _>("local_item_id"),
+                product_ref: row.get::<Option<String>, _>("product_ref"),
+                quantity: row.get::<f64, _>("quantity"),
+                unit_price_cents: row.get::<Option<i64>, _>("unit_price_cents"),
+                line_total_cents: row.get::<Option<i64>, _>("line_total_cents"),
+                modifiers: modifiers_by_item.remove(&id).unwrap_or_default(),
+                discounts: discounts_by_item.remove(&id).unwrap_or_default(),
+            }
+        })
+        .collect();
+
+    let tax_rows = sqlx::query(
+        r#"
+        SELECT name, CAST(rate_percent AS DOUBLE) AS rate_percent, net_cents, tax_cents, included, source
+        FROM order_taxes
+        WHERE order_id = ?
+        ORDER BY position
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+
+    let taxes = tax_rows
+        .into_iter()
+        .map(|row| OrderTaxRow {
+            name: row.get::<Option<String>, _>("name"),
+            rate_percent: row.get::<f64, _>("rate_percent"),
+            net_cents: row.get::<Option<i64>, _>("net_cents"),
+            tax_cents: row.get::<i64, _>("tax_cents"),
+            included: row.get::<bool, _>("included"),
+            source: row.get::<String, _>("source"),
         })
         .collect();
 
@@ -161,27 +375,55 @@ async fn get_order_detail(
             id: row.get::<String, _>("id"),
             kind: row.get::<String, _>("kind"),
             amount_cents: row.get::<i64, _>("amount_cents"),
-            occurred_at: row
-                .get::<chrono::NaiveDateTime, _>("occurred_at")
-                .format("%Y-%m-%dT%H:%M:%S")
-                .to_string(),
+            occurred_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("occurred_at")),
         })
         .collect();
 
-    let device_id: String = order_row.get("device_id");
-    let local_order_id: String = order_row.get("local_order_id");
-    let rc_rows = sqlx::query(
+    let refund_rows = sqlx::query(
         r#"
-        SELECT id, local_receipt_id, occurred_at
-        FROM receipts
-        WHERE order_id = ? OR (store_id = ? AND device_id = ? AND local_order_id = ?)
+        SELECT id, local_refund_id, kind, amount_cents, reason, staff_id, staff_name,
+               refunded_transaction_id, occurred_at
+        FROM order_refunds
+        WHERE order_id = ?
         ORDER BY occurred_at
         "#,
     )
     .bind(order_uuid.to_string())
-    .bind(&store_id)
-    .bind(&device_id)
-    .bind(&local_order_id)
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+
+    let refunds = refund_rows
+        .into_iter()
+        .map(|row| RefundRow {
+            id: row.get::<String, _>("id"),
+            local_refund_id: row.get::<String, _>("local_refund_id"),
+            kind: row.get::<String, _>("kind"),
+            amount_cents: row.get::<i64, _>("amount_cents"),
+            reason: row.get::<Option<String>, _>("reason"),
+            staff_id: row.get::<Option<String>, _>("staff_id"),
+            staff_name: row.get::<Option<String>, _>("staff_name"),
+            refunded_transaction_id: row.get::<Option<String>, _>("refunded_transaction_id"),
+            occurred_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("occurred_at")),
+        })
+        .collect();
+
+    // Receipts by order, or by any linked device's local order id (not linked yet).
+    let rc_rows = sqlx::query(
+        r#"
+        SELECT rc.id, rc.local_receipt_id, rc.occurred_at
+        FROM receipts rc
+        WHERE rc.order_id = ?
+           OR EXISTS (
+             SELECT 1 FROM order_device_refs dr
+             WHERE dr.order_id = ? AND dr.store_id = rc.store_id AND dr.device_id = rc.device_id
+               AND dr.local_order_id = rc.local_order_id
+           )
+        ORDER BY rc.occurred_at
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .bind(order_uuid.to_string())
     .fetch_all(db)
     .await
     .map_err(internal)?;
@@ -191,31 +433,159 @@ async fn get_order_detail(
         .map(|row| ReceiptRow {
             id: row.get::<String, _>("id"),
             local_receipt_id: row.get::<String, _>("local_receipt_id"),
-            occurred_at: row
-                .get::<chrono::NaiveDateTime, _>("occurred_at")
-                .format("%Y-%m-%dT%H:%M:%S")
-                .to_string(),
+            occurred_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("occurred_at")),
+        })
+        .collect();
+
+    let owner_device_id: String = order_row.get("device_id");
+    let owner_local_order_id: String = order_row.get("local_order_id");
+    let device_rows = sqlx::query(
+        r#"
+        SELECT dr.device_id, d.device_label, dr.local_order_id, dr.first_seen_at
+        FROM order_device_refs dr
+        LEFT JOIN devices d ON d.id = dr.device_id
+        WHERE dr.order_id = ?
+        ORDER BY dr.first_seen_at, dr.device_id
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+    let devices = device_rows
+        .into_iter()
+        .map(|row| {
+            let device_id: String = row.get("device_id");
+            let local_order_id: String = row.get("local_order_id");
+            OrderDeviceRow {
+                is_owner: device_id == owner_device_id && local_order_id == owner_local_order_id,
+                device_id,
+                device_label: row.get::<Option<String>, _>("device_label"),
+                local_order_id,
+                first_seen_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("first_seen_at")),
+            }
         })
         .collect();
 
+    let commands = load_order_commands(db, &user, &order_row, order_uuid, &clock).await?;
+    let occurred_at = order_row.get::<chrono::NaiveDateTime, _>("occurred_at").and_utc();
+
     Ok(Json(OrderDetailResponse {
         id: order_row.get::<String, _>("id"),
         org_id: order_row.get::<String, _>("org_id"),
         store_id: order_row.get::<String, _>("store_id"),
         device_id: order_row.get::<String, _>("device_id"),
         local_order_id: order_row.get::<String, _>("local_order_id"),
+        global_order_id: order_row.get::<String, _>("global_order_id"),
         status: order_row.get::<String, _>("status"),
+        service_type: order_row.get::<Option<String>, _>("service_type"),
         total_cents: order_row.get::<Option<i64>, _>("total_cents"),
-        occurred_at: order_row
-            .get::<chrono::NaiveDateTime, _>("occurred_at")
-            .format("%Y-%m-%dT%H:%M:%S")
-            .to_string(),
+        refunded_cents: order_row.get::<i64, _>("refunded_cents"),
+        tip_cents: order_row.get::<Option<i64>, _>("tip_cents"),
+        service_charge_cents: order_row.get::<Option<i64>, _>("service_charge_cents"),
+        occurred_at: clock.format_local(occurred_at),
+        voided_at: order_row
+            .get::<Option<chrono::NaiveDateTime>, _>("voided_at")
+            .map(|at| clock.format_local_naive(at)),
+        void_reason: order_row.get::<Option<String>, _>("void_reason"),
+        voided_by_staff_id: order_row.get::<Option<String>, _>("voided_by_staff_id"),
+        voided_by_staff_name: order_row.get::<Option<String>, _>("voided_by_staff_name"),
+        trading_day: clock.trading_day(occurred_at).to_string(),
         items,
+        discounts,
+        taxes,
         transactions,
+        refunds,
         receipts,
+        devices,
+        commands,
     }))
 }
 
+/// Commands targeting the order, newest first, with approval trails and status timelines.
+async fn load_order_commands(
+    db: &db::DbPool,
+    user: &CurrentUser,
+    order_row: &sqlx::mysql::MySqlRow,
+    order_uuid: Uuid,
+    clock: &StoreClock,
+) -> Result<Vec<OrderCommandRow>, (StatusCode, String)> {
+    let org_id: String = order_row.get("org_id");
+    let store_id: String = order_row.get("store_id");
+
+    let rows = sqlx::query(
+        r#"
+        SELECT q.command_id, q.command_type, q.status, q.created_at, q.required_approvals,
+               q.approved_at, q.rejected_at, q.delivered_at, q.acked_at, q.failed_at, q.expired_reason,
+               q.ack_result, q.requested_by_user_id, COALESCE(u.display_name, u.email) AS requested_by
+        FROM device_command_queue q
+        LEFT JOIN cloud_users u ON u.id = q.requested_by_user_id
+        WHERE q.target_type = 'order' AND q.target_id = ?
+        ORDER BY q.created_at DESC
+        "#,
+    )
+    .bind(order_uuid.to_string())
+    .fetch_all(db)
+    .await
+    .map_err(internal)?;
+
+    let command_ids: Vec<String> = rows.iter().map(|r| r.get::<String, _>("command_id")).collect();
+    let approvals = db::list_command_approvals(db, &command_ids, clock)
+        .await
+        .map_err(internal)?;
+    let timelines = db::list_command_timelines(db, &command_ids, clock)
+        .await
+        .map_err(internal)?;
+    let can_approve = match (Uuid::parse_str(&org_id), Uuid::parse_str(&store_id)) {
+        (Ok(org), Ok(store)) if rows.iter().any(|r| r.get::<String, _>("status") == "pending_approval") => {
+            db::user_can_approve_commands(db, &user.0, org, store)
+                .await
+                .map_err(internal)?
+        }
+        _ => false,
+    };
+
+    let fmt = |v: Option<chrono::NaiveDateTime>| v.map(|at| clock.format_local_naive(at));
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let command_id: String = row.get("command_id");
+            let status: String = row.get("status");
+            let trail: Vec<CommandApproval> = approvals
+                .iter()
+                .filter(|a| a.command_id == command_id)
+                .cloned()
+                .collect();
+            let timeline: Vec<CommandStatusChange> = timelines
+                .iter()
+                .filter(|t| t.command_id == command_id)
+                .cloned()
+                .collect();
+            let is_requester =
+                row.get::<Option<String>, _>("requested_by_user_id").as_deref() == Some(user.0.as_str());
+            let already_decided = trail.iter().any(|a| a.approver_user_id == user.0);
+            OrderCommandRow {
+                can_decide: status == "pending_approval" && !already_decided && (can_approve || is_requester),
+                command_id,
+                command_type: row.get("command_type"),
+                status,
+                requested_by: row.get("requested_by"),
+                created_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("created_at")),
+                required_approvals: row.get("required_approvals"),
+                approved_at: fmt(row.get("approved_at")),
+                rejected_at: fmt(row.get("rejected_at")),
+                delivered_at: fmt(row.get("delivered_at")),
+                acked_at: fmt(row.get("acked_at")),
+                failed_at: fmt(row.get("failed_at")),
+                expired_reason: row.get("expired_reason"),
+                ack_result: row.get("ack_result"),
+                timeline,
+                approvals: trail,
+            }
+        })
+        .collect())
+}
+
 async fn enqueue_order_command(
     State(state): State<AppState>,
     user: CurrentUser,
@@ -238,7 +608,7 @@ async fn enqueue_order_command(
 
     let order_row = sqlx::query(
         r#"
-        SELECT org_id, store_id, device_id, local_order_id
+        SELECT org_id, store_id, device_id, local_order_id, global_order_id
         FROM orders
         WHERE id = ?
         "#,
@@ -256,6 +626,7 @@ async fn enqueue_order_command(
     let store_id_s: String = order_row.get("store_id");
     let device_id_s: String = order_row.get("device_id");
     let local_order_id: String = order_row.get("local_order_id");
+    let global_order_id: String = order_row.get("global_order_id");
     let org_id = Uuid::parse_str(&org_id_s).map_err(|_| internal("invalid org_id"))?;
     let store_id = Uuid::parse_str(&store_id_s).map_err(|_| internal("invalid store_id"))?;
     let device_id = Uuid::parse_str(&device_id_s).map_err(|_| internal("invalid device_id"))?;
@@ -267,37 +638,35 @@ async fn enqueue_order_command(
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
 
-    let command_id = Uuid::new_v4();
-    let command_body = serde_json::json!({ "local_order_id": local_order_id });
-
-    sqlx::query(
-        r#"
-        INSERT INTO device_command_queue (
-          command_id,
-          org_id,
-          store_id,
-          device_id,
-          command_type,
-          command_body,
-          status,
-          sensitive,
-          created_at
-        )
-        VALUES (?, ?, ?, ?, ?, ?, 'queued', 1, CURRENT_TIMESTAMP(3))
-        "#,
+    // Sent to the owning device; global_order_id lets other devices match it too.
+    let command_body = serde_json::json!({
+        "local_order_id": local_order_id,
+        "global_order_id": global_order_id,
+    });
+    let expires_at = body
+        .expires_in_seconds
+        .map(|secs| chrono::Utc::now() + chrono::Duration::seconds(i64::from(secs)));
+    let command_id = db::enqueue_device_command(
+        db,
+        &db::NewDeviceCommand {
+            org_id,
+            store_id,
+            device_id,
+            command_type: &body.command_type,
+            command_body: &command_body,
+            sensitive: true,
+            expires_at,
+            target: Some(CommandEntity::Order(order_uuid)),
+            requested_by_user_id: Some(&user.0),
+        },
     )
-    .bind(command_id.to_string())
-    .bind(org_id.to_string())
-    .bind(store_id.to_string())
-    .bind(device_id.to_string())
-    .bind(&body.command_type)
-    .bind(command_body)
-    .execute(db)
     .await
     .map_err(internal)?;
+    // Sensitive: the device is notified once a second user approves (portal_commands.rs).
 
     Ok(Json(EnqueueCommandResponse {
         command_id: command_id.to_string(),
+        status: "pending_approval".to_string(),
     }))
 }
 
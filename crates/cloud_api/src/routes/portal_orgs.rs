@@ -10,7 +10,8 @@ use uuid::Uuid;
 
 use crate::session::CurrentUser;
 use crate::state::AppState;
-use db::{reactivate_cloud_sync, suspend_cloud_sync};
+use db::{get_device_seq_progress, reactivate_cloud_sync, suspend_cloud_sync};
+use domain::SeqRange;
 
 #[derive(Debug, Serialize)]
 pub struct OrgSummary {
@@ -67,6 +68,22 @@ pub struct DeviceRow {
     pub hardware_fingerprint: Option<String>,
     pub status: String,
     pub last_seen_at: Option<String>,
+    /// Device holds an open sync WebSocket (heartbeat within the last 90 s).
+    pub online: bool,
+    /// When the current WebSocket session started (only while online).
+    pub online_since: Option<String>,
+    /// Highest contiguous event seq stored for this device.
+    pub ack_seq: Option<i64>,
+    /// Highest event seq received from this device.
+    pub highest_seq: Option<i64>,
+    /// Seq ranges never received (holes in the device's event history).
+    pub missing_seq_ranges: Vec<SeqRange>,
+    /// Total number of missing seqs across missing_seq_ranges.
+    pub missing_seq_count: i64,
+    /// Device clock minus cloud clock (ms) at the last measurement.
+    pub clock_skew_ms: Option<i64>,
+    /// clock_skew_ms is beyond the threshold: event times from this device are being corrected.
+    pub clock_skewed: bool,
 }
 
 #[derive(Debug, Serialize)]
@@ -357,22 +374,34 @@ async fn get_store_devices(
           d.is_primary,
           d.hardware_fingerprint,
           d.status,
-          ss.updated_at AS last_seen_at
+          GREATEST(COALESCE(d.last_seen_at, ss.updated_at), COALESCE(ss.updated_at, d.last_seen_at)) AS last_seen_at,
+          CASE WHEN d.online_since IS NOT NULL
+                AND d.last_seen_at > NOW(3) - INTERVAL ? SECOND
+               THEN d.online_since END AS online_since,
+          ss.clock_skew_ms
         FROM devices d
         LEFT JOIN device_sync_state ss ON ss.device_id = d.id
         WHERE d.store_id = ?
         ORDER BY d.is_primary DESC, d.created_at DESC
         "#,
     )
+    .bind(db::DEVICE_ONLINE_WINDOW_SECS)
     .bind(store_uuid.to_string())
     .fetch_all(db)
     .await
     .map_err(internal)?;
 
-    let devices = rows
-        .into_iter()
-        .map(|row| DeviceRow {
-            id: row.get::<String, _>("id"),
+    let mut devices = Vec::with_capacity(rows.len());
+    for row in rows {
+        let id = row.get::<String, _>("id");
+        let online_since = row.get::<Option<chrono::NaiveDateTime>, _>("online_since");
+        let clock_skew_ms = row.get::<Option<i64>, _>("clock_skew_ms");
+        let progress = match Uuid::parse_str(&id) {
+            Ok(device_uuid) => get_device_seq_progress(db, device_uuid).await.map_err(internal)?,
+            Err(_) => Default::default(),
+        };
+        devices.push(DeviceRow {
+            id,
             local_device_id: None,
             device_label: row.get::<Option<String>, _>("device_label"),
             device_name: row.get::<Option<String>, _>("device_name"),
@@ -382,8 +411,21 @@ async fn get_store_devices(
             last_seen_at: row
                 .get::<Option<chrono::NaiveDateTime>, _>("last_seen_at")
                 .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
-        })
-        .collect();
+            online: online_since.is_some(),
+            online_since: online_since.map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            ack_seq: progress.ack_seq,
+            highest_seq: progress.highest_seq,
+            missing_seq_count: progress.missing.iter().map(|(start, end)| end - start + 1).sum(),
+            missing_seq_ranges: progress
+                .missing
+                .iter()
+                .map(|&(start, end)| SeqRange { start, end })
+                .collect(),
+            clock_skew_ms,
+            clock_skewed: clock_skew_ms
+                .is_some_and(|ms| ms.abs() >= db::CLOCK_SKEW_THRESHOLD_SECS * 1000),
+        });
+    }
 
     Ok(Json(StoreDevicesResponse {
         store_id,
@@ -0,0 +1,138 @@
+//! Portal: projection queue status per store, dead-lettered events and replay.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{
+    list_dead_letters_for_store, list_projection_cursors_for_store, replay_dead_letter,
+    DeadLetterReplay, DeadLetterRow, ProjectionCursorRow,
+};
+
+#[derive(Debug, Serialize)]
+pub struct ProjectionCursorsResponse {
+    pub cursors: Vec<ProjectionCursorRow>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeadLettersQuery {
+    #[serde(default)]
+    pub include_resolved: bool,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeadLettersResponse {
+    pub dead_letters: Vec<DeadLetterRow>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReplayDeadLetterResponse {
+    pub resolved: bool,
+    pub error: Option<String>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route(
+            "/portal/stores/:store_id/projection/cursors",
+            get(get_projection_cursors),
+        )
+        .route(
+            "/portal/stores/:store_id/projection/dead-letters",
+            get(get_dead_letters),
+        )
+        .route(
+            "/portal/stores/:store_id/projection/dead-letters/:dead_letter_id/replay",
+            post(post_replay_dead_letter),
+        )
+}
+
+async fn authorize_store(
+    state: &AppState,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<(db::DbPool, Uuid), (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = Uuid::parse_str(store_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid store_id".to_string()))?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    Ok((db.clone(), store_uuid))
+}
+
+async fn get_projection_cursors(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(store_id): Path<String>,
+) -> Result<Json<ProjectionCursorsResponse>, (StatusCode, String)> {
+    let (db, store_uuid) = authorize_store(&state, &user, &store_id).await?;
+    let cursors = list_projection_cursors_for_store(&db, store_uuid)
+        .await
+        .map_err(internal)?;
+    Ok(Json(ProjectionCursorsResponse { cursors }))
+}
+
+async fn get_dead_letters(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(store_id): Path<String>,
+    Query(q): Query<DeadLettersQuery>,
+) -> Result<Json<DeadLettersResponse>, (StatusCode, String)> {
+    let (db, store_uuid) = authorize_store(&state, &user, &store_id).await?;
+    let limit = q.limit.unwrap_or(100).min(500) as i64;
+    let dead_letters = list_dead_letters_for_store(&db, store_uuid, q.include_resolved, limit)
+        .await
+        .map_err(internal)?;
+    Ok(Json(DeadLettersResponse { dead_letters }))
+}
+
+async fn post_replay_dead_letter(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path((store_id, dead_letter_id)): Path<(String, String)>,
+) -> Result<Json<ReplayDeadLetterResponse>, (StatusCode, String)> {
+    let (db, store_uuid) = authorize_store(&state, &user, &store_id).await?;
+    let dead_letter_uuid = Uuid::parse_str(&dead_letter_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid dead_letter_id".to_string()))?;
+
+    match replay_dead_letter(&db, store_uuid, dead_letter_uuid)
+        .await
+        .map_err(internal)?
+    {
+        DeadLetterReplay::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            "dead letter not found in this store".to_string(),
+        )),
+        DeadLetterReplay::AlreadyResolved => Err((
+            StatusCode::CONFLICT,
+            "dead letter already resolved".to_string(),
+        )),
+        DeadLetterReplay::Projected => Ok(Json(ReplayDeadLetterResponse {
+            resolved: true,
+            error: None,
+        })),
+        DeadLetterReplay::Failed(error) => Ok(Json(ReplayDeadLetterResponse {
+            resolved: false,
+            error: Some(error),
+        })),
+    }
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
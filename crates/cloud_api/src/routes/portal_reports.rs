@@ -0,0 +1,757 @@
+//! Portal sales reports: takings by hour, day or week, by item and category, by payment kind,
+//! and VAT summaries, for an org, a franchise or a store over a range of business days. Every
+//! report is also available as CSV (`?format=csv`).
+
+use std::collections::{BTreeMap, HashMap};
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::{Datelike, Duration, NaiveDate};
+use domain::{ServiceType, StoreClock, VatRate};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::export::csv_response;
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{ReportBucket, ReportItemSales, ReportPaymentTotal, ReportScope, ReportWindow};
+
+/// Longest range a single report may cover.
+const MAX_REPORT_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize)]
+pub struct ReportQuery {
+    /// Exactly one of org_id, franchise_id, store_id.
+    pub org_id: Option<String>,
+    pub franchise_id: Option<String>,
+    pub store_id: Option<String>,
+    /// First business day (YYYY-MM-DD). Defaults to `to`.
+    pub from: Option<String>,
+    /// Last business day, inclusive (YYYY-MM-DD). Defaults to each store's current trading day.
+    pub to: Option<String>,
+    /// Sales report: hour (hour of day), day (default) or week (weeks starting Monday).
+    /// VAT report: range (default, the whole range), month or quarter.
+    pub group_by: Option<String>,
+    /// `csv` for a CSV download; JSON otherwise.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SalesGrouping {
+    Hour,
+    Day,
+    Week,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VatGrouping {
+    Range,
+    Month,
+    Quarter,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SalesTotals {
+    pub order_count: i64,
+    pub item_count: f64,
+    /// Order totals before refunds (voided orders excluded).
+    pub gross_cents: i64,
+    /// Refunds made in the period, whenever the order was taken.
+    pub refund_cents: i64,
+    /// Net sales: gross_cents - refund_cents.
+    pub sales_cents: i64,
+    /// Discounts given; gross is already after them.
+    pub discount_cents: i64,
+    /// Service charges, included in gross.
+    pub service_charge_cents: i64,
+    /// Tips (not part of sales).
+    pub tip_cents: i64,
+    /// sales_cents / order_count, rounded; 0 without orders.
+    pub average_basket_cents: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SalesPeriod {
+    /// "13:00" (hour), the business day (day) or the Monday the week starts on (week).
+    pub period: String,
+    #[serde(flatten)]
+    pub totals: SalesTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SalesReport {
+    /// Requested range; null when defaulted to each store's current trading day.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub store_count: usize,
+    pub group_by: SalesGrouping,
+    pub totals: SalesTotals,
+    pub periods: Vec<SalesPeriod>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ItemSalesReport {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub store_count: usize,
+    pub items: Vec<ReportItemSales>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategorySales {
+    pub category: String,
+    pub quantity: f64,
+    pub sales_cents: i64,
+    /// Distinct items sold in the category.
+    pub item_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CategorySalesReport {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub store_count: usize,
+    pub categories: Vec<CategorySales>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PaymentReport {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub store_count: usize,
+    /// Net of refunds.
+    pub total_cents: i64,
+    pub refund_cents: i64,
+    pub payments: Vec<ReportPaymentTotal>,
+}
+
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct VatTotals {
+    pub net_cents: i64,
+    pub vat_cents: i64,
+    pub gross_cents: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatSummaryLine {
+    /// "2026-07-01/2026-09-30" (range), "2026-07" (month) or "2026-Q3" (quarter).
+    pub period: String,
+    /// standard, reduced, zero, or other for rates that are none of these.
+    pub rate: String,
+    pub rate_percent: f64,
+    /// eat_in, takeaway (including delivery) or unspecified.
+    pub service_type: String,
+    #[serde(flatten)]
+    pub totals: VatTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatRateTotal {
+    pub rate: String,
+    pub rate_percent: f64,
+    #[serde(flatten)]
+    pub totals: VatTotals,
+}
+
+#[derive(Debug, Serialize)]
+pub struct VatReport {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub store_count: usize,
+    pub group_by: VatGrouping,
+    pub totals: VatTotals,
+    pub rates: Vec<VatRateTotal>,
+    pub lines: Vec<VatSummaryLine>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/portal/reports/sales", get(get_sales_report))
+        .route("/portal/reports/items", get(get_item_report))
+        .route("/portal/reports/categories", get(get_category_report))
+        .route("/portal/reports/payments", get(get_payment_report))
+        .route("/portal/reports/vat", get(get_vat_report))
+}
+
+/// Stores and UTC windows a report covers, after access checks.
+struct ResolvedReport {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    clocks: HashMap<String, StoreClock>,
+    windows: Vec<ReportWindow>,
+    csv: bool,
+}
+
+impl ResolvedReport {
+    fn range(&self) -> (Option<String>, Option<String>) {
+        (self.from.map(|d| d.to_string()), self.to.map(|d| d.to_string()))
+    }
+
+    fn csv_filename(&self, report: &str) -> String {
+        match (self.from, self.to) {
+            (Some(from), Some(to)) if from == to => format!("{}-{}.csv", report, from),
+            (Some(from), Some(to)) => format!("{}-{}-to-{}.csv", report, from, to),
+            _ => format!("{}-today.csv", report),
+        }
+    }
+}
+
+async fn resolve_report(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    q: &ReportQuery,
+) -> Result<ResolvedReport, (StatusCode, String)> {
+    let csv = match q.format.as_deref() {
+        None | Some("json") => false,
+        Some("csv") => true,
+        Some(_) => return Err((StatusCode::BAD_REQUEST, "format must be json or csv".to_string())),
+    };
+    let to = q.to.as_deref().map(|d| parse_day(d, "to")).transpose()?;
+    let from = match q.from.as_deref() {
+        Some(d) => Some(parse_day(d, "from")?),
+        None => to,
+    };
+    if from.is_some() && to.is_none() {
+        return Err((StatusCode::BAD_REQUEST, "to is required with from".to_string()));
+    }
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err((StatusCode::BAD_REQUEST, "from must not be after to".to_string()));
+        }
+        if (to - from).num_days() >= MAX_REPORT_DAYS {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("reports cover at most {} days", MAX_REPORT_DAYS),
+            ));
+        }
+    }
+
+    let scope = match (q.org_id.as_deref(), q.franchise_id.as_deref(), q.store_id.as_deref()) {
+        (Some(id), None, None) => ReportScope::Org(parse_uuid(id, "org_id")?),
+        (None, Some(id), None) => ReportScope::Franchise(parse_uuid(id, "franchise_id")?),
+        (None, None, Some(id)) => ReportScope::Store(parse_uuid(id, "store_id")?),
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "exactly one of org_id, franchise_id or store_id is required".to_string(),
+            ))
+        }
+    };
+    // Org and franchise reports need org access; a store report needs access to the store.
+    let allowed = match scope {
+        ReportScope::Org(org_id) => db::user_can_access_org(db, &user.0, org_id)
+            .await
+            .map_err(internal)?,
+        ReportScope::Franchise(franchise_id) => {
+            let org_id = db::get_franchise_org_id(db, franchise_id)
+                .await
+                .map_err(internal)?
+                .ok_or((StatusCode::NOT_FOUND, "franchise not found".to_string()))?;
+            db::user_can_access_org(db, &user.0, org_id)
+                .await
+                .map_err(internal)?
+        }
+        ReportScope::Store(store_id) => db::user_can_access_store(db, &user.0, store_id)
+            .await
+            .map_err(internal)?,
+    };
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "not in your account".to_string()));
+    }
+
+    let stores = db::list_report_stores(db, scope).await.map_err(internal)?;
+    if matches!(scope, ReportScope::Store(_)) && stores.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "store not found".to_string()));
+    }
+
+    // One window per distinct clock, each cut at that clock's business-day boundaries.
+    let mut stores_by_clock: HashMap<StoreClock, Vec<String>> = HashMap::new();
+    for (store_id, clock) in &stores {
+        stores_by_clock.entry(*clock).or_default().push(store_id.clone());
+    }
+    let windows = stores_by_clock
+        .into_iter()
+        .map(|(clock, store_ids)| {
+            let (start, end) = match (from, to) {
+                (Some(from), Some(to)) => clock.range_bounds(from, to),
+                _ => clock.day_bounds(clock.today()),
+            };
+            ReportWindow {
+                store_ids,
+                clock,
+                start: start.naive_utc(),
+                end: end.naive_utc(),
+            }
+        })
+        .collect();
+
+    Ok(ResolvedReport {
+        from,
+        to,
+        clocks: stores.into_iter().collect(),
+        windows,
+        csv,
+    })
+}
+
+async fn get_sales_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<ReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let group_by = match q.group_by.as_deref() {
+        Some("hour") => SalesGrouping::Hour,
+        None | Some("day") => SalesGrouping::Day,
+        Some("week") => SalesGrouping::Week,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "group_by must be hour, day or week".to_string(),
+            ))
+        }
+    };
+    let report = resolve_report(db, &user, &q).await?;
+    // Totalled per hour or business day in SQL; weeks are folded from days.
+    let bucket = match group_by {
+        SalesGrouping::Hour => ReportBucket::LocalHour,
+        SalesGrouping::Day | SalesGrouping::Week => ReportBucket::TradingDay,
+    };
+    let orders = db::list_report_order_totals(db, &report.windows, bucket)
+        .await
+        .map_err(internal)?;
+    let refunds = db::list_report_refund_totals(db, &report.windows, bucket)
+        .await
+        .map_err(internal)?;
+
+    let period_of = |period: &str| match (group_by, NaiveDate::parse_from_str(period, "%Y-%m-%d")) {
+        (SalesGrouping::Week, Ok(day)) => {
+            (day - Duration::days(day.weekday().num_days_from_monday() as i64)).to_string()
+        }
+        _ => period.to_string(),
+    };
+    let mut totals = SalesTotals::default();
+    let mut periods: BTreeMap<String, SalesTotals> = BTreeMap::new();
+    for row in &orders {
+        add_orders(periods.entry(period_of(&row.period)).or_default(), row);
+        add_orders(&mut totals, row);
+    }
+    for row in &refunds {
+        add_refund(periods.entry(period_of(&row.period)).or_default(), row.amount_cents);
+        add_refund(&mut totals, row.amount_cents);
+    }
+    let mut periods: Vec<SalesPeriod> = periods
+        .into_iter()
+        .map(|(period, mut totals)| {
+            totals.average_basket_cents = average_basket(&totals);
+            SalesPeriod { period, totals }
+        })
+        .collect();
+    totals.average_basket_cents = average_basket(&totals);
+
+    if report.csv {
+        periods.push(SalesPeriod {
+            period: "Total".to_string(),
+            totals,
+        });
+        let rows = periods
+            .iter()
+            .map(|p| {
+                vec![
+                    p.period.clone(),
+                    p.totals.order_count.to_string(),
+                    p.totals.item_count.to_string(),
+                    p.totals.gross_cents.to_string(),
+                    p.totals.refund_cents.to_string(),
+                    p.totals.sales_cents.to_string(),
+                    p.totals.discount_cents.to_string(),
+                    p.totals.service_charge_cents.to_string(),
+                    p.totals.tip_cents.to_string(),
+                    p.totals.average_basket_cents.to_string(),
+                ]
+            })
+            .collect();
+        return Ok(csv_response(
+            &report.csv_filename("sales"),
+            &[
+                "period",
+                "orders",
+                "items",
+                "gross_cents",
+                "refund_cents",
+                "sales_cents",
+                "discount_cents",
+                "service_charge_cents",
+                "tip_cents",
+                "average_basket_cents",
+            ],
+            rows,
+        ));
+    }
+
+    let (from, to) = report.range();
+    Ok(Json(SalesReport {
+        from,
+        to,
+        store_count: report.clocks.len(),
+        group_by,
+        totals,
+        periods,
+    })
+    .into_response())
+}
+
+async fn get_item_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<ReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let report = resolve_report(db, &user, &q).await?;
+    let items = db::list_report_item_sales(db, &report.windows)
+        .await
+        .map_err(internal)?;
+
+    if report.csv {
+        let rows = items
+            .iter()
+            .map(|i| {
+                vec![
+                    i.item.clone(),
+                    i.category.clone(),
+                    i.quantity.to_string(),
+                    i.sales_cents.to_string(),
+                    i.order_count.to_string(),
+                ]
+            })
+            .collect();
+        return Ok(csv_response(
+            &report.csv_filename("items"),
+            &["item", "category", "quantity", "sales_cents", "orders"],
+            rows,
+        ));
+    }
+
+    let (from, to) = report.range();
+    Ok(Json(ItemSalesReport {
+        from,
+        to,
+        store_count: report.clocks.len(),
+        items,
+    })
+    .into_response())
+}
+
+async fn get_category_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<ReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let report = resolve_report(db, &user, &q).await?;
+    let items = db::list_report_item_sales(db, &report.windows)
+        .await
+        .map_err(internal)?;
+
+    let mut by_category: HashMap<String, CategorySales> = HashMap::new();
+    for item in items {
+        let entry = by_category
+            .entry(item.category.clone())
+            .or_insert_with(|| CategorySales {
+                category: item.category.clone(),
+                quantity: 0.0,
+                sales_cents: 0,
+                item_count: 0,
+            });
+        entry.quantity += item.quantity;
+        entry.sales_cents += item.sales_cents;
+        entry.item_count += 1;
+    }
+    let mut categories: Vec<CategorySales> = by_category.into_values().collect();
+    categories.sort_by(|a, b| {
+        b.sales_cents
+            .cmp(&a.sales_cents)
+            .then_with(|| a.category.cmp(&b.category))
+    });
+
+    if report.csv {
+        let rows = categories
+            .iter()
+            .map(|c| {
+                vec![
+                    c.category.clone(),
+                    c.quantity.to_string(),
+                    c.sales_cents.to_string(),
+                    c.item_count.to_string(),
+                ]
+            })
+            .collect();
+        return Ok(csv_response(
+            &report.csv_filename("categories"),
+            &["category", "quantity", "sales_cents", "items"],
+            rows,
+        ));
+    }
+
+    let (from, to) = report.range();
+    Ok(Json(CategorySalesReport {
+        from,
+        to,
+        store_count: report.clocks.len(),
+        categories,
+    })
+    .into_response())
+}
+
+async fn get_payment_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<ReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let report = resolve_report(db, &user, &q).await?;
+    let payments = db::list_report_payment_totals(db, &report.windows)
+        .await
+        .map_err(internal)?;
+    let total_cents: i64 = payments.iter().map(|p| p.amount_cents).sum();
+    let refund_cents: i64 = payments.iter().map(|p| p.refund_cents).sum();
+
+    if report.csv {
+        let mut rows: Vec<Vec<String>> = payments
+            .iter()
+            .map(|p| {
+                vec![
+                    p.kind.clone(),
+                    p.transaction_count.to_string(),
+                    p.refund_count.to_string(),
+                    p.refund_cents.to_string(),
+                    p.amount_cents.to_string(),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            "Total".to_string(),
+            payments.iter().map(|p| p.transaction_count).sum::<i64>().to_string(),
+            payments.iter().map(|p| p.refund_count).sum::<i64>().to_string(),
+            refund_cents.to_string(),
+            total_cents.to_string(),
+        ]);
+        return Ok(csv_response(
+            &report.csv_filename("payments"),
+            &["kind", "transactions", "refunds", "refund_cents", "amount_cents"],
+            rows,
+        ));
+    }
+
+    let (from, to) = report.range();
+    Ok(Json(PaymentReport {
+        from,
+        to,
+        store_count: report.clocks.len(),
+        total_cents,
+        refund_cents,
+        payments,
+    })
+    .into_response())
+}
+
+/// Output VAT by rate and eat-in / takeaway, net of refunds, per range, month or quarter.
+async fn get_vat_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Query(q): Query<ReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let group_by = match q.group_by.as_deref() {
+        None | Some("range") => VatGrouping::Range,
+        Some("month") => VatGrouping::Month,
+        Some("quarter") => VatGrouping::Quarter,
+        Some(_) => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "group_by must be range, month or quarter".to_string(),
+            ))
+        }
+    };
+    let report = resolve_report(db, &user, &q).await?;
+    let vat_lines = db::list_report_vat_lines(db, &report.windows)
+        .await
+        .map_err(internal)?;
+
+    let range_label = match (report.from, report.to) {
+        (Some(from), Some(to)) => format!("{}/{}", from, to),
+        _ => "today".to_string(),
+    };
+    // Keyed by (period, rate, rate in hundredths of a percent, service type).
+    let mut lines: BTreeMap<(String, String, i64, String), VatTotals> = BTreeMap::new();
+    for line in &vat_lines {
+        let clock = report.clocks.get(&line.store_id).copied().unwrap_or_default();
+        let day = clock.trading_day(line.occurred_at.and_utc());
+        let period = match group_by {
+            VatGrouping::Range => range_label.clone(),
+            VatGrouping::Month => day.format("%Y-%m").to_string(),
+            VatGrouping::Quarter => format!("{}-Q{}", day.year(), day.month0() / 3 + 1),
+        };
+        let rate = line
+            .name
+            .as_deref()
+            .and_then(VatRate::parse)
+            .or_else(|| VatRate::from_percent(line.rate_percent))
+            .map(|r| r.as_str())
+            .unwrap_or("other");
+        let service_type = match line.service_type.as_deref().and_then(ServiceType::parse) {
+            Some(s) if s.is_takeaway() => "takeaway",
+            Some(_) => "eat_in",
+            None => "unspecified",
+        };
+        let key = (
+            period,
+            rate.to_string(),
+            (line.rate_percent * 100.0).round() as i64,
+            service_type.to_string(),
+        );
+        let totals = lines.entry(key).or_default();
+        totals.net_cents += line.net_cents;
+        totals.vat_cents += line.tax_cents;
+        totals.gross_cents += line.net_cents + line.tax_cents;
+    }
+    let lines: Vec<VatSummaryLine> = lines
+        .into_iter()
+        .map(|((period, rate, rate_bps, service_type), totals)| VatSummaryLine {
+            period,
+            rate,
+            rate_percent: rate_bps as f64 / 100.0,
+            service_type,
+            totals,
+        })
+        .collect();
+
+    let mut totals = VatTotals::default();
+    let mut by_rate: BTreeMap<(String, i64), VatTotals> = BTreeMap::new();
+    for line in &lines {
+        let rate = by_rate
+            .entry((line.rate.clone(), (line.rate_percent * 100.0).round() as i64))
+            .or_default();
+        for t in [rate, &mut totals] {
+            t.net_cents += line.totals.net_cents;
+            t.vat_cents += line.totals.vat_cents;
+            t.gross_cents += line.totals.gross_cents;
+        }
+    }
+
+    if report.csv {
+        let mut rows: Vec<Vec<String>> = lines
+            .iter()
+            .map(|l| {
+                vec![
+                    l.period.clone(),
+                    l.rate.clone(),
+                    l.rate_percent.to_string(),
+                    l.service_type.clone(),
+                    l.totals.net_cents.to_string(),
+                    l.totals.vat_cents.to_string(),
+                    l.totals.gross_cents.to_string(),
+                ]
+            })
+            .collect();
+        rows.push(vec![
+            "Total".to_string(),
+            String::new(),
+            String::new(),
+            String::new(),
+            totals.net_cents.to_string(),
+            totals.vat_cents.to_string(),
+            totals.gross_cents.to_string(),
+        ]);
+        return Ok(csv_response(
+            &report.csv_filename("vat"),
+            &[
+                "period",
+                "rate",
+                "rate_percent",
+                "service_type",
+                "net_cents",
+                "vat_cents",
+                "gross_cents",
+            ],
+            rows,
+        ));
+    }
+
+    let (from, to) = report.range();
+    Ok(Json(VatReport {
+        from,
+        to,
+        store_count: report.clocks.len(),
+        group_by,
+        totals,
+        rates: by_rate
+            .into_iter()
+            .map(|((rate, rate_bps), totals)| VatRateTotal {
+                rate,
+                rate_percent: rate_bps as f64 / 100.0,
+                totals,
+            })
+            .collect(),
+        lines,
+    })
+    .into_response())
+}
+
+fn add_orders(totals: &mut SalesTotals, orders: &db::ReportOrderTotals) {
+    totals.order_count += orders.order_count;
+    totals.item_count += orders.item_count;
+    totals.gross_cents += orders.total_cents;
+    totals.sales_cents += orders.total_cents;
+    totals.discount_cents += orders.discount_cents;
+    totals.service_charge_cents += orders.service_charge_cents;
+    totals.tip_cents += orders.tip_cents;
+}
+
+fn add_refund(totals: &mut SalesTotals, amount_cents: i64) {
+    totals.refund_cents += amount_cents;
+    totals.sales_cents -= amount_cents;
+}
+
+fn average_basket(totals: &SalesTotals) -> i64 {
+    if totals.order_count == 0 {
+        0
+    } else {
+        (totals.sales_cents as f64 / totals.order_count as f64).round() as i64
+    }
+}
+
+fn parse_day(value: &str, field: &str) -> Result<NaiveDate, (StatusCode, String)> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("{} must be YYYY-MM-DD", field)))
+}
+
+fn parse_uuid(value: &str, field: &str) -> Result<Uuid, (StatusCode, String)> {
+    Uuid::parse_str(value).map_err(|_| (StatusCode::BAD_REQUEST, format!("invalid {}", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
@@ -12,10 +12,12 @@ use crate::session::CurrentUser;
 use crate::state::AppState;
 use db::{
     create_pos_menu_category, create_pos_menu_item, enqueue_apply_menu_for_store,
-    ensure_pos_menu, get_device_id_for_store, update_pos_menu_category_by_id,
+    ensure_pos_menu, get_device_id_for_store, get_store_clock, update_pos_menu_category_by_id,
     update_pos_menu_category_image_by_id, update_pos_menu_item_by_id,
-    update_pos_menu_item_image_by_id,
+    update_pos_menu_item_image_by_id, update_pos_menu_item_vat_by_id, update_store_clock,
+    MenuPublish, MenuVersionSource,
 };
+use domain::{parse_timezone, StoreClock, VatRate};
 
 #[derive(Debug, Deserialize)]
 pub struct StorePathParams {
@@ -35,6 +37,10 @@ pub struct MenuItem {
     pub name: String,
     pub description: Option<String>,
     pub price_pence: Option<i64>,
+    /// VAT rate band: standard (also when null), reduced or zero.
+    pub vat_rate: Option<String>,
+    /// Band for takeaway and delivery orders; null = vat_rate.
+    pub takeaway_vat_rate: Option<String>,
     pub active: bool,
     pub image_path: Option<String>,
     pub remaining: Option<f64>,
@@ -60,11 +66,22 @@ pub struct StoreOrderRow {
     pub local_order_id: String,
     pub status: String,
     pub total_cents: Option<i64>,
+    /// Store-local time with offset.
     pub occurred_at: String,
+    /// Business day (YYYY-MM-DD) in the store's timezone, after the trading-day cutoff.
+    pub trading_day: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StoreOrdersQuery {
+    /// Only orders from this business day (YYYY-MM-DD); `today` for the current one.
+    pub trading_day: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 pub struct StoreOrdersResponse {
+    /// Set when the request filtered by trading_day.
+    pub trading_day: Option<String>,
     pub orders: Vec<StoreOrderRow>,
 }
 
@@ -77,6 +94,20 @@ pub struct CommandRow {
     pub device_id: String,
     pub created_at: String,
     pub sensitive: bool,
+    pub expires_at: Option<String>,
+    pub delivered_at: Option<String>,
+    pub delivery_attempts: u32,
+    pub max_attempts: Option<u32>,
+    /// Set when status is `expired`.
+    pub expired_at: Option<String>,
+    /// `ttl_elapsed` or `max_attempts_reached`.
+    pub expired_reason: Option<String>,
+    /// Entity the command acts on: `order`, `menu` or `delivery_order`.
+    pub target_type: Option<String>,
+    pub target_id: Option<String>,
+    pub acked_at: Option<String>,
+    pub failed_at: Option<String>,
+    pub ack_result: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Serialize)]
@@ -91,6 +122,22 @@ pub struct StoreMetaResponse {
     pub name: String,
     pub canonical_device_id: Option<String>,
     pub canonical_device_name: Option<String>,
+    /// IANA timezone used for reporting.
+    pub timezone: String,
+    /// Local time the trading day starts (HH:MM).
+    pub trading_day_cutoff: String,
+    /// Current business day (YYYY-MM-DD).
+    pub trading_day: String,
+    /// Timezone reported by the store's POS, if any (may differ from `timezone`).
+    pub pos_timezone: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchReportingSettingsBody {
+    /// IANA timezone, e.g. "Europe/Dublin".
+    pub timezone: Option<String>,
+    /// Local time the trading day starts, "HH:MM" (e.g. "04:00").
+    pub trading_day_cutoff: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -99,6 +146,9 @@ pub struct PatchMenuItemBody {
     pub price_pence: Option<i64>,
     pub description: Option<Option<String>>,
     pub active: Option<bool>,
+    /// "standard", "reduced" or "zero".
+    pub vat_rate: Option<Option<String>>,
+    pub takeaway_vat_rate: Option<Option<String>>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -125,6 +175,8 @@ pub struct CreateMenuItemBody {
     pub active: bool,
     #[serde(default)]
     pub customer_editable: bool,
+    pub vat_rate: Option<String>,
+    pub takeaway_vat_rate: Option<String>,
 }
 
 fn default_true() -> bool {
@@ -162,6 +214,10 @@ pub fn router(_state: AppState) -> Router<AppState> {
             patch(patch_store_menu_category),
         )
         .route("/portal/stores/:store_id/meta", get(get_store_meta))
+        .route(
+            "/portal/stores/:store_id/reporting-settings",
+            patch(patch_store_reporting_settings),
+        )
         .route("/portal/stores/:store_id/orders", get(get_store_orders))
         .route("/portal/stores/:store_id/commands", get(get_store_commands))
 }
@@ -192,7 +248,13 @@ async fn get_store_meta(
           s.org_id,
           s.name,
           s.canonical_device_id,
-          d.device_name AS canonical_device_name
+          d.device_name AS canonical_device_name,
+          s.timezone,
+          s.trading_day_cutoff,
+          (SELECT p.timezone FROM pos_store_sync p
+           WHERE p.store_id = s.id
+           ORDER BY p.device_id = s.canonical_device_id DESC
+           LIMIT 1) AS pos_timezone
         FROM stores s
         LEFT JOIN devices d ON d.id = s.canonical_device_id
         WHERE s.id = ?
@@ -207,15 +269,63 @@ async fn get_store_meta(
         return Err((StatusCode::NOT_FOUND, "store not found".to_string()));
     };
 
+    let timezone = row.get::<String, _>("timezone");
+    let cutoff = row.get::<chrono::NaiveTime, _>("trading_day_cutoff");
+    let clock = StoreClock::new(&timezone, cutoff);
     Ok(Json(StoreMetaResponse {
         id: row.get::<String, _>("id"),
         org_id: row.get::<String, _>("org_id"),
         name: row.get::<String, _>("name"),
         canonical_device_id: row.get::<Option<String>, _>("canonical_device_id"),
         canonical_device_name: row.get::<Option<String>, _>("canonical_device_name"),
+        timezone,
+        trading_day_cutoff: cutoff.format("%H:%M").to_string(),
+        trading_day: clock.today().to_string(),
+        pos_timezone: row.get::<Option<String>, _>("pos_timezone"),
     }))
 }
 
+async fn patch_store_reporting_settings(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Json(body): Json<PatchReportingSettingsBody>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let store_uuid = Uuid::parse_str(&store_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid store_id".to_string()))?;
+
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+
+    let timezone = match body.timezone.as_deref() {
+        Some(tz) => Some(
+            parse_timezone(tz)
+                .ok_or((StatusCode::BAD_REQUEST, "unknown timezone (use an IANA name, e.g. Europe/Dublin)".to_string()))?
+                .name(),
+        ),
+        None => None,
+    };
+    let cutoff = match body.trading_day_cutoff.as_deref() {
+        Some(cutoff) => Some(
+            chrono::NaiveTime::parse_from_str(cutoff.trim(), "%H:%M")
+                .map_err(|_| (StatusCode::BAD_REQUEST, "trading_day_cutoff must be HH:MM".to_string()))?,
+        ),
+        None => None,
+    };
+    update_store_clock(db, store_uuid, timezone, cutoff)
+        .await
+        .map_err(internal)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
 async fn get_store_menu_and_items(
     State(state): State<AppState>,
     user: CurrentUser,
@@ -307,6 +417,8 @@ async fn get_store_menu_and_items(
                   i.name,
                   i.description,
                   i.price_pence,
+                  i.vat_rate,
+                  i.takeaway_vat_rate,
                   i.active,
                   i.image_path,
                   y.estimated_total,
@@ -340,6 +452,8 @@ async fn get_store_menu_and_items(
                     name: row.get::<String, _>("name"),
                     description: row.get::<Option<String>, _>("description"),
                     price_pence: row.get::<Option<i64>, _>("price_pence"),
+                    vat_rate: row.get::<Option<String>, _>("vat_rate"),
+                    takeaway_vat_rate: row.get::<Option<String>, _>("takeaway_vat_rate"),
                     active: row.get::<bool, _>("active"),
                     image_path: row.get::<Option<String>, _>("image_path"),
                     estimated_total: row.get::<Option<f64>, _>("estimated_total"),
@@ -359,6 +473,8 @@ async fn get_store_menu_and_items(
               i.name,
               i.description,
               i.price_pence,
+              i.vat_rate,
+              i.takeaway_vat_rate,
               i.active,
               i.image_path,
               y.estimated_total,
@@ -390,6 +506,8 @@ async fn get_store_menu_and_items(
                 name: row.get::<String, _>("name"),
                 description: row.get::<Option<String>, _>("description"),
                 price_pence: row.get::<Option<i64>, _>("price_pence"),
+                vat_rate: row.get::<Option<String>, _>("vat_rate"),
+                takeaway_vat_rate: row.get::<Option<String>, _>("takeaway_vat_rate"),
                 active: row.get::<bool, _>("active"),
                 image_path: row.get::<Option<String>, _>("image_path"),
                 estimated_total: row.get::<Option<f64>, _>("estimated_total"),
@@ -407,6 +525,7 @@ async fn get_store_orders(
     State(state): State<AppState>,
     user: CurrentUser,
     Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Query(q): Query<StoreOrdersQuery>,
 ) -> Result<Json<StoreOrdersResponse>, (StatusCode, String)> {
     let db = state.db.as_ref().ok_or((
         StatusCode::SERVICE_UNAVAILABLE,
@@ -422,35 +541,66 @@ async fn get_store_orders(
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
 
+    let clock = get_store_clock(db, store_uuid)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "store not found".to_string()))?;
+    let trading_day = match q.trading_day.as_deref() {
+        None => None,
+        Some("today") => Some(clock.today()),
+        Some(day) => Some(
+            chrono::NaiveDate::parse_from_str(day, "%Y-%m-%d")
+                .map_err(|_| (StatusCode::BAD_REQUEST, "trading_day must be YYYY-MM-DD or today".to_string()))?,
+        ),
+    };
+    // Without a day filter the bounds are open (most recent 100 orders).
+    let (start, end) = match trading_day {
+        Some(day) => {
+            let (start, end) = clock.day_bounds(day);
+            (Some(start.naive_utc()), Some(end.naive_utc()))
+        }
+        None => (None, None),
+    };
+
     let rows = sqlx::query(
         r#"
         SELECT id, local_order_id, status, total_cents, occurred_at
         FROM orders
         WHERE store_id = ?
+          AND (? IS NULL OR occurred_at >= ?)
+          AND (? IS NULL OR occurred_at < ?)
         ORDER BY occurred_at DESC
         LIMIT 100
         "#,
     )
     .bind(store_uuid.to_string())
+    .bind(start)
+    .bind(start)
+    .bind(end)
+    .bind(end)
     .fetch_all(db)
     .await
     .map_err(internal)?;
 
     let orders = rows
         .into_iter()
-        .map(|row| StoreOrderRow {
-            id: row.get::<String, _>("id"),
-            local_order_id: row.get::<String, _>("local_order_id"),
-            status: row.get::<String, _>("status"),
-            total_cents: row.get::<Option<i64>, _>("total_cents"),
-            occurred_at: row
-                .get::<chrono::NaiveDateTime, _>("occurred_at")
-                .format("%Y-%m-%dT%H:%M:%S")
-                .to_string(),
+        .map(|row| {
+            let occurred_at = row.get::<chrono::NaiveDateTime, _>("occurred_at").and_utc();
+            StoreOrderRow {
+                id: row.get::<String, _>("id"),
+                local_order_id: row.get::<String, _>("local_order_id"),
+                status: row.get::<String, _>("status"),
+                total_cents: row.get::<Option<i64>, _>("total_cents"),
+                occurred_at: clock.format_local(occurred_at),
+                trading_day: clock.trading_day(occurred_at).to_string(),
+            }
         })
         .collect();
 
-    Ok(Json(StoreOrdersResponse { orders }))
+    Ok(Json(StoreOrdersResponse {
+        trading_day: trading_day.map(|d| d.to_string()),
+        orders,
+    }))
 }
 
 async fn get_store_commands(
@@ -481,7 +631,18 @@ async fn get_store_commands(
           status,
           device_id,
           created_at,
-          sensitive
+          sensitive,
+          expires_at,
+          delivered_at,
+          delivery_attempts,
+          max_attempts,
+          expired_at,
+          expired_reason,
+          target_type,
+          target_id,
+          acked_at,
+          failed_at,
+          ack_result
         FROM device_command_queue
         WHERE store_id = ?
         ORDER BY created_at DESC
@@ -513,6 +674,27 @@ async fn get_store_commands(
                     .format("%Y-%m-%dT%H:%M:%S")
                     .to_string(),
                 sensitive: row.get::<bool, _>("sensitive"),
+                expires_at: row
+                    .get::<Option<chrono::NaiveDateTime>, _>("expires_at")
+                    .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                delivered_at: row
+                    .get::<Option<chrono::NaiveDateTime>, _>("delivered_at")
+                    .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                delivery_attempts: row.get::<u32, _>("delivery_attempts"),
+                max_attempts: row.get::<Option<u32>, _>("max_attempts"),
+                expired_at: row
+                    .get::<Option<chrono::NaiveDateTime>, _>("expired_at")
+                    .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                expired_reason: row.get::<Option<String>, _>("expired_reason"),
+                target_type: row.get::<Option<String>, _>("target_type"),
+                target_id: row.get::<Option<String>, _>("target_id"),
+                acked_at: row
+                    .get::<Option<chrono::NaiveDateTime>, _>("acked_at")
+                    .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                failed_at: row
+                    .get::<Option<chrono::NaiveDateTime>, _>("failed_at")
+                    .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+                ack_result: row.get::<Option<serde_json::Value>, _>("ack_result"),
             }
         })
         .collect();
@@ -539,6 +721,7 @@ async fn post_create_store_menu_category(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
 
     let device_id = match get_device_id_for_store(db, store_uuid).await.map_err(internal)? {
         Some(d) => d,
@@ -584,7 +767,10 @@ async fn post_create_store_menu_category(
     .await
     .map_err(internal)?;
 
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
 
     Ok((
         StatusCode::CREATED,
@@ -615,6 +801,7 @@ async fn post_create_store_menu_item(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
 
     let device_id = match get_device_id_for_store(db, store_uuid).await.map_err(internal)? {
         Some(d) => d,
@@ -672,6 +859,9 @@ async fn post_create_store_menu_item(
     let local_store_id: Option<String> =
         local_store_row.map(|(s,)| s).or_else(|| Some(store_uuid.to_string()));
 
+    let vat_rate = parse_vat_rate(body.vat_rate.as_deref(), "vat_rate")?;
+    let takeaway_vat_rate = parse_vat_rate(body.takeaway_vat_rate.as_deref(), "takeaway_vat_rate")?;
+
     let local_item_id = format!("cloud-{}", Uuid::new_v4());
 
     let new_id = create_pos_menu_item(
@@ -690,8 +880,16 @@ async fn post_create_store_menu_item(
     )
     .await
     .map_err(internal)?;
+    if vat_rate.is_some() || takeaway_vat_rate.is_some() {
+        update_pos_menu_item_vat_by_id(db, new_id, Some(vat_rate), Some(takeaway_vat_rate))
+            .await
+            .map_err(internal)?;
+    }
 
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
 
     Ok((
         StatusCode::CREATED,
@@ -700,6 +898,8 @@ async fn post_create_store_menu_item(
             name: body.name.trim().to_string(),
             description: body.description.clone(),
             price_pence: body.price_pence,
+            vat_rate: vat_rate.map(|r| r.as_str().to_string()),
+            takeaway_vat_rate: takeaway_vat_rate.map(|r| r.as_str().to_string()),
             active: body.active,
             image_path: None,
             remaining: None,
@@ -729,8 +929,19 @@ async fn patch_store_menu_item(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
     let item_uuid = Uuid::parse_str(&item_id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "invalid item_id".to_string()))?;
+    let vat_rate = body
+        .vat_rate
+        .as_ref()
+        .map(|r| parse_vat_rate(r.as_deref(), "vat_rate"))
+        .transpose()?;
+    let takeaway_vat_rate = body
+        .takeaway_vat_rate
+        .as_ref()
+        .map(|r| parse_vat_rate(r.as_deref(), "takeaway_vat_rate"))
+        .transpose()?;
 
     let exists: Option<(i32,)> = sqlx::query_as(
         r#"
@@ -758,8 +969,14 @@ async fn patch_store_menu_item(
     )
     .await
     .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    update_pos_menu_item_vat_by_id(db, item_uuid, vat_rate, takeaway_vat_rate)
+        .await
+        .map_err(internal)?;
 
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
@@ -782,6 +999,7 @@ async fn upload_store_menu_item_image(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
     let item_uuid = Uuid::parse_str(&item_id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "invalid item_id".to_string()))?;
 
@@ -856,7 +1074,10 @@ async fn upload_store_menu_item_image(
     update_pos_menu_item_image_by_id(db, item_uuid, &relative_path)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
 
     Ok(Json(serde_json::json!({
         "url": format!("/uploads/{}", relative_path),
@@ -883,6 +1104,7 @@ async fn upload_store_menu_category_image(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
     let category_uuid = Uuid::parse_str(&category_id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "invalid category_id".to_string()))?;
 
@@ -965,7 +1187,10 @@ async fn upload_store_menu_category_image(
     update_pos_menu_category_image_by_id(db, category_uuid, &relative_path)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
 
     Ok(Json(serde_json::json!({
         "url": format!("/uploads/{}", relative_path),
@@ -992,6 +1217,7 @@ async fn patch_store_menu_category(
     if !allowed {
         return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
     }
+    ensure_store_menu_editable(db, store_uuid).await?;
     let category_uuid = Uuid::parse_str(&category_id)
         .map_err(|_| (StatusCode::BAD_REQUEST, "invalid category_id".to_string()))?;
 
@@ -1015,10 +1241,41 @@ async fn patch_store_menu_category(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let _ = enqueue_apply_menu_for_store(db, store_uuid).await;
+    let publish = MenuPublish::new(MenuVersionSource::PortalEdit, Some(&user.0));
+    if let Ok(devices) = enqueue_apply_menu_for_store(db, store_uuid, &publish).await {
+        state.command_notifier.notify_all(&devices);
+    }
     Ok(StatusCode::NO_CONTENT)
 }
 
+/// Stores on a head-office menu template sync the template's menu, so edits to the device menu
+/// would be overwritten; change the template or the store's overrides instead.
+async fn ensure_store_menu_editable(db: &sqlx::MySqlPool, store_id: Uuid) -> Result<(), (StatusCode, String)> {
+    if db::get_store_menu_template(db, store_id)
+        .await
+        .map_err(internal)?
+        .is_some()
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "store menu is managed by a head-office menu template; edit the template or the store's overrides".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// A VAT rate band from a request body; None (or null) clears it.
+fn parse_vat_rate(value: Option<&str>, field: &str) -> Result<Option<VatRate>, (StatusCode, String)> {
+    value
+        .map(|v| {
+            VatRate::parse(v).ok_or((
+                StatusCode::BAD_REQUEST,
+                format!("{} must be standard, reduced or zero", field),
+            ))
+        })
+        .transpose()
+}
+
 fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
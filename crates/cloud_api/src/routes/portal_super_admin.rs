@@ -14,7 +14,8 @@ use crate::session::CurrentUser;
 use crate::state::AppState;
 use db::{
     create_activation_key, create_organization, create_store, get_org_id_by_slug, grant_entitlement,
-    is_super_admin,
+    is_super_admin, list_store_ids_for_org, rebuild_store_read_model, RebuildError, RebuildReport,
+    RebuildScope,
 };
 
 #[derive(Debug, Serialize)]
@@ -51,6 +52,21 @@ pub struct CreateCustomerResponse {
     pub cloud_sync_granted: bool,
 }
 
+/// Rebuild the read model for one store or every store in an org.
+#[derive(Debug, Deserialize)]
+pub struct RebuildReadModelRequest {
+    pub store_id: Option<Uuid>,
+    pub org_id: Option<Uuid>,
+    /// "orders" | "menu" | "all" (default).
+    pub scope: Option<RebuildScope>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RebuildReadModelResponse {
+    pub scope: RebuildScope,
+    pub stores: Vec<RebuildReport>,
+}
+
 fn hash_activation_key(key: &str) -> String {
     let mut hasher = Sha256::new();
     hasher.update(key.trim().as_bytes());
@@ -73,6 +89,7 @@ pub fn router(_state: AppState) -> Router<AppState> {
         .route("/portal/super/orgs", get(list_orgs_for_super_admin))
         .route("/portal/super/orgs/:org_id", get(get_org_detail_super_admin))
         .route("/portal/super/create-customer", post(create_customer))
+        .route("/portal/super/read-model/rebuild", post(rebuild_read_model))
 }
 
 /// Returns 204 if the current user has super-admin access, 403 otherwise. Used by super-admin pages to redirect non-allowed users.
@@ -241,6 +258,59 @@ async fn create_customer(
     }))
 }
 
+/// Reset and replay the read model (orders and/or menu) from device_event_log. Runs synchronously,
+/// one store after another. See db::rebuild.
+async fn rebuild_read_model(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Json(req): Json<RebuildReadModelRequest>,
+) -> Result<Json<RebuildReadModelResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+
+    let ok = is_super_admin(db, &user.0)
+        .await
+        .map_err(internal)?;
+    if !ok {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Super admin access required.".to_string(),
+        ));
+    }
+
+    let store_ids = match (req.store_id, req.org_id) {
+        (Some(store_id), None) => vec![store_id],
+        (None, Some(org_id)) => list_store_ids_for_org(db, org_id).await.map_err(internal)?,
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "exactly one of store_id or org_id is required".to_string(),
+            ))
+        }
+    };
+    let scope = req.scope.unwrap_or(RebuildScope::All);
+
+    let mut stores = Vec::with_capacity(store_ids.len());
+    for store_id in store_ids {
+        tracing::info!("read-model rebuild ({:?}) started for store {} by {}", scope, store_id, user.0);
+        let mut progress = |done: u64, total: u64| {
+            tracing::info!("read-model rebuild store {}: {}/{} events", store_id, done, total);
+        };
+        let report = rebuild_store_read_model(db, store_id, scope, &mut progress)
+            .await
+            .map_err(|e| match e {
+                RebuildError::InProgress => (StatusCode::CONFLICT, e.to_string()),
+                RebuildError::Db(e) => internal(e),
+            })?;
+        tracing::info!("read-model rebuild finished: {:?}", report);
+        stores.push(report);
+    }
+
+    Ok(Json(RebuildReadModelResponse { scope, stores }))
+}
+
 fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
     (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
 }
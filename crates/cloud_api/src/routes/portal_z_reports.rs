@@ -0,0 +1,485 @@
+//! Portal Z-reports: the end-of-day report per store trading day (written once the day has
+//! closed; see db::close_due_z_reports), with per-device reports, the totals devices reported
+//! with z_report_closed and any mismatches. Closed reports download as CSV or PDF.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::get,
+    Json, Router,
+};
+use chrono::NaiveDate;
+use domain::StoreClock;
+use serde::{Deserialize, Serialize};
+use sqlx::Row;
+use uuid::Uuid;
+
+use crate::export::{csv_response, pdf_response};
+use crate::session::CurrentUser;
+use crate::state::AppState;
+use db::{DeviceZReportClose, ZReport, ZReportFigures, ZReportTenderTotal};
+
+/// Days listed when the request gives no range.
+const DEFAULT_LIST_DAYS: i64 = 30;
+
+#[derive(Debug, Deserialize)]
+pub struct StorePathParams {
+    pub store_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZReportPathParams {
+    pub store_id: String,
+    pub trading_day: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZReportListQuery {
+    /// First business day (YYYY-MM-DD); defaults to 30 days before `to`.
+    pub from: Option<String>,
+    /// Last business day (YYYY-MM-DD); defaults to the store's current trading day.
+    pub to: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ZReportQuery {
+    /// `csv` or `pdf` to download a closed day's report; JSON otherwise.
+    pub format: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZReportSummary {
+    pub id: String,
+    pub trading_day: String,
+    pub order_count: i64,
+    pub gross_cents: i64,
+    pub void_cents: i64,
+    pub refund_cents: i64,
+    pub net_cents: i64,
+    pub receipt_count: i64,
+    pub closed_at: String,
+    /// Device closes for the day whose totals disagree with the cloud.
+    pub mismatch_count: i64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZReportListResponse {
+    pub from: String,
+    pub to: String,
+    pub reports: Vec<ZReportSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReceiptMark {
+    pub receipt_id: String,
+    /// Store-local time with offset.
+    pub at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZReportTotals {
+    /// Null for the whole-store report.
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    pub order_count: i64,
+    pub gross_cents: i64,
+    pub void_count: i64,
+    pub void_cents: i64,
+    pub refund_count: i64,
+    pub refund_cents: i64,
+    pub net_cents: i64,
+    pub tenders: Vec<ZReportTenderTotal>,
+    pub receipt_count: i64,
+    pub first_receipt: Option<ReceiptMark>,
+    pub last_receipt: Option<ReceiptMark>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ZReportDetail {
+    pub store_id: String,
+    pub store_name: String,
+    pub trading_day: String,
+    /// `closed` (stored, final) or `open` (figures so far, computed on request).
+    pub status: &'static str,
+    pub timezone: String,
+    pub trading_day_cutoff: String,
+    /// Store-local bounds of the trading day.
+    pub period_start: String,
+    pub period_end: String,
+    pub closed_at: Option<String>,
+    pub store: ZReportTotals,
+    /// Per-device reports (closed days only).
+    pub devices: Vec<ZReportTotals>,
+    /// Totals devices reported with z_report_closed, with reconciliation results.
+    pub device_closes: Vec<DeviceZReportClose>,
+}
+
+pub fn router(_state: AppState) -> Router<AppState> {
+    Router::new()
+        .route("/portal/stores/:store_id/z-reports", get(list_z_reports))
+        .route(
+            "/portal/stores/:store_id/z-reports/:trading_day",
+            get(get_z_report),
+        )
+}
+
+/// Name and clock of a store the user can access.
+async fn authorize_store(
+    db: &sqlx::MySqlPool,
+    user: &CurrentUser,
+    store_id: &str,
+) -> Result<(Uuid, String, StoreClock), (StatusCode, String)> {
+    let store_uuid = Uuid::parse_str(store_id)
+        .map_err(|_| (StatusCode::BAD_REQUEST, "invalid store_id".to_string()))?;
+    let allowed = db::user_can_access_store(db, &user.0, store_uuid)
+        .await
+        .map_err(internal)?;
+    if !allowed {
+        return Err((StatusCode::FORBIDDEN, "store not in your account".to_string()));
+    }
+    let row = sqlx::query("SELECT name, timezone, trading_day_cutoff FROM stores WHERE id = ?")
+        .bind(store_uuid.to_string())
+        .fetch_optional(db)
+        .await
+        .map_err(internal)?
+        .ok_or((StatusCode::NOT_FOUND, "store not found".to_string()))?;
+    let clock = StoreClock::new(
+        &row.get::<String, _>("timezone"),
+        row.get::<chrono::NaiveTime, _>("trading_day_cutoff"),
+    );
+    Ok((store_uuid, row.get::<String, _>("name"), clock))
+}
+
+async fn list_z_reports(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(StorePathParams { store_id }): Path<StorePathParams>,
+    Query(q): Query<ZReportListQuery>,
+) -> Result<Json<ZReportListResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let (store_uuid, _, clock) = authorize_store(db, &user, &store_id).await?;
+
+    let to = match q.to.as_deref() {
+        Some(d) => parse_day(d, "to")?,
+        None => clock.today(),
+    };
+    let from = match q.from.as_deref() {
+        Some(d) => parse_day(d, "from")?,
+        None => to - chrono::Duration::days(DEFAULT_LIST_DAYS),
+    };
+    let reports = db::list_z_reports_for_store(db, store_uuid, from, to)
+        .await
+        .map_err(internal)?;
+
+    Ok(Json(ZReportListResponse {
+        from: from.to_string(),
+        to: to.to_string(),
+        reports: reports
+            .into_iter()
+            .map(|r| ZReportSummary {
+                id: r.id,
+                trading_day: r.trading_day.to_string(),
+                order_count: r.figures.order_count,
+                gross_cents: r.figures.gross_cents,
+                void_cents: r.figures.void_cents,
+                refund_cents: r.figures.refund_cents,
+                net_cents: r.figures.net_cents,
+                receipt_count: r.figures.receipt_count,
+                closed_at: r.clock.format_local_naive(r.closed_at),
+                mismatch_count: r.mismatch_count,
+            })
+            .collect(),
+    }))
+}
+
+async fn get_z_report(
+    State(state): State<AppState>,
+    user: CurrentUser,
+    Path(ZReportPathParams {
+        store_id,
+        trading_day,
+    }): Path<ZReportPathParams>,
+    Query(q): Query<ZReportQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let (store_uuid, store_name, current_clock) = authorize_store(db, &user, &store_id).await?;
+    let day = parse_day(&trading_day, "trading_day")?;
+    let format = q.format.as_deref().unwrap_or("json");
+    if !matches!(format, "json" | "csv" | "pdf") {
+        return Err((StatusCode::BAD_REQUEST, "format must be json, csv or pdf".to_string()));
+    }
+
+    let reports = db::get_z_reports_for_day(db, store_uuid, day)
+        .await
+        .map_err(internal)?;
+    let device_closes = db::list_device_z_report_closes(db, store_uuid, day)
+        .await
+        .map_err(internal)?;
+
+    let detail = match reports.split_first() {
+        Some((store_report, device_reports)) => {
+            let clock = store_report.clock;
+            ZReportDetail {
+                store_id: store_uuid.to_string(),
+                store_name,
+                trading_day: day.to_string(),
+                status: "closed",
+                timezone: clock.timezone.name().to_string(),
+                trading_day_cutoff: clock.trading_day_cutoff.format("%H:%M").to_string(),
+                period_start: clock.format_local_naive(store_report.period_start),
+                period_end: clock.format_local_naive(store_report.period_end),
+                closed_at: Some(clock.format_local_naive(store_report.closed_at)),
+                store: totals(store_report, &clock),
+                devices: device_reports.iter().map(|r| totals(r, &clock)).collect(),
+                device_closes,
+            }
+        }
+        None => {
+            if format != "json" {
+                return Err((
+                    StatusCode::CONFLICT,
+                    "trading day not closed yet; downloads are available once its Z-report is written"
+                        .to_string(),
+                ));
+            }
+            let clock = current_clock;
+            let (start, end) = clock.day_bounds(day);
+            let mut conn = db.acquire().await.map_err(internal)?;
+            let figures = db::compute_z_report(&mut conn, store_uuid, None, start.naive_utc(), end.naive_utc())
+                .await
+                .map_err(internal)?;
+            ZReportDetail {
+                store_id: store_uuid.to_string(),
+                store_name,
+                trading_day: day.to_string(),
+                status: "open",
+                timezone: clock.timezone.name().to_string(),
+                trading_day_cutoff: clock.trading_day_cutoff.format("%H:%M").to_string(),
+                period_start: clock.format_local(start),
+                period_end: clock.format_local(end),
+                closed_at: None,
+                store: figures_totals(None, None, &figures, &clock),
+                devices: Vec::new(),
+                device_closes,
+            }
+        }
+    };
+
+    let filename = format!("z-report-{}-{}", slug(&detail.store_name), detail.trading_day);
+    match format {
+        "csv" => Ok(z_report_csv(&format!("{}.csv", filename), &detail)),
+        "pdf" => Ok(pdf_response(
+            &format!("{}.pdf", filename),
+            &format!("Z-report: {} {}", detail.store_name, detail.trading_day),
+            &z_report_lines(&detail),
+        )),
+        _ => Ok(Json(detail).into_response()),
+    }
+}
+
+fn totals(report: &ZReport, clock: &StoreClock) -> ZReportTotals {
+    figures_totals(
+        report.device_id.clone(),
+        report.device_name.clone(),
+        &report.figures,
+        clock,
+    )
+}
+
+fn figures_totals(
+    device_id: Option<String>,
+    device_name: Option<String>,
+    f: &ZReportFigures,
+    clock: &StoreClock,
+) -> ZReportTotals {
+    let mark = |id: &Option<String>, at: Option<chrono::NaiveDateTime>| match (id, at) {
+        (Some(id), Some(at)) => Some(ReceiptMark {
+            receipt_id: id.clone(),
+            at: clock.format_local_naive(at),
+        }),
+        _ => None,
+    };
+    ZReportTotals {
+        device_id,
+        device_name,
+        order_count: f.order_count,
+        gross_cents: f.gross_cents,
+        void_count: f.void_count,
+        void_cents: f.void_cents,
+        refund_count: f.refund_count,
+        refund_cents: f.refund_cents,
+        net_cents: f.net_cents,
+        tenders: f.tenders.clone(),
+        receipt_count: f.receipt_count,
+        first_receipt: mark(&f.first_receipt_id, f.first_receipt_at),
+        last_receipt: mark(&f.last_receipt_id, f.last_receipt_at),
+    }
+}
+
+/// One row for the store and one per device; a `tender_<kind>_cents` column per payment kind.
+fn z_report_csv(filename: &str, detail: &ZReportDetail) -> Response {
+    let reports: Vec<&ZReportTotals> = std::iter::once(&detail.store).chain(detail.devices.iter()).collect();
+    let mut kinds: Vec<&str> = reports
+        .iter()
+        .flat_map(|r| r.tenders.iter().map(|t| t.kind.as_str()))
+        .collect();
+    kinds.sort_unstable();
+    kinds.dedup();
+
+    let mut headers = vec![
+        "trading_day",
+        "scope",
+        "device_id",
+        "device_name",
+        "orders",
+        "gross_cents",
+        "voids",
+        "void_cents",
+        "refunds",
+        "refund_cents",
+        "net_cents",
+        "receipts",
+        "first_receipt_id",
+        "first_receipt_at",
+        "last_receipt_id",
+        "last_receipt_at",
+    ];
+    let tender_headers: Vec<String> = kinds.iter().map(|k| format!("tender_{}_cents", k)).collect();
+    headers.extend(tender_headers.iter().map(|h| h.as_str()));
+
+    let rows = reports
+        .iter()
+        .map(|r| {
+            let mut row = vec![
+                detail.trading_day.clone(),
+                if r.device_id.is_some() { "device" } else { "store" }.to_string(),
+                r.device_id.clone().unwrap_or_default(),
+                r.device_name.clone().unwrap_or_default(),
+                r.order_count.to_string(),
+                r.gross_cents.to_string(),
+                r.void_count.to_string(),
+                r.void_cents.to_string(),
+                r.refund_count.to_string(),
+                r.refund_cents.to_string(),
+                r.net_cents.to_string(),
+                r.receipt_count.to_string(),
+                r.first_receipt.as_ref().map(|m| m.receipt_id.clone()).unwrap_or_default(),
+                r.first_receipt.as_ref().map(|m| m.at.clone()).unwrap_or_default(),
+                r.last_receipt.as_ref().map(|m| m.receipt_id.clone()).unwrap_or_default(),
+                r.last_receipt.as_ref().map(|m| m.at.clone()).unwrap_or_default(),
+            ];
+            for kind in &kinds {
+                let amount: i64 = r.tenders.iter().filter(|t| t.kind == *kind).map(|t| t.amount_cents).sum();
+                row.push(amount.to_string());
+            }
+            row
+        })
+        .collect();
+    csv_response(filename, &headers, rows)
+}
+
+/// Printable Z-report: the store section, each device, then the device closes.
+fn z_report_lines(detail: &ZReportDetail) -> Vec<String> {
+    let mut lines = vec![
+        format!(
+            "Trading day  {} ({}, from {})",
+            detail.trading_day, detail.timezone, detail.trading_day_cutoff
+        ),
+        format!("Period       {} to {}", detail.period_start, detail.period_end),
+        format!("Closed at    {}", detail.closed_at.as_deref().unwrap_or("-")),
+        String::new(),
+    ];
+    push_totals(&mut lines, "STORE", &detail.store);
+    for device in &detail.devices {
+        let name = device
+            .device_name
+            .clone()
+            .or_else(|| device.device_id.clone())
+            .unwrap_or_default();
+        push_totals(&mut lines, &format!("DEVICE {}", name), device);
+    }
+    if !detail.device_closes.is_empty() {
+        lines.push("DEVICE CLOSES".to_string());
+        for close in &detail.device_closes {
+            let status = match close.mismatch {
+                Some(true) => "MISMATCH",
+                Some(false) => "matches",
+                None => "not reconciled",
+            };
+            lines.push(format!(
+                "  {:<24} Z {:<12} net {:>12}  {}",
+                close.device_name.as_deref().unwrap_or(&close.device_id),
+                close.local_z_report_id,
+                close.net_cents.map(money).unwrap_or_else(|| "-".to_string()),
+                status
+            ));
+            if let Some(serde_json::Value::Array(mismatches)) = &close.mismatches {
+                for m in mismatches {
+                    lines.push(format!(
+                        "    {}: device {}, cloud {}",
+                        m["field"].as_str().unwrap_or(""),
+                        m["device"],
+                        m["cloud"]
+                    ));
+                }
+            }
+        }
+    }
+    lines
+}
+
+fn push_totals(lines: &mut Vec<String>, heading: &str, t: &ZReportTotals) {
+    lines.push(heading.to_string());
+    lines.push(format!("  {:<28}{:>14}", "Orders", t.order_count));
+    lines.push(format!("  {:<28}{:>14}", "Gross", money(t.gross_cents)));
+    lines.push(format!("  {:<28}{:>14}", format!("Voids ({})", t.void_count), money(t.void_cents)));
+    lines.push(format!("  {:<28}{:>14}", format!("Refunds ({})", t.refund_count), money(t.refund_cents)));
+    lines.push(format!("  {:<28}{:>14}", "Net", money(t.net_cents)));
+    if !t.tenders.is_empty() {
+        lines.push("  Tenders".to_string());
+        for tender in &t.tenders {
+            lines.push(format!(
+                "    {:<26}{:>14}",
+                format!("{} ({})", tender.kind, tender.transaction_count),
+                money(tender.amount_cents)
+            ));
+        }
+    }
+    lines.push(format!("  {:<28}{:>14}", "Receipts", t.receipt_count));
+    if let (Some(first), Some(last)) = (&t.first_receipt, &t.last_receipt) {
+        lines.push(format!("    first {} at {}", first.receipt_id, first.at));
+        lines.push(format!("    last  {} at {}", last.receipt_id, last.at));
+    }
+    lines.push(String::new());
+}
+
+/// Minor units as a decimal amount, e.g. -1234 -> "-12.34".
+fn money(cents: i64) -> String {
+    let sign = if cents < 0 { "-" } else { "" };
+    format!("{}{}.{:02}", sign, cents.abs() / 100, cents.abs() % 100)
+}
+
+fn slug(name: &str) -> String {
+    let slug = db::slug_from_title(name);
+    if slug.is_empty() {
+        "store".to_string()
+    } else {
+        slug
+    }
+}
+
+fn parse_day(value: &str, field: &str) -> Result<NaiveDate, (StatusCode, String)> {
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .map_err(|_| (StatusCode::BAD_REQUEST, format!("{} must be YYYY-MM-DD", field)))
+}
+
+fn internal<E: std::fmt::Display>(err: E) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+}
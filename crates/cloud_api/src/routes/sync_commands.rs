@@ -1,21 +1,30 @@
+use std::collections::{HashSet, VecDeque};
+use std::convert::Infallible;
+use std::time::Duration;
+
 use axum::{
     extract::{Query, State},
     http::{HeaderMap, StatusCode},
+    response::sse::{Event, KeepAlive, Sse},
     routing::{get, post},
     Json, Router,
 };
+use futures_util::Stream;
 use serde::Deserialize;
+use uuid::Uuid;
 
+use crate::command_notify::CommandSubscription;
 use crate::state::AppState;
 use db::{
     ack_command, fetch_deliverable_commands, has_active_entitlement, mark_command_delivered,
-    validate_device_token,
+    record_device_clock_skew, validate_device_token, DbPool, DeviceIdentity, CLOCK_SKEW_THRESHOLD_SECS,
 };
 use domain::{CommandAckRequest, DeviceCommandOut, SyncCommandsResponse};
 
 pub fn router(_state: AppState) -> axum::Router<AppState> {
     Router::new()
         .route("/sync/commands", get(get_commands))
+        .route("/sync/commands/stream", get(stream_commands))
         .route("/sync/commands/ack", post(ack_command_handler))
 }
 
@@ -26,32 +35,89 @@ fn hash_token(token: &str) -> String {
     format!("{:x}", hasher.finalize())
 }
 
+/// Upper bound for `?wait=` on GET /sync/commands (long-poll).
+const MAX_LONG_POLL_SECS: u64 = 60;
+/// How often an SSE stream re-reads the queue without a notification (catches commands queued
+/// by other instances).
+const SSE_RECHECK: Duration = Duration::from_secs(30);
+
 #[derive(Debug, Deserialize)]
 pub struct CommandsQuery {
     pub limit: Option<u32>,
+    /// Long-poll: hold the request up to this many seconds (max 60) until a command is queued.
+    pub wait: Option<u64>,
 }
 
-async fn get_commands(
-    State(state): State<AppState>,
-    headers: HeaderMap,
-    Query(q): Query<CommandsQuery>,
-) -> Result<Json<SyncCommandsResponse>, (StatusCode, String)> {
-    let db = state.db.as_ref().ok_or((
-        StatusCode::SERVICE_UNAVAILABLE,
-        "database not available".to_string(),
-    ))?;
+/// SHA-256 of the device bearer token from the Authorization header.
+pub(crate) fn bearer_token_hash(headers: &HeaderMap) -> Result<String, (StatusCode, String)> {
     let token = headers
         .get(axum::http::header::AUTHORIZATION)
         .and_then(|v| v.to_str().ok())
         .and_then(|s| s.strip_prefix("Bearer "))
         .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing or invalid Authorization".to_string()))?;
-    let token_hash = hash_token(token);
-    let identity = validate_device_token(db, &token_hash)
+    Ok(hash_token(token))
+}
+
+/// Device clock at request time (RFC3339), sent with sync calls for clock-skew detection.
+pub(crate) const DEVICE_TIME_HEADER: &str = "x-device-time";
+
+/// Authenticate the device bearer token and check the Cloud Sync entitlement. Records the
+/// device's clock skew when the request carries a valid X-Device-Time; a malformed one is logged
+/// and ignored.
+pub(crate) async fn authorize_device(
+    db: &DbPool,
+    headers: &HeaderMap,
+) -> Result<DeviceIdentity, (StatusCode, String)> {
+    let token_hash = bearer_token_hash(headers)?;
+    let identity = authorize_device_token(db, &token_hash).await?;
+    if let Some(device_time) = headers.get(DEVICE_TIME_HEADER) {
+        match device_time.to_str().ok().map(clock_skew_ms) {
+            Some(Ok(skew_ms)) => record_clock_skew(db, &identity, skew_ms).await,
+            _ => tracing::warn!(
+                "device {} sent invalid X-Device-Time {:?}; clock skew not recorded",
+                identity.device_id,
+                device_time
+            ),
+        }
+    }
+    Ok(identity)
+}
+
+/// Device clock minus cloud clock, in milliseconds, from the device's current time (RFC3339).
+pub(crate) fn clock_skew_ms(device_time: &str) -> Result<i64, (StatusCode, String)> {
+    let device_time = chrono::DateTime::parse_from_rfc3339(device_time.trim()).map_err(|_| {
+        (
+            StatusCode::BAD_REQUEST,
+            "invalid device time: expected RFC3339".to_string(),
+        )
+    })?;
+    Ok((device_time.with_timezone(&chrono::Utc) - chrono::Utc::now()).num_milliseconds())
+}
+
+/// Store the device's measured skew (best effort: a failure only loses this measurement).
+pub(crate) async fn record_clock_skew(db: &DbPool, identity: &DeviceIdentity, skew_ms: i64) {
+    if skew_ms.abs() >= CLOCK_SKEW_THRESHOLD_SECS * 1000 {
+        tracing::warn!("device {} clock is off by {} ms", identity.device_id, skew_ms);
+    }
+    if let Err(e) =
+        record_device_clock_skew(db, identity.device_id, identity.org_id, identity.store_id, skew_ms).await
+    {
+        tracing::warn!("record clock skew for device {}: {}", identity.device_id, e);
+    }
+}
+
+/// Same as `authorize_device`, from an already hashed token (the sync WebSocket re-checks it on
+/// every heartbeat).
+pub(crate) async fn authorize_device_token(
+    db: &DbPool,
+    token_hash: &str,
+) -> Result<DeviceIdentity, (StatusCode, String)> {
+    let identity = validate_device_token(db, token_hash)
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
         .ok_or_else(|| (StatusCode::UNAUTHORIZED, "invalid or revoked device token".to_string()))?;
 
-    // Enforce Cloud Sync entitlement at org level before delivering commands.
+    // Enforce Cloud Sync entitlement at org level before command traffic.
     let cloud_sync_ok = has_active_entitlement(db, identity.org_id, "cloud_sync")
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
@@ -61,31 +127,148 @@ async fn get_commands(
             serde_json::json!({ "error": "Cloud sync not enabled for this organization" }).to_string(),
         ));
     }
+    Ok(identity)
+}
 
-    let limit = q.limit.unwrap_or(50).min(200) as i64;
-    let rows = fetch_deliverable_commands(db, identity.device_id, limit)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-
-    let commands: Vec<DeviceCommandOut> = rows
+/// Read deliverable commands for the device without marking them delivered. Streams mark each
+/// command (`mark_command_delivered`) only when they actually send it, so re-reading the queue
+/// does not count delivery attempts.
+pub(crate) async fn deliverable_commands(
+    db: &DbPool,
+    device_id: Uuid,
+    limit: i64,
+) -> Result<Vec<DeviceCommandOut>, sqlx::Error> {
+    let rows = fetch_deliverable_commands(db, device_id, limit).await?;
+    Ok(rows
         .into_iter()
-        .map(|r| {
-            let command_id = uuid::Uuid::parse_str(&r.command_id)
-                .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, "invalid command_id from db".to_string()))?;
-            Ok(DeviceCommandOut {
+        .filter_map(|r| {
+            let command_id = Uuid::parse_str(&r.command_id).ok()?;
+            Some(DeviceCommandOut {
                 command_id,
                 command_type: r.command_type,
                 sensitive: r.sensitive,
                 command_body: r.command_body,
             })
         })
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect())
+}
 
+/// Read deliverable commands for the device and mark them delivered.
+async fn take_commands(
+    db: &DbPool,
+    device_id: Uuid,
+    limit: i64,
+) -> Result<Vec<DeviceCommandOut>, sqlx::Error> {
+    let commands = deliverable_commands(db, device_id, limit).await?;
     for cmd in &commands {
-        let _ = mark_command_delivered(db, cmd.command_id).await;
+        mark_command_delivered(db, cmd.command_id).await?;
     }
+    Ok(commands)
+}
 
-    Ok(Json(SyncCommandsResponse { commands }))
+async fn get_commands(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(q): Query<CommandsQuery>,
+) -> Result<Json<SyncCommandsResponse>, (StatusCode, String)> {
+    let db = state.db.as_ref().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    let identity = authorize_device(db, &headers).await?;
+    let limit = q.limit.unwrap_or(50).min(200) as i64;
+
+    let wait = Duration::from_secs(q.wait.unwrap_or(0).min(MAX_LONG_POLL_SECS));
+    let deadline = tokio::time::Instant::now() + wait;
+    // Subscribe before the first read so a command queued in between still wakes us.
+    let mut subscription = state.command_notifier.subscribe(identity.device_id);
+    loop {
+        let commands = take_commands(db, identity.device_id, limit)
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        let remaining = deadline.saturating_duration_since(tokio::time::Instant::now());
+        if !commands.is_empty() || remaining.is_zero() {
+            return Ok(Json(SyncCommandsResponse { commands }));
+        }
+        if !subscription.wait(remaining).await {
+            // Timed out: one last read, then return (possibly empty).
+            let commands = take_commands(db, identity.device_id, limit)
+                .await
+                .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+            return Ok(Json(SyncCommandsResponse { commands }));
+        }
+    }
+}
+
+/// SSE alternative to polling: sends each deliverable command once per connection as a
+/// `command` event (data = DeviceCommandOut JSON, id = command_id), as soon as it is queued.
+async fn stream_commands(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, String)> {
+    let db = state
+        .db
+        .clone()
+        .ok_or((
+            StatusCode::SERVICE_UNAVAILABLE,
+            "database not available".to_string(),
+        ))?;
+    let identity = authorize_device(&db, &headers).await?;
+    let subscription = state.command_notifier.subscribe(identity.device_id);
+    tracing::info!("device {} opened command stream", identity.device_id);
+
+    struct StreamState {
+        db: DbPool,
+        device_id: Uuid,
+        subscription: CommandSubscription,
+        sent: HashSet<Uuid>,
+        pending: VecDeque<DeviceCommandOut>,
+        first: bool,
+    }
+    let init = StreamState {
+        db,
+        device_id: identity.device_id,
+        subscription,
+        sent: HashSet::new(),
+        pending: VecDeque::new(),
+        first: true,
+    };
+    let stream = futures_util::stream::unfold(init, |mut st| async move {
+        loop {
+            if let Some(cmd) = st.pending.pop_front() {
+                if let Err(e) = mark_command_delivered(&st.db, cmd.command_id).await {
+                    tracing::warn!("command stream for device {} failed: {}", st.device_id, e);
+                    return None;
+                }
+                let event = Event::default()
+                    .event("command")
+                    .id(cmd.command_id.to_string())
+                    .json_data(&cmd)
+                    .unwrap_or_else(|_| Event::default().comment("unserializable command"));
+                return Some((Ok(event), st));
+            }
+            if !st.first {
+                st.subscription.wait(SSE_RECHECK).await;
+            }
+            st.first = false;
+            // Only commands not yet sent on this stream are queued (and marked delivered when
+            // written), so the periodic re-read does not count delivery attempts.
+            match deliverable_commands(&st.db, st.device_id, 200).await {
+                Ok(commands) => {
+                    for cmd in commands {
+                        if st.sent.insert(cmd.command_id) {
+                            st.pending.push_back(cmd);
+                        }
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("command stream for device {} failed: {}", st.device_id, e);
+                    return None;
+                }
+            }
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
 }
 
 async fn ack_command_handler(
@@ -97,50 +280,35 @@ async fn ack_command_handler(
         StatusCode::SERVICE_UNAVAILABLE,
         "database not available".to_string(),
     ))?;
-    let token = headers
-        .get(axum::http::header::AUTHORIZATION)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer "))
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing or invalid Authorization".to_string()))?;
-    let token_hash = hash_token(token);
-    let identity = validate_device_token(db, &token_hash)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "invalid or revoked device token".to_string()))?;
+    // If Cloud Sync has been removed, we no longer accept command traffic from this device.
+    let identity = authorize_device(db, &headers).await?;
 
-    // Enforce Cloud Sync entitlement for acknowledgements as well; if Cloud Sync
-    // has been removed, we no longer accept command traffic from this device.
-    let cloud_sync_ok = has_active_entitlement(db, identity.org_id, "cloud_sync")
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    if !cloud_sync_ok {
-        return Err((
-            StatusCode::FORBIDDEN,
-            serde_json::json!({ "error": "Cloud sync not enabled for this organization" }).to_string(),
-        ));
-    }
+    apply_command_ack(db, identity.device_id, &req).await?;
+    Ok(StatusCode::OK)
+}
 
+/// Record a device's ack/fail for one of its commands. Shared by POST /sync/commands/ack and the
+/// `command_ack` message on the sync WebSocket.
+pub(crate) async fn apply_command_ack(
+    db: &DbPool,
+    device_id: Uuid,
+    req: &CommandAckRequest,
+) -> Result<(), (StatusCode, String)> {
     let status = match req.status.as_str() {
         "acked" | "failed" => req.status.as_str(),
         _ => return Err((StatusCode::BAD_REQUEST, "status must be 'acked' or 'failed'".to_string())),
     };
 
-    let updated = ack_command(
-        db,
-        identity.device_id,
-        req.command_id,
-        status,
-        req.result.as_ref(),
-    )
-    .await
-    .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    let updated = ack_command(db, device_id, req.command_id, status, req.result.as_ref())
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
     if !updated {
         return Err((
             StatusCode::NOT_FOUND,
-            "command not found or already acked/failed".to_string(),
+            "command not found, already acked/failed, or expired".to_string(),
         ));
     }
 
-    Ok(StatusCode::OK)
+    Ok(())
 }
@@ -4,25 +4,25 @@ use axum::{
     routing::post,
     Json, Router,
 };
+use std::collections::HashSet;
+
 use db::{
-    has_active_entitlement, insert_event_idempotent, project_event_to_read_model,
-    project_event_to_orders, update_device_sync_state_ack_seq, validate_device_token,
+    ensure_projection_cursor, find_existing_event_ids, get_device_clock_skew, insert_events_batch,
+    lock_device_sync_state, record_device_clock_skew, record_received_seqs, DbPool, DeviceIdentity,
+    NewDeviceEvent, CLOCK_SKEW_CORRECTION_WINDOW_SECS, CLOCK_SKEW_THRESHOLD_SECS,
 };
-use domain::{SyncEventsRequest, SyncEventsResponse};
+use domain::{DeviceEvent, SeqRange, SyncEventResult, SyncEventStatus, SyncEventsRequest, SyncEventsResponse};
 
+use crate::routes::sync_commands::{authorize_device, clock_skew_ms};
 use crate::state::AppState;
 
+/// Cap on missing ranges returned per response (lowest first); the rest follow once these are filled.
+const MAX_REPORTED_GAPS: usize = 100;
+
 pub fn router(_state: AppState) -> axum::Router<AppState> {
     Router::new().route("/sync/events", post(sync_events))
 }
 
-fn hash_token(token: &str) -> String {
-    use sha2::{Digest, Sha256};
-    let mut hasher = Sha256::new();
-    hasher.update(token.trim().as_bytes());
-    format!("{:x}", hasher.finalize())
-}
-
 async fn sync_events(
     State(state): State<AppState>,
     headers: HeaderMap,
@@ -32,97 +32,181 @@ async fn sync_events(
         StatusCode::SERVICE_UNAVAILABLE,
         "database not available".to_string(),
     ))?;
-    let token = headers
-        .get(axum::http::header::AUTHORIZATION)
-        .and_then(|v| v.to_str().ok())
-        .and_then(|s| s.strip_prefix("Bearer "))
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "missing or invalid Authorization".to_string()))?;
-    let token_hash = hash_token(token);
-    let identity = validate_device_token(db, &token_hash)
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .ok_or_else(|| (StatusCode::UNAUTHORIZED, "invalid or revoked device token".to_string()))?;
-
-    // Enforce Cloud Sync entitlement at org level before accepting events.
-    let cloud_sync_ok = has_active_entitlement(db, identity.org_id, "cloud_sync")
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    if !cloud_sync_ok {
-        return Err((
-            StatusCode::FORBIDDEN,
-            serde_json::json!({ "error": "Cloud sync not enabled for this organization" }).to_string(),
-        ));
-    }
-    let mut ack_seq = req.last_ack_seq;
+    // Enforces the Cloud Sync entitlement at org level before accepting events.
+    let identity = authorize_device(db, &headers).await?;
+    ingest_events(&state, db, &identity, &req).await.map(Json)
+}
 
-    for e in &req.events {
-        let occurred_at = match chrono::DateTime::parse_from_rfc3339(&e.occurred_at) {
-            Ok(dt) => dt.with_timezone(&chrono::Utc),
-            Err(_) => {
-                return Err((
-                    StatusCode::BAD_REQUEST,
-                    format!("invalid occurred_at for event {}: expected RFC3339", e.event_id),
-                ));
-            }
-        };
+/// Store one event batch for an authenticated device. Shared by POST /sync/events and the
+/// `events` message on the sync WebSocket.
+pub(crate) async fn ingest_events(
+    state: &AppState,
+    db: &DbPool,
+    identity: &DeviceIdentity,
+    req: &SyncEventsRequest,
+) -> Result<SyncEventsResponse, (StatusCode, String)> {
+    let mut results = Vec::with_capacity(req.events.len());
+    let mut accepted: Vec<NewDeviceEvent<'_>> = Vec::new();
+    let mut seen = HashSet::new();
 
-        let inserted = insert_event_idempotent(
-            db,
-            identity.org_id,
-            identity.store_id,
-            identity.device_id,
-            e.event_id,
-            e.seq,
-            &e.event_type,
-            &e.event_body,
-            occurred_at,
-        )
+    // Whole batch runs in one transaction: duplicates are detected up front and the accepted rows
+    // are written with multi-row inserts. Projection into the read models happens afterwards in
+    // the projection worker (see projection_worker.rs), which retries and dead-letters failures.
+    let mut tx = db
+        .begin()
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    // One batch at a time per device (REST, WebSocket and client retries alike), so its events
+    // commit in log_pos order and duplicates are detected against everything already stored.
+    lock_device_sync_state(&mut tx, identity.device_id, identity.org_id, identity.store_id)
         .await
         .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
 
-        if inserted {
-            if let Err(err) = project_event_to_read_model(
-                db,
-                identity.org_id,
-                identity.store_id,
+    // Skew measured with this batch, else the device's last measurement (e.g. from X-Device-Time).
+    // A malformed device_time must not cost the device its batch: the events are stored without
+    // skew correction.
+    let skew_ms = match req.device_time.as_deref().map(|t| (t, clock_skew_ms(t))) {
+        Some((device_time, Err(_))) => {
+            tracing::warn!(
+                "device {} sent invalid device_time {:?}; storing batch without clock skew correction",
                 identity.device_id,
-                &e.event_type,
-                &e.event_body,
-                occurred_at,
-            )
-            .await
-            {
-                tracing::warn!("read model projection failed for {}: {}", e.event_type, err);
+                device_time
+            );
+            None
+        }
+        Some((_, Ok(skew_ms))) => {
+            if skew_ms.abs() >= CLOCK_SKEW_THRESHOLD_SECS * 1000 {
+                tracing::warn!("device {} clock is off by {} ms", identity.device_id, skew_ms);
             }
-            let _ = project_event_to_orders(
-                db,
+            record_device_clock_skew(
+                &mut *tx,
+                identity.device_id,
                 identity.org_id,
                 identity.store_id,
-                identity.device_id,
-                &e.event_type,
-                &e.event_body,
-                occurred_at,
+                skew_ms,
             )
-            .await;
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+            Some(skew_ms)
         }
+        None => get_device_clock_skew(&mut *tx, identity.device_id)
+            .await
+            .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?,
+    };
+    // Below the threshold the device clock is trusted as-is.
+    let correction_ms = skew_ms.filter(|ms| ms.abs() >= CLOCK_SKEW_THRESHOLD_SECS * 1000);
+    // The device clock now; events recorded long before it keep their time (see
+    // CLOCK_SKEW_CORRECTION_WINDOW_SECS).
+    let device_now = chrono::Utc::now() + chrono::Duration::milliseconds(skew_ms.unwrap_or(0));
+    let correction_window = chrono::Duration::seconds(CLOCK_SKEW_CORRECTION_WINDOW_SECS);
+
+    let event_ids: Vec<uuid::Uuid> = req.events.iter().map(|e| e.event_id).collect();
+    let existing = find_existing_event_ids(&mut tx, identity.device_id, &event_ids)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    for e in &req.events {
+        let occurred_at = match chrono::DateTime::parse_from_rfc3339(&e.occurred_at) {
+            Ok(dt) => dt.with_timezone(&chrono::Utc),
+            Err(_) => {
+                results.push(rejected(e.event_id, "invalid occurred_at: expected RFC3339".to_string()));
+                continue;
+            }
+        };
 
-        if let Some(seq) = e.seq {
-            ack_seq = Some(match ack_seq {
-                Some(curr) => curr.max(seq),
-                None => seq,
+        if existing.contains(&e.event_id) || seen.contains(&e.event_id) {
+            results.push(SyncEventResult {
+                event_id: e.event_id,
+                status: SyncEventStatus::Duplicate,
+                reason: None,
             });
+            continue;
         }
+
+        // Validate the body against the typed schema; malformed events are never stored.
+        let schema_version = e.schema_version.unwrap_or(1);
+        if let Err(err) = DeviceEvent::parse(schema_version, &e.event_type, &e.event_body) {
+            results.push(rejected(e.event_id, err.to_string()));
+            continue;
+        }
+
+        seen.insert(e.event_id);
+        let correction_ms = correction_ms.filter(|_| device_now - occurred_at <= correction_window);
+        accepted.push(NewDeviceEvent {
+            event_id: e.event_id,
+            seq: e.seq,
+            event_type: &e.event_type,
+            event_body: &e.event_body,
+            schema_version,
+            occurred_at,
+            corrected_occurred_at: correction_ms
+                .map(|ms| occurred_at - chrono::Duration::milliseconds(ms)),
+            clock_skew_ms: correction_ms,
+        });
+        results.push(SyncEventResult {
+            event_id: e.event_id,
+            status: SyncEventStatus::Accepted,
+            reason: None,
+        });
     }
 
-    update_device_sync_state_ack_seq(
-        db,
+    insert_events_batch(
+        &mut tx,
+        identity.org_id,
+        identity.store_id,
+        identity.device_id,
+        &accepted,
+    )
+    .await
+    .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+    ensure_projection_cursor(&mut tx, identity.device_id, identity.org_id, identity.store_id)
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    // Seqs now stored for this device: accepted and duplicate events. Rejected events are not
+    // stored, so their seqs stay missing and the ack never moves past them.
+    let stored_seqs: Vec<i64> = req
+        .events
+        .iter()
+        .zip(&results)
+        .filter(|(_, r)| r.status != SyncEventStatus::Rejected)
+        .filter_map(|(e, _)| e.seq)
+        .collect();
+    let progress = record_received_seqs(
+        &mut tx,
         identity.device_id,
         identity.org_id,
         identity.store_id,
-        ack_seq,
+        &stored_seqs,
     )
     .await
     .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
 
-    Ok(Json(SyncEventsResponse { ack_seq }))
+    tx.commit()
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    if !accepted.is_empty() {
+        state.projection_notify.notify_one();
+    }
+
+    Ok(SyncEventsResponse {
+        ack_seq: progress.ack_seq,
+        missing_seq_ranges: progress
+            .missing
+            .iter()
+            .take(MAX_REPORTED_GAPS)
+            .map(|&(start, end)| SeqRange { start, end })
+            .collect(),
+        results,
+        clock_skew_ms: correction_ms,
+    })
+}
+
+fn rejected(event_id: uuid::Uuid, reason: String) -> SyncEventResult {
+    SyncEventResult {
+        event_id,
+        status: SyncEventStatus::Rejected,
+        reason: Some(reason),
+    }
 }
@@ -10,7 +10,10 @@ use axum::{
 use serde::Deserialize;
 
 use crate::state::AppState;
-use db::{get_store_menu_for_sync, has_active_entitlement, validate_device_token, SyncMenuCategory, SyncMenuItem};
+use db::{
+    apply_menu_dayparts, get_store_menu_for_sync, has_active_entitlement, validate_device_token,
+    SyncMenuCategory, SyncMenuItem,
+};
 
 fn hash_token(token: &str) -> String {
     use sha2::{Digest, Sha256};
@@ -94,7 +97,13 @@ async fn get_menu(
         .await
         .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
 
-    let (categories, items) = menu.unwrap_or((Vec::new(), Vec::new()));
+    let (categories, mut items) = menu.unwrap_or((Vec::new(), Vec::new()));
+    // Dayparts only restrict the store's own menu; a copied menu is taken whole.
+    if q.copy_from_store_id.is_none() {
+        apply_menu_dayparts(db, store_id, &mut items, chrono::Utc::now())
+            .await
+            .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+    }
 
     Ok(Json(SyncMenuResponse { categories, items }))
 }
@@ -0,0 +1,268 @@
+//! GET /sync/ws: one WebSocket per device multiplexing event upload, command delivery and command
+//! acks (framing in domain::SyncClientMessage / SyncServerMessage). Requests go through the same
+//! code as the REST endpoints; commands are pushed as soon as they are queued. While the socket
+//! is open the device is marked online (devices.online_since / last_seen_at).
+
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::time::Duration;
+
+use axum::{
+    extract::{
+        ws::{CloseFrame, Message, WebSocket, WebSocketUpgrade},
+        State,
+    },
+    http::{HeaderMap, StatusCode},
+    response::Response,
+    routing::get,
+    Router,
+};
+use tokio::time::Instant;
+use uuid::Uuid;
+
+use crate::routes::sync_commands::{
+    apply_command_ack, authorize_device, authorize_device_token, bearer_token_hash, clock_skew_ms, record_clock_skew,
+    deliverable_commands,
+};
+use crate::routes::sync_events::ingest_events;
+use crate::state::AppState;
+use db::{
+    mark_command_delivered, mark_device_offline, mark_device_online, touch_device_seen, DbPool, DeviceIdentity,
+};
+use domain::{SyncClientMessage, SyncServerMessage};
+
+/// Ping interval; a socket silent for two intervals is closed. Each heartbeat also re-checks the
+/// device token and Cloud Sync entitlement and re-reads the command queue (commands queued on
+/// another instance arrive at the latest then).
+const HEARTBEAT: Duration = Duration::from_secs(30);
+
+/// Close codes (RFC 6455).
+const CLOSE_NORMAL: u16 = 1000;
+const CLOSE_POLICY: u16 = 1008;
+const CLOSE_ERROR: u16 = 1011;
+
+pub fn router(_state: AppState) -> axum::Router<AppState> {
+    Router::new().route("/sync/ws", get(sync_ws))
+}
+
+async fn sync_ws(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    ws: WebSocketUpgrade,
+) -> Result<Response, (StatusCode, String)> {
+    let db = state.db.clone().ok_or((
+        StatusCode::SERVICE_UNAVAILABLE,
+        "database not available".to_string(),
+    ))?;
+    // Authenticate before upgrading so a bad token gets a plain 401/403.
+    let token_hash = bearer_token_hash(&headers)?;
+    let identity = authorize_device(&db, &headers).await?;
+    Ok(ws.on_upgrade(move |socket| run_connection(socket, state, db, identity, token_hash)))
+}
+
+async fn run_connection(
+    mut socket: WebSocket,
+    state: AppState,
+    db: DbPool,
+    identity: DeviceIdentity,
+    token_hash: String,
+) {
+    let device_id = identity.device_id;
+    state.device_presence.connect(device_id);
+    if let Err(e) = mark_device_online(&db, device_id).await {
+        tracing::warn!("sync ws: mark device {} online: {}", device_id, e);
+    }
+    tracing::info!("device {} connected to sync ws", device_id);
+
+    let (code, reason) = serve(&mut socket, &state, &db, &identity, &token_hash).await;
+    let _ = socket
+        .send(Message::Close(Some(CloseFrame {
+            code,
+            reason: Cow::Owned(reason.clone()),
+        })))
+        .await;
+
+    if state.device_presence.disconnect(device_id) {
+        if let Err(e) = mark_device_offline(&db, device_id).await {
+            tracing::warn!("sync ws: mark device {} offline: {}", device_id, e);
+        }
+    }
+    tracing::info!("device {} disconnected from sync ws ({}: {})", device_id, code, reason);
+}
+
+/// Run the connection until it ends; returns the close code and reason to send.
+async fn serve(
+    socket: &mut WebSocket,
+    state: &AppState,
+    db: &DbPool,
+    identity: &DeviceIdentity,
+    token_hash: &str,
+) -> (u16, String) {
+    let device_id = identity.device_id;
+    // Subscribe before the first queue read so nothing queued in between is missed.
+    let mut subscription = state.command_notifier.subscribe(device_id);
+    let hello = SyncServerMessage::Hello {
+        device_id,
+        server_time: chrono::Utc::now().to_rfc3339(),
+        heartbeat_seconds: HEARTBEAT.as_secs() as u32,
+    };
+    if send(socket, &hello).await.is_err() {
+        return (CLOSE_NORMAL, "send failed".to_string());
+    }
+
+    let mut sent = HashSet::new();
+    if let Err(close) = push_commands(socket, db, device_id, &mut sent).await {
+        return close;
+    }
+
+    let mut heartbeat = tokio::time::interval_at(Instant::now() + HEARTBEAT, HEARTBEAT);
+    let mut last_heard = Instant::now();
+    loop {
+        tokio::select! {
+            frame = socket.recv() => {
+                let msg = match frame {
+                    Some(Ok(msg)) => msg,
+                    Some(Err(e)) => return (CLOSE_NORMAL, e.to_string()),
+                    None => return (CLOSE_NORMAL, "closed by device".to_string()),
+                };
+                last_heard = Instant::now();
+                let reply = match msg {
+                    Message::Text(text) => handle_text(state, db, identity, &text).await,
+                    Message::Binary(_) => Some(SyncServerMessage::Error {
+                        request_id: None,
+                        status: StatusCode::BAD_REQUEST.as_u16(),
+                        message: "binary frames are not supported; send JSON text frames".to_string(),
+                    }),
+                    Message::Close(_) => return (CLOSE_NORMAL, "closed by device".to_string()),
+                    Message::Ping(_) | Message::Pong(_) => None,
+                };
+                if let Some(reply) = reply {
+                    if send(socket, &reply).await.is_err() {
+                        return (CLOSE_NORMAL, "send failed".to_string());
+                    }
+                }
+            }
+            woken = subscription.wait(HEARTBEAT) => {
+                if woken {
+                    if let Err(close) = push_commands(socket, db, device_id, &mut sent).await {
+                        return close;
+                    }
+                }
+            }
+            _ = heartbeat.tick() => {
+                if last_heard.elapsed() > HEARTBEAT * 2 {
+                    return (CLOSE_POLICY, "heartbeat timeout".to_string());
+                }
+                // Token revoked or Cloud Sync removed since connect: stop serving this device.
+                match authorize_device_token(db, token_hash).await {
+                    Ok(_) => {}
+                    Err((status, message)) if status.is_client_error() => return (CLOSE_POLICY, message),
+                    Err((_, message)) => tracing::warn!("sync ws: re-authorize device {}: {}", device_id, message),
+                }
+                if let Err(e) = touch_device_seen(db, device_id).await {
+                    tracing::warn!("sync ws: touch device {}: {}", device_id, e);
+                }
+                if let Err(close) = push_commands(socket, db, device_id, &mut sent).await {
+                    return close;
+                }
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    return (CLOSE_NORMAL, "send failed".to_string());
+                }
+            }
+        }
+    }
+}
+
+async fn handle_text(
+    state: &AppState,
+    db: &DbPool,
+    identity: &DeviceIdentity,
+    text: &str,
+) -> Option<SyncServerMessage> {
+    let msg: SyncClientMessage = match serde_json::from_str(text) {
+        Ok(msg) => msg,
+        Err(e) => {
+            return Some(SyncServerMessage::Error {
+                request_id: None,
+                status: StatusCode::BAD_REQUEST.as_u16(),
+                message: format!("invalid message: {}", e),
+            })
+        }
+    };
+    let reply = match msg {
+        SyncClientMessage::Events { request_id, batch } => {
+            match ingest_events(state, db, identity, &batch).await {
+                Ok(response) => SyncServerMessage::EventsResult {
+                    request_id,
+                    response,
+                },
+                Err((status, message)) => SyncServerMessage::Error {
+                    request_id,
+                    status: status.as_u16(),
+                    message,
+                },
+            }
+        }
+        SyncClientMessage::CommandAck { request_id, ack } => {
+            let result = apply_command_ack(db, identity.device_id, &ack).await;
+            SyncServerMessage::CommandAckResult {
+                request_id,
+                command_id: ack.command_id,
+                ok: result.is_ok(),
+                error: result.err().map(|(_, message)| message),
+            }
+        }
+        SyncClientMessage::Ping {
+            request_id,
+            device_time,
+        } => {
+            if let Some(device_time) = device_time {
+                match clock_skew_ms(&device_time) {
+                    Ok(skew_ms) => record_clock_skew(db, identity, skew_ms).await,
+                    Err((status, message)) => {
+                        return Some(SyncServerMessage::Error {
+                            request_id,
+                            status: status.as_u16(),
+                            message,
+                        })
+                    }
+                }
+            }
+            SyncServerMessage::Pong { request_id }
+        }
+    };
+    Some(reply)
+}
+
+/// Send deliverable commands not yet sent on this connection, marking each delivered once it is
+/// written (commands already sent are skipped without counting an attempt; a delivered command
+/// only becomes deliverable again after COMMAND_REDELIVERY_SECS).
+async fn push_commands(
+    socket: &mut WebSocket,
+    db: &DbPool,
+    device_id: Uuid,
+    sent: &mut HashSet<Uuid>,
+) -> Result<(), (u16, String)> {
+    let commands = deliverable_commands(db, device_id, 200)
+        .await
+        .map_err(|e| (CLOSE_ERROR, e.to_string()))?;
+    for command in commands {
+        if sent.contains(&command.command_id) {
+            continue;
+        }
+        let command_id = command.command_id;
+        send(socket, &SyncServerMessage::Command { command })
+            .await
+            .map_err(|e| (CLOSE_NORMAL, e.to_string()))?;
+        sent.insert(command_id);
+        mark_command_delivered(db, command_id)
+            .await
+            .map_err(|e| (CLOSE_ERROR, e.to_string()))?;
+    }
+    Ok(())
+}
+
+async fn send(socket: &mut WebSocket, msg: &SyncServerMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(msg).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text)).await
+}
@@ -1,7 +1,19 @@
+use std::sync::Arc;
+
 use db::PgPool;
+use tokio::sync::Notify;
+
+use crate::command_notify::CommandNotifier;
+use crate::presence::DevicePresence;
 
 /// Shared app state for Axum handlers. DB is optional so the server can start and serve the web UI when Postgres is not running.
 #[derive(Clone)]
 pub struct AppState {
     pub db: Option<PgPool>,
+    /// Wakes the projection worker when new events land in device_event_log.
+    pub projection_notify: Arc<Notify>,
+    /// Wakes long-poll / SSE command requests when a command is queued for a device.
+    pub command_notifier: Arc<CommandNotifier>,
+    /// Open sync WebSocket connections per device on this instance.
+    pub device_presence: Arc<DevicePresence>,
 }
@@ -0,0 +1,29 @@
+//! Background task that writes Z-reports for store trading days that have closed (see
+//! db::close_due_z_reports).
+
+use std::time::Duration;
+
+use db::{close_due_z_reports, DbPool};
+
+const CLOSE_INTERVAL: Duration = Duration::from_secs(600);
+
+pub fn spawn(pool: DbPool) {
+    tokio::spawn(async move {
+        tracing::info!("z-report closer started");
+        let mut interval = tokio::time::interval(CLOSE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match close_due_z_reports(&pool).await {
+                Ok(closed) if closed.days > 0 => {
+                    tracing::info!(
+                        "closed {} store trading day(s), {} z-report(s) written",
+                        closed.days,
+                        closed.reports
+                    );
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!("z-report closer pass failed: {}", e),
+            }
+        }
+    });
+}
@@ -0,0 +1,301 @@
+//! Two-person approval for sensitive device commands.
+//!
+//! `enqueue_device_command` puts a sensitive command in `pending_approval` with
+//! `required_approvals` = SENSITIVE_COMMAND_APPROVALS. Users other than the requester who hold an
+//! approver role for the command's org or store approve or reject it here; once the quorum is met
+//! the command moves to `queued` and is delivered as usual. One rejection ends it (`rejected`).
+
+use std::fmt;
+
+use domain::StoreClock;
+use sqlx::{MySqlPool, Row};
+use uuid::Uuid;
+
+use crate::command_results::log_command_status;
+
+/// Approvals needed (from users other than the requester) before a sensitive command is delivered.
+pub const SENSITIVE_COMMAND_APPROVALS: u32 = 1;
+
+/// Roles that may approve or reject sensitive commands in their own org or store.
+pub const COMMAND_APPROVER_ROLES: &[&str] = &["head_office_admin", "head_office_ops", "store_manager", "finance"];
+
+/// Traqr-internal roles that may approve in any org (sales reps cannot).
+const INTERNAL_APPROVER_ROLES: &str = "'super_admin', 'sa_owner', 'sa_manager'";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApprovalDecision {
+    Approve,
+    Reject,
+}
+
+impl ApprovalDecision {
+    fn as_str(self) -> &'static str {
+        match self {
+            ApprovalDecision::Approve => "approve",
+            ApprovalDecision::Reject => "reject",
+        }
+    }
+}
+
+/// Org, store and device a command belongs to (for access checks before deciding).
+#[derive(Debug, Clone)]
+pub struct CommandScope {
+    pub org_id: Uuid,
+    pub store_id: Uuid,
+    pub device_id: Uuid,
+    pub status: String,
+    pub requested_by_user_id: Option<String>,
+}
+
+pub async fn get_command_scope(pool: &MySqlPool, command_id: Uuid) -> Result<Option<CommandScope>, sqlx::Error> {
+    let row: Option<(String, String, String, String, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT org_id, store_id, device_id, status, requested_by_user_id
+        FROM device_command_queue
+        WHERE command_id = ?
+        "#,
+    )
+    .bind(command_id.to_string())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.and_then(|(org_id, store_id, device_id, status, requested_by_user_id)| {
+        Some(CommandScope {
+            org_id: Uuid::parse_str(&org_id).ok()?,
+            store_id: Uuid::parse_str(&store_id).ok()?,
+            device_id: Uuid::parse_str(&device_id).ok()?,
+            status,
+            requested_by_user_id,
+        })
+    }))
+}
+
+/// True if the user holds an approver role in the org (org membership) or the store (store
+/// membership), or is a Traqr owner/manager.
+pub async fn user_can_approve_commands(
+    pool: &MySqlPool,
+    user_id: &str,
+    org_id: Uuid,
+    store_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let placeholders = vec!["?"; COMMAND_APPROVER_ROLES.len()].join(", ");
+    let sql = format!(
+        r#"
+        SELECT EXISTS(
+          SELECT 1
+          FROM org_memberships om
+          JOIN cloud_roles r ON r.id = om.role_id
+          WHERE om.user_id = ? AND om.status = 'active'
+            AND (r.code IN ({internal}) OR (om.org_id = ? AND r.code IN ({p})))
+        ) OR EXISTS(
+          SELECT 1
+          FROM store_memberships sm
+          JOIN cloud_roles r ON r.id = sm.role_id
+          WHERE sm.user_id = ? AND sm.store_id = ? AND sm.status = 'active' AND r.code IN ({p})
+        ) AS can_approve
+        "#,
+        p = placeholders,
+        internal = INTERNAL_APPROVER_ROLES
+    );
+    let mut query = sqlx::query_as::<_, (i64,)>(&sql)
+        .bind(user_id)
+        .bind(org_id.to_string());
+    for role in COMMAND_APPROVER_ROLES {
+        query = query.bind(*role);
+    }
+    query = query.bind(user_id).bind(store_id.to_string());
+    for role in COMMAND_APPROVER_ROLES {
+        query = query.bind(*role);
+    }
+    let (can_approve,) = query.fetch_one(pool).await?;
+    Ok(can_approve != 0)
+}
+
+/// Why a decision was refused.
+#[derive(Debug)]
+pub enum CommandDecisionError {
+    NotFound,
+    /// The command is not awaiting approval (holds its current status).
+    NotPending(String),
+    /// The requester cannot approve their own command.
+    SelfApproval,
+    /// This user has already approved or rejected the command.
+    AlreadyDecided,
+    Db(sqlx::Error),
+}
+
+impl fmt::Display for CommandDecisionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CommandDecisionError::NotFound => write!(f, "command not found"),
+            CommandDecisionError::NotPending(status) => {
+                write!(f, "command is not awaiting approval (status: {})", status)
+            }
+            CommandDecisionError::SelfApproval => {
+                write!(f, "a command must be approved by someone other than the requester")
+            }
+            CommandDecisionError::AlreadyDecided => write!(f, "you have already decided on this command"),
+            CommandDecisionError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for CommandDecisionError {
+    fn from(e: sqlx::Error) -> Self {
+        CommandDecisionError::Db(e)
+    }
+}
+
+/// State of a command after a decision.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommandDecisionOutcome {
+    /// `pending_approval`, `queued` (quorum met, now deliverable) or `rejected`.
+    pub status: String,
+    pub approvals: u32,
+    pub required_approvals: u32,
+}
+
+/// Record an approval or rejection by `user_id`. The caller checks store access and approver role
+/// first (see `user_can_approve_commands`).
+pub async fn decide_command(
+    pool: &MySqlPool,
+    command_id: Uuid,
+    user_id: &str,
+    decision: ApprovalDecision,
+    comment: Option<&str>,
+) -> Result<CommandDecisionOutcome, CommandDecisionError> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(
+        r#"
+        SELECT org_id, status, requested_by_user_id, required_approvals
+        FROM device_command_queue
+        WHERE command_id = ?
+        FOR UPDATE
+        "#,
+    )
+    .bind(command_id.to_string())
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or(CommandDecisionError::NotFound)?;
+    let org_id: String = row.get("org_id");
+    let status: String = row.get("status");
+    let requested_by: Option<String> = row.get("requested_by_user_id");
+    let required_approvals: u32 = row.get("required_approvals");
+
+    if status != "pending_approval" {
+        return Err(CommandDecisionError::NotPending(status));
+    }
+    // The requester may withdraw (reject) their own command, but not approve it.
+    if decision == ApprovalDecision::Approve && requested_by.as_deref() == Some(user_id) {
+        return Err(CommandDecisionError::SelfApproval);
+    }
+
+    let inserted = sqlx::query(
+        r#"
+        INSERT IGNORE INTO approvals (org_id, command_id, approver_user_id, decision, comment)
+        VALUES (?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&org_id)
+    .bind(command_id.to_string())
+    .bind(user_id)
+    .bind(decision.as_str())
+    .bind(comment)
+    .execute(&mut *tx)
+    .await?;
+    if inserted.rows_affected() == 0 {
+        return Err(CommandDecisionError::AlreadyDecided);
+    }
+
+    let (approvals,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*) FROM approvals
+        WHERE command_id = ? AND decision = 'approve' AND approver_user_id <> COALESCE(?, '')
+        "#,
+    )
+    .bind(command_id.to_string())
+    .bind(&requested_by)
+    .fetch_one(&mut *tx)
+    .await?;
+    let approvals = approvals as u32;
+
+    let new_status = match decision {
+        ApprovalDecision::Reject => {
+            sqlx::query(
+                "UPDATE device_command_queue SET status = 'rejected', rejected_at = CURRENT_TIMESTAMP(3) WHERE command_id = ?",
+            )
+            .bind(command_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            log_command_status(&mut tx, command_id, "rejected", None).await?;
+            "rejected"
+        }
+        ApprovalDecision::Approve if approvals >= required_approvals => {
+            sqlx::query(
+                "UPDATE device_command_queue SET status = 'queued', approved_at = CURRENT_TIMESTAMP(3) WHERE command_id = ?",
+            )
+            .bind(command_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            log_command_status(&mut tx, command_id, "queued", Some("approved")).await?;
+            "queued"
+        }
+        ApprovalDecision::Approve => "pending_approval",
+    };
+    tx.commit().await?;
+
+    Ok(CommandDecisionOutcome {
+        status: new_status.to_string(),
+        approvals,
+        required_approvals,
+    })
+}
+
+/// One approve/reject decision on a command.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommandApproval {
+    pub command_id: String,
+    pub approver_user_id: String,
+    pub approver_name: Option<String>,
+    pub decision: String,
+    pub comment: Option<String>,
+    pub created_at: String,
+}
+
+/// Decisions on the given commands, oldest first, with times local to `clock`.
+pub async fn list_command_approvals(
+    pool: &MySqlPool,
+    command_ids: &[String],
+    clock: &StoreClock,
+) -> Result<Vec<CommandApproval>, sqlx::Error> {
+    if command_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        r#"
+        SELECT a.command_id, a.approver_user_id, COALESCE(u.display_name, u.email) AS approver_name,
+               a.decision, a.comment, a.created_at
+        FROM approvals a
+        LEFT JOIN cloud_users u ON u.id = a.approver_user_id
+        WHERE a.command_id IN ({})
+        ORDER BY a.created_at
+        "#,
+        vec!["?"; command_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for id in command_ids {
+        query = query.bind(id);
+    }
+    let rows = query.fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| CommandApproval {
+            command_id: row.get("command_id"),
+            approver_user_id: row.get("approver_user_id"),
+            approver_name: row.get("approver_name"),
+            decision: row.get("decision"),
+            comment: row.get("comment"),
+            created_at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("created_at")),
+        })
+        .collect())
+}
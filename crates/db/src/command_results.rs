@@ -0,0 +1,98 @@
+//! Command outcomes: the entity a command acts on (device_command_queue.target_type/target_id)
+//! and the per-command status timeline (device_command_status_log). An ack does not change its
+//! target: a voided or refunded order is projected from the device's order_voided /
+//! order_refunded events, which carry the void and refund details.
+
+use domain::StoreClock;
+use sqlx::{MySqlConnection, MySqlPool, Row};
+use uuid::Uuid;
+
+/// Entity a command acts on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CommandEntity {
+    /// orders.id (void_order, refund_order).
+    Order(Uuid),
+    /// The store whose menu is pushed (apply_menu).
+    Menu(Uuid),
+    /// delivery_orders.id (delivery_order from an integration webhook).
+    DeliveryOrder(Uuid),
+}
+
+impl CommandEntity {
+    pub fn target_type(self) -> &'static str {
+        match self {
+            CommandEntity::Order(_) => "order",
+            CommandEntity::Menu(_) => "menu",
+            CommandEntity::DeliveryOrder(_) => "delivery_order",
+        }
+    }
+
+    pub fn target_id(self) -> Uuid {
+        match self {
+            CommandEntity::Order(id) | CommandEntity::Menu(id) | CommandEntity::DeliveryOrder(id) => id,
+        }
+    }
+}
+
+/// Append a status change to the command's timeline. Call in the same transaction as the status
+/// update.
+pub(crate) async fn log_command_status(
+    conn: &mut MySqlConnection,
+    command_id: Uuid,
+    status: &str,
+    detail: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO device_command_status_log (command_id, status, detail) VALUES (?, ?, ?)",
+    )
+    .bind(command_id.to_string())
+    .bind(status)
+    .bind(detail)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// One entry of a command's status timeline.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct CommandStatusChange {
+    pub command_id: String,
+    pub status: String,
+    /// e.g. "approved", "attempt 2", the expiry reason, or the device's failure message.
+    pub detail: Option<String>,
+    pub at: String,
+}
+
+/// Status timelines for the given commands, oldest change first, with times local to `clock`.
+pub async fn list_command_timelines(
+    pool: &MySqlPool,
+    command_ids: &[String],
+    clock: &StoreClock,
+) -> Result<Vec<CommandStatusChange>, sqlx::Error> {
+    if command_ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        r#"
+        SELECT command_id, status, detail, created_at
+        FROM device_command_status_log
+        WHERE command_id IN ({})
+        ORDER BY command_id, created_at, id
+        "#,
+        vec!["?"; command_ids.len()].join(", ")
+    );
+    let mut query = sqlx::query(&sql);
+    for id in command_ids {
+        query = query.bind(id);
+    }
+    let rows = query.fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| CommandStatusChange {
+            command_id: row.get("command_id"),
+            status: row.get("status"),
+            detail: row.get("detail"),
+            at: clock.format_local_naive(row.get::<chrono::NaiveDateTime, _>("created_at")),
+        })
+        .collect())
+}
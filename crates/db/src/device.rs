@@ -1,6 +1,6 @@
 //! Device activation: lookup activation key, create device, issue token.
 
-use sqlx::MySqlPool;
+use sqlx::{MySqlConnection, MySqlExecutor, MySqlPool};
 use uuid::Uuid;
 
 /// Activation key row (lookup by key_hash). UUID columns decoded as String from MySQL CHAR(36).
@@ -161,7 +161,7 @@ pub async fn create_device(
 
 /// Update device display name and primary flag (from device_updated event or activate).
 pub async fn update_device_name_primary(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     device_name: Option<&str>,
     is_primary: bool,
@@ -176,7 +176,7 @@ pub async fn update_device_name_primary(
         "#,
     )
     .bind(device_id.to_string())
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
 
     if let Some((store_id,)) = row {
@@ -192,7 +192,7 @@ pub async fn update_device_name_primary(
             )
             .bind(&store_id)
             .bind(device_id.to_string())
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
             sqlx::query(
@@ -204,7 +204,7 @@ pub async fn update_device_name_primary(
             )
             .bind(device_name)
             .bind(device_id.to_string())
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
 
             sqlx::query(
@@ -216,7 +216,7 @@ pub async fn update_device_name_primary(
             )
             .bind(device_id.to_string())
             .bind(&store_id)
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         } else {
             // Only update the name; do not change canonical assignment when a
@@ -231,7 +231,7 @@ pub async fn update_device_name_primary(
             )
             .bind(device_name)
             .bind(device_id.to_string())
-            .execute(pool)
+            .execute(&mut *conn)
             .await?;
         }
     } else {
@@ -244,7 +244,7 @@ pub async fn update_device_name_primary(
         .bind(device_name)
         .bind(is_primary)
         .bind(device_id.to_string())
-        .execute(pool)
+        .execute(&mut *conn)
         .await?;
     }
     Ok(())
@@ -316,7 +316,7 @@ pub async fn create_device_token(
 
 /// Canonical device for a store (if explicitly set on the stores table).
 pub async fn get_canonical_device_for_store(
-    pool: &MySqlPool,
+    executor: impl MySqlExecutor<'_>,
     store_id: Uuid,
 ) -> Result<Option<Uuid>, sqlx::Error> {
     let row: Option<(Option<String>,)> = sqlx::query_as(
@@ -327,7 +327,7 @@ pub async fn get_canonical_device_for_store(
         "#,
     )
     .bind(store_id.to_string())
-    .fetch_optional(pool)
+    .fetch_optional(executor)
     .await?;
 
     Ok(row.and_then(|(maybe_id,)| maybe_id.and_then(|s| Uuid::parse_str(&s).ok())))
@@ -337,11 +337,11 @@ pub async fn get_canonical_device_for_store(
 /// canonical device for the store. If no canonical is set, returns false so
 /// callers can decide how strict they want to be.
 pub async fn is_device_canonical_for_store(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     store_id: Uuid,
     device_id: Uuid,
 ) -> Result<bool, sqlx::Error> {
-    let canonical = get_canonical_device_for_store(pool, store_id).await?;
+    let canonical = get_canonical_device_for_store(&mut *conn, store_id).await?;
     Ok(matches!(canonical, Some(id) if id == device_id))
 }
 
@@ -401,3 +401,55 @@ pub async fn create_device_sync_state(
     .await?;
     Ok(())
 }
+
+/// A device is considered online while its last heartbeat is younger than this.
+pub const DEVICE_ONLINE_WINDOW_SECS: i64 = 90;
+
+/// Device opened a sync WebSocket.
+pub async fn mark_device_online(pool: &MySqlPool, device_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE devices
+        SET online_since = COALESCE(online_since, CURRENT_TIMESTAMP(3)), last_seen_at = CURRENT_TIMESTAMP(3)
+        WHERE id = ?
+        "#,
+    )
+    .bind(device_id.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Heartbeat on an open sync WebSocket.
+pub async fn touch_device_seen(pool: &MySqlPool, device_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE devices SET last_seen_at = CURRENT_TIMESTAMP(3) WHERE id = ?")
+        .bind(device_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Last sync WebSocket for the device closed.
+pub async fn mark_device_offline(pool: &MySqlPool, device_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE devices SET online_since = NULL, last_seen_at = CURRENT_TIMESTAMP(3) WHERE id = ?",
+    )
+    .bind(device_id.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Active devices of a store, oldest first.
+pub async fn list_active_device_ids_for_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT id FROM devices WHERE store_id = ? AND status = 'active' ORDER BY created_at",
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().filter_map(|(s,)| Uuid::parse_str(&s).ok()).collect())
+}
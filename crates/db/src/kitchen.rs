@@ -0,0 +1,215 @@
+//! Kitchen display timings: order_sent_to_kitchen, item_started, item_bumped and order_ready
+//! projected into orders.kitchen_sent_at / kitchen_ready_at and order_kitchen_items, and the
+//! queries behind the portal's kitchen report and live tickets. Times are kept order-insensitive
+//! (earliest send and start, latest bump and ready) so devices may sync in any order.
+
+use domain::OrderSentToKitchen;
+use sqlx::{MySqlConnection, MySqlPool};
+use uuid::Uuid;
+
+/// A kitchen event's order line.
+#[derive(Debug, Clone, Copy)]
+pub struct KitchenLine<'a> {
+    pub org_id: Uuid,
+    pub store_id: Uuid,
+    pub order_id: Uuid,
+    pub local_item_id: &'a str,
+    pub station: Option<&'a str>,
+}
+
+/// What happened to a line in the kitchen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KitchenStep {
+    Sent,
+    Started,
+    Bumped,
+}
+
+/// order_sent_to_kitchen: the order's first send, and each line sent (every line of the order
+/// with a local_item_id when the event names none).
+pub async fn record_kitchen_send(
+    conn: &mut MySqlConnection,
+    org_id: Uuid,
+    store_id: Uuid,
+    order_id: Uuid,
+    event: &OrderSentToKitchen,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    mark_order_sent(&mut *conn, order_id, occurred_at).await?;
+    let item_ids: Vec<String> = match &event.item_ids {
+        Some(ids) => ids.iter().filter(|id| !id.trim().is_empty()).cloned().collect(),
+        None => sqlx::query_as::<_, (String,)>(
+            "SELECT local_item_id FROM order_items WHERE order_id = ? AND local_item_id IS NOT NULL",
+        )
+        .bind(order_id.to_string())
+        .fetch_all(&mut *conn)
+        .await?
+        .into_iter()
+        .map(|(id,)| id)
+        .collect(),
+    };
+    for local_item_id in &item_ids {
+        let line = KitchenLine {
+            org_id,
+            store_id,
+            order_id,
+            local_item_id,
+            station: event.station.as_deref(),
+        };
+        record_kitchen_step(&mut *conn, &line, KitchenStep::Sent, occurred_at).await?;
+    }
+    Ok(())
+}
+
+/// A line sent, started or bumped. A started or bumped line counts as sent by then; the latest
+/// bump wins (a recalled line is bumped again).
+pub async fn record_kitchen_step(
+    conn: &mut MySqlConnection,
+    line: &KitchenLine<'_>,
+    step: KitchenStep,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    mark_order_sent(&mut *conn, line.order_id, occurred_at).await?;
+    let started_at = (step == KitchenStep::Started).then_some(occurred_at);
+    let bumped_at = (step == KitchenStep::Bumped).then_some(occurred_at);
+    sqlx::query(
+        r#"
+        INSERT INTO order_kitchen_items (id, org_id, store_id, order_id, local_item_id, station, sent_at, started_at, bumped_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+          station = COALESCE(VALUES(station), station),
+          sent_at = LEAST(COALESCE(sent_at, VALUES(sent_at)), VALUES(sent_at)),
+          started_at = LEAST(COALESCE(started_at, VALUES(started_at)), COALESCE(VALUES(started_at), started_at)),
+          bumped_at = GREATEST(COALESCE(bumped_at, VALUES(bumped_at)), COALESCE(VALUES(bumped_at), bumped_at))
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(line.org_id.to_string())
+    .bind(line.store_id.to_string())
+    .bind(line.order_id.to_string())
+    .bind(line.local_item_id)
+    .bind(line.station)
+    .bind(occurred_at)
+    .bind(started_at)
+    .bind(bumped_at)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// order_ready: the ticket's latest ready time.
+pub async fn record_kitchen_ready(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    mark_order_sent(&mut *conn, order_id, occurred_at).await?;
+    sqlx::query(
+        "UPDATE orders SET kitchen_ready_at = GREATEST(COALESCE(kitchen_ready_at, ?), ?) WHERE id = ?",
+    )
+    .bind(occurred_at)
+    .bind(occurred_at)
+    .bind(order_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn mark_order_sent(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE orders SET kitchen_sent_at = LEAST(COALESCE(kitchen_sent_at, ?), ?) WHERE id = ?")
+        .bind(occurred_at)
+        .bind(occurred_at)
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// An order sent to the kitchen.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct KitchenTicket {
+    pub order_id: String,
+    pub local_order_id: String,
+    pub sent_at: chrono::NaiveDateTime,
+    pub ready_at: Option<chrono::NaiveDateTime>,
+}
+
+/// A line's kitchen times, named from the store's menu (else its product_ref, else its
+/// local_item_id).
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct KitchenItem {
+    pub order_id: String,
+    pub local_item_id: String,
+    pub item: String,
+    pub station: Option<String>,
+    pub quantity: f64,
+    pub sent_at: Option<chrono::NaiveDateTime>,
+    pub started_at: Option<chrono::NaiveDateTime>,
+    pub bumped_at: Option<chrono::NaiveDateTime>,
+}
+
+/// Tickets first sent to the kitchen in [start, end), oldest first; voided orders are left out.
+/// With `open_only`, only tickets not ready yet.
+pub async fn list_kitchen_tickets(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+    open_only: bool,
+) -> Result<Vec<KitchenTicket>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT o.id AS order_id, o.local_order_id, o.kitchen_sent_at AS sent_at, o.kitchen_ready_at AS ready_at
+        FROM orders o
+        WHERE o.store_id = ? AND o.kitchen_sent_at >= ? AND o.kitchen_sent_at < ? AND o.status <> 'voided' {}
+        ORDER BY o.kitchen_sent_at, o.id
+        "#,
+        if open_only { "AND o.kitchen_ready_at IS NULL" } else { "" }
+    );
+    sqlx::query_as(&sql)
+        .bind(store_id.to_string())
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await
+}
+
+/// Kitchen lines of the tickets [`list_kitchen_tickets`] returns for the same arguments.
+pub async fn list_kitchen_items(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+    open_only: bool,
+) -> Result<Vec<KitchenItem>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT
+          ki.order_id,
+          ki.local_item_id,
+          COALESCE(mi.name, oi.product_ref, ki.local_item_id) AS item,
+          ki.station,
+          CAST(COALESCE(oi.quantity, 1) AS DOUBLE) AS quantity,
+          ki.sent_at,
+          ki.started_at,
+          ki.bumped_at
+        FROM order_kitchen_items ki
+        JOIN orders o ON o.id = ki.order_id
+        LEFT JOIN order_items oi ON oi.order_id = ki.order_id AND oi.local_item_id = ki.local_item_id
+        LEFT JOIN store_menu_items mi ON mi.store_id = o.store_id AND mi.local_item_id = oi.product_ref
+        WHERE o.store_id = ? AND o.kitchen_sent_at >= ? AND o.kitchen_sent_at < ? AND o.status <> 'voided' {}
+        ORDER BY ki.sent_at, ki.order_id, ki.local_item_id
+        "#,
+        if open_only { "AND o.kitchen_ready_at IS NULL" } else { "" }
+    );
+    sqlx::query_as(&sql)
+        .bind(store_id.to_string())
+        .bind(start)
+        .bind(end)
+        .fetch_all(pool)
+        .await
+}
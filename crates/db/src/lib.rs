@@ -1,35 +1,57 @@
+mod approvals;
 mod auth;
 mod blog;
+mod command_results;
 mod device;
 mod delivery_integrations;
 mod docs;
+mod kitchen;
+mod menu_schedule;
+mod menu_templates;
+mod menu_versions;
+mod order_search;
 mod orders;
 mod profile;
+mod projection;
 mod read_model;
+mod rebuild;
+mod reports;
 mod sync;
 mod tenancy;
 mod entitlements;
 mod super_admin;
 mod utils;
+mod z_reports;
 
 use sqlx::{migrate::Migrator, MySql, Pool};
 use std::path::Path;
 
 pub type DbPool = Pool<MySql>;
 
+pub use approvals::*;
 pub use auth::*;
 pub use blog::*;
+pub use command_results::*;
 pub use device::*;
 pub use delivery_integrations::*;
 pub use docs::*;
+pub use kitchen::*;
+pub use menu_schedule::*;
+pub use menu_templates::*;
+pub use menu_versions::*;
+pub use order_search::*;
 pub use orders::*;
 pub use profile::*;
+pub use projection::*;
 pub use read_model::*;
+pub use rebuild::*;
+pub use reports::*;
 pub use sync::*;
 pub use tenancy::*;
 pub use entitlements::*;
 pub use super_admin::*;
-pub use utils::{slug_from_title, user_can_access_org, user_can_access_store};
+pub use z_reports::*;
+pub use utils::{list_accessible_store_ids, slug_from_title, user_can_access_org, user_can_access_store};
 
 pub async fn connect(database_url: &str) -> Result<DbPool, sqlx::Error> {
     Pool::<MySql>::connect(database_url).await
@@ -0,0 +1,557 @@
+//! Scheduled menu changes and dayparts. A scheduled change makes a menu version or a set of item
+//! prices live at a store-local time; dayparts are store-local windows outside which their
+//! categories and items are sent inactive. [`run_due_menu_schedules`] (the menu scheduler) applies
+//! due changes and re-sends a store's menu when its open dayparts change. Dayparts shape what is
+//! sent (apply_menu, GET /sync/menu), not the recorded menu versions.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, NaiveDateTime, NaiveTime, Utc, Weekday};
+use domain::{parse_weekday, weekday_str, DaypartWindow, StoreClock};
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::menu_templates::{get_store_menu_template, list_store_menu_overrides, set_store_menu_override};
+use crate::menu_versions::{
+    latest_menu_version, restore_menu_version, MenuPublish, MenuRestoreError, MenuVersionSource,
+};
+use crate::read_model::{get_device_id_for_store, SyncMenuItem};
+use crate::sync::enqueue_apply_menu_for_store;
+use crate::tenancy::get_store_clock;
+
+/// A new price for one item in a scheduled change.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledPrice {
+    pub local_item_id: String,
+    pub price_pence: i64,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ScheduledMenuChange {
+    pub id: String,
+    pub store_id: String,
+    /// Store-local wall-clock time.
+    pub run_at: NaiveDateTime,
+    pub version: Option<i32>,
+    pub prices: Option<sqlx::types::Json<Vec<ScheduledPrice>>>,
+    /// pending, applied, failed or cancelled.
+    pub status: String,
+    pub error: Option<String>,
+    pub applied_at: Option<NaiveDateTime>,
+    pub applied_version: Option<i32>,
+    pub created_by_user_id: String,
+    pub created_at: NaiveDateTime,
+}
+
+/// What a scheduled change does: exactly one of `version` and `prices`.
+#[derive(Debug, Clone, Copy)]
+pub struct NewScheduledMenuChange<'a> {
+    pub run_at: NaiveDateTime,
+    pub version: Option<i32>,
+    pub prices: Option<&'a [ScheduledPrice]>,
+    pub created_by_user_id: &'a str,
+}
+
+const CHANGE_SELECT: &str = r#"
+    SELECT id, store_id, run_at, version, prices, status, error, applied_at, applied_version,
+           created_by_user_id, created_at
+    FROM scheduled_menu_changes
+"#;
+
+pub async fn create_scheduled_menu_change(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    change: &NewScheduledMenuChange<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO scheduled_menu_changes (id, store_id, run_at, version, prices, created_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(store_id.to_string())
+    .bind(change.run_at)
+    .bind(change.version)
+    .bind(change.prices.map(sqlx::types::Json))
+    .bind(change.created_by_user_id)
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+/// A store's scheduled changes, latest run time first.
+pub async fn list_scheduled_menu_changes(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Vec<ScheduledMenuChange>, sqlx::Error> {
+    let sql = format!("{} WHERE store_id = ? ORDER BY run_at DESC, created_at DESC", CHANGE_SELECT);
+    sqlx::query_as(&sql)
+        .bind(store_id.to_string())
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_scheduled_menu_change(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    change_id: Uuid,
+) -> Result<Option<ScheduledMenuChange>, sqlx::Error> {
+    let sql = format!("{} WHERE store_id = ? AND id = ?", CHANGE_SELECT);
+    sqlx::query_as(&sql)
+        .bind(store_id.to_string())
+        .bind(change_id.to_string())
+        .fetch_optional(pool)
+        .await
+}
+
+/// Cancel a pending change. False if it is not pending (or does not exist).
+pub async fn cancel_scheduled_menu_change(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    change_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query(
+        "UPDATE scheduled_menu_changes SET status = 'cancelled' WHERE store_id = ? AND id = ? AND status = 'pending'",
+    )
+    .bind(store_id.to_string())
+    .bind(change_id.to_string())
+    .execute(pool)
+    .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+#[derive(Debug, Clone)]
+pub struct MenuDaypart {
+    pub id: String,
+    pub name: String,
+    pub window: DaypartWindow,
+    pub local_category_ids: Vec<String>,
+    pub local_item_ids: Vec<String>,
+    pub updated_at: NaiveDateTime,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct NewMenuDaypart<'a> {
+    pub name: &'a str,
+    pub start_time: NaiveTime,
+    pub end_time: NaiveTime,
+    /// Empty = every day.
+    pub days: &'a [Weekday],
+    pub local_category_ids: &'a [String],
+    pub local_item_ids: &'a [String],
+}
+
+#[derive(sqlx::FromRow)]
+struct DaypartRow {
+    id: String,
+    name: String,
+    start_time: NaiveTime,
+    end_time: NaiveTime,
+    days: Option<String>,
+    updated_at: NaiveDateTime,
+}
+
+/// A store's dayparts, by start time.
+pub async fn list_menu_dayparts(pool: &MySqlPool, store_id: Uuid) -> Result<Vec<MenuDaypart>, sqlx::Error> {
+    let rows: Vec<DaypartRow> = sqlx::query_as(
+        r#"
+        SELECT id, name, start_time, end_time, days, updated_at
+        FROM menu_dayparts WHERE store_id = ? ORDER BY start_time, name
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?;
+    let entries: Vec<(String, String, String)> = sqlx::query_as(
+        r#"
+        SELECT e.daypart_id, e.entry_type, e.local_id
+        FROM menu_daypart_entries e
+        JOIN menu_dayparts d ON d.id = e.daypart_id
+        WHERE d.store_id = ?
+        ORDER BY e.local_id
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?;
+    let mut dayparts: Vec<MenuDaypart> = rows
+        .into_iter()
+        .map(|row| MenuDaypart {
+            id: row.id,
+            name: row.name,
+            window: DaypartWindow {
+                start: row.start_time,
+                end: row.end_time,
+                days: row
+                    .days
+                    .as_deref()
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter_map(parse_weekday)
+                    .collect(),
+            },
+            local_category_ids: Vec::new(),
+            local_item_ids: Vec::new(),
+            updated_at: row.updated_at,
+        })
+        .collect();
+    for (daypart_id, entry_type, local_id) in entries {
+        let Some(daypart) = dayparts.iter_mut().find(|d| d.id == daypart_id) else {
+            continue;
+        };
+        match entry_type.as_str() {
+            "category" => daypart.local_category_ids.push(local_id),
+            "item" => daypart.local_item_ids.push(local_id),
+            _ => {}
+        }
+    }
+    Ok(dayparts)
+}
+
+pub async fn create_menu_daypart(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    daypart: &NewMenuDaypart<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO menu_dayparts (id, store_id, name, start_time, end_time, days)
+        VALUES (?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(store_id.to_string())
+    .bind(daypart.name)
+    .bind(daypart.start_time)
+    .bind(daypart.end_time)
+    .bind(days_column(daypart.days))
+    .execute(&mut *tx)
+    .await?;
+    insert_daypart_entries(&mut tx, id, daypart).await?;
+    tx.commit().await?;
+    Ok(id)
+}
+
+/// Replace a daypart's window and entries. False if it does not exist.
+pub async fn update_menu_daypart(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    daypart_id: Uuid,
+    daypart: &NewMenuDaypart<'_>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let exists: Option<(String,)> =
+        sqlx::query_as("SELECT id FROM menu_dayparts WHERE id = ? AND store_id = ? FOR UPDATE")
+            .bind(daypart_id.to_string())
+            .bind(store_id.to_string())
+            .fetch_optional(&mut *tx)
+            .await?;
+    if exists.is_none() {
+        return Ok(false);
+    }
+    sqlx::query(
+        r#"
+        UPDATE menu_dayparts
+        SET name = ?, start_time = ?, end_time = ?, days = ?, updated_at = CURRENT_TIMESTAMP(3)
+        WHERE id = ?
+        "#,
+    )
+    .bind(daypart.name)
+    .bind(daypart.start_time)
+    .bind(daypart.end_time)
+    .bind(days_column(daypart.days))
+    .bind(daypart_id.to_string())
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM menu_daypart_entries WHERE daypart_id = ?")
+        .bind(daypart_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    insert_daypart_entries(&mut tx, daypart_id, daypart).await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+pub async fn delete_menu_daypart(pool: &MySqlPool, store_id: Uuid, daypart_id: Uuid) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM menu_dayparts WHERE id = ? AND store_id = ?")
+        .bind(daypart_id.to_string())
+        .bind(store_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+async fn insert_daypart_entries(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    daypart_id: Uuid,
+    daypart: &NewMenuDaypart<'_>,
+) -> Result<(), sqlx::Error> {
+    let entries = daypart
+        .local_category_ids
+        .iter()
+        .map(|id| ("category", id))
+        .chain(daypart.local_item_ids.iter().map(|id| ("item", id)));
+    for (entry_type, local_id) in entries {
+        sqlx::query("INSERT IGNORE INTO menu_daypart_entries (daypart_id, entry_type, local_id) VALUES (?, ?, ?)")
+            .bind(daypart_id.to_string())
+            .bind(entry_type)
+            .bind(local_id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(())
+}
+
+fn days_column(days: &[Weekday]) -> Option<String> {
+    (!days.is_empty()).then(|| days.iter().map(|d| weekday_str(*d)).collect::<Vec<_>>().join(","))
+}
+
+/// Ids of the dayparts open at a store-local time.
+pub fn open_daypart_ids(dayparts: &[MenuDaypart], local: NaiveDateTime) -> Vec<String> {
+    dayparts
+        .iter()
+        .filter(|d| d.window.is_open(local))
+        .map(|d| d.id.clone())
+        .collect()
+}
+
+/// Mark items inactive outside their dayparts. An item assigned to dayparts is available while
+/// one of them is open; an item with none of its own follows its category's dayparts; items with
+/// neither are unaffected.
+pub fn restrict_to_dayparts(items: &mut [SyncMenuItem], dayparts: &[MenuDaypart], local: NaiveDateTime) {
+    let open: HashSet<&str> = dayparts
+        .iter()
+        .filter(|d| d.window.is_open(local))
+        .map(|d| d.id.as_str())
+        .collect();
+    let mut item_dayparts: HashMap<&str, Vec<&str>> = HashMap::new();
+    let mut category_dayparts: HashMap<&str, Vec<&str>> = HashMap::new();
+    for d in dayparts {
+        for id in &d.local_item_ids {
+            item_dayparts.entry(id.as_str()).or_default().push(d.id.as_str());
+        }
+        for id in &d.local_category_ids {
+            category_dayparts.entry(id.as_str()).or_default().push(d.id.as_str());
+        }
+    }
+    for item in items.iter_mut() {
+        let assigned = item_dayparts.get(item.local_item_id.as_str()).or_else(|| {
+            item.local_category_id
+                .as_deref()
+                .and_then(|c| category_dayparts.get(c))
+        });
+        if let Some(assigned) = assigned {
+            if !assigned.iter().any(|id| open.contains(id)) {
+                item.active = false;
+            }
+        }
+    }
+}
+
+/// Apply the store's dayparts at `now` to a menu about to be sent to devices.
+pub async fn apply_menu_dayparts(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    items: &mut [SyncMenuItem],
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let dayparts = list_menu_dayparts(pool, store_id).await?;
+    if dayparts.is_empty() {
+        return Ok(());
+    }
+    let clock = get_store_clock(pool, store_id).await?.unwrap_or_default();
+    restrict_to_dayparts(items, &dayparts, clock.local_time(now));
+    Ok(())
+}
+
+/// Record which of the store's dayparts are open at `now`. True if that differs from when the
+/// menu was last sent for them (the menu should be sent again).
+pub async fn update_daypart_state(pool: &MySqlPool, store_id: Uuid, now: DateTime<Utc>) -> Result<bool, sqlx::Error> {
+    let dayparts = list_menu_dayparts(pool, store_id).await?;
+    let clock = get_store_clock(pool, store_id).await?.unwrap_or_default();
+    let mut open = open_daypart_ids(&dayparts, clock.local_time(now));
+    open.sort();
+    let open = open.join(",");
+    let previous: Option<(String,)> =
+        sqlx::query_as("SELECT open_daypart_ids FROM menu_daypart_state WHERE store_id = ?")
+            .bind(store_id.to_string())
+            .fetch_optional(pool)
+            .await?;
+    if previous.as_ref().is_some_and(|(p,)| *p == open) {
+        return Ok(false);
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO menu_daypart_state (store_id, open_daypart_ids) VALUES (?, ?)
+        ON DUPLICATE KEY UPDATE open_daypart_ids = VALUES(open_daypart_ids)
+        "#,
+    )
+    .bind(store_id.to_string())
+    .bind(&open)
+    .execute(pool)
+    .await?;
+    Ok(true)
+}
+
+/// Result of a menu scheduler pass.
+#[derive(Debug, Default, Clone)]
+pub struct MenuScheduleRun {
+    pub applied: u64,
+    pub failed: u64,
+    /// Stores whose menu was re-sent because their open dayparts changed.
+    pub daypart_switches: u64,
+    /// Devices apply_menu was queued for, to notify once the pass is done.
+    pub device_ids: Vec<Uuid>,
+}
+
+/// Apply every pending scheduled change whose store-local run time has passed (oldest first),
+/// claiming each one (`applying`) before it is applied, then re-send the menu of every store whose open dayparts changed.
+pub async fn run_due_menu_schedules(pool: &MySqlPool) -> Result<MenuScheduleRun, sqlx::Error> {
+    let mut run = MenuScheduleRun::default();
+    let now = Utc::now();
+    let sql = format!("{} WHERE status = 'pending' ORDER BY run_at, created_at", CHANGE_SELECT);
+    let pending: Vec<ScheduledMenuChange> = sqlx::query_as(&sql).fetch_all(pool).await?;
+    let mut clocks: HashMap<String, StoreClock> = HashMap::new();
+    for change in pending {
+        let Ok(store_id) = Uuid::parse_str(&change.store_id) else {
+            continue;
+        };
+        let clock = match clocks.get(&change.store_id) {
+            Some(clock) => *clock,
+            None => {
+                let clock = get_store_clock(pool, store_id).await?.unwrap_or_default();
+                clocks.insert(change.store_id.clone(), clock);
+                clock
+            }
+        };
+        if clock.local_to_utc(change.run_at) > now {
+            continue;
+        }
+        // Claim the change so an overlapping pass (or another instance) does not apply it twice.
+        let claimed = sqlx::query(
+            "UPDATE scheduled_menu_changes SET status = 'applying' WHERE id = ? AND status = 'pending'",
+        )
+        .bind(&change.id)
+        .execute(pool)
+        .await?;
+        if claimed.rows_affected() == 0 {
+            continue;
+        }
+        match apply_scheduled_menu_change(pool, store_id, &change).await {
+            Ok((version, device_ids)) => {
+                run.device_ids.extend(device_ids);
+                sqlx::query(
+                    r#"
+                    UPDATE scheduled_menu_changes
+                    SET status = 'applied', applied_at = CURRENT_TIMESTAMP(3), applied_version = ?
+                    WHERE id = ? AND status = 'applying'
+                    "#,
+                )
+                .bind(version)
+                .bind(&change.id)
+                .execute(pool)
+                .await?;
+                run.applied += 1;
+            }
+            Err(MenuRestoreError::Db(e)) => {
+                // Hand it back so the next pass retries it.
+                let _ = sqlx::query(
+                    "UPDATE scheduled_menu_changes SET status = 'pending' WHERE id = ? AND status = 'applying'",
+                )
+                .bind(&change.id)
+                .execute(pool)
+                .await;
+                return Err(e);
+            }
+            Err(e) => {
+                sqlx::query(
+                    "UPDATE scheduled_menu_changes SET status = 'failed', error = ? WHERE id = ? AND status = 'applying'",
+                )
+                    .bind(e.to_string())
+                    .bind(&change.id)
+                    .execute(pool)
+                    .await?;
+                run.failed += 1;
+            }
+        }
+    }
+
+    let stores: Vec<(String,)> =
+        sqlx::query_as("SELECT store_id FROM menu_dayparts UNION SELECT store_id FROM menu_daypart_state")
+            .fetch_all(pool)
+            .await?;
+    for (store_id,) in stores {
+        let Ok(store_id) = Uuid::parse_str(&store_id) else {
+            continue;
+        };
+        if update_daypart_state(pool, store_id, now).await? {
+            let publish = MenuPublish::new(MenuVersionSource::Daypart, None);
+            let device_ids = enqueue_apply_menu_for_store(pool, store_id, &publish).await?;
+            run.device_ids.extend(device_ids);
+            run.daypart_switches += 1;
+        }
+    }
+    Ok(run)
+}
+
+/// Make a scheduled change live and queue apply_menu. Returns the menu version sent and the
+/// devices it was queued for.
+async fn apply_scheduled_menu_change(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    change: &ScheduledMenuChange,
+) -> Result<(Option<i32>, Vec<Uuid>), MenuRestoreError> {
+    let user_id = change.created_by_user_id.as_str();
+    if let Some(version) = change.version {
+        restore_menu_version(pool, store_id, version, user_id).await?;
+    } else if let Some(prices) = &change.prices {
+        if get_store_menu_template(pool, store_id).await?.is_some() {
+            // On a template: prices become store overrides, keeping any availability override.
+            let overrides = list_store_menu_overrides(pool, store_id).await?;
+            for price in prices.iter() {
+                let active = overrides
+                    .iter()
+                    .find(|o| o.local_item_id == price.local_item_id)
+                    .and_then(|o| o.active);
+                set_store_menu_override(
+                    pool,
+                    store_id,
+                    &price.local_item_id,
+                    Some(price.price_pence),
+                    active,
+                    user_id,
+                )
+                .await?;
+            }
+        } else {
+            let device_id = get_device_id_for_store(pool, store_id)
+                .await?
+                .ok_or(MenuRestoreError::NoDevice)?;
+            for price in prices.iter() {
+                sqlx::query(
+                    r#"
+                    UPDATE pos_menu_items SET price_pence = ?, updated_at = CURRENT_TIMESTAMP(3)
+                    WHERE device_id = ? AND local_item_id = ?
+                    "#,
+                )
+                .bind(price.price_pence)
+                .bind(device_id.to_string())
+                .bind(&price.local_item_id)
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+    let publish = MenuPublish {
+        source: MenuVersionSource::Scheduled,
+        published_by_user_id: Some(user_id),
+        restored_version: change.version,
+    };
+    let device_ids = enqueue_apply_menu_for_store(pool, store_id, &publish).await?;
+    Ok((latest_menu_version(pool, store_id).await?, device_ids))
+}
@@ -0,0 +1,715 @@
+//! Head-office menu templates: an org- or franchise-level master menu, published to selected
+//! stores, with per-store price and availability overrides. A store on a template syncs the menu
+//! it was last published plus its overrides (see [`get_store_template_menu`], used by
+//! get_store_menu_for_sync and so by GET /sync/menu and apply_menu) instead of its canonical
+//! device's menu.
+
+use std::collections::HashMap;
+
+use domain::VatRate;
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::read_model::{get_store_menu_for_sync, SyncMenuCategory, SyncMenuItem};
+
+/// A menu as devices receive it (GET /sync/menu, apply_menu). Stored per store at publish.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PublishedMenu {
+    pub categories: Vec<SyncMenuCategory>,
+    pub items: Vec<SyncMenuItem>,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MenuTemplate {
+    pub id: String,
+    pub org_id: String,
+    /// Set for a franchise template; it may only be published to that franchise's stores.
+    pub franchise_id: Option<String>,
+    pub name: String,
+    pub created_by_user_id: Option<String>,
+    pub created_at: chrono::NaiveDateTime,
+    pub updated_at: chrono::NaiveDateTime,
+    pub category_count: i64,
+    pub item_count: i64,
+    pub store_count: i64,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MenuTemplateCategory {
+    pub id: String,
+    pub local_category_id: String,
+    pub name: String,
+    pub position: i32,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MenuTemplateItem {
+    pub id: String,
+    pub local_item_id: String,
+    /// Template category row id.
+    pub category_id: Option<String>,
+    pub local_category_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub price_pence: Option<i64>,
+    pub vat_rate: Option<String>,
+    pub takeaway_vat_rate: Option<String>,
+    pub active: bool,
+    pub image_path: Option<String>,
+    pub customer_editable: bool,
+}
+
+/// A new template item.
+#[derive(Debug, Clone)]
+pub struct NewMenuTemplateItem<'a> {
+    pub local_category_id: Option<&'a str>,
+    pub name: &'a str,
+    pub description: Option<&'a str>,
+    pub price_pence: Option<i64>,
+    pub vat_rate: Option<VatRate>,
+    pub takeaway_vat_rate: Option<VatRate>,
+    pub active: bool,
+    pub customer_editable: bool,
+}
+
+/// Changes to a template item. Omitted fields are not changed; Some(None) clears the field.
+#[derive(Debug, Clone, Default)]
+pub struct MenuTemplateItemPatch<'a> {
+    pub local_category_id: Option<Option<&'a str>>,
+    pub name: Option<&'a str>,
+    pub description: Option<Option<&'a str>>,
+    pub price_pence: Option<Option<i64>>,
+    pub vat_rate: Option<Option<VatRate>>,
+    pub takeaway_vat_rate: Option<Option<VatRate>>,
+    pub active: Option<bool>,
+    pub customer_editable: Option<bool>,
+}
+
+/// A store on a template and when it was last published.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MenuTemplateStore {
+    pub store_id: String,
+    pub store_name: String,
+    pub published_at: chrono::NaiveDateTime,
+    pub published_by_user_id: Option<String>,
+}
+
+/// The template a store is on and the menu it was last published (before overrides).
+#[derive(Debug, Clone)]
+pub struct StoreMenuTemplate {
+    pub template_id: String,
+    pub template_name: String,
+    pub published_at: chrono::NaiveDateTime,
+    pub menu: PublishedMenu,
+}
+
+/// A store's price and availability over its template menu; None = the template's value.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct StoreMenuOverride {
+    pub local_item_id: String,
+    pub price_pence: Option<i64>,
+    pub active: Option<bool>,
+    pub updated_by_user_id: Option<String>,
+    pub updated_at: chrono::NaiveDateTime,
+}
+
+const TEMPLATE_SELECT: &str = r#"
+    SELECT
+      t.id, t.org_id, t.franchise_id, t.name, t.created_by_user_id, t.created_at, t.updated_at,
+      (SELECT CAST(COUNT(*) AS SIGNED) FROM menu_template_categories c WHERE c.template_id = t.id) AS category_count,
+      (SELECT CAST(COUNT(*) AS SIGNED) FROM menu_template_items i WHERE i.template_id = t.id) AS item_count,
+      (SELECT CAST(COUNT(*) AS SIGNED) FROM menu_template_stores s WHERE s.template_id = t.id) AS store_count
+    FROM menu_templates t
+"#;
+
+pub async fn create_menu_template(
+    pool: &MySqlPool,
+    org_id: Uuid,
+    franchise_id: Option<Uuid>,
+    name: &str,
+    user_id: &str,
+) -> Result<Uuid, sqlx::Error> {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        "INSERT INTO menu_templates (id, org_id, franchise_id, name, created_by_user_id) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(id.to_string())
+    .bind(org_id.to_string())
+    .bind(franchise_id.map(|f| f.to_string()))
+    .bind(name)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(id)
+}
+
+/// An org's templates (org-wide and franchise), by name.
+pub async fn list_menu_templates(pool: &MySqlPool, org_id: Uuid) -> Result<Vec<MenuTemplate>, sqlx::Error> {
+    let sql = format!("{} WHERE t.org_id = ? ORDER BY t.name, t.id", TEMPLATE_SELECT);
+    sqlx::query_as(&sql)
+        .bind(org_id.to_string())
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn get_menu_template(pool: &MySqlPool, template_id: Uuid) -> Result<Option<MenuTemplate>, sqlx::Error> {
+    let sql = format!("{} WHERE t.id = ?", TEMPLATE_SELECT);
+    sqlx::query_as(&sql)
+        .bind(template_id.to_string())
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn rename_menu_template(pool: &MySqlPool, template_id: Uuid, name: &str) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("UPDATE menu_templates SET name = ? WHERE id = ?")
+        .bind(name)
+        .bind(template_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Seed a template with a store's current menu (categories and items keep their local ids, so
+/// devices that already have them update them in place). Existing rows with the same local ids
+/// are overwritten.
+pub async fn copy_store_menu_into_template(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    store_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    let Some((categories, items)) = get_store_menu_for_sync(pool, store_id).await? else {
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    for c in &categories {
+        sqlx::query(
+            r#"
+            INSERT INTO menu_template_categories (id, template_id, local_category_id, name, position)
+            VALUES (?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE name = VALUES(name), position = VALUES(position)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(template_id.to_string())
+        .bind(&c.local_category_id)
+        .bind(&c.name)
+        .bind(c.position)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for i in &items {
+        sqlx::query(
+            r#"
+            INSERT INTO menu_template_items
+              (id, template_id, local_item_id, local_category_id, name, description, price_pence, vat_rate,
+               takeaway_vat_rate, active, image_path, customer_editable)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+              local_category_id = VALUES(local_category_id), name = VALUES(name), description = VALUES(description),
+              price_pence = VALUES(price_pence), vat_rate = VALUES(vat_rate), takeaway_vat_rate = VALUES(takeaway_vat_rate),
+              active = VALUES(active), image_path = VALUES(image_path), customer_editable = VALUES(customer_editable)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(template_id.to_string())
+        .bind(&i.local_item_id)
+        .bind(&i.local_category_id)
+        .bind(&i.name)
+        .bind(&i.description)
+        .bind(i.price_pence)
+        .bind(&i.vat_rate)
+        .bind(&i.takeaway_vat_rate)
+        .bind(i.active)
+        .bind(&i.image_path)
+        .bind(i.customer_editable)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn list_menu_template_categories(
+    pool: &MySqlPool,
+    template_id: Uuid,
+) -> Result<Vec<MenuTemplateCategory>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT id, local_category_id, name, position
+        FROM menu_template_categories WHERE template_id = ? ORDER BY position, name
+        "#,
+    )
+    .bind(template_id.to_string())
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn list_menu_template_items(
+    pool: &MySqlPool,
+    template_id: Uuid,
+) -> Result<Vec<MenuTemplateItem>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT
+          i.id, i.local_item_id, c.id AS category_id, i.local_category_id, i.name, i.description, i.price_pence,
+          i.vat_rate, i.takeaway_vat_rate, i.active, i.image_path, i.customer_editable
+        FROM menu_template_items i
+        LEFT JOIN menu_template_categories c ON c.template_id = i.template_id AND c.local_category_id = i.local_category_id
+        WHERE i.template_id = ?
+        ORDER BY i.name, i.id
+        "#,
+    )
+    .bind(template_id.to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// Local id of a template category (by row id), if it is in the template.
+pub async fn get_menu_template_category_local_id(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    category_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as(
+        "SELECT local_category_id FROM menu_template_categories WHERE id = ? AND template_id = ?",
+    )
+    .bind(category_id.to_string())
+    .bind(template_id.to_string())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(s,)| s))
+}
+
+/// Add a category; returns its row id and local id.
+pub async fn create_menu_template_category(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    name: &str,
+    position: i32,
+) -> Result<(Uuid, String), sqlx::Error> {
+    let id = Uuid::new_v4();
+    let local_category_id = format!("hq-{}", Uuid::new_v4());
+    sqlx::query(
+        "INSERT INTO menu_template_categories (id, template_id, local_category_id, name, position) VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(id.to_string())
+    .bind(template_id.to_string())
+    .bind(&local_category_id)
+    .bind(name)
+    .bind(position)
+    .execute(pool)
+    .await?;
+    Ok((id, local_category_id))
+}
+
+pub async fn update_menu_template_category(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    category_id: Uuid,
+    name: Option<&str>,
+    position: Option<i32>,
+) -> Result<bool, sqlx::Error> {
+    let mut sets = vec!["updated_at = CURRENT_TIMESTAMP(3)"];
+    if name.is_some() {
+        sets.push("name = ?");
+    }
+    if position.is_some() {
+        sets.push("position = ?");
+    }
+    let q = format!(
+        "UPDATE menu_template_categories SET {} WHERE id = ? AND template_id = ?",
+        sets.join(", ")
+    );
+    let mut query = sqlx::query(&q);
+    if let Some(n) = name {
+        query = query.bind(n);
+    }
+    if let Some(p) = position {
+        query = query.bind(p);
+    }
+    let res = query
+        .bind(category_id.to_string())
+        .bind(template_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Remove a category; its items stay in the template without a category.
+pub async fn delete_menu_template_category(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    category_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let Some(local_category_id) = get_menu_template_category_local_id(pool, template_id, category_id).await? else {
+        return Ok(false);
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query("UPDATE menu_template_items SET local_category_id = NULL WHERE template_id = ? AND local_category_id = ?")
+        .bind(template_id.to_string())
+        .bind(&local_category_id)
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM menu_template_categories WHERE id = ?")
+        .bind(category_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Add an item; returns its row id and local id.
+pub async fn create_menu_template_item(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    item: &NewMenuTemplateItem<'_>,
+) -> Result<(Uuid, String), sqlx::Error> {
+    let id = Uuid::new_v4();
+    let local_item_id = format!("hq-{}", Uuid::new_v4());
+    sqlx::query(
+        r#"
+        INSERT INTO menu_template_items
+          (id, template_id, local_item_id, local_category_id, name, description, price_pence, vat_rate,
+           takeaway_vat_rate, active, customer_editable)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(template_id.to_string())
+    .bind(&local_item_id)
+    .bind(item.local_category_id)
+    .bind(item.name)
+    .bind(item.description)
+    .bind(item.price_pence)
+    .bind(item.vat_rate.map(|r| r.as_str()))
+    .bind(item.takeaway_vat_rate.map(|r| r.as_str()))
+    .bind(item.active)
+    .bind(item.customer_editable)
+    .execute(pool)
+    .await?;
+    Ok((id, local_item_id))
+}
+
+pub async fn update_menu_template_item(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    item_id: Uuid,
+    patch: &MenuTemplateItemPatch<'_>,
+) -> Result<bool, sqlx::Error> {
+    let mut sets = vec!["updated_at = CURRENT_TIMESTAMP(3)"];
+    if patch.local_category_id.is_some() {
+        sets.push("local_category_id = ?");
+    }
+    if patch.name.is_some() {
+        sets.push("name = ?");
+    }
+    if patch.description.is_some() {
+        sets.push("description = ?");
+    }
+    if patch.price_pence.is_some() {
+        sets.push("price_pence = ?");
+    }
+    if patch.vat_rate.is_some() {
+        sets.push("vat_rate = ?");
+    }
+    if patch.takeaway_vat_rate.is_some() {
+        sets.push("takeaway_vat_rate = ?");
+    }
+    if patch.active.is_some() {
+        sets.push("active = ?");
+    }
+    if patch.customer_editable.is_some() {
+        sets.push("customer_editable = ?");
+    }
+    let q = format!(
+        "UPDATE menu_template_items SET {} WHERE id = ? AND template_id = ?",
+        sets.join(", ")
+    );
+    let mut query = sqlx::query(&q);
+    if let Some(c) = patch.local_category_id {
+        query = query.bind(c);
+    }
+    if let Some(n) = patch.name {
+        query = query.bind(n);
+    }
+    if let Some(d) = patch.description {
+        query = query.bind(d);
+    }
+    if let Some(p) = patch.price_pence {
+        query = query.bind(p);
+    }
+    if let Some(rate) = patch.vat_rate {
+        query = query.bind(rate.map(|r| r.as_str()));
+    }
+    if let Some(rate) = patch.takeaway_vat_rate {
+        query = query.bind(rate.map(|r| r.as_str()));
+    }
+    if let Some(a) = patch.active {
+        query = query.bind(a);
+    }
+    if let Some(e) = patch.customer_editable {
+        query = query.bind(e);
+    }
+    let res = query
+        .bind(item_id.to_string())
+        .bind(template_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn delete_menu_template_item(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    item_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM menu_template_items WHERE id = ? AND template_id = ?")
+        .bind(item_id.to_string())
+        .bind(template_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// The template's current content as devices would receive it (items without a local store id).
+pub async fn build_menu_template(pool: &MySqlPool, template_id: Uuid) -> Result<PublishedMenu, sqlx::Error> {
+    let categories = list_menu_template_categories(pool, template_id)
+        .await?
+        .into_iter()
+        .map(|c| SyncMenuCategory {
+            local_category_id: c.local_category_id,
+            local_menu_id: "default".to_string(),
+            name: c.name,
+            position: c.position,
+            image_path: None,
+        })
+        .collect();
+    let items = list_menu_template_items(pool, template_id)
+        .await?
+        .into_iter()
+        .map(|i| SyncMenuItem {
+            local_item_id: i.local_item_id,
+            local_store_id: None,
+            local_category_id: i.local_category_id,
+            name: i.name,
+            description: i.description,
+            price_pence: i.price_pence,
+            vat_rate: i.vat_rate,
+            takeaway_vat_rate: i.takeaway_vat_rate,
+            active: i.active,
+            image_path: i.image_path,
+            customer_editable: i.customer_editable,
+        })
+        .collect();
+    Ok(PublishedMenu { categories, items })
+}
+
+/// Publish the template's current content to these stores (moving them onto this template if
+/// they were on another). Stores on the template that are not listed keep what they were last
+/// published. The caller enqueues apply_menu for the stores.
+pub async fn publish_menu_template(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    store_ids: &[Uuid],
+    user_id: &str,
+) -> Result<(), sqlx::Error> {
+    let menu = build_menu_template(pool, template_id).await?;
+    let body = serde_json::to_value(&menu).map_err(|e| sqlx::Error::Protocol(e.to_string()))?;
+    let mut tx = pool.begin().await?;
+    for store_id in store_ids {
+        sqlx::query(
+            r#"
+            INSERT INTO menu_template_stores (store_id, template_id, menu_body, published_at, published_by_user_id)
+            VALUES (?, ?, ?, CURRENT_TIMESTAMP(3), ?)
+            ON DUPLICATE KEY UPDATE
+              template_id = VALUES(template_id), menu_body = VALUES(menu_body),
+              published_at = VALUES(published_at), published_by_user_id = VALUES(published_by_user_id)
+            "#,
+        )
+        .bind(store_id.to_string())
+        .bind(template_id.to_string())
+        .bind(&body)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Take a store off a template; it goes back to its canonical device's menu. Its overrides are
+/// kept for when it is published a template again.
+pub async fn remove_store_from_menu_template(
+    pool: &MySqlPool,
+    template_id: Uuid,
+    store_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM menu_template_stores WHERE store_id = ? AND template_id = ?")
+        .bind(store_id.to_string())
+        .bind(template_id.to_string())
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+pub async fn list_menu_template_stores(
+    pool: &MySqlPool,
+    template_id: Uuid,
+) -> Result<Vec<MenuTemplateStore>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT ts.store_id, s.name AS store_name, ts.published_at, ts.published_by_user_id
+        FROM menu_template_stores ts
+        JOIN stores s ON s.id = ts.store_id
+        WHERE ts.template_id = ?
+        ORDER BY s.name, s.id
+        "#,
+    )
+    .bind(template_id.to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// The template a store is on, if any, with the menu it was last published.
+pub async fn get_store_menu_template(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Option<StoreMenuTemplate>, sqlx::Error> {
+    let row: Option<(String, String, chrono::NaiveDateTime, serde_json::Value)> = sqlx::query_as(
+        r#"
+        SELECT ts.template_id, t.name, ts.published_at, ts.menu_body
+        FROM menu_template_stores ts
+        JOIN menu_templates t ON t.id = ts.template_id
+        WHERE ts.store_id = ?
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(template_id, template_name, published_at, body)| StoreMenuTemplate {
+        template_id,
+        template_name,
+        published_at,
+        menu: serde_json::from_value(body).unwrap_or_default(),
+    }))
+}
+
+pub async fn list_store_menu_overrides(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Vec<StoreMenuOverride>, sqlx::Error> {
+    sqlx::query_as(
+        r#"
+        SELECT local_item_id, price_pence, active, updated_by_user_id, updated_at
+        FROM store_menu_overrides WHERE store_id = ? ORDER BY local_item_id
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await
+}
+
+/// Set a store's price and availability for an item; with both None the override is removed.
+pub async fn set_store_menu_override(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    local_item_id: &str,
+    price_pence: Option<i64>,
+    active: Option<bool>,
+    user_id: &str,
+) -> Result<(), sqlx::Error> {
+    if price_pence.is_none() && active.is_none() {
+        delete_store_menu_override(pool, store_id, local_item_id).await?;
+        return Ok(());
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO store_menu_overrides (id, store_id, local_item_id, price_pence, active, updated_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+          price_pence = VALUES(price_pence), active = VALUES(active), updated_by_user_id = VALUES(updated_by_user_id)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(store_id.to_string())
+    .bind(local_item_id)
+    .bind(price_pence)
+    .bind(active)
+    .bind(user_id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn delete_store_menu_override(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    local_item_id: &str,
+) -> Result<bool, sqlx::Error> {
+    let res = sqlx::query("DELETE FROM store_menu_overrides WHERE store_id = ? AND local_item_id = ?")
+        .bind(store_id.to_string())
+        .bind(local_item_id)
+        .execute(pool)
+        .await?;
+    Ok(res.rows_affected() > 0)
+}
+
+/// Layer a store's overrides on a template menu and give items the store's local store id.
+/// Overrides for items not on the menu are ignored.
+pub fn apply_store_menu_overrides(
+    menu: &mut PublishedMenu,
+    overrides: &[StoreMenuOverride],
+    local_store_id: &str,
+) {
+    let by_item: HashMap<&str, &StoreMenuOverride> =
+        overrides.iter().map(|o| (o.local_item_id.as_str(), o)).collect();
+    for item in &mut menu.items {
+        if item.local_store_id.is_none() {
+            item.local_store_id = Some(local_store_id.to_string());
+        }
+        if let Some(o) = by_item.get(item.local_item_id.as_str()) {
+            if let Some(price) = o.price_pence {
+                item.price_pence = Some(price);
+            }
+            if let Some(active) = o.active {
+                item.active = active;
+            }
+        }
+    }
+}
+
+/// POS local store id devices know the store by (from the canonical device, else any synced
+/// device), or the cloud store id.
+pub async fn get_store_local_id(pool: &MySqlPool, store_id: Uuid) -> Result<String, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as(
+        r#"
+        SELECT p.local_store_id
+        FROM pos_store_sync p
+        JOIN stores s ON s.id = p.store_id
+        WHERE p.store_id = ?
+        ORDER BY p.device_id = s.canonical_device_id DESC, p.updated_at DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(s,)| s).unwrap_or_else(|| store_id.to_string()))
+}
+
+/// The effective menu of a store on a template: what it was last published plus its overrides.
+/// None if the store is not on a template.
+pub async fn get_store_template_menu(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Option<PublishedMenu>, sqlx::Error> {
+    let Some(template) = get_store_menu_template(pool, store_id).await? else {
+        return Ok(None);
+    };
+    let mut menu = template.menu;
+    let overrides = list_store_menu_overrides(pool, store_id).await?;
+    let local_store_id = get_store_local_id(pool, store_id).await?;
+    apply_store_menu_overrides(&mut menu, &overrides, &local_store_id);
+    Ok(Some(menu))
+}
@@ -0,0 +1,515 @@
+//! Menu versions: each menu published to a store's devices is kept as an immutable numbered
+//! version (menu_publishes) with its author and reason, so versions can be listed, diffed and
+//! rolled back. A rollback restores the version's source (the canonical device's menu rows, or
+//! the store's head-office template menu) and publishes it again as a new version.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+
+use serde::Serialize;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use crate::menu_templates::{get_store_menu_template, PublishedMenu};
+use crate::read_model::{
+    get_device_id_for_store, get_store_menu_for_sync, SyncMenuCategory, SyncMenuItem,
+};
+
+/// Why a menu was published.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MenuVersionSource {
+    /// A store menu edit in the portal.
+    PortalEdit,
+    /// A head-office template published to the store.
+    TemplatePublish,
+    /// The store was taken off its template.
+    TemplateRemoved,
+    /// A store price or availability override changed.
+    StoreOverride,
+    Rollback,
+    /// apply_menu sent from the command center.
+    Command,
+    /// A scheduled menu change went live.
+    Scheduled,
+    /// The store's open dayparts changed.
+    Daypart,
+}
+
+impl MenuVersionSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MenuVersionSource::PortalEdit => "portal_edit",
+            MenuVersionSource::TemplatePublish => "template_publish",
+            MenuVersionSource::TemplateRemoved => "template_removed",
+            MenuVersionSource::StoreOverride => "store_override",
+            MenuVersionSource::Rollback => "rollback",
+            MenuVersionSource::Command => "command",
+            MenuVersionSource::Scheduled => "scheduled",
+            MenuVersionSource::Daypart => "daypart",
+        }
+    }
+}
+
+/// Who published a store's menu and why, recorded with the version.
+#[derive(Debug, Clone, Copy)]
+pub struct MenuPublish<'a> {
+    pub source: MenuVersionSource,
+    pub published_by_user_id: Option<&'a str>,
+    /// For a rollback (or a scheduled version), the version restored.
+    pub restored_version: Option<i32>,
+}
+
+impl<'a> MenuPublish<'a> {
+    pub fn new(source: MenuVersionSource, published_by_user_id: Option<&'a str>) -> Self {
+        Self {
+            source,
+            published_by_user_id,
+            restored_version: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct MenuVersion {
+    pub version: i32,
+    pub source: String,
+    pub restored_version: Option<i32>,
+    pub template_id: Option<String>,
+    pub published_by_user_id: Option<String>,
+    /// Display name, else email, of the author.
+    pub published_by: Option<String>,
+    pub published_at: chrono::NaiveDateTime,
+    pub category_count: i64,
+    pub item_count: i64,
+}
+
+/// A version with the menu that was sent.
+#[derive(Debug, Clone)]
+pub struct MenuVersionDetail {
+    pub version: MenuVersion,
+    pub menu: PublishedMenu,
+    /// Template menu before the store's overrides, when the store was on a template.
+    pub template_menu: Option<PublishedMenu>,
+}
+
+const VERSION_SELECT: &str = r#"
+    SELECT
+      p.version, p.source, p.restored_version, p.template_id, p.published_by_user_id,
+      COALESCE(u.display_name, u.email) AS published_by, p.published_at,
+      CAST(COALESCE(JSON_LENGTH(p.menu_body, '$.categories'), 0) AS SIGNED) AS category_count,
+      CAST(COALESCE(JSON_LENGTH(p.menu_body, '$.items'), 0) AS SIGNED) AS item_count
+    FROM menu_publishes p
+    LEFT JOIN cloud_users u ON u.id = p.published_by_user_id
+"#;
+
+/// Record the store's current menu as a new version and return it; if it is the same as the
+/// latest version (and no version is being restored), that version is returned instead. None if
+/// the store has no menu yet. The store row is locked while the next version number is taken.
+pub async fn record_menu_version(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    publish: &MenuPublish<'_>,
+) -> Result<Option<(i32, PublishedMenu)>, sqlx::Error> {
+    let Some((categories, items)) = get_store_menu_for_sync(pool, store_id).await? else {
+        return Ok(None);
+    };
+    let menu = PublishedMenu { categories, items };
+    let template = get_store_menu_template(pool, store_id).await?;
+    let body = to_json(&menu)?;
+    let template_body = template.as_ref().map(|t| to_json(&t.menu)).transpose()?;
+    let template_id = template.as_ref().map(|t| t.template_id.clone());
+
+    // Concurrent publishes (a portal edit and the scheduler) take the next number in turn.
+    let mut tx = pool.begin().await?;
+    sqlx::query("SELECT id FROM stores WHERE id = ? FOR UPDATE")
+        .bind(store_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    let latest: Option<(i32, serde_json::Value, Option<String>, Option<serde_json::Value>)> = sqlx::query_as(
+        r#"
+        SELECT version, menu_body, template_id, template_body
+        FROM menu_publishes WHERE store_id = ? ORDER BY version DESC LIMIT 1
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+    if let Some((version, latest_body, latest_template_id, latest_template_body)) = &latest {
+        let unchanged = *latest_body == body
+            && *latest_template_id == template_id
+            && *latest_template_body == template_body;
+        if unchanged && publish.restored_version.is_none() {
+            return Ok(Some((*version, menu)));
+        }
+    }
+
+    let version = latest.map(|(v, ..)| v).unwrap_or(0) + 1;
+    sqlx::query(
+        r#"
+        INSERT INTO menu_publishes
+          (id, store_id, version, menu_body, template_id, template_body, source, restored_version, published_by_user_id)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(store_id.to_string())
+    .bind(version)
+    .bind(&body)
+    .bind(&template_id)
+    .bind(&template_body)
+    .bind(publish.source.as_str())
+    .bind(publish.restored_version)
+    .bind(publish.published_by_user_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(Some((version, menu)))
+}
+
+/// A store's versions, newest first.
+pub async fn list_menu_versions(pool: &MySqlPool, store_id: Uuid) -> Result<Vec<MenuVersion>, sqlx::Error> {
+    let sql = format!("{} WHERE p.store_id = ? ORDER BY p.version DESC", VERSION_SELECT);
+    sqlx::query_as(&sql)
+        .bind(store_id.to_string())
+        .fetch_all(pool)
+        .await
+}
+
+/// The store's latest version number, if any.
+pub async fn latest_menu_version(pool: &MySqlPool, store_id: Uuid) -> Result<Option<i32>, sqlx::Error> {
+    let (version,): (Option<i32>,) = sqlx::query_as("SELECT MAX(version) FROM menu_publishes WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .fetch_one(pool)
+        .await?;
+    Ok(version)
+}
+
+pub async fn get_menu_version(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    version: i32,
+) -> Result<Option<MenuVersionDetail>, sqlx::Error> {
+    let sql = format!("{} WHERE p.store_id = ? AND p.version = ?", VERSION_SELECT);
+    let Some(info) = sqlx::query_as::<_, MenuVersion>(&sql)
+        .bind(store_id.to_string())
+        .bind(version)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let (body, template_body): (serde_json::Value, Option<serde_json::Value>) = sqlx::query_as(
+        "SELECT menu_body, template_body FROM menu_publishes WHERE store_id = ? AND version = ?",
+    )
+    .bind(store_id.to_string())
+    .bind(version)
+    .fetch_one(pool)
+    .await?;
+    Ok(Some(MenuVersionDetail {
+        version: info,
+        menu: serde_json::from_value(body).unwrap_or_default(),
+        template_menu: template_body.and_then(|b| serde_json::from_value(b).ok()),
+    }))
+}
+
+/// One field that differs between two versions of an item or category.
+#[derive(Debug, Clone, Serialize)]
+pub struct MenuFieldChange {
+    pub field: String,
+    pub from: serde_json::Value,
+    pub to: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MenuItemChange {
+    pub local_item_id: String,
+    /// Name in the newer version.
+    pub name: String,
+    /// Set when the price changed.
+    pub price_from_pence: Option<i64>,
+    pub price_to_pence: Option<i64>,
+    pub changes: Vec<MenuFieldChange>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MenuCategoryChange {
+    pub local_category_id: String,
+    pub name: String,
+    pub changes: Vec<MenuFieldChange>,
+}
+
+/// What changed from one menu to another, matched by local ids.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct MenuDiff {
+    pub items_added: Vec<SyncMenuItem>,
+    pub items_removed: Vec<SyncMenuItem>,
+    pub items_changed: Vec<MenuItemChange>,
+    pub categories_added: Vec<SyncMenuCategory>,
+    pub categories_removed: Vec<SyncMenuCategory>,
+    pub categories_changed: Vec<MenuCategoryChange>,
+}
+
+pub fn diff_menus(from: &PublishedMenu, to: &PublishedMenu) -> MenuDiff {
+    let mut diff = MenuDiff::default();
+
+    let old_items: BTreeMap<&str, &SyncMenuItem> =
+        from.items.iter().map(|i| (i.local_item_id.as_str(), i)).collect();
+    let new_items: BTreeMap<&str, &SyncMenuItem> =
+        to.items.iter().map(|i| (i.local_item_id.as_str(), i)).collect();
+    for (id, new) in &new_items {
+        match old_items.get(id) {
+            None => diff.items_added.push((*new).clone()),
+            Some(old) => {
+                let changes = field_changes(old, new);
+                if !changes.is_empty() {
+                    let price_changed = old.price_pence != new.price_pence;
+                    diff.items_changed.push(MenuItemChange {
+                        local_item_id: new.local_item_id.clone(),
+                        name: new.name.clone(),
+                        price_from_pence: old.price_pence.filter(|_| price_changed),
+                        price_to_pence: new.price_pence.filter(|_| price_changed),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+    for (id, old) in &old_items {
+        if !new_items.contains_key(id) {
+            diff.items_removed.push((*old).clone());
+        }
+    }
+
+    let old_categories: BTreeMap<&str, &SyncMenuCategory> =
+        from.categories.iter().map(|c| (c.local_category_id.as_str(), c)).collect();
+    let new_categories: BTreeMap<&str, &SyncMenuCategory> =
+        to.categories.iter().map(|c| (c.local_category_id.as_str(), c)).collect();
+    for (id, new) in &new_categories {
+        match old_categories.get(id) {
+            None => diff.categories_added.push((*new).clone()),
+            Some(old) => {
+                let changes = field_changes(old, new);
+                if !changes.is_empty() {
+                    diff.categories_changed.push(MenuCategoryChange {
+                        local_category_id: new.local_category_id.clone(),
+                        name: new.name.clone(),
+                        changes,
+                    });
+                }
+            }
+        }
+    }
+    for (id, old) in &old_categories {
+        if !new_categories.contains_key(id) {
+            diff.categories_removed.push((*old).clone());
+        }
+    }
+    diff
+}
+
+/// Fields whose serialized values differ, in field order.
+fn field_changes<T: Serialize>(old: &T, new: &T) -> Vec<MenuFieldChange> {
+    let (Ok(serde_json::Value::Object(old)), Ok(serde_json::Value::Object(new))) =
+        (serde_json::to_value(old), serde_json::to_value(new))
+    else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter_map(|(field, to)| {
+            let from = old.get(field).cloned().unwrap_or(serde_json::Value::Null);
+            (from != *to).then(|| MenuFieldChange {
+                field: field.clone(),
+                from,
+                to: to.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Why a version could not be restored.
+#[derive(Debug)]
+pub enum MenuRestoreError {
+    NotFound,
+    /// The version came from a head-office template that has since been deleted.
+    TemplateGone,
+    /// The version is a device menu and the store has no device to hold it.
+    NoDevice,
+    Db(sqlx::Error),
+}
+
+impl fmt::Display for MenuRestoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MenuRestoreError::NotFound => write!(f, "menu version not found"),
+            MenuRestoreError::TemplateGone => {
+                write!(f, "the menu template this version came from has been deleted")
+            }
+            MenuRestoreError::NoDevice => write!(f, "no device linked to this store"),
+            MenuRestoreError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for MenuRestoreError {
+    fn from(e: sqlx::Error) -> Self {
+        MenuRestoreError::Db(e)
+    }
+}
+
+/// Make a version the store's menu again: a template version puts the store back on that template
+/// with the template menu it had (the store's current overrides apply on top); a device version
+/// takes the store off any template and writes the menu back to its canonical device's rows
+/// (categories and items not in the version are removed). The caller then publishes it
+/// (enqueue_apply_menu_for_store with the restored version set).
+pub async fn restore_menu_version(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    version: i32,
+    user_id: &str,
+) -> Result<(), MenuRestoreError> {
+    let detail = get_menu_version(pool, store_id, version)
+        .await?
+        .ok_or(MenuRestoreError::NotFound)?;
+
+    if let Some(template_menu) = &detail.template_menu {
+        let template_id = detail.version.template_id.as_deref().ok_or(MenuRestoreError::TemplateGone)?;
+        sqlx::query(
+            r#"
+            INSERT INTO menu_template_stores (store_id, template_id, menu_body, published_at, published_by_user_id)
+            VALUES (?, ?, ?, CURRENT_TIMESTAMP(3), ?)
+            ON DUPLICATE KEY UPDATE
+              template_id = VALUES(template_id), menu_body = VALUES(menu_body),
+              published_at = VALUES(published_at), published_by_user_id = VALUES(published_by_user_id)
+            "#,
+        )
+        .bind(store_id.to_string())
+        .bind(template_id)
+        .bind(to_json(template_menu)?)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+        return Ok(());
+    }
+
+    let device_id = get_device_id_for_store(pool, store_id)
+        .await?
+        .ok_or(MenuRestoreError::NoDevice)?;
+    let (org_id,): (String,) = sqlx::query_as("SELECT org_id FROM stores WHERE id = ?")
+        .bind(store_id.to_string())
+        .fetch_one(pool)
+        .await?;
+    let menu = &detail.menu;
+
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM menu_template_stores WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+    let menu_ids: HashSet<&str> = menu.categories.iter().map(|c| c.local_menu_id.as_str()).collect();
+    for local_menu_id in menu_ids {
+        sqlx::query("INSERT IGNORE INTO pos_menus (org_id, device_id, local_menu_id) VALUES (?, ?, ?)")
+            .bind(&org_id)
+            .bind(device_id.to_string())
+            .bind(local_menu_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    for c in &menu.categories {
+        sqlx::query(
+            r#"
+            INSERT INTO pos_menu_categories (id, org_id, device_id, local_menu_id, local_category_id, name, position, image_path)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+              local_menu_id = VALUES(local_menu_id), name = VALUES(name), position = VALUES(position),
+              image_path = VALUES(image_path)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&org_id)
+        .bind(device_id.to_string())
+        .bind(&c.local_menu_id)
+        .bind(&c.local_category_id)
+        .bind(&c.name)
+        .bind(c.position)
+        .bind(&c.image_path)
+        .execute(&mut *tx)
+        .await?;
+    }
+    for i in &menu.items {
+        sqlx::query(
+            r#"
+            INSERT INTO pos_menu_items
+              (id, org_id, device_id, local_item_id, local_store_id, local_category_id, name, description, price_pence,
+               vat_rate, takeaway_vat_rate, active, image_path, customer_editable)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            ON DUPLICATE KEY UPDATE
+              local_store_id = VALUES(local_store_id), local_category_id = VALUES(local_category_id),
+              name = VALUES(name), description = VALUES(description), price_pence = VALUES(price_pence),
+              vat_rate = VALUES(vat_rate), takeaway_vat_rate = VALUES(takeaway_vat_rate), active = VALUES(active),
+              image_path = VALUES(image_path), customer_editable = VALUES(customer_editable)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(&org_id)
+        .bind(device_id.to_string())
+        .bind(&i.local_item_id)
+        .bind(&i.local_store_id)
+        .bind(&i.local_category_id)
+        .bind(&i.name)
+        .bind(&i.description)
+        .bind(i.price_pence)
+        .bind(&i.vat_rate)
+        .bind(&i.takeaway_vat_rate)
+        .bind(i.active)
+        .bind(&i.image_path)
+        .bind(i.customer_editable)
+        .execute(&mut *tx)
+        .await?;
+    }
+    delete_missing(
+        &mut tx,
+        "pos_menu_categories",
+        "local_category_id",
+        device_id,
+        menu.categories.iter().map(|c| c.local_category_id.as_str()).collect(),
+    )
+    .await?;
+    delete_missing(
+        &mut tx,
+        "pos_menu_items",
+        "local_item_id",
+        device_id,
+        menu.items.iter().map(|i| i.local_item_id.as_str()).collect(),
+    )
+    .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Delete the device's rows in `table` whose `id_column` is not in `keep`.
+async fn delete_missing(
+    tx: &mut sqlx::Transaction<'_, sqlx::MySql>,
+    table: &str,
+    id_column: &str,
+    device_id: Uuid,
+    keep: Vec<&str>,
+) -> Result<(), sqlx::Error> {
+    let mut sql = format!("DELETE FROM {} WHERE device_id = ?", table);
+    if !keep.is_empty() {
+        sql.push_str(&format!(
+            " AND {} NOT IN ({})",
+            id_column,
+            vec!["?"; keep.len()].join(", ")
+        ));
+    }
+    let mut query = sqlx::query(&sql).bind(device_id.to_string());
+    for id in keep {
+        query = query.bind(id);
+    }
+    query.execute(&mut **tx).await?;
+    Ok(())
+}
+
+fn to_json(menu: &PublishedMenu) -> Result<serde_json::Value, sqlx::Error> {
+    serde_json::to_value(menu).map_err(|e| sqlx::Error::Protocol(e.to_string()))
+}
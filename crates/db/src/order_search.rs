@@ -0,0 +1,321 @@
+//! Order search over the orders read model: filters on time, amount, status, payment kind,
+//! device, delivery provider, product and reference, sorted by time or amount, with keyset
+//! pagination. Stores come in windows (stores sharing a clock and the UTC range of the requested
+//! business days for that clock), as for reports.
+
+use sqlx::mysql::{MySql, MySqlArguments};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+/// Stores to search and, when a date range was asked for, the UTC range [start, end) for them.
+#[derive(Debug, Clone)]
+pub struct OrderSearchWindow {
+    pub store_ids: Vec<String>,
+    pub start: Option<chrono::NaiveDateTime>,
+    pub end: Option<chrono::NaiveDateTime>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OrderSort {
+    #[default]
+    NewestFirst,
+    OldestFirst,
+    TotalDesc,
+    TotalAsc,
+}
+
+impl OrderSort {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "newest" => Some(Self::NewestFirst),
+            "oldest" => Some(Self::OldestFirst),
+            "total_desc" => Some(Self::TotalDesc),
+            "total_asc" => Some(Self::TotalAsc),
+            _ => None,
+        }
+    }
+}
+
+/// The last row of a page; the next page starts after it. The order id breaks ties.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OrderSearchCursor {
+    pub occurred_at: chrono::NaiveDateTime,
+    pub total_cents: i64,
+    pub id: String,
+}
+
+impl OrderSearchCursor {
+    /// Opaque to clients: "<occurred_at micros>_<total_cents>_<order id>".
+    pub fn encode(&self) -> String {
+        format!(
+            "{}_{}_{}",
+            self.occurred_at.and_utc().timestamp_micros(),
+            self.total_cents,
+            self.id
+        )
+    }
+
+    pub fn decode(s: &str) -> Option<Self> {
+        let mut parts = s.splitn(3, '_');
+        let micros: i64 = parts.next()?.parse().ok()?;
+        let total_cents: i64 = parts.next()?.parse().ok()?;
+        let id = Uuid::parse_str(parts.next()?).ok()?;
+        Some(Self {
+            occurred_at: chrono::DateTime::from_timestamp_micros(micros)?.naive_utc(),
+            total_cents,
+            id: id.to_string(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct OrderSearch {
+    pub windows: Vec<OrderSearchWindow>,
+    /// Bounds on the order total, inclusive. Orders without a total count as 0.
+    pub min_total_cents: Option<i64>,
+    pub max_total_cents: Option<i64>,
+    /// Any of these statuses; all statuses when empty.
+    pub statuses: Vec<String>,
+    /// Orders with at least one transaction of this kind (e.g. "card", "cash").
+    pub payment_kind: Option<String>,
+    /// Orders this device sent events for (owned or merged; see order_device_refs).
+    pub device_id: Option<Uuid>,
+    pub delivery_provider: Option<String>,
+    /// Substring of a line's item name on the store's menu, or of its product_ref.
+    pub product: Option<String>,
+    /// Exact cloud order id, global order id, any device's local order id, receipt id or delivery
+    /// platform order id.
+    pub reference: Option<String>,
+    pub sort: OrderSort,
+    pub after: Option<OrderSearchCursor>,
+    pub limit: u32,
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct OrderSearchRow {
+    pub id: String,
+    pub store_id: String,
+    pub store_name: String,
+    pub device_id: String,
+    pub device_label: Option<String>,
+    pub local_order_id: String,
+    pub global_order_id: String,
+    pub status: String,
+    pub service_type: Option<String>,
+    pub delivery_provider: Option<String>,
+    pub external_order_id: Option<String>,
+    /// COALESCE(total_cents, 0), the value filtered and sorted on.
+    pub total_cents: i64,
+    pub refunded_cents: i64,
+    pub item_count: f64,
+    pub occurred_at: chrono::NaiveDateTime,
+}
+
+impl OrderSearchRow {
+    pub fn cursor(&self) -> OrderSearchCursor {
+        OrderSearchCursor {
+            occurred_at: self.occurred_at,
+            total_cents: self.total_cents,
+            id: self.id.clone(),
+        }
+    }
+}
+
+/// One page of matching orders, at most `search.limit` rows, in `search.sort` order.
+pub async fn search_orders(
+    pool: &MySqlPool,
+    search: &OrderSearch,
+) -> Result<Vec<OrderSearchRow>, sqlx::Error> {
+    if search.windows.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut conditions = vec![format!("({})", window_condition(&search.windows))];
+    if search.min_total_cents.is_some() {
+        conditions.push("COALESCE(o.total_cents, 0) >= ?".to_string());
+    }
+    if search.max_total_cents.is_some() {
+        conditions.push("COALESCE(o.total_cents, 0) <= ?".to_string());
+    }
+    if !search.statuses.is_empty() {
+        conditions.push(format!(
+            "o.status IN ({})",
+            vec!["?"; search.statuses.len()].join(", ")
+        ));
+    }
+    if search.payment_kind.is_some() {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM transactions t WHERE t.order_id = o.id AND t.kind = ? AND t.amount_cents > 0)"
+                .to_string(),
+        );
+    }
+    if search.device_id.is_some() {
+        conditions.push(
+            "EXISTS (SELECT 1 FROM order_device_refs dr WHERE dr.order_id = o.id AND dr.device_id = ?)"
+                .to_string(),
+        );
+    }
+    if search.delivery_provider.is_some() {
+        conditions.push("o.delivery_provider = ?".to_string());
+    }
+    if search.product.is_some() {
+        conditions.push(
+            r#"EXISTS (
+              SELECT 1
+              FROM order_items oi
+              LEFT JOIN store_menu_items mi ON mi.store_id = o.store_id AND mi.local_item_id = oi.product_ref
+              WHERE oi.order_id = o.id AND (mi.name LIKE ? OR oi.product_ref LIKE ?)
+            )"#
+            .to_string(),
+        );
+    }
+    if search.reference.is_some() {
+        conditions.push(
+            r#"(o.id = ? OR o.global_order_id = ? OR o.external_order_id = ?
+               OR EXISTS (SELECT 1 FROM order_device_refs dr WHERE dr.order_id = o.id AND dr.local_order_id = ?)
+               OR EXISTS (SELECT 1 FROM receipts r WHERE r.order_id = o.id AND r.local_receipt_id = ?))"#
+                .to_string(),
+        );
+    }
+    let (order_by, after) = match search.sort {
+        OrderSort::NewestFirst => (
+            "o.occurred_at DESC, o.id DESC",
+            "(o.occurred_at < ? OR (o.occurred_at = ? AND o.id < ?))",
+        ),
+        OrderSort::OldestFirst => (
+            "o.occurred_at ASC, o.id ASC",
+            "(o.occurred_at > ? OR (o.occurred_at = ? AND o.id > ?))",
+        ),
+        OrderSort::TotalDesc => (
+            "COALESCE(o.total_cents, 0) DESC, o.id DESC",
+            "(COALESCE(o.total_cents, 0) < ? OR (COALESCE(o.total_cents, 0) = ? AND o.id < ?))",
+        ),
+        OrderSort::TotalAsc => (
+            "COALESCE(o.total_cents, 0) ASC, o.id ASC",
+            "(COALESCE(o.total_cents, 0) > ? OR (COALESCE(o.total_cents, 0) = ? AND o.id > ?))",
+        ),
+    };
+    if search.after.is_some() {
+        conditions.push(after.to_string());
+    }
+
+    let sql = format!(
+        r#"
+        SELECT
+          o.id,
+          o.store_id,
+          s.name AS store_name,
+          o.device_id,
+          d.device_label,
+          o.local_order_id,
+          o.global_order_id,
+          o.status,
+          o.service_type,
+          o.delivery_provider,
+          o.external_order_id,
+          CAST(COALESCE(o.total_cents, 0) AS SIGNED) AS total_cents,
+          CAST(o.refunded_cents AS SIGNED) AS refunded_cents,
+          (SELECT CAST(COALESCE(SUM(oi.quantity), 0) AS DOUBLE) FROM order_items oi WHERE oi.order_id = o.id) AS item_count,
+          o.occurred_at
+        FROM orders o
+        JOIN stores s ON s.id = o.store_id
+        LEFT JOIN devices d ON d.id = o.device_id
+        WHERE {}
+        ORDER BY {}
+        LIMIT ?
+        "#,
+        conditions.join(" AND "),
+        order_by
+    );
+
+    let product_pattern = search.product.as_deref().map(like_pattern);
+    let mut query = bind_windows(sqlx::query_as::<_, OrderSearchRow>(&sql), &search.windows);
+    if let Some(min) = search.min_total_cents {
+        query = query.bind(min);
+    }
+    if let Some(max) = search.max_total_cents {
+        query = query.bind(max);
+    }
+    for status in &search.statuses {
+        query = query.bind(status);
+    }
+    if let Some(kind) = &search.payment_kind {
+        query = query.bind(kind);
+    }
+    if let Some(device_id) = search.device_id {
+        query = query.bind(device_id.to_string());
+    }
+    if let Some(provider) = &search.delivery_provider {
+        query = query.bind(provider);
+    }
+    if let Some(pattern) = &product_pattern {
+        query = query.bind(pattern).bind(pattern);
+    }
+    if let Some(reference) = &search.reference {
+        query = query
+            .bind(reference)
+            .bind(reference)
+            .bind(reference)
+            .bind(reference)
+            .bind(reference);
+    }
+    if let Some(cursor) = &search.after {
+        query = match search.sort {
+            OrderSort::NewestFirst | OrderSort::OldestFirst => query
+                .bind(cursor.occurred_at)
+                .bind(cursor.occurred_at),
+            OrderSort::TotalDesc | OrderSort::TotalAsc => query
+                .bind(cursor.total_cents)
+                .bind(cursor.total_cents),
+        }
+        .bind(&cursor.id);
+    }
+    query.bind(search.limit).fetch_all(pool).await
+}
+
+/// `%term%` with LIKE wildcards in the term escaped.
+fn like_pattern(term: &str) -> String {
+    let escaped = term
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("%{}%", escaped)
+}
+
+fn window_condition(windows: &[OrderSearchWindow]) -> String {
+    windows
+        .iter()
+        .map(|w| {
+            let mut condition = format!(
+                "o.store_id IN ({})",
+                vec!["?"; w.store_ids.len()].join(", ")
+            );
+            if w.start.is_some() {
+                condition.push_str(" AND o.occurred_at >= ?");
+            }
+            if w.end.is_some() {
+                condition.push_str(" AND o.occurred_at < ?");
+            }
+            format!("({})", condition)
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+fn bind_windows<'q>(
+    mut query: sqlx::query::QueryAs<'q, MySql, OrderSearchRow, MySqlArguments>,
+    windows: &'q [OrderSearchWindow],
+) -> sqlx::query::QueryAs<'q, MySql, OrderSearchRow, MySqlArguments> {
+    for w in windows {
+        for id in &w.store_ids {
+            query = query.bind(id);
+        }
+        if let Some(start) = w.start {
+            query = query.bind(start);
+        }
+        if let Some(end) = w.end {
+            query = query.bind(end);
+        }
+    }
+    query
+}
@@ -2,43 +2,266 @@
 //! Keeps POS local ids (e.g. event_body.order_id -> orders.local_order_id) so the portal can
 //! reference them when building void_order / refund_order commands.
 
-use sqlx::MySqlPool;
+use std::collections::BTreeMap;
+
+use chrono::SubsecRound;
+use domain::{
+    order_accepts_edits, DeviceEvent, GlobalOrderId, OrderDiscount, OrderLine, OrderPayload, OrderRevision,
+    OrderTax, ServiceType, VatRate,
+};
+use sqlx::MySqlConnection;
 use uuid::Uuid;
 
-/// Upsert order by (store_id, device_id, local_order_id). Sets total_cents and occurred_at.
-/// Call get_order_id_by_local after this to get the cloud order id.
-pub async fn upsert_order(
-    pool: &MySqlPool,
-    org_id: Uuid,
-    store_id: Uuid,
-    device_id: Uuid,
-    local_order_id: &str,
-    total_cents: Option<i64>,
+use crate::kitchen::{record_kitchen_ready, record_kitchen_send, record_kitchen_step, KitchenLine, KitchenStep};
+use crate::z_reports::record_device_z_report_close;
+
+/// An event's reference to an order: the sending device's local order id and, when the device
+/// sent one, the order's global id.
+#[derive(Debug, Clone, Copy)]
+pub struct OrderRef<'a> {
+    pub org_id: Uuid,
+    pub store_id: Uuid,
+    pub device_id: Uuid,
+    pub local_order_id: &'a str,
+    pub global_order_id: Option<&'a GlobalOrderId>,
+}
+
+/// Find the cloud order an event refers to: by the device's own local id, then by global id
+/// (linking the device's local id to the order). With `open`, an order not found is opened from
+/// this event, owned by the sending device, with the global id sent or the cloud order id. See
+/// domain::order_identity for the rules.
+pub async fn resolve_order(
+    conn: &mut MySqlConnection,
+    order_ref: &OrderRef<'_>,
     occurred_at: chrono::DateTime<chrono::Utc>,
-) -> Result<(), sqlx::Error> {
+    open: bool,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    if let Some(id) = get_order_id_by_local(
+        &mut *conn,
+        order_ref.store_id,
+        order_ref.device_id,
+        order_ref.local_order_id,
+    )
+    .await?
+    {
+        return Ok(Some(id));
+    }
+    if let Some(global_order_id) = order_ref.global_order_id {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT id FROM orders WHERE store_id = ? AND global_order_id = ?")
+                .bind(order_ref.store_id.to_string())
+                .bind(global_order_id.as_str())
+                .fetch_optional(&mut *conn)
+                .await?;
+        if let Some(id) = row.and_then(|(s,)| Uuid::parse_str(&s).ok()) {
+            link_order_device(&mut *conn, id, order_ref, occurred_at).await?;
+            return Ok(Some(id));
+        }
+    }
+    if !open {
+        return Ok(None);
+    }
+
     let id = Uuid::new_v4();
+    let global_order_id = order_ref
+        .global_order_id
+        .map(|g| g.as_str().to_string())
+        .unwrap_or_else(|| id.to_string());
     sqlx::query(
         r#"
-        INSERT INTO orders (id, org_id, store_id, device_id, local_order_id, status, total_cents, occurred_at)
-        VALUES (?, ?, ?, ?, ?, 'open', ?, ?)
-        ON DUPLICATE KEY UPDATE total_cents = COALESCE(VALUES(total_cents), total_cents), occurred_at = VALUES(occurred_at)
+        INSERT INTO orders (id, org_id, store_id, device_id, local_order_id, global_order_id, status, occurred_at)
+        VALUES (?, ?, ?, ?, ?, ?, 'open', ?)
         "#,
     )
     .bind(id.to_string())
-    .bind(org_id.to_string())
-    .bind(store_id.to_string())
-    .bind(device_id.to_string())
-    .bind(local_order_id)
-    .bind(total_cents)
+    .bind(order_ref.org_id.to_string())
+    .bind(order_ref.store_id.to_string())
+    .bind(order_ref.device_id.to_string())
+    .bind(order_ref.local_order_id)
+    .bind(global_order_id)
+    .bind(occurred_at)
+    .execute(&mut *conn)
+    .await?;
+    link_order_device(&mut *conn, id, order_ref, occurred_at).await?;
+    Ok(Some(id))
+}
+
+/// Order a kitchen event refers to. With a global id the order is opened if its own events have
+/// not been projected yet. Without one, a kitchen screen's local id is matched against the store's
+/// orders from every device (kitchen screens echo the till's order id); the event is dropped if no
+/// order, or more than one, has that id.
+async fn resolve_kitchen_order(
+    conn: &mut MySqlConnection,
+    order_ref: &OrderRef<'_>,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    if order_ref.global_order_id.is_some() {
+        return resolve_order(conn, order_ref, occurred_at, true).await;
+    }
+    if let Some(id) = resolve_order(&mut *conn, order_ref, occurred_at, false).await? {
+        return Ok(Some(id));
+    }
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT DISTINCT order_id FROM order_device_refs WHERE store_id = ? AND local_order_id = ? LIMIT 2",
+    )
+    .bind(order_ref.store_id.to_string())
+    .bind(order_ref.local_order_id)
+    .fetch_all(&mut *conn)
+    .await?;
+    let [(id,)] = rows.as_slice() else {
+        return Ok(None);
+    };
+    let Ok(id) = Uuid::parse_str(id) else {
+        return Ok(None);
+    };
+    link_order_device(&mut *conn, id, order_ref, occurred_at).await?;
+    Ok(Some(id))
+}
+
+/// Map the device's local order id to the order, and link its receipts that arrived first.
+async fn link_order_device(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    order_ref: &OrderRef<'_>,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO order_device_refs (id, org_id, store_id, device_id, order_id, local_order_id, first_seen_at)
+        VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE first_seen_at = LEAST(first_seen_at, VALUES(first_seen_at))
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(order_ref.org_id.to_string())
+    .bind(order_ref.store_id.to_string())
+    .bind(order_ref.device_id.to_string())
+    .bind(order_id.to_string())
+    .bind(order_ref.local_order_id)
     .bind(occurred_at)
-    .execute(pool)
+    .execute(&mut *conn)
+    .await?;
+    backfill_receipt_order_id(
+        &mut *conn,
+        order_ref.store_id,
+        order_ref.device_id,
+        order_ref.local_order_id,
+        order_id,
+    )
+    .await
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct OrderOwner {
+    device_id: String,
+    created_event_at: Option<chrono::NaiveDateTime>,
+}
+
+/// order_created: the earliest one (by [`OrderRevision`]) makes its device the order's owner and
+/// its time the order's time.
+async fn claim_order_owner(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    order_ref: &OrderRef<'_>,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    let occurred_at = occurred_at.trunc_subsecs(3);
+    let owner: Option<OrderOwner> =
+        sqlx::query_as("SELECT device_id, created_event_at FROM orders WHERE id = ?")
+            .bind(order_id.to_string())
+            .fetch_optional(&mut *conn)
+            .await?;
+    let Some(owner) = owner else {
+        return Ok(());
+    };
+    let incoming = OrderRevision {
+        occurred_at,
+        device_id: order_ref.device_id,
+    };
+    let earliest = match (owner.created_event_at, Uuid::parse_str(&owner.device_id).ok()) {
+        (Some(at), Some(device_id)) => {
+            incoming
+                < OrderRevision {
+                    occurred_at: at.and_utc(),
+                    device_id,
+                }
+        }
+        _ => true,
+    };
+    if !earliest {
+        return Ok(());
+    }
+    sqlx::query(
+        r#"
+        UPDATE orders
+        SET device_id = ?, local_order_id = ?, created_event_at = ?, occurred_at = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(order_ref.device_id.to_string())
+    .bind(order_ref.local_order_id)
+    .bind(occurred_at)
+    .bind(occurred_at)
+    .bind(order_id.to_string())
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
+#[derive(Debug, sqlx::FromRow)]
+struct OrderEditState {
+    status: String,
+    content_revision_at: Option<chrono::NaiveDateTime>,
+    content_revision_device_id: Option<String>,
+}
+
+/// Whether an order_created / order_updated from this device at this time may replace the
+/// order's content (last writer wins; voided orders are final). Records the new revision when
+/// it may.
+async fn accept_order_edit(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    device_id: Uuid,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<bool, sqlx::Error> {
+    let state: Option<OrderEditState> = sqlx::query_as(
+        "SELECT status, content_revision_at, content_revision_device_id FROM orders WHERE id = ?",
+    )
+    .bind(order_id.to_string())
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(state) = state else {
+        return Ok(false);
+    };
+    let current = match (
+        state.content_revision_at,
+        state.content_revision_device_id.as_deref().and_then(|id| Uuid::parse_str(id).ok()),
+    ) {
+        (Some(at), Some(device_id)) => Some(OrderRevision {
+            occurred_at: at.and_utc(),
+            device_id,
+        }),
+        _ => None,
+    };
+    let incoming = OrderRevision {
+        occurred_at: occurred_at.trunc_subsecs(3),
+        device_id,
+    };
+    if !order_accepts_edits(&state.status) || !incoming.supersedes(current.as_ref()) {
+        return Ok(false);
+    }
+    sqlx::query("UPDATE orders SET content_revision_at = ?, content_revision_device_id = ? WHERE id = ?")
+        .bind(incoming.occurred_at)
+        .bind(device_id.to_string())
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    Ok(true)
+}
+
 /// Link receipts that have this local_order_id but no order_id yet (e.g. receipt_created arrived before order_created).
 pub async fn backfill_receipt_order_id(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     store_id: Uuid,
     device_id: Uuid,
     local_order_id: &str,
@@ -56,39 +279,39 @@ pub async fn backfill_receipt_order_id(
     .bind(store_id.to_string())
     .bind(device_id.to_string())
     .bind(local_order_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
-/// Get cloud order id by POS local order id.
+/// Get cloud order id by a device's POS local order id (its own or one linked to a merged order).
 pub async fn get_order_id_by_local(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     store_id: Uuid,
     device_id: Uuid,
     local_order_id: &str,
 ) -> Result<Option<Uuid>, sqlx::Error> {
     let row: Option<(String,)> = sqlx::query_as(
-        "SELECT id FROM orders WHERE store_id = ? AND device_id = ? AND local_order_id = ?",
+        "SELECT order_id FROM order_device_refs WHERE store_id = ? AND device_id = ? AND local_order_id = ?",
     )
     .bind(store_id.to_string())
     .bind(device_id.to_string())
     .bind(local_order_id)
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
     Ok(row.and_then(|(s,)| Uuid::parse_str(&s).ok()))
 }
 
-/// Insert order_item for an order (cloud order id).
+/// Insert order_item for an order (cloud order id). Returns the new line's id.
 pub async fn insert_order_item(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     order_id: Uuid,
     local_item_id: Option<&str>,
     product_ref: Option<&str>,
     quantity: f64,
     unit_price_cents: Option<i64>,
     line_total_cents: Option<i64>,
-) -> Result<(), sqlx::Error> {
+) -> Result<Uuid, sqlx::Error> {
     let id = Uuid::new_v4();
     sqlx::query(
         r#"
@@ -103,14 +326,281 @@ pub async fn insert_order_item(
     .bind(quantity)
     .bind(unit_price_cents)
     .bind(line_total_cents)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
+    Ok(id)
+}
+
+/// Replace a line's modifiers and line-level discounts with those sent for it.
+async fn replace_line_adjustments(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    order_item_id: &str,
+    item: &OrderLine,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM order_item_modifiers WHERE order_item_id = ?")
+        .bind(order_item_id)
+        .execute(&mut *conn)
+        .await?;
+    for (position, modifier) in item.modifiers.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO order_item_modifiers (id, order_id, order_item_id, name, quantity, price_delta_cents, position)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(order_id.to_string())
+        .bind(order_item_id)
+        .bind(&modifier.name)
+        .bind(modifier.quantity)
+        .bind(modifier.price_delta_cents)
+        .bind(position as i32)
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query("DELETE FROM order_discounts WHERE order_item_id = ?")
+        .bind(order_item_id)
+        .execute(&mut *conn)
+        .await?;
+    insert_discounts(&mut *conn, order_id, Some(order_item_id), &item.discounts).await
+}
+
+async fn insert_discounts(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    order_item_id: Option<&str>,
+    discounts: &[OrderDiscount],
+) -> Result<(), sqlx::Error> {
+    for (position, discount) in discounts.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO order_discounts (id, order_id, order_item_id, local_discount_id, name, code, amount_cents, percent, position)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(order_id.to_string())
+        .bind(order_item_id)
+        .bind(discount.discount_id.as_deref())
+        .bind(discount.name.as_deref())
+        .bind(discount.code.as_deref())
+        .bind(discount.amount_cents.abs())
+        .bind(discount.percent)
+        .bind(position as i32)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Replace the order-level discounts.
+pub async fn replace_order_discounts(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    discounts: &[OrderDiscount],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM order_discounts WHERE order_id = ? AND order_item_id IS NULL")
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    insert_discounts(&mut *conn, order_id, None, discounts).await
+}
+
+/// Replace the order's tax lines.
+pub async fn replace_order_taxes(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    taxes: &[OrderTax],
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM order_taxes WHERE order_id = ?")
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    for (position, tax) in taxes.iter().enumerate() {
+        sqlx::query(
+            r#"
+            INSERT INTO order_taxes (id, order_id, name, rate_percent, net_cents, tax_cents, included, position)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(order_id.to_string())
+        .bind(tax.name.as_deref())
+        .bind(tax.rate_percent)
+        .bind(tax.net_cents)
+        .bind(tax.tax_cents)
+        .bind(tax.included)
+        .bind(position as i32)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Record the order-level parts of order_created / order_updated that were sent: discounts,
+/// tax lines, tip, service charge and service type. Parts left out keep their recorded values.
+async fn apply_order_adjustments(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    payload: &OrderPayload,
+) -> Result<(), sqlx::Error> {
+    if let Some(discounts) = &payload.discounts {
+        replace_order_discounts(&mut *conn, order_id, discounts).await?;
+    }
+    if let Some(taxes) = &payload.taxes {
+        replace_order_taxes(&mut *conn, order_id, taxes).await?;
+    }
+    if let Some(service_type) = payload.service_type {
+        sqlx::query("UPDATE orders SET service_type = ? WHERE id = ?")
+            .bind(service_type.as_str())
+            .bind(order_id.to_string())
+            .execute(&mut *conn)
+            .await?;
+    }
+    if payload.delivery_provider.is_some() || payload.external_order_id.is_some() {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET delivery_provider = COALESCE(?, delivery_provider),
+                external_order_id = COALESCE(?, external_order_id)
+            WHERE id = ?
+            "#,
+        )
+        .bind(payload.delivery_provider.as_deref())
+        .bind(payload.external_order_id.as_deref())
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    }
+    if payload.tip_cents.is_some() || payload.service_charge_cents.is_some() {
+        sqlx::query(
+            r#"
+            UPDATE orders
+            SET tip_cents = COALESCE(?, tip_cents), service_charge_cents = COALESCE(?, service_charge_cents)
+            WHERE id = ?
+            "#,
+        )
+        .bind(payload.tip_cents)
+        .bind(payload.service_charge_cents)
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    }
     Ok(())
 }
 
+/// Compute the order's VAT lines from its menu items' rate bands when the POS sent none: each
+/// line is taxed at its item's band on the store's menu (`store_menu_items`, so lines from every
+/// till match; the takeaway band for takeaway and delivery orders; items without a band, or not
+/// on the menu, are standard rated), order-level discounts are shared across bands in proportion
+/// to their sales, and prices are VAT inclusive. Service charge and tips are left out.
+pub async fn compute_order_vat(conn: &mut MySqlConnection, order_id: Uuid) -> Result<(), sqlx::Error> {
+    let (pos_lines,): (i64,) =
+        sqlx::query_as("SELECT COUNT(*) FROM order_taxes WHERE order_id = ? AND source = 'pos'")
+            .bind(order_id.to_string())
+            .fetch_one(&mut *conn)
+            .await?;
+    if pos_lines > 0 {
+        return Ok(());
+    }
+    sqlx::query("DELETE FROM order_taxes WHERE order_id = ? AND source = 'computed'")
+        .bind(order_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+
+    let lines: Vec<VatLine> = sqlx::query_as(
+        r#"
+        SELECT
+          CAST(COALESCE(oi.line_total_cents, ROUND(oi.unit_price_cents * oi.quantity), 0) AS SIGNED) AS gross_cents,
+          mi.vat_rate,
+          mi.takeaway_vat_rate
+        FROM order_items oi
+        JOIN orders o ON o.id = oi.order_id
+        LEFT JOIN store_menu_items mi ON mi.store_id = o.store_id AND mi.local_item_id = oi.product_ref
+        WHERE oi.order_id = ?
+        "#,
+    )
+    .bind(order_id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+    let (service_type,): (Option<String>,) = sqlx::query_as("SELECT service_type FROM orders WHERE id = ?")
+        .bind(order_id.to_string())
+        .fetch_one(&mut *conn)
+        .await?;
+    let takeaway = service_type
+        .as_deref()
+        .and_then(ServiceType::parse)
+        .is_some_and(|s| s.is_takeaway());
+    let (order_discount_cents,): (i64,) = sqlx::query_as(
+        "SELECT CAST(COALESCE(SUM(amount_cents), 0) AS SIGNED) FROM order_discounts WHERE order_id = ? AND order_item_id IS NULL",
+    )
+    .bind(order_id.to_string())
+    .fetch_one(&mut *conn)
+    .await?;
+
+    for (position, (rate, gross_cents, tax_cents)) in vat_by_band(&lines, takeaway, order_discount_cents)
+        .into_iter()
+        .enumerate()
+    {
+        sqlx::query(
+            r#"
+            INSERT INTO order_taxes (id, order_id, name, rate_percent, net_cents, tax_cents, included, source, position)
+            VALUES (?, ?, ?, ?, ?, ?, TRUE, 'computed', ?)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(order_id.to_string())
+        .bind(rate.as_str())
+        .bind(rate.percent())
+        .bind(gross_cents - tax_cents)
+        .bind(tax_cents)
+        .bind(position as i32)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
+/// An order line's gross and its item's VAT bands on the store's menu (None when the item has
+/// no band or is not on the menu).
+#[derive(Debug, Clone, sqlx::FromRow)]
+struct VatLine {
+    gross_cents: i64,
+    vat_rate: Option<String>,
+    takeaway_vat_rate: Option<String>,
+}
+
+/// Gross (after its share of order-level discounts) and included VAT per band, lowest band first.
+fn vat_by_band(lines: &[VatLine], takeaway: bool, order_discount_cents: i64) -> Vec<(VatRate, i64, i64)> {
+    let mut by_rate: BTreeMap<VatRate, i64> = BTreeMap::new();
+    for line in lines {
+        let band = if takeaway {
+            line.takeaway_vat_rate.as_deref().or(line.vat_rate.as_deref())
+        } else {
+            line.vat_rate.as_deref()
+        };
+        let rate = band.and_then(VatRate::parse).unwrap_or(VatRate::Standard);
+        *by_rate.entry(rate).or_default() += line.gross_cents;
+    }
+    let lines_cents: i64 = by_rate.values().sum();
+    by_rate
+        .into_iter()
+        .map(|(rate, gross_cents)| {
+            let discount_cents = if lines_cents > 0 {
+                (order_discount_cents as f64 * gross_cents as f64 / lines_cents as f64).round() as i64
+            } else {
+                0
+            };
+            let gross_cents = gross_cents - discount_cents;
+            (rate, gross_cents, rate.included_tax(gross_cents))
+        })
+        .collect()
+}
+
 /// Insert transaction (idempotent by local_transaction_id).
 pub async fn upsert_transaction(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
@@ -135,14 +625,14 @@ pub async fn upsert_transaction(
     .bind(kind)
     .bind(amount_cents)
     .bind(occurred_at)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 /// Get cloud transaction id by POS local transaction id.
 pub async fn get_transaction_id_by_local(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     store_id: Uuid,
     device_id: Uuid,
     local_transaction_id: &str,
@@ -153,7 +643,7 @@ pub async fn get_transaction_id_by_local(
     .bind(store_id.to_string())
     .bind(device_id.to_string())
     .bind(local_transaction_id)
-    .fetch_optional(pool)
+    .fetch_optional(&mut *conn)
     .await?;
     Ok(row.and_then(|(s,)| Uuid::parse_str(&s).ok()))
 }
@@ -161,7 +651,7 @@ pub async fn get_transaction_id_by_local(
 /// Insert receipt (idempotent by local_receipt_id). Stores local_order_id so we can link
 /// when order_created is processed later, and so order detail can fetch by (store, device, local_order_id).
 pub async fn upsert_receipt(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
@@ -190,14 +680,168 @@ pub async fn upsert_receipt(
     .bind(transaction_id.map(|u| u.to_string()))
     .bind(local_receipt_id)
     .bind(occurred_at)
-    .execute(pool)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Mark an order voided, with who voided it and why (order_voided).
+pub async fn void_order(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    reason: Option<&str>,
+    staff_id: Option<&str>,
+    staff_name: Option<&str>,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE orders
+        SET status = 'voided', voided_at = ?, void_reason = ?, voided_by_staff_id = ?, voided_by_staff_name = ?
+        WHERE id = ?
+        "#,
+    )
+    .bind(occurred_at)
+    .bind(reason)
+    .bind(staff_id)
+    .bind(staff_name)
+    .bind(order_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// A refund from order_refunded or transaction_refunded.
+#[derive(Debug, Clone)]
+pub struct NewRefund<'a> {
+    pub local_refund_id: &'a str,
+    pub order_id: Option<Uuid>,
+    /// Payment refunded (transaction_refunded).
+    pub refunded_transaction_id: Option<Uuid>,
+    pub kind: &'a str,
+    /// Positive amount refunded, in minor units.
+    pub amount_cents: i64,
+    pub reason: Option<&'a str>,
+    pub staff_id: Option<&'a str>,
+    pub staff_name: Option<&'a str>,
+}
+
+/// Record a refund: an order_refunds row, a negative transactions row so transaction sums are
+/// net, and the order's refunded total and status. Idempotent by local_refund_id.
+pub async fn record_refund(
+    conn: &mut MySqlConnection,
+    org_id: Uuid,
+    store_id: Uuid,
+    device_id: Uuid,
+    refund: &NewRefund<'_>,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    let local_transaction_id = format!("refund:{}", refund.local_refund_id);
+    upsert_transaction(
+        &mut *conn,
+        org_id,
+        store_id,
+        device_id,
+        refund.order_id,
+        &local_transaction_id,
+        refund.kind,
+        -refund.amount_cents,
+        occurred_at,
+    )
+    .await?;
+    let transaction_id = get_transaction_id_by_local(&mut *conn, store_id, device_id, &local_transaction_id).await?;
+    if let Some(refunded) = refund.refunded_transaction_id {
+        sqlx::query("UPDATE transactions SET refund_of_transaction_id = ? WHERE id = ?")
+            .bind(refunded.to_string())
+            .bind(transaction_id.map(|u| u.to_string()))
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO order_refunds (
+          id, org_id, store_id, device_id, order_id, transaction_id, refunded_transaction_id,
+          local_refund_id, kind, amount_cents, reason, staff_id, staff_name, occurred_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+          order_id = COALESCE(VALUES(order_id), order_id), transaction_id = VALUES(transaction_id),
+          refunded_transaction_id = COALESCE(VALUES(refunded_transaction_id), refunded_transaction_id),
+          kind = VALUES(kind), amount_cents = VALUES(amount_cents), reason = VALUES(reason),
+          staff_id = VALUES(staff_id), staff_name = VALUES(staff_name), occurred_at = VALUES(occurred_at)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(org_id.to_string())
+    .bind(store_id.to_string())
+    .bind(device_id.to_string())
+    .bind(refund.order_id.map(|u| u.to_string()))
+    .bind(transaction_id.map(|u| u.to_string()))
+    .bind(refund.refunded_transaction_id.map(|u| u.to_string()))
+    .bind(refund.local_refund_id)
+    .bind(refund.kind)
+    .bind(refund.amount_cents)
+    .bind(refund.reason)
+    .bind(refund.staff_id)
+    .bind(refund.staff_name)
+    .bind(occurred_at)
+    .execute(&mut *conn)
+    .await?;
+    if let Some(order_id) = refund.order_id {
+        update_order_refund_status(&mut *conn, order_id).await?;
+    }
+    Ok(())
+}
+
+/// Recompute refunded_cents from order_refunds and set status 'refunded' once the order total is
+/// covered, 'partially_refunded' before that. Voided orders stay voided.
+async fn update_order_refund_status(conn: &mut MySqlConnection, order_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE orders o
+        SET o.refunded_cents = (SELECT COALESCE(SUM(r.amount_cents), 0) FROM order_refunds r WHERE r.order_id = o.id),
+            o.status = CASE
+              WHEN o.status = 'voided' OR o.refunded_cents = 0 THEN o.status
+              WHEN o.refunded_cents >= COALESCE(o.total_cents,
+                     (SELECT SUM(oi.line_total_cents) FROM order_items oi WHERE oi.order_id = o.id), 0)
+                THEN 'refunded'
+              ELSE 'partially_refunded'
+            END
+        WHERE o.id = ?
+        "#,
+    )
+    .bind(order_id.to_string())
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
+/// Amount of the order not refunded yet, and the kind of its first payment.
+async fn get_order_refundable(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+) -> Result<(i64, Option<String>), sqlx::Error> {
+    let row: Option<(i64, Option<String>)> = sqlx::query_as(
+        r#"
+        SELECT
+          CAST(COALESCE(o.total_cents,
+                        (SELECT SUM(oi.line_total_cents) FROM order_items oi WHERE oi.order_id = o.id),
+                        0) - o.refunded_cents AS SIGNED),
+          (SELECT t.kind FROM transactions t
+           WHERE t.order_id = o.id AND t.amount_cents > 0
+           ORDER BY t.occurred_at LIMIT 1)
+        FROM orders o WHERE o.id = ?
+        "#,
+    )
+    .bind(order_id.to_string())
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(row.unwrap_or((0, None)))
+}
+
 /// Insert order_event (append-only).
 pub async fn insert_order_event(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     order_id: Uuid,
@@ -219,106 +863,243 @@ pub async fn insert_order_event(
     .bind(event_type)
     .bind(event_body)
     .bind(occurred_at)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
-/// Normalise order_id from event body (POS may send string or number).
-fn local_order_id_from_body(event_body: &serde_json::Value) -> String {
-    match event_body.get("order_id") {
-        Some(serde_json::Value::String(s)) => s.clone(),
-        Some(serde_json::Value::Number(n)) => n.to_string(),
-        _ => String::new(),
+/// A line added, removed or changed by [`reconcile_order_lines`], recorded in order_events as
+/// order_line_added / order_line_removed / order_line_changed.
+#[derive(Debug, Clone)]
+pub struct OrderLineChange {
+    pub event_type: &'static str,
+    pub body: serde_json::Value,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct ExistingLine {
+    id: String,
+    local_item_id: Option<String>,
+    product_ref: Option<String>,
+    quantity: f64,
+    unit_price_cents: Option<i64>,
+    line_total_cents: Option<i64>,
+}
+
+/// Make the order's lines match `items`, the order's full line set. Lines are keyed by
+/// local_item_id: new ids are inserted, known ids updated in place, ids no longer sent deleted.
+/// Lines without a local_item_id cannot be matched, so they are replaced wholesale and not
+/// reported as changes.
+pub async fn reconcile_order_lines(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    items: &[OrderLine],
+) -> Result<Vec<OrderLineChange>, sqlx::Error> {
+    let existing: Vec<ExistingLine> = sqlx::query_as(
+        r#"
+        SELECT id, local_item_id, product_ref, CAST(quantity AS DOUBLE) AS quantity, unit_price_cents, line_total_cents
+        FROM order_items
+        WHERE order_id = ?
+        "#,
+    )
+    .bind(order_id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+
+    // Last line wins when the POS sends the same id twice.
+    let mut keyed: Vec<&OrderLine> = Vec::new();
+    for item in items.iter().filter(|i| i.local_item_id.is_some()) {
+        keyed.retain(|k| k.local_item_id != item.local_item_id);
+        keyed.push(item);
     }
+
+    let mut changes = Vec::new();
+    for line in &existing {
+        let ExistingLine {
+            id,
+            local_item_id,
+            product_ref,
+            quantity,
+            unit_price_cents,
+            line_total_cents,
+        } = line;
+        let Some(local_item_id) = local_item_id else {
+            sqlx::query("DELETE FROM order_items WHERE id = ?")
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+            continue;
+        };
+        match keyed.iter().find(|i| i.local_item_id.as_deref() == Some(local_item_id.as_str())) {
+            Some(item) => {
+                replace_line_adjustments(&mut *conn, order_id, id, item).await?;
+                let unchanged = item.product_ref == *product_ref
+                    && item.quantity == *quantity
+                    && item.unit_price_cents == *unit_price_cents
+                    && item.line_total_cents == *line_total_cents;
+                if unchanged {
+                    continue;
+                }
+                sqlx::query(
+                    r#"
+                    UPDATE order_items
+                    SET product_ref = ?, quantity = ?, unit_price_cents = ?, line_total_cents = ?,
+                        updated_at = CURRENT_TIMESTAMP(3)
+                    WHERE id = ?
+                    "#,
+                )
+                .bind(item.product_ref.as_deref())
+                .bind(item.quantity)
+                .bind(item.unit_price_cents)
+                .bind(item.line_total_cents)
+                .bind(id)
+                .execute(&mut *conn)
+                .await?;
+                changes.push(OrderLineChange {
+                    event_type: "order_line_changed",
+                    body: serde_json::json!({
+                        "local_item_id": local_item_id,
+                        "product_ref": item.product_ref,
+                        "quantity": item.quantity,
+                        "previous_quantity": quantity,
+                        "unit_price_cents": item.unit_price_cents,
+                        "previous_unit_price_cents": unit_price_cents,
+                        "line_total_cents": item.line_total_cents,
+                        "previous_line_total_cents": line_total_cents,
+                    }),
+                });
+            }
+            None => {
+                sqlx::query("DELETE FROM order_items WHERE id = ?")
+                    .bind(id)
+                    .execute(&mut *conn)
+                    .await?;
+                changes.push(OrderLineChange {
+                    event_type: "order_line_removed",
+                    body: serde_json::json!({
+                        "local_item_id": local_item_id,
+                        "product_ref": product_ref,
+                        "quantity": quantity,
+                        "line_total_cents": line_total_cents,
+                    }),
+                });
+            }
+        }
+    }
+
+    for item in items {
+        let is_new = match item.local_item_id.as_deref() {
+            Some(local) => {
+                keyed.iter().any(|k| std::ptr::eq(*k, item))
+                    && !existing.iter().any(|e| e.local_item_id.as_deref() == Some(local))
+            }
+            None => true,
+        };
+        if !is_new {
+            continue;
+        }
+        let order_item_id = insert_order_item(
+            &mut *conn,
+            order_id,
+            item.local_item_id.as_deref(),
+            item.product_ref.as_deref(),
+            item.quantity,
+            item.unit_price_cents,
+            item.line_total_cents,
+        )
+        .await?;
+        replace_line_adjustments(&mut *conn, order_id, &order_item_id.to_string(), item).await?;
+        if let Some(local_item_id) = &item.local_item_id {
+            changes.push(OrderLineChange {
+                event_type: "order_line_added",
+                body: serde_json::json!({
+                    "local_item_id": local_item_id,
+                    "product_ref": item.product_ref,
+                    "quantity": item.quantity,
+                    "unit_price_cents": item.unit_price_cents,
+                    "line_total_cents": item.line_total_cents,
+                }),
+            });
+        }
+    }
+    Ok(changes)
 }
 
-/// Project a single event into the orders read model. Keeps local_order_id from event_body
-/// so the portal can use it for void_order / refund_order command bodies.
-/// Tolerates missing or malformed event_body fields (no-op or partial update).
+/// Set total_cents to the POS total when sent, otherwise to the sum of the order's lines (a line
+/// without a total counts unit price × quantity) less order-level discounts plus the service
+/// charge. Orders without priced lines keep their total.
+pub async fn recompute_order_total(
+    conn: &mut MySqlConnection,
+    order_id: Uuid,
+    total_cents: Option<i64>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE orders o
+        SET o.total_cents = COALESCE(
+          ?,
+          (SELECT CAST(SUM(COALESCE(oi.line_total_cents, ROUND(oi.unit_price_cents * oi.quantity))) AS SIGNED)
+           FROM order_items oi WHERE oi.order_id = o.id)
+            - (SELECT COALESCE(SUM(d.amount_cents), 0) FROM order_discounts d
+               WHERE d.order_id = o.id AND d.order_item_id IS NULL)
+            + COALESCE(o.service_charge_cents, 0),
+          o.total_cents)
+        WHERE o.id = ?
+        "#,
+    )
+    .bind(total_cents)
+    .bind(order_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Project a single event into the orders read model. Keeps local_order_id from the event
+/// so the portal can use it for void_order / refund_order command bodies. Events from several
+/// devices about one order (same global_order_id) are merged into it; see
+/// domain::order_identity.
+/// `event_body` is the raw body, stored in order_events.
 pub async fn project_event_to_orders(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
-    event_type: &str,
+    event: &DeviceEvent,
     event_body: &serde_json::Value,
     occurred_at: chrono::DateTime<chrono::Utc>,
 ) -> Result<(), sqlx::Error> {
-    let local_order_id = local_order_id_from_body(event_body);
-    if local_order_id.is_empty() {
-        return Ok(());
-    }
-
-    match event_type {
-        "order_created" => {
-            let total_cents = event_body
-                .get("total_cents")
-                .or(event_body.get("total"))
-                .and_then(|v| v.as_i64());
-            upsert_order(
-                pool,
+    let event_type = event.event_type();
+    match event {
+        DeviceEvent::OrderCreated(e) => {
+            let order_ref = OrderRef {
                 org_id,
                 store_id,
                 device_id,
-                &local_order_id,
-                total_cents,
-                occurred_at,
-            )
-            .await?;
-            let order_id = match get_order_id_by_local(pool, store_id, device_id, &local_order_id).await? {
-                Some(id) => id,
-                None => return Ok(()),
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
             };
-            let _ = backfill_receipt_order_id(pool, store_id, device_id, &local_order_id, order_id).await;
-            let items = event_body
-                .get("items")
-                .or(event_body.get("line_items"))
-                .and_then(|v| v.as_array());
-            if let Some(items) = items {
-                for item in items {
-                    let qty = item
-                        .get("quantity")
-                        .and_then(|v| v.as_f64())
-                        .or_else(|| item.get("qty").and_then(|v| v.as_f64()))
-                        .unwrap_or(1.0);
-                    let unit = item
-                        .get("unit_price_cents")
-                        .or(item.get("price_pence"))
-                        .or(item.get("unit_price"))
-                        .and_then(|v| v.as_i64())
-                        .or_else(|| item.get("price").and_then(|v| v.as_f64()).map(|p| (p * 100.0) as i64));
-                    let line = item
-                        .get("line_total_cents")
-                        .or(item.get("line_total"))
-                        .and_then(|v| v.as_i64())
-                        .or_else(|| item.get("line_total").and_then(|v| v.as_f64()).map(|p| (p * 100.0) as i64));
-                    let local_item_id = item
-                        .get("id")
-                        .or(item.get("item_id"))
-                        .or(item.get("local_item_id"))
-                        .and_then(|v| v.as_str());
-                    let product_ref = item
-                        .get("product_ref")
-                        .or(item.get("product_id"))
-                        .or(item.get("menu_item_id"))
-                        .or(item.get("name"))
-                        .or(item.get("product_name"))
-                        .and_then(|v| v.as_str());
-                    let _ = insert_order_item(
-                        pool,
-                        order_id,
-                        local_item_id,
-                        product_ref,
-                        qty,
-                        unit,
-                        line,
-                    )
-                    .await;
+            let Some(order_id) = resolve_order(&mut *conn, &order_ref, occurred_at, true).await? else {
+                return Ok(());
+            };
+            claim_order_owner(&mut *conn, order_id, &order_ref, occurred_at).await?;
+            // A re-sent order_created reconciles rather than duplicating lines; an older one
+            // than the order's last edit (from another device) changes nothing.
+            if accept_order_edit(&mut *conn, order_id, device_id, occurred_at).await? {
+                if let Some(items) = &e.items {
+                    reconcile_order_lines(&mut *conn, order_id, items).await?;
+                }
+                if let Some(total_cents) = e.total_cents {
+                    sqlx::query("UPDATE orders SET total_cents = ? WHERE id = ?")
+                        .bind(total_cents)
+                        .bind(order_id.to_string())
+                        .execute(&mut *conn)
+                        .await?;
                 }
+                apply_order_adjustments(&mut *conn, order_id, e).await?;
+                compute_order_vat(&mut *conn, order_id).await?;
             }
-            let _ = insert_order_event(
-                pool,
+            insert_order_event(
+                &mut *conn,
                 org_id,
                 store_id,
                 order_id,
@@ -326,61 +1107,37 @@ pub async fn project_event_to_orders(
                 event_body,
                 occurred_at,
             )
-            .await;
+            .await?;
         }
-        "order_updated" => {
-            let order_id = match get_order_id_by_local(pool, store_id, device_id, &local_order_id).await? {
-                Some(id) => id,
-                None => return Ok(()),
+        DeviceEvent::OrderUpdated(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
             };
-            let items = event_body
-                .get("items")
-                .or(event_body.get("line_items"))
-                .and_then(|v| v.as_array());
-            if let Some(items) = items {
-                for item in items {
-                    let qty = item
-                        .get("quantity")
-                        .and_then(|v| v.as_f64())
-                        .or_else(|| item.get("qty").and_then(|v| v.as_f64()))
-                        .unwrap_or(1.0);
-                    let unit = item
-                        .get("unit_price_cents")
-                        .or(item.get("price_pence"))
-                        .or(item.get("unit_price"))
-                        .and_then(|v| v.as_i64())
-                        .or_else(|| item.get("price").and_then(|v| v.as_f64()).map(|p| (p * 100.0) as i64));
-                    let line = item
-                        .get("line_total_cents")
-                        .or(item.get("line_total"))
-                        .and_then(|v| v.as_i64())
-                        .or_else(|| item.get("line_total").and_then(|v| v.as_f64()).map(|p| (p * 100.0) as i64));
-                    let local_item_id = item
-                        .get("id")
-                        .or(item.get("item_id"))
-                        .or(item.get("local_item_id"))
-                        .and_then(|v| v.as_str());
-                    let product_ref = item
-                        .get("product_ref")
-                        .or(item.get("product_id"))
-                        .or(item.get("menu_item_id"))
-                        .or(item.get("name"))
-                        .or(item.get("product_name"))
-                        .and_then(|v| v.as_str());
-                    let _ = insert_order_item(
-                        pool,
-                        order_id,
-                        local_item_id,
-                        product_ref,
-                        qty,
-                        unit,
-                        line,
-                    )
-                    .await;
+            let open = e.global_order_id.is_some();
+            let Some(order_id) = resolve_order(&mut *conn, &order_ref, occurred_at, open).await? else {
+                return Ok(());
+            };
+            let mut changes = Vec::new();
+            if accept_order_edit(&mut *conn, order_id, device_id, occurred_at).await? {
+                if let Some(items) = &e.items {
+                    changes = reconcile_order_lines(&mut *conn, order_id, items).await?;
+                }
+                apply_order_adjustments(&mut *conn, order_id, e).await?;
+                let affects_total = e.items.is_some()
+                    || e.total_cents.is_some()
+                    || e.discounts.is_some()
+                    || e.service_charge_cents.is_some();
+                if affects_total {
+                    recompute_order_total(&mut *conn, order_id, e.total_cents).await?;
                 }
+                compute_order_vat(&mut *conn, order_id).await?;
             }
-            let _ = insert_order_event(
-                pool,
+            insert_order_event(
+                &mut *conn,
                 org_id,
                 store_id,
                 order_id,
@@ -388,44 +1145,44 @@ pub async fn project_event_to_orders(
                 event_body,
                 occurred_at,
             )
-            .await;
-        }
-        "transaction_completed" => {
-            let local_tx_id = event_body
-                .get("transaction_id")
-                .or(event_body.get("local_transaction_id"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            if local_tx_id.is_empty() {
-                return Ok(());
+            .await?;
+            for change in &changes {
+                insert_order_event(
+                    &mut *conn,
+                    org_id,
+                    store_id,
+                    order_id,
+                    change.event_type,
+                    &change.body,
+                    occurred_at,
+                )
+                .await?;
             }
-            let order_id = get_order_id_by_local(pool, store_id, device_id, &local_order_id).await?;
-            let amount_cents = event_body
-                .get("amount_cents")
-                .or(event_body.get("amount"))
-                .and_then(|v| v.as_i64())
-                .unwrap_or(0);
-            let kind = event_body
-                .get("kind")
-                .or(event_body.get("payment_method"))
-                .or(event_body.get("provider"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("payment");
+        }
+        DeviceEvent::TransactionCompleted(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let order_id = resolve_order(&mut *conn, &order_ref, occurred_at, e.global_order_id.is_some()).await?;
             upsert_transaction(
-                pool,
+                &mut *conn,
                 org_id,
                 store_id,
                 device_id,
                 order_id,
-                local_tx_id,
-                kind,
-                amount_cents,
+                &e.transaction_id,
+                &e.kind,
+                e.amount_cents,
                 occurred_at,
             )
             .await?;
             if let Some(oid) = order_id {
-                let _ = insert_order_event(
-                    pool,
+                insert_order_event(
+                    &mut *conn,
                     org_id,
                     store_id,
                     oid,
@@ -433,41 +1190,38 @@ pub async fn project_event_to_orders(
                     event_body,
                     occurred_at,
                 )
-                .await;
+                .await?;
             }
         }
-        "receipt_created" => {
-            let local_receipt_id = event_body
-                .get("receipt_id")
-                .or(event_body.get("local_receipt_id"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            let local_tx_id = event_body
-                .get("transaction_id")
-                .or(event_body.get("local_transaction_id"))
-                .and_then(|v| v.as_str())
-                .unwrap_or("");
-            let order_id = get_order_id_by_local(pool, store_id, device_id, &local_order_id).await?;
-            let transaction_id = if local_tx_id.is_empty() {
-                None
-            } else {
-                get_transaction_id_by_local(pool, store_id, device_id, local_tx_id).await?
+        DeviceEvent::ReceiptCreated(e) => {
+            let local_order_id = e.order_id.as_str();
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id,
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let order_id = resolve_order(&mut *conn, &order_ref, occurred_at, e.global_order_id.is_some()).await?;
+            let transaction_id = match &e.transaction_id {
+                Some(local_tx_id) => get_transaction_id_by_local(&mut *conn, store_id, device_id, local_tx_id).await?,
+                None => None,
             };
             upsert_receipt(
-                pool,
+                &mut *conn,
                 org_id,
                 store_id,
                 device_id,
                 order_id,
                 transaction_id,
-                &local_order_id,
-                local_receipt_id,
+                local_order_id,
+                &e.receipt_id,
                 occurred_at,
             )
             .await?;
             if let Some(oid) = order_id {
-                let _ = insert_order_event(
-                    pool,
+                insert_order_event(
+                    &mut *conn,
                     org_id,
                     store_id,
                     oid,
@@ -475,10 +1229,229 @@ pub async fn project_event_to_orders(
                     event_body,
                     occurred_at,
                 )
-                .await;
+                .await?;
+            }
+        }
+        DeviceEvent::OrderVoided(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let open = e.global_order_id.is_some();
+            let Some(order_id) = resolve_order(&mut *conn, &order_ref, occurred_at, open).await? else {
+                return Ok(());
+            };
+            void_order(
+                &mut *conn,
+                order_id,
+                e.reason.as_deref(),
+                e.staff_id.as_deref(),
+                e.staff_name.as_deref(),
+                occurred_at,
+            )
+            .await?;
+            insert_order_event(&mut *conn, org_id, store_id, order_id, event_type, event_body, occurred_at).await?;
+        }
+        DeviceEvent::OrderRefunded(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let open = e.global_order_id.is_some();
+            let Some(order_id) = resolve_order(&mut *conn, &order_ref, occurred_at, open).await? else {
+                return Ok(());
+            };
+            let local_refund_id = e
+                .refund_id
+                .clone()
+                .filter(|id| !id.trim().is_empty())
+                .unwrap_or_else(|| format!("order:{}", e.order_id));
+            let (refundable, payment_kind) = get_order_refundable(&mut *conn, order_id).await?;
+            // A repeated full refund (same default refund id) keeps its original amount.
+            let amount_cents = match e.amount_cents {
+                Some(amount) => amount.abs(),
+                None => {
+                    let (existing,): (i64,) = sqlx::query_as(
+                        "SELECT COALESCE(MAX(amount_cents), 0) FROM order_refunds WHERE store_id = ? AND device_id = ? AND local_refund_id = ?",
+                    )
+                    .bind(store_id.to_string())
+                    .bind(device_id.to_string())
+                    .bind(&local_refund_id)
+                    .fetch_one(&mut *conn)
+                    .await?;
+                    existing + refundable.max(0)
+                }
+            };
+            let kind = e
+                .kind
+                .clone()
+                .filter(|k| !k.trim().is_empty())
+                .or(payment_kind)
+                .unwrap_or_else(|| "refund".to_string());
+            let refund = NewRefund {
+                local_refund_id: &local_refund_id,
+                order_id: Some(order_id),
+                refunded_transaction_id: None,
+                kind: &kind,
+                amount_cents,
+                reason: e.reason.as_deref(),
+                staff_id: e.staff_id.as_deref(),
+                staff_name: e.staff_name.as_deref(),
+            };
+            record_refund(&mut *conn, org_id, store_id, device_id, &refund, occurred_at).await?;
+            insert_order_event(&mut *conn, org_id, store_id, order_id, event_type, event_body, occurred_at).await?;
+        }
+        DeviceEvent::TransactionRefunded(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let order_id = resolve_order(&mut *conn, &order_ref, occurred_at, e.global_order_id.is_some()).await?;
+            // The payment may have been taken on another device of a merged order.
+            let refunded: Option<(String, String)> = sqlx::query_as(
+                r#"
+                SELECT id, kind FROM transactions
+                WHERE store_id = ? AND local_transaction_id = ? AND (device_id = ? OR order_id = ?)
+                ORDER BY device_id = ? DESC
+                LIMIT 1
+                "#,
+            )
+            .bind(store_id.to_string())
+            .bind(&e.transaction_id)
+            .bind(device_id.to_string())
+            .bind(order_id.map(|u| u.to_string()))
+            .bind(device_id.to_string())
+            .fetch_optional(&mut *conn)
+            .await?;
+            let (refunded_transaction_id, kind) = match refunded {
+                Some((id, kind)) => (Uuid::parse_str(&id).ok(), kind),
+                None => (None, "refund".to_string()),
+            };
+            let refund = NewRefund {
+                local_refund_id: &e.refund_id,
+                order_id,
+                refunded_transaction_id,
+                kind: &kind,
+                amount_cents: e.amount_cents.abs(),
+                reason: e.reason.as_deref(),
+                staff_id: e.staff_id.as_deref(),
+                staff_name: e.staff_name.as_deref(),
+            };
+            record_refund(&mut *conn, org_id, store_id, device_id, &refund, occurred_at).await?;
+            if let Some(oid) = order_id {
+                insert_order_event(&mut *conn, org_id, store_id, oid, event_type, event_body, occurred_at).await?;
             }
         }
+        DeviceEvent::OrderSentToKitchen(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let Some(order_id) = resolve_kitchen_order(&mut *conn, &order_ref, occurred_at).await? else {
+                return Ok(());
+            };
+            record_kitchen_send(&mut *conn, org_id, store_id, order_id, e, occurred_at).await?;
+            insert_order_event(&mut *conn, org_id, store_id, order_id, event_type, event_body, occurred_at).await?;
+        }
+        DeviceEvent::ItemStarted(e) | DeviceEvent::ItemBumped(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let Some(order_id) = resolve_kitchen_order(&mut *conn, &order_ref, occurred_at).await? else {
+                return Ok(());
+            };
+            let step = match event {
+                DeviceEvent::ItemStarted(_) => KitchenStep::Started,
+                _ => KitchenStep::Bumped,
+            };
+            let line = KitchenLine {
+                org_id,
+                store_id,
+                order_id,
+                local_item_id: e.item_id.as_str(),
+                station: e.station.as_deref(),
+            };
+            record_kitchen_step(&mut *conn, &line, step, occurred_at).await?;
+            insert_order_event(&mut *conn, org_id, store_id, order_id, event_type, event_body, occurred_at).await?;
+        }
+        DeviceEvent::OrderReady(e) => {
+            let order_ref = OrderRef {
+                org_id,
+                store_id,
+                device_id,
+                local_order_id: e.order_id.as_str(),
+                global_order_id: e.global_order_id.as_ref(),
+            };
+            let Some(order_id) = resolve_kitchen_order(&mut *conn, &order_ref, occurred_at).await? else {
+                return Ok(());
+            };
+            record_kitchen_ready(&mut *conn, order_id, occurred_at).await?;
+            insert_order_event(&mut *conn, org_id, store_id, order_id, event_type, event_body, occurred_at).await?;
+        }
+        DeviceEvent::ZReportClosed(e) => {
+            record_device_z_report_close(&mut *conn, org_id, store_id, device_id, e, occurred_at).await?;
+        }
         _ => {}
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(gross_cents: i64, vat_rate: Option<&str>, takeaway_vat_rate: Option<&str>) -> VatLine {
+        VatLine {
+            gross_cents,
+            vat_rate: vat_rate.map(str::to_string),
+            takeaway_vat_rate: takeaway_vat_rate.map(str::to_string),
+        }
+    }
+
+    #[test]
+    fn lines_are_taxed_at_their_store_menu_band() {
+        // A sandwich (standard eat in, zero to take away) and a coffee, rung up on a till that is
+        // not the store's menu device: the bands come from the store's menu all the same.
+        let lines = [line(600, Some("standard"), Some("zero")), line(300, Some("standard"), None)];
+        assert_eq!(vat_by_band(&lines, false, 0), vec![(VatRate::Standard, 900, 150)]);
+        assert_eq!(
+            vat_by_band(&lines, true, 0),
+            vec![(VatRate::Standard, 300, 50), (VatRate::Zero, 600, 0)]
+        );
+    }
+
+    #[test]
+    fn items_off_the_menu_are_standard_rated() {
+        let lines = [line(1200, None, None), line(525, Some("reduced"), None)];
+        assert_eq!(
+            vat_by_band(&lines, false, 0),
+            vec![(VatRate::Standard, 1200, 200), (VatRate::Reduced, 525, 25)]
+        );
+    }
+
+    #[test]
+    fn order_discounts_are_shared_in_proportion_to_sales() {
+        let lines = [line(900, Some("standard"), None), line(300, Some("zero"), None)];
+        assert_eq!(
+            vat_by_band(&lines, false, 120),
+            vec![(VatRate::Standard, 810, 135), (VatRate::Zero, 270, 0)]
+        );
+        assert_eq!(vat_by_band(&[], false, 100), vec![]);
+    }
+}
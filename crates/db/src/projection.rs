@@ -0,0 +1,477 @@
+//! Projection queue: project device_event_log into the read models asynchronously.
+//! Each device has a cursor (device_projection_cursors) at the last projected log_pos. A failing
+//! event blocks its device with exponential backoff until it succeeds or runs out of attempts,
+//! at which point it is parked in projection_dead_letters and the cursor moves past it.
+
+use domain::DeviceEvent;
+use sqlx::{mysql::MySqlRow, Connection, MySqlConnection, MySqlPool, Row};
+use uuid::Uuid;
+
+use crate::orders::project_event_to_orders;
+use crate::read_model::project_event_to_read_model;
+
+/// Failed attempts before an event is dead-lettered.
+pub const PROJECTION_MAX_ATTEMPTS: i32 = 6;
+
+/// Cap on the retry delay between attempts.
+const PROJECTION_MAX_BACKOFF_SECS: i64 = 300;
+
+/// Run both projections (menu/config read model and orders) for one event.
+pub async fn project_event(
+    conn: &mut MySqlConnection,
+    org_id: Uuid,
+    store_id: Uuid,
+    device_id: Uuid,
+    event: &DeviceEvent,
+    event_body: &serde_json::Value,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    project_event_to_read_model(
+        &mut *conn,
+        org_id,
+        store_id,
+        device_id,
+        event,
+        event_body,
+        occurred_at,
+    )
+    .await?;
+    project_event_to_orders(
+        &mut *conn,
+        org_id,
+        store_id,
+        device_id,
+        event,
+        event_body,
+        occurred_at,
+    )
+    .await
+}
+
+/// A device_event_log row loaded for projection.
+#[derive(Debug)]
+pub struct LoggedEvent {
+    pub id: String,
+    pub event_id: String,
+    pub log_pos: i64,
+    pub org_id: Uuid,
+    pub store_id: Uuid,
+    pub device_id: Uuid,
+    pub event_type: String,
+    pub event_body: serde_json::Value,
+    pub schema_version: u32,
+    /// corrected_occurred_at when the device clock was skewed, else occurred_at as reported.
+    pub occurred_at: chrono::DateTime<chrono::Utc>,
+}
+
+/// Columns selected for LoggedEvent::from_row.
+pub(crate) const LOGGED_EVENT_COLUMNS: &str =
+    "id, event_id, log_pos, org_id, store_id, device_id, event_type, event_body, schema_version, occurred_at, corrected_occurred_at";
+
+impl LoggedEvent {
+    pub(crate) fn from_row(row: &MySqlRow) -> Self {
+        Self {
+            id: row.get("id"),
+            event_id: row.get("event_id"),
+            log_pos: row.get("log_pos"),
+            org_id: Uuid::parse_str(&row.get::<String, _>("org_id")).unwrap_or_default(),
+            store_id: Uuid::parse_str(&row.get::<String, _>("store_id")).unwrap_or_default(),
+            device_id: Uuid::parse_str(&row.get::<String, _>("device_id")).unwrap_or_default(),
+            event_type: row.get("event_type"),
+            event_body: row.get("event_body"),
+            schema_version: row.get("schema_version"),
+            occurred_at: row
+                .get::<Option<chrono::DateTime<chrono::Utc>>, _>("corrected_occurred_at")
+                .unwrap_or_else(|| row.get("occurred_at")),
+        }
+    }
+
+    /// Parse the typed event. Ok(None) for event types without a schema (nothing to project).
+    /// Bodies stored before ingest validation existed can fail here; they end up dead-lettered.
+    pub fn parse(&self) -> Result<Option<DeviceEvent>, sqlx::Error> {
+        DeviceEvent::parse(self.schema_version, &self.event_type, &self.event_body)
+            .map_err(|e| sqlx::Error::Decode(Box::new(e)))
+    }
+
+    /// Project this event inside a savepoint so a failure leaves no partial writes behind.
+    pub(crate) async fn project_in_savepoint(&self, conn: &mut MySqlConnection) -> Result<(), sqlx::Error> {
+        let Some(event) = self.parse()? else {
+            return Ok(());
+        };
+        let mut savepoint = conn.begin().await?;
+        let result = project_event(
+            &mut savepoint,
+            self.org_id,
+            self.store_id,
+            self.device_id,
+            &event,
+            &self.event_body,
+            self.occurred_at,
+        )
+        .await;
+        match result {
+            Ok(()) => savepoint.commit().await,
+            Err(err) => {
+                savepoint.rollback().await?;
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Park an event in projection_dead_letters (or refresh an existing entry for it).
+pub(crate) async fn record_dead_letter(
+    conn: &mut MySqlConnection,
+    event: &LoggedEvent,
+    attempts: i32,
+    error: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO projection_dead_letters
+          (org_id, store_id, device_id, event_log_id, event_id, event_type, attempts, last_error)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE attempts = VALUES(attempts), last_error = VALUES(last_error), resolved_at = NULL
+        "#,
+    )
+    .bind(event.org_id.to_string())
+    .bind(event.store_id.to_string())
+    .bind(event.device_id.to_string())
+    .bind(&event.id)
+    .bind(&event.event_id)
+    .bind(&event.event_type)
+    .bind(attempts)
+    .bind(error)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Create the projection cursor for a device if it does not exist yet (starts at the beginning of the log).
+pub async fn ensure_projection_cursor(
+    conn: &mut MySqlConnection,
+    device_id: Uuid,
+    org_id: Uuid,
+    store_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT IGNORE INTO device_projection_cursors (device_id, org_id, store_id, last_projected_pos)
+        VALUES (?, ?, ?, 0)
+        "#,
+    )
+    .bind(device_id.to_string())
+    .bind(org_id.to_string())
+    .bind(store_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Devices whose cursor is due and that have logged events past it.
+pub async fn list_devices_pending_projection(
+    pool: &MySqlPool,
+    limit: i64,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT c.device_id
+        FROM device_projection_cursors c
+        WHERE c.next_attempt_at <= CURRENT_TIMESTAMP(3)
+          AND (c.rebuild_until IS NULL OR c.rebuild_until <= CURRENT_TIMESTAMP(3))
+          AND EXISTS (
+            SELECT 1 FROM device_event_log l
+            WHERE l.device_id = c.device_id AND l.log_pos > c.last_projected_pos
+          )
+        ORDER BY c.next_attempt_at
+        LIMIT ?
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().filter_map(|(s,)| Uuid::parse_str(&s).ok()).collect())
+}
+
+/// Outcome of one projection pass over a device's pending events.
+#[derive(Debug, Default)]
+pub struct ProjectionPass {
+    pub projected: u64,
+    pub dead_lettered: u64,
+    /// True when the pass stopped on a failing event that will be retried later.
+    pub blocked: bool,
+}
+
+fn backoff_secs(attempts: i32) -> i64 {
+    (1i64 << attempts.clamp(0, 16)).min(PROJECTION_MAX_BACKOFF_SECS)
+}
+
+/// Project up to `batch_size` pending events for one device, in log order, inside one transaction.
+/// The cursor row is locked with SKIP LOCKED so concurrent workers never project the same device;
+/// cursors leased by a read-model rebuild (rebuild_until) are skipped.
+pub async fn project_pending_for_device(
+    pool: &MySqlPool,
+    device_id: Uuid,
+    batch_size: i64,
+) -> Result<ProjectionPass, sqlx::Error> {
+    let mut pass = ProjectionPass::default();
+    let mut tx = pool.begin().await?;
+
+    let cursor = sqlx::query(
+        r#"
+        SELECT last_projected_pos, attempts
+        FROM device_projection_cursors
+        WHERE device_id = ? AND next_attempt_at <= CURRENT_TIMESTAMP(3)
+          AND (rebuild_until IS NULL OR rebuild_until <= CURRENT_TIMESTAMP(3))
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .bind(device_id.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(cursor) = cursor else {
+        return Ok(pass);
+    };
+    let mut last_pos: i64 = cursor.get("last_projected_pos");
+    let mut attempts: i32 = cursor.get("attempts");
+
+    let sql = format!(
+        "SELECT {} FROM device_event_log WHERE device_id = ? AND log_pos > ? ORDER BY log_pos LIMIT ?",
+        LOGGED_EVENT_COLUMNS
+    );
+    let events: Vec<LoggedEvent> = sqlx::query(&sql)
+        .bind(device_id.to_string())
+        .bind(last_pos)
+        .bind(batch_size)
+        .fetch_all(&mut *tx)
+        .await?
+        .iter()
+        .map(LoggedEvent::from_row)
+        .collect();
+
+    let mut last_error: Option<String> = None;
+    for ev in &events {
+        match ev.project_in_savepoint(&mut tx).await {
+            Ok(()) => {
+                last_pos = ev.log_pos;
+                attempts = 0;
+                pass.projected += 1;
+            }
+            Err(err) => {
+                attempts += 1;
+                tracing::warn!(
+                    "projection failed for device {} event {} ({}), attempt {}: {}",
+                    device_id,
+                    ev.event_id,
+                    ev.event_type,
+                    attempts,
+                    err
+                );
+                if attempts < PROJECTION_MAX_ATTEMPTS {
+                    last_error = Some(err.to_string());
+                    pass.blocked = true;
+                    break;
+                }
+                record_dead_letter(&mut tx, ev, attempts, &err.to_string()).await?;
+                last_pos = ev.log_pos;
+                attempts = 0;
+                pass.dead_lettered += 1;
+            }
+        }
+    }
+
+    let delay = if pass.blocked { backoff_secs(attempts) } else { 0 };
+    sqlx::query(
+        r#"
+        UPDATE device_projection_cursors
+        SET last_projected_pos = ?, attempts = ?, last_error = ?,
+            next_attempt_at = TIMESTAMPADD(SECOND, ?, CURRENT_TIMESTAMP(3))
+        WHERE device_id = ?
+        "#,
+    )
+    .bind(last_pos)
+    .bind(attempts)
+    .bind(last_error)
+    .bind(delay)
+    .bind(device_id.to_string())
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(pass)
+}
+
+/// Projection cursor state for a device (portal view).
+#[derive(Debug, serde::Serialize)]
+pub struct ProjectionCursorRow {
+    pub device_id: String,
+    pub device_name: Option<String>,
+    pub last_projected_pos: i64,
+    pub pending_events: i64,
+    pub attempts: i32,
+    pub last_error: Option<String>,
+    pub next_attempt_at: String,
+}
+
+pub async fn list_projection_cursors_for_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Vec<ProjectionCursorRow>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT
+          c.device_id,
+          d.device_name,
+          c.last_projected_pos,
+          (SELECT COUNT(*) FROM device_event_log l
+           WHERE l.device_id = c.device_id AND l.log_pos > c.last_projected_pos) AS pending_events,
+          c.attempts,
+          c.last_error,
+          c.next_attempt_at
+        FROM device_projection_cursors c
+        LEFT JOIN devices d ON d.id = c.device_id
+        WHERE c.store_id = ?
+        ORDER BY d.device_name, c.device_id
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ProjectionCursorRow {
+            device_id: row.get("device_id"),
+            device_name: row.get("device_name"),
+            last_projected_pos: row.get("last_projected_pos"),
+            pending_events: row.get("pending_events"),
+            attempts: row.get("attempts"),
+            last_error: row.get("last_error"),
+            next_attempt_at: row
+                .get::<chrono::NaiveDateTime, _>("next_attempt_at")
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string(),
+        })
+        .collect())
+}
+
+/// Dead-lettered event (portal view).
+#[derive(Debug, serde::Serialize)]
+pub struct DeadLetterRow {
+    pub id: String,
+    pub device_id: String,
+    pub event_id: String,
+    pub event_type: String,
+    pub attempts: i32,
+    pub last_error: String,
+    pub replay_count: i32,
+    pub resolved_at: Option<String>,
+    pub created_at: String,
+}
+
+pub async fn list_dead_letters_for_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    include_resolved: bool,
+    limit: i64,
+) -> Result<Vec<DeadLetterRow>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT id, device_id, event_id, event_type, attempts, last_error, replay_count, resolved_at, created_at
+        FROM projection_dead_letters
+        WHERE store_id = ? AND (? OR resolved_at IS NULL)
+        ORDER BY created_at DESC
+        LIMIT ?
+        "#,
+    )
+    .bind(store_id.to_string())
+    .bind(include_resolved)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| DeadLetterRow {
+            id: row.get("id"),
+            device_id: row.get("device_id"),
+            event_id: row.get("event_id"),
+            event_type: row.get("event_type"),
+            attempts: row.get("attempts"),
+            last_error: row.get("last_error"),
+            replay_count: row.get("replay_count"),
+            resolved_at: row
+                .get::<Option<chrono::NaiveDateTime>, _>("resolved_at")
+                .map(|dt| dt.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            created_at: row
+                .get::<chrono::NaiveDateTime, _>("created_at")
+                .format("%Y-%m-%dT%H:%M:%S")
+                .to_string(),
+        })
+        .collect())
+}
+
+/// Result of replaying a dead-lettered event.
+#[derive(Debug)]
+pub enum DeadLetterReplay {
+    NotFound,
+    AlreadyResolved,
+    Projected,
+    Failed(String),
+}
+
+/// Re-project a dead-lettered event now (outside the device's cursor order). On success the
+/// dead letter is marked resolved; on failure its last_error and replay_count are updated.
+pub async fn replay_dead_letter(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    dead_letter_id: Uuid,
+) -> Result<DeadLetterReplay, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let row = sqlx::query(
+        r#"
+        SELECT dl.resolved_at, dl.event_log_id
+        FROM projection_dead_letters dl
+        WHERE dl.id = ? AND dl.store_id = ?
+        FOR UPDATE
+        "#,
+    )
+    .bind(dead_letter_id.to_string())
+    .bind(store_id.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(row) = row else {
+        return Ok(DeadLetterReplay::NotFound);
+    };
+    if row.get::<Option<chrono::NaiveDateTime>, _>("resolved_at").is_some() {
+        return Ok(DeadLetterReplay::AlreadyResolved);
+    }
+    let sql = format!("SELECT {} FROM device_event_log WHERE id = ?", LOGGED_EVENT_COLUMNS);
+    let event = sqlx::query(&sql)
+        .bind(row.get::<String, _>("event_log_id"))
+        .fetch_one(&mut *tx)
+        .await
+        .map(|r| LoggedEvent::from_row(&r))?;
+
+    let outcome = match event.project_in_savepoint(&mut tx).await {
+        Ok(()) => {
+            sqlx::query(
+                "UPDATE projection_dead_letters SET resolved_at = CURRENT_TIMESTAMP(3), replay_count = replay_count + 1 WHERE id = ?",
+            )
+            .bind(dead_letter_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            DeadLetterReplay::Projected
+        }
+        Err(err) => {
+            sqlx::query(
+                "UPDATE projection_dead_letters SET last_error = ?, replay_count = replay_count + 1 WHERE id = ?",
+            )
+            .bind(err.to_string())
+            .bind(dead_letter_id.to_string())
+            .execute(&mut *tx)
+            .await?;
+            DeadLetterReplay::Failed(err.to_string())
+        }
+    };
+    tx.commit().await?;
+    Ok(outcome)
+}
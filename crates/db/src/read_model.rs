@@ -1,7 +1,8 @@
 //! Read model: project device_event_log into store, menu, categories, items, modifiers, dish yields.
 //! All ids are POS local strings (store_id, category_id, item_id, etc.) for reference in commands.
 
-use sqlx::{MySqlPool, Row};
+use domain::{DeviceEvent, VatRate};
+use sqlx::{MySqlConnection, MySqlExecutor, MySqlPool, Row};
 use uuid::Uuid;
 
 use crate::device::{is_device_canonical_for_store, update_device_name_primary};
@@ -31,7 +32,7 @@ pub async fn get_device_id_for_store(pool: &MySqlPool, store_id: Uuid) -> Result
 }
 
 /// Categories and items for sync (GET /api/sync/menu). Uses same shape as read model.
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SyncMenuCategory {
     pub local_category_id: String,
     pub local_menu_id: String,
@@ -40,7 +41,7 @@ pub struct SyncMenuCategory {
     pub image_path: Option<String>,
 }
 
-#[derive(Debug, serde::Serialize)]
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct SyncMenuItem {
     pub local_item_id: String,
     pub local_store_id: Option<String>,
@@ -48,15 +49,24 @@ pub struct SyncMenuItem {
     pub name: String,
     pub description: Option<String>,
     pub price_pence: Option<i64>,
+    /// VAT rate band (standard / reduced / zero); None = standard.
+    pub vat_rate: Option<String>,
+    /// Band for takeaway and delivery orders; None = same as vat_rate.
+    pub takeaway_vat_rate: Option<String>,
     pub active: bool,
     pub image_path: Option<String>,
     pub customer_editable: bool,
 }
 
+/// The store's menu: its head-office template menu with the store's overrides when it is on a
+/// template, otherwise its canonical device's menu. None if neither exists yet.
 pub async fn get_store_menu_for_sync(
     pool: &MySqlPool,
     store_id: Uuid,
 ) -> Result<Option<(Vec<SyncMenuCategory>, Vec<SyncMenuItem>)>, sqlx::Error> {
+    if let Some(menu) = crate::menu_templates::get_store_template_menu(pool, store_id).await? {
+        return Ok(Some((menu.categories, menu.items)));
+    }
     let device_id = match get_device_id_for_store(pool, store_id).await? {
         Some(d) => d,
         None => return Ok(None),
@@ -85,7 +95,8 @@ pub async fn get_store_menu_for_sync(
 
     let item_rows = sqlx::query(
         r#"
-        SELECT local_item_id, local_store_id, local_category_id, name, description, price_pence, active, image_path, customer_editable
+        SELECT local_item_id, local_store_id, local_category_id, name, description, price_pence, vat_rate,
+               takeaway_vat_rate, active, image_path, customer_editable
         FROM pos_menu_items WHERE device_id = ? ORDER BY name
         "#,
     )
@@ -102,6 +113,8 @@ pub async fn get_store_menu_for_sync(
             name: row.get::<String, _>("name"),
             description: row.get::<Option<String>, _>("description"),
             price_pence: row.get::<Option<i64>, _>("price_pence"),
+            vat_rate: row.get::<Option<String>, _>("vat_rate"),
+            takeaway_vat_rate: row.get::<Option<String>, _>("takeaway_vat_rate"),
             active: row.get::<bool, _>("active"),
             image_path: row.get::<Option<String>, _>("image_path"),
             customer_editable: row.get::<bool, _>("customer_editable"),
@@ -112,7 +125,7 @@ pub async fn get_store_menu_for_sync(
 }
 
 pub async fn upsert_pos_store(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
@@ -133,7 +146,7 @@ pub async fn upsert_pos_store(
     .bind(local_store_id)
     .bind(name)
     .bind(timezone)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
@@ -141,7 +154,7 @@ pub async fn upsert_pos_store(
 // ---------- Menus ----------
 
 pub async fn ensure_pos_menu(
-    pool: &MySqlPool,
+    executor: impl MySqlExecutor<'_>,
     org_id: Uuid,
     device_id: Uuid,
     local_menu_id: &str,
@@ -155,7 +168,7 @@ pub async fn ensure_pos_menu(
     .bind(org_id.to_string())
     .bind(device_id.to_string())
     .bind(local_menu_id)
-    .execute(pool)
+    .execute(executor)
     .await?;
     Ok(())
 }
@@ -192,7 +205,7 @@ pub async fn create_pos_menu_category(
 }
 
 pub async fn upsert_pos_menu_category(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     device_id: Uuid,
     local_menu_id: &str,
@@ -215,7 +228,7 @@ pub async fn upsert_pos_menu_category(
     .bind(name)
     .bind(position)
     .bind(image_path)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
@@ -262,7 +275,7 @@ pub async fn create_pos_menu_item(
 }
 
 pub async fn upsert_pos_menu_item(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     device_id: Uuid,
     local_item_id: &str,
@@ -301,13 +314,13 @@ pub async fn upsert_pos_menu_item(
     .bind(active)
     .bind(image_path)
     .bind(customer_editable)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 pub async fn update_pos_menu_category_image(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_category_id: &str,
     image_path: &str,
@@ -318,13 +331,13 @@ pub async fn update_pos_menu_category_image(
     .bind(image_path)
     .bind(device_id.to_string())
     .bind(local_category_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 pub async fn delete_pos_menu_item(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_item_id: &str,
 ) -> Result<(), sqlx::Error> {
@@ -333,13 +346,13 @@ pub async fn delete_pos_menu_item(
     )
     .bind(device_id.to_string())
     .bind(local_item_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 pub async fn update_pos_menu_item_active(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_item_id: &str,
     active: bool,
@@ -350,7 +363,7 @@ pub async fn update_pos_menu_item_active(
     .bind(active)
     .bind(device_id.to_string())
     .bind(local_item_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
@@ -399,6 +412,37 @@ pub async fn update_pos_menu_item_by_id(
     Ok(res.rows_affected() > 0)
 }
 
+/// Set a menu item's VAT rate bands by cloud row id. Omitted fields are not changed; Some(None)
+/// clears the band (standard rate, or the eat-in band for takeaway).
+pub async fn update_pos_menu_item_vat_by_id(
+    pool: &MySqlPool,
+    item_id: Uuid,
+    vat_rate: Option<Option<VatRate>>,
+    takeaway_vat_rate: Option<Option<VatRate>>,
+) -> Result<bool, sqlx::Error> {
+    let mut sets = vec!["updated_at = CURRENT_TIMESTAMP(3)"];
+    if vat_rate.is_some() {
+        sets.push("vat_rate = ?");
+    }
+    if takeaway_vat_rate.is_some() {
+        sets.push("takeaway_vat_rate = ?");
+    }
+    if sets.len() == 1 {
+        return Ok(false);
+    }
+    let q = format!("UPDATE pos_menu_items SET {} WHERE id = ?", sets.join(", "));
+    let mut query = sqlx::query(&q);
+    if let Some(rate) = vat_rate {
+        query = query.bind(rate.map(|r| r.as_str()));
+    }
+    if let Some(rate) = takeaway_vat_rate {
+        query = query.bind(rate.map(|r| r.as_str()));
+    }
+    query = query.bind(item_id.to_string());
+    let res = query.execute(pool).await?;
+    Ok(res.rows_affected() > 0)
+}
+
 /// Update menu category by cloud row id (for portal edits).
 pub async fn update_pos_menu_category_by_id(
     pool: &MySqlPool,
@@ -430,7 +474,7 @@ pub async fn update_pos_menu_category_by_id(
 }
 
 pub async fn update_pos_menu_item_image(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_item_id: &str,
     image_path: &str,
@@ -441,7 +485,7 @@ pub async fn update_pos_menu_item_image(
     .bind(image_path)
     .bind(device_id)
     .bind(local_item_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
@@ -479,7 +523,7 @@ pub async fn update_pos_menu_category_image_by_id(
 // ---------- Menu item modifiers (replace all for item) ----------
 
 pub async fn delete_pos_menu_item_modifiers(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_menu_item_id: &str,
 ) -> Result<(), sqlx::Error> {
@@ -488,13 +532,13 @@ pub async fn delete_pos_menu_item_modifiers(
     )
     .bind(device_id.to_string())
     .bind(local_menu_item_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 pub async fn insert_pos_menu_item_modifier(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_menu_item_id: &str,
     name: &str,
@@ -513,7 +557,7 @@ pub async fn insert_pos_menu_item_modifier(
     .bind(name)
     .bind(price_delta_pence)
     .bind(position)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
@@ -521,7 +565,7 @@ pub async fn insert_pos_menu_item_modifier(
 // ---------- Dish yields ----------
 
 pub async fn upsert_pos_dish_yield(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_menu_item_id: &str,
     estimated_total: Option<f64>,
@@ -543,13 +587,13 @@ pub async fn upsert_pos_dish_yield(
     .bind(estimated_total)
     .bind(remaining)
     .bind(warning_threshold)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
 pub async fn adjust_pos_dish_yield_remaining(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     device_id: Uuid,
     local_menu_item_id: &str,
     remaining: Option<f64>,
@@ -562,19 +606,20 @@ pub async fn adjust_pos_dish_yield_remaining(
     .bind(remaining)
     .bind(device_id.to_string())
     .bind(local_menu_item_id)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
 
-/// Dispatch by event_type and upsert/delete into store, menu, categories, items, modifiers, yields.
+/// Dispatch by event type and upsert/delete into store, menu, categories, items, modifiers, yields.
 /// Order/payment events (order_created, transaction_completed, receipt_created) are handled by orders::project_event_to_orders.
+/// `event_body` is the raw body, kept for alert details.
 pub async fn project_event_to_read_model(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
-    event_type: &str,
+    event: &DeviceEvent,
     event_body: &serde_json::Value,
     _occurred_at: chrono::DateTime<chrono::Utc>,
 ) -> Result<(), sqlx::Error> {
@@ -582,23 +627,8 @@ pub async fn project_event_to_read_model(
     // device for the store. If a non-canonical device attempts to change these,
     // we skip the projection, log a warning, and record an alert row so the
     // portal can surface it later.
-    let is_config_event = matches!(
-        event_type,
-        "store_updated"
-            | "menu_category_created"
-            | "menu_category_renamed"
-            | "menu_category_image"
-            | "menu_item_created"
-            | "menu_item_deleted"
-            | "menu_item_visibility"
-            | "menu_item_image"
-            | "menu_item_modifiers_set"
-            | "dish_yield_upserted"
-            | "dish_yield_adjusted"
-    );
-
-    if is_config_event {
-        let is_canonical = is_device_canonical_for_store(pool, store_id, device_id).await?;
+    if event.is_config_event() {
+        let is_canonical = is_device_canonical_for_store(&mut *conn, store_id, device_id).await?;
         if !is_canonical {
             let details = serde_json::to_string(event_body).ok();
             tracing::warn!(
@@ -606,165 +636,109 @@ pub async fn project_event_to_read_model(
                 org_id,
                 store_id,
                 device_id,
-                event_type
+                event.event_type()
             );
-            let _ = insert_device_config_alert(
-                pool,
+            insert_device_config_alert(
+                &mut *conn,
                 org_id,
                 store_id,
                 device_id,
-                event_type,
+                event.event_type(),
                 details.as_deref(),
             )
-            .await;
+            .await?;
             return Ok(());
         }
     }
 
-    match event_type {
-        "store_updated" => {
-            let local_store_id = event_body.get("store_id").and_then(|v| v.as_str()).unwrap_or("");
-            let name = event_body.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            let timezone = event_body.get("timezone").and_then(|v| v.as_str()).unwrap_or("Europe/London");
-            if !local_store_id.is_empty() {
-                upsert_pos_store(pool, org_id, store_id, device_id, local_store_id, name, timezone).await?;
-            }
+    match event {
+        DeviceEvent::StoreUpdated(e) => {
+            upsert_pos_store(&mut *conn, org_id, store_id, device_id, e.store_id.as_str(), &e.name, &e.timezone).await?;
         }
-        "menu_category_created" => {
-            let local_menu_id = event_body
-                .get("menu_id")
-                .and_then(|v| v.as_str())
-                .filter(|s| !s.is_empty())
-                .unwrap_or("default");
-            let local_category_id = event_body.get("category_id").and_then(|v| v.as_str()).unwrap_or("");
-            let name = event_body.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            let position = event_body.get("position").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
-            if !local_category_id.is_empty() {
-                let _ = ensure_pos_menu(pool, org_id, device_id, local_menu_id).await;
-                upsert_pos_menu_category(pool, org_id, device_id, local_menu_id, local_category_id, name, position, None).await?;
-            }
+        DeviceEvent::MenuCategoryCreated(e) => {
+            let local_menu_id = e.menu_id.as_deref().filter(|s| !s.is_empty()).unwrap_or("default");
+            ensure_pos_menu(&mut *conn, org_id, device_id, local_menu_id).await?;
+            upsert_pos_menu_category(&mut *conn, org_id, device_id, local_menu_id, e.category_id.as_str(), &e.name, e.position, None).await?;
         }
-        "menu_category_renamed" => {
-            let local_category_id = event_body.get("category_id").and_then(|v| v.as_str()).unwrap_or("");
-            let name = event_body.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            if !local_category_id.is_empty() {
-                // Upsert with same category_id; we need local_menu_id - get from existing row or use empty and rely on ON DUPLICATE
-                let row: Option<(String,)> = sqlx::query_as(
-                    "SELECT local_menu_id FROM pos_menu_categories WHERE device_id = ? AND local_category_id = ?",
-                )
-                .bind(device_id.to_string())
-                .bind(local_category_id)
-                .fetch_optional(pool)
-                .await?;
-                if let Some((local_menu_id,)) = row {
-                    upsert_pos_menu_category(pool, org_id, device_id, &local_menu_id, local_category_id, name, 0, None).await?;
-                }
+        DeviceEvent::MenuCategoryRenamed(e) => {
+            // Upsert with same category_id; local_menu_id comes from the existing row
+            let row: Option<(String,)> = sqlx::query_as(
+                "SELECT local_menu_id FROM pos_menu_categories WHERE device_id = ? AND local_category_id = ?",
+            )
+            .bind(device_id.to_string())
+            .bind(e.category_id.as_str())
+            .fetch_optional(&mut *conn)
+            .await?;
+            if let Some((local_menu_id,)) = row {
+                upsert_pos_menu_category(&mut *conn, org_id, device_id, &local_menu_id, e.category_id.as_str(), &e.name, 0, None).await?;
             }
         }
-        "menu_category_image" => {
-            let local_category_id = event_body.get("category_id").and_then(|v| v.as_str()).unwrap_or("");
-            let image_path = event_body.get("image_path").and_then(|v| v.as_str()).unwrap_or("");
-            if !local_category_id.is_empty() {
-                update_pos_menu_category_image(pool, device_id, local_category_id, image_path).await?;
-            }
+        DeviceEvent::MenuCategoryImage(e) => {
+            update_pos_menu_category_image(&mut *conn, device_id, e.category_id.as_str(), &e.image_path).await?;
         }
-        "menu_item_created" => {
-            let local_item_id = event_body.get("item_id").and_then(|v| v.as_str()).unwrap_or("");
-            let local_store_id = event_body.get("store_id").and_then(|v| v.as_str());
-            let local_category_id = event_body.get("category_id").and_then(|v| v.as_str());
-            let name = event_body.get("name").and_then(|v| v.as_str()).unwrap_or("");
-            let description = event_body.get("description").and_then(|v| v.as_str());
-            let price_pence = event_body
-                .get("price_pence")
-                .and_then(|v| v.as_i64())
-                .or_else(|| event_body.get("price").and_then(|v| v.as_i64()))
-                .or_else(|| event_body.get("price").and_then(|v| v.as_f64()).map(|p| (p * 100.0) as i64));
-            let active = event_body.get("active").and_then(|v| v.as_bool()).unwrap_or(true);
-            let image_path = event_body.get("image_path").and_then(|v| v.as_str());
-            let customer_editable = event_body.get("customer_editable").and_then(|v| v.as_bool()).unwrap_or(false);
-            if !local_item_id.is_empty() {
-                upsert_pos_menu_item(
-                    pool,
-                    org_id,
-                    device_id,
-                    local_item_id,
-                    local_store_id,
-                    local_category_id,
-                    name,
-                    description,
-                    price_pence,
-                    active,
-                    image_path,
-                    customer_editable,
-                )
-                .await?;
-            }
+        DeviceEvent::MenuItemCreated(e) => {
+            upsert_pos_menu_item(
+                &mut *conn,
+                org_id,
+                device_id,
+                e.item_id.as_str(),
+                e.store_id.as_deref(),
+                e.category_id.as_deref(),
+                &e.name,
+                e.description.as_deref(),
+                e.price_pence,
+                e.active,
+                e.image_path.as_deref(),
+                e.customer_editable,
+            )
+            .await?;
         }
-        "menu_item_deleted" => {
-            let local_item_id = event_body.get("item_id").and_then(|v| v.as_str()).unwrap_or("");
-            if !local_item_id.is_empty() {
-                delete_pos_menu_item(pool, device_id, local_item_id).await?;
-            }
+        DeviceEvent::MenuItemDeleted(e) => {
+            delete_pos_menu_item(&mut *conn, device_id, e.item_id.as_str()).await?;
         }
-        "menu_item_visibility" => {
-            let local_item_id = event_body.get("item_id").and_then(|v| v.as_str()).unwrap_or("");
-            let active = event_body.get("active").and_then(|v| v.as_bool()).unwrap_or(true);
-            if !local_item_id.is_empty() {
-                update_pos_menu_item_active(pool, device_id, local_item_id, active).await?;
-            }
+        DeviceEvent::MenuItemVisibility(e) => {
+            update_pos_menu_item_active(&mut *conn, device_id, e.item_id.as_str(), e.active).await?;
         }
-        "menu_item_image" => {
-            let local_item_id = event_body.get("item_id").and_then(|v| v.as_str()).unwrap_or("");
-            let image_path = event_body.get("image_path").and_then(|v| v.as_str()).unwrap_or("");
-            if !local_item_id.is_empty() {
-                update_pos_menu_item_image(pool, device_id, local_item_id, image_path).await?;
-            }
+        DeviceEvent::MenuItemImage(e) => {
+            update_pos_menu_item_image(&mut *conn, device_id, e.item_id.as_str(), &e.image_path).await?;
         }
-        "menu_item_modifiers_set" => {
-            let local_menu_item_id = event_body.get("menu_item_id").and_then(|v| v.as_str()).unwrap_or("");
-            if local_menu_item_id.is_empty() {
-                return Ok(());
-            }
-            delete_pos_menu_item_modifiers(pool, device_id, local_menu_item_id).await?;
-            if let Some(modifiers) = event_body.get("modifiers").and_then(|v| v.as_array()) {
-                for (idx, m) in modifiers.iter().enumerate() {
-                    let name = m.get("name").and_then(|v| v.as_str()).unwrap_or("");
-                    let price_delta_pence = m.get("price_delta_pence").and_then(|v| v.as_i64()).unwrap_or(0) as i32;
-                    let position = m.get("position").and_then(|v| v.as_i64()).unwrap_or(idx as i64) as i32;
-                    let _ = insert_pos_menu_item_modifier(
-                        pool,
-                        device_id,
-                        local_menu_item_id,
-                        name,
-                        price_delta_pence,
-                        position,
-                    )
-                    .await;
-                }
+        DeviceEvent::MenuItemModifiersSet(e) => {
+            let local_menu_item_id = e.menu_item_id.as_str();
+            delete_pos_menu_item_modifiers(&mut *conn, device_id, local_menu_item_id).await?;
+            for (idx, m) in e.modifiers.iter().enumerate() {
+                insert_pos_menu_item_modifier(
+                    &mut *conn,
+                    device_id,
+                    local_menu_item_id,
+                    &m.name,
+                    m.price_delta_pence,
+                    m.position.unwrap_or(idx as i32),
+                )
+                .await?;
             }
         }
-        "dish_yield_upserted" => {
-            let local_menu_item_id = event_body.get("menu_item_id").and_then(|v| v.as_str()).unwrap_or("");
-            let estimated_total = event_body.get("estimated_total").and_then(|v| v.as_f64());
-            let remaining = event_body.get("remaining").and_then(|v| v.as_f64());
-            let warning_threshold = event_body.get("warning_threshold").and_then(|v| v.as_f64());
-            if !local_menu_item_id.is_empty() {
-                upsert_pos_dish_yield(pool, device_id, local_menu_item_id, estimated_total, remaining, warning_threshold).await?;
-            }
+        DeviceEvent::DishYieldUpserted(e) => {
+            upsert_pos_dish_yield(&mut *conn, device_id, e.menu_item_id.as_str(), e.estimated_total, e.remaining, e.warning_threshold).await?;
         }
-        "dish_yield_adjusted" => {
-            let local_menu_item_id = event_body.get("menu_item_id").and_then(|v| v.as_str()).unwrap_or("");
-            let remaining = event_body.get("remaining").and_then(|v| v.as_f64());
-            if !local_menu_item_id.is_empty() {
-                adjust_pos_dish_yield_remaining(pool, device_id, local_menu_item_id, remaining).await?;
-            }
+        DeviceEvent::DishYieldAdjusted(e) => {
+            adjust_pos_dish_yield_remaining(&mut *conn, device_id, e.menu_item_id.as_str(), e.remaining).await?;
         }
-        "device_updated" => {
-            let device_name = event_body.get("device_name").and_then(|v| v.as_str()).filter(|s| !s.is_empty());
-            let is_primary = event_body.get("is_primary").and_then(|v| v.as_bool()).unwrap_or(false);
-            update_device_name_primary(pool, device_id, device_name, is_primary).await?;
+        DeviceEvent::DeviceUpdated(e) => {
+            let device_name = e.device_name.as_deref().filter(|s| !s.is_empty());
+            update_device_name_primary(&mut *conn, device_id, device_name, e.is_primary).await?;
         }
-        _ => {}
+        DeviceEvent::OrderCreated(_)
+        | DeviceEvent::OrderUpdated(_)
+        | DeviceEvent::TransactionCompleted(_)
+        | DeviceEvent::ReceiptCreated(_)
+        | DeviceEvent::OrderVoided(_)
+        | DeviceEvent::OrderRefunded(_)
+        | DeviceEvent::TransactionRefunded(_)
+        | DeviceEvent::OrderSentToKitchen(_)
+        | DeviceEvent::ItemStarted(_)
+        | DeviceEvent::ItemBumped(_)
+        | DeviceEvent::OrderReady(_)
+        | DeviceEvent::ZReportClosed(_) => {}
     }
     Ok(())
 }
@@ -0,0 +1,467 @@
+//! Read-model rebuild: wipe a store's projected rows and replay device_event_log through the
+//! projection functions. Used after projection bugs are fixed so existing data picks up the fix.
+//!
+//! The store's events are replayed in log_pos order (the order the live worker saw them), in
+//! batches of one transaction each, so a large store never holds its locks for the whole rebuild.
+//! While it runs the rebuild holds a lease on the store's projection cursors (rebuild_until) that
+//! keeps the background worker and other rebuilds out; only events at or below each device's
+//! cursor are replayed, so the cursors stay valid and the worker carries on afterwards with
+//! whatever was still pending. Readers see the store part-rebuilt until the last batch commits.
+//!
+//! Orders keep their ids and global_order_ids: the orders rows and their device refs stay, only
+//! their projected columns and child rows are reset, so commands, approvals and timelines that
+//! point at an order still resolve.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use sqlx::{MySqlConnection, MySqlExecutor, MySqlPool};
+use uuid::Uuid;
+
+use crate::orders::project_event_to_orders;
+use crate::projection::{record_dead_letter, LoggedEvent, LOGGED_EVENT_COLUMNS};
+use crate::read_model::project_event_to_read_model;
+
+/// Events replayed per batch (one transaction each).
+const REPLAY_PAGE: i64 = 500;
+
+/// How long the cursor lease lasts; renewed by every batch. A rebuild that dies leaves the
+/// worker paused for at most this long.
+const REBUILD_LEASE_SECS: i64 = 600;
+
+/// Which read models to rebuild.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RebuildScope {
+    /// orders, order_items, order_events, transactions, receipts, order_refunds and device
+    /// Z-report closes.
+    /// Closed Z-reports (z_reports) are kept.
+    Orders,
+    /// pos_store_sync, pos_menus, pos_menu_*, pos_dish_yields and device_config_alerts.
+    /// Rows created in the portal (local ids prefixed "cloud-") and uploaded images are kept.
+    Menu,
+    All,
+}
+
+impl RebuildScope {
+    fn orders(self) -> bool {
+        matches!(self, RebuildScope::Orders | RebuildScope::All)
+    }
+
+    fn menu(self) -> bool {
+        matches!(self, RebuildScope::Menu | RebuildScope::All)
+    }
+}
+
+impl FromStr for RebuildScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "orders" => Ok(RebuildScope::Orders),
+            "menu" => Ok(RebuildScope::Menu),
+            "all" => Ok(RebuildScope::All),
+            other => Err(format!("unknown scope '{}': expected orders, menu or all", other)),
+        }
+    }
+}
+
+/// Counts for one store's rebuild.
+#[derive(Debug, Default, serde::Serialize)]
+pub struct RebuildReport {
+    pub store_id: String,
+    pub devices: u64,
+    pub events_total: u64,
+    pub events_projected: u64,
+    /// Events that failed to project; they are (re)recorded in projection_dead_letters.
+    pub events_failed: u64,
+    pub orders: i64,
+    pub menu_items: i64,
+}
+
+/// Why a store could not be rebuilt.
+#[derive(Debug)]
+pub enum RebuildError {
+    /// Another rebuild of the store holds its cursors.
+    InProgress,
+    Db(sqlx::Error),
+}
+
+impl fmt::Display for RebuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RebuildError::InProgress => write!(f, "a rebuild of this store is already running"),
+            RebuildError::Db(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for RebuildError {
+    fn from(e: sqlx::Error) -> Self {
+        RebuildError::Db(e)
+    }
+}
+
+/// Store ids belonging to an org (for org-wide rebuilds).
+pub async fn list_store_ids_for_org(pool: &MySqlPool, org_id: Uuid) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT id FROM stores WHERE org_id = ? ORDER BY name")
+        .bind(org_id.to_string())
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().filter_map(|(s,)| Uuid::parse_str(&s).ok()).collect())
+}
+
+/// Rebuild the selected read models for one store. `on_progress(done, total)` is called after
+/// every batch of replayed events. On failure the batches already committed stay and the lease is
+/// released; run the rebuild again to finish it.
+pub async fn rebuild_store_read_model(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    scope: RebuildScope,
+    on_progress: &mut (dyn FnMut(u64, u64) + Send),
+) -> Result<RebuildReport, RebuildError> {
+    let mut report = RebuildReport {
+        store_id: store_id.to_string(),
+        ..Default::default()
+    };
+    let mut tx = pool.begin().await?;
+
+    // Wait for any in-flight worker pass on these devices, then lease them until the rebuild ends.
+    let cursors: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT device_id, CAST(COALESCE(rebuild_until > CURRENT_TIMESTAMP(3), 0) AS SIGNED)
+        FROM device_projection_cursors WHERE store_id = ? FOR UPDATE
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(&mut *tx)
+    .await?;
+    if cursors.iter().any(|(_, leased)| *leased != 0) {
+        return Err(RebuildError::InProgress);
+    }
+    report.devices = cursors.len() as u64;
+    renew_lease(&mut tx, store_id).await?;
+
+    let (total,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*)
+        FROM device_event_log l
+        JOIN device_projection_cursors c ON c.device_id = l.device_id
+        WHERE l.store_id = ? AND l.log_pos <= c.last_projected_pos
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_one(&mut *tx)
+    .await?;
+    report.events_total = total as u64;
+
+    let images = if scope.menu() {
+        let images = load_menu_images(&mut tx, store_id).await?;
+        clear_menu_read_model(&mut tx, store_id).await?;
+        images
+    } else {
+        MenuImages::default()
+    };
+    if scope.orders() {
+        clear_orders_read_model(&mut tx, store_id).await?;
+    }
+    tx.commit().await?;
+
+    let result = replay_store(pool, store_id, scope, &images, &mut report, on_progress).await;
+    if let Err(err) = &result {
+        tracing::warn!("rebuild: store {} failed: {}", store_id, err);
+        if let Err(e) = release_lease(pool, store_id).await {
+            tracing::warn!("rebuild: releasing the lease on store {} failed: {}", store_id, e);
+        }
+    }
+    result?;
+    Ok(report)
+}
+
+/// Replay the store's projected events in log_pos order, one transaction per batch, then restore
+/// what is not in the event log and release the lease.
+async fn replay_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    scope: RebuildScope,
+    images: &MenuImages,
+    report: &mut RebuildReport,
+    on_progress: &mut (dyn FnMut(u64, u64) + Send),
+) -> Result<(), sqlx::Error> {
+    let open_dead_letters: HashSet<String> = sqlx::query_as::<_, (String,)>(
+        "SELECT event_log_id FROM projection_dead_letters WHERE store_id = ? AND resolved_at IS NULL",
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(id,)| id)
+    .collect();
+
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM device_event_log l
+        JOIN device_projection_cursors c ON c.device_id = l.device_id
+        WHERE l.store_id = ? AND l.log_pos > ? AND l.log_pos <= c.last_projected_pos
+        ORDER BY l.log_pos
+        LIMIT ?
+        "#,
+        LOGGED_EVENT_COLUMNS
+            .split(", ")
+            .map(|c| format!("l.{}", c))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    let mut after_pos = 0i64;
+    let mut done = 0u64;
+    loop {
+        let mut tx = pool.begin().await?;
+        renew_lease(&mut tx, store_id).await?;
+        let events: Vec<LoggedEvent> = sqlx::query(&sql)
+            .bind(store_id.to_string())
+            .bind(after_pos)
+            .bind(REPLAY_PAGE)
+            .fetch_all(&mut *tx)
+            .await?
+            .iter()
+            .map(LoggedEvent::from_row)
+            .collect();
+        let Some(last) = events.last() else {
+            tx.commit().await?;
+            break;
+        };
+        after_pos = last.log_pos;
+        for ev in &events {
+            match replay_event(&mut tx, ev, scope).await {
+                Ok(()) => {
+                    report.events_projected += 1;
+                    if open_dead_letters.contains(&ev.id) {
+                        sqlx::query(
+                            "UPDATE projection_dead_letters SET resolved_at = CURRENT_TIMESTAMP(3) WHERE event_log_id = ?",
+                        )
+                        .bind(&ev.id)
+                        .execute(&mut *tx)
+                        .await?;
+                    }
+                }
+                Err(err) => {
+                    report.events_failed += 1;
+                    tracing::warn!(
+                        "rebuild: event {} ({}) on device {} failed: {}",
+                        ev.event_id,
+                        ev.event_type,
+                        ev.device_id,
+                        err
+                    );
+                    record_dead_letter(&mut tx, ev, 1, &err.to_string()).await?;
+                }
+            }
+        }
+        tx.commit().await?;
+        done += events.len() as u64;
+        on_progress(done, report.events_total);
+    }
+
+    let mut tx = pool.begin().await?;
+    if scope.menu() {
+        restore_menu_images(&mut tx, images).await?;
+    }
+    let (orders,): (i64,) = sqlx::query_as("SELECT COUNT(*) FROM orders WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .fetch_one(&mut *tx)
+        .await?;
+    let (menu_items,): (i64,) = sqlx::query_as(
+        "SELECT COUNT(*) FROM pos_menu_items WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?)",
+    )
+    .bind(store_id.to_string())
+    .fetch_one(&mut *tx)
+    .await?;
+    report.orders = orders;
+    report.menu_items = menu_items;
+    release_lease(&mut *tx, store_id).await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+async fn renew_lease(conn: &mut MySqlConnection, store_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE device_projection_cursors SET rebuild_until = TIMESTAMPADD(SECOND, ?, CURRENT_TIMESTAMP(3)) WHERE store_id = ?",
+    )
+    .bind(REBUILD_LEASE_SECS)
+    .bind(store_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn release_lease(executor: impl MySqlExecutor<'_>, store_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE device_projection_cursors SET rebuild_until = NULL WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Project one event for the selected scope inside a savepoint.
+async fn replay_event(
+    conn: &mut MySqlConnection,
+    ev: &LoggedEvent,
+    scope: RebuildScope,
+) -> Result<(), sqlx::Error> {
+    if scope == RebuildScope::All {
+        return ev.project_in_savepoint(conn).await;
+    }
+    let Some(event) = ev.parse()? else {
+        return Ok(());
+    };
+    let mut savepoint = sqlx::Connection::begin(&mut *conn).await?;
+    let result = if scope.menu() {
+        project_event_to_read_model(
+            &mut savepoint,
+            ev.org_id,
+            ev.store_id,
+            ev.device_id,
+            &event,
+            &ev.event_body,
+            ev.occurred_at,
+        )
+        .await
+    } else {
+        project_event_to_orders(
+            &mut savepoint,
+            ev.org_id,
+            ev.store_id,
+            ev.device_id,
+            &event,
+            &ev.event_body,
+            ev.occurred_at,
+        )
+        .await
+    };
+    match result {
+        Ok(()) => savepoint.commit().await,
+        Err(err) => {
+            savepoint.rollback().await?;
+            Err(err)
+        }
+    }
+}
+
+/// Reset the store's orders to what resolve_order creates and delete what the events project
+/// under them. Orders and order_device_refs stay so order ids are stable.
+async fn clear_orders_read_model(conn: &mut MySqlConnection, store_id: Uuid) -> Result<(), sqlx::Error> {
+    let store_scoped = [
+        "DELETE FROM z_report_device_closes WHERE store_id = ?",
+        "DELETE FROM receipts WHERE store_id = ?",
+        "DELETE FROM order_kitchen_items WHERE store_id = ?",
+        "DELETE FROM order_item_modifiers WHERE order_id IN (SELECT id FROM orders WHERE store_id = ?)",
+        "DELETE FROM order_discounts WHERE order_id IN (SELECT id FROM orders WHERE store_id = ?)",
+        "DELETE FROM order_taxes WHERE order_id IN (SELECT id FROM orders WHERE store_id = ?)",
+        "DELETE FROM order_items WHERE order_id IN (SELECT id FROM orders WHERE store_id = ?)",
+        "DELETE FROM order_events WHERE order_id IN (SELECT id FROM orders WHERE store_id = ?)",
+    ];
+    for sql in store_scoped {
+        sqlx::query(sql)
+            .bind(store_id.to_string())
+            .execute(&mut *conn)
+            .await?;
+    }
+    sqlx::query("DELETE FROM order_refunds WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query("DELETE FROM transactions WHERE store_id = ?")
+        .bind(store_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    sqlx::query(
+        r#"
+        UPDATE orders
+        SET status = 'open', total_cents = NULL, refunded_cents = 0, tip_cents = NULL,
+            service_charge_cents = NULL, service_type = NULL, delivery_provider = NULL,
+            external_order_id = NULL, created_event_at = NULL, content_revision_at = NULL,
+            content_revision_device_id = NULL, kitchen_sent_at = NULL, kitchen_ready_at = NULL,
+            voided_at = NULL, void_reason = NULL, voided_by_staff_id = NULL, voided_by_staff_name = NULL
+        WHERE store_id = ?
+        "#,
+    )
+    .bind(store_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+async fn clear_menu_read_model(conn: &mut MySqlConnection, store_id: Uuid) -> Result<(), sqlx::Error> {
+    let device_scoped = [
+        "DELETE FROM pos_menu_item_modifiers WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?)",
+        "DELETE FROM pos_dish_yields WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?)",
+        "DELETE FROM pos_menu_items WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?) AND local_item_id NOT LIKE 'cloud-%'",
+        "DELETE FROM pos_menu_categories WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?) AND local_category_id NOT LIKE 'cloud-%'",
+        "DELETE FROM pos_menus WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?)",
+        "DELETE FROM pos_store_sync WHERE store_id = ?",
+        "DELETE FROM device_config_alerts WHERE store_id = ?",
+    ];
+    for sql in device_scoped {
+        sqlx::query(sql)
+            .bind(store_id.to_string())
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Uploaded images are portal-side state, not in the event log; keep them across a menu rebuild.
+#[derive(Debug, Default)]
+struct MenuImages {
+    items: Vec<(String, String, String)>,
+    categories: Vec<(String, String, String)>,
+}
+
+async fn load_menu_images(conn: &mut MySqlConnection, store_id: Uuid) -> Result<MenuImages, sqlx::Error> {
+    let items = sqlx::query_as(
+        r#"
+        SELECT device_id, local_item_id, image_path FROM pos_menu_items
+        WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?) AND image_path IS NOT NULL
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+    let categories = sqlx::query_as(
+        r#"
+        SELECT device_id, local_category_id, image_path FROM pos_menu_categories
+        WHERE device_id IN (SELECT id FROM devices WHERE store_id = ?) AND image_path IS NOT NULL
+        "#,
+    )
+    .bind(store_id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(MenuImages { items, categories })
+}
+
+async fn restore_menu_images(conn: &mut MySqlConnection, images: &MenuImages) -> Result<(), sqlx::Error> {
+    for (device_id, local_id, path) in &images.items {
+        sqlx::query(
+            "UPDATE pos_menu_items SET image_path = ? WHERE device_id = ? AND local_item_id = ? AND image_path IS NULL",
+        )
+        .bind(path)
+        .bind(device_id)
+        .bind(local_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    for (device_id, local_id, path) in &images.categories {
+        sqlx::query(
+            "UPDATE pos_menu_categories SET image_path = ? WHERE device_id = ? AND local_category_id = ? AND image_path IS NULL",
+        )
+        .bind(path)
+        .bind(device_id)
+        .bind(local_id)
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
+
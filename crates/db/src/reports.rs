@@ -0,0 +1,446 @@
+//! Sales reporting over the orders read model (orders, order_items, transactions, order_refunds)
+//! with item names and categories from the POS menu read model. Queries take report windows: a
+//! set of stores sharing a reporting clock and the UTC range of the requested business days for that
+//! clock, so stores in different timezones are each cut at their own trading-day boundaries.
+
+use domain::StoreClock;
+use sqlx::mysql::{MySql, MySqlArguments};
+use sqlx::query::Query;
+use sqlx::{MySqlPool, Row};
+use uuid::Uuid;
+
+/// Which stores a report covers.
+#[derive(Debug, Clone, Copy)]
+pub enum ReportScope {
+    Org(Uuid),
+    Franchise(Uuid),
+    Store(Uuid),
+}
+
+/// Stores sharing a reporting clock and the UTC range [start, end) to report on for them.
+#[derive(Debug, Clone)]
+pub struct ReportWindow {
+    pub store_ids: Vec<String>,
+    pub clock: StoreClock,
+    pub start: chrono::NaiveDateTime,
+    pub end: chrono::NaiveDateTime,
+}
+
+/// Store-local period sales are grouped by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReportBucket {
+    /// Hour of the day, "13:00".
+    LocalHour,
+    /// Business day, "2026-03-28".
+    TradingDay,
+}
+
+/// Stores in scope (including inactive ones, so past takings still count) with their clocks.
+pub async fn list_report_stores(
+    pool: &MySqlPool,
+    scope: ReportScope,
+) -> Result<Vec<(String, StoreClock)>, sqlx::Error> {
+    let (column, id) = match scope {
+        ReportScope::Org(id) => ("org_id", id),
+        ReportScope::Franchise(id) => ("franchise_id", id),
+        ReportScope::Store(id) => ("id", id),
+    };
+    let sql = format!("SELECT id, timezone, trading_day_cutoff FROM stores WHERE {} = ?", column);
+    let rows: Vec<(String, String, chrono::NaiveTime)> = sqlx::query_as(&sql)
+        .bind(id.to_string())
+        .fetch_all(pool)
+        .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, timezone, cutoff)| (id, StoreClock::new(&timezone, cutoff)))
+        .collect())
+}
+
+/// Takings of the orders in one period.
+#[derive(Debug, Clone)]
+pub struct ReportOrderTotals {
+    /// See [`ReportBucket`].
+    pub period: String,
+    pub order_count: i64,
+    /// Order totals, or the sum of the lines when the POS sent no total.
+    pub total_cents: i64,
+    pub item_count: f64,
+    /// Order- and line-level discounts.
+    pub discount_cents: i64,
+    pub service_charge_cents: i64,
+    pub tip_cents: i64,
+}
+
+/// Orders in the windows, excluding voided ones, totalled per period. Refunded orders still count
+/// at their full total; their refunds are taken off by refund time (see [`list_report_refund_totals`]).
+pub async fn list_report_order_totals(
+    pool: &MySqlPool,
+    windows: &[ReportWindow],
+    bucket: ReportBucket,
+) -> Result<Vec<ReportOrderTotals>, sqlx::Error> {
+    if windows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let spans = report_spans(windows, bucket);
+    let sql = format!(
+        r#"
+        SELECT
+          period,
+          COUNT(*) AS order_count,
+          CAST(COALESCE(SUM(total_cents), 0) AS SIGNED) AS total_cents,
+          CAST(COALESCE(SUM(item_count), 0) AS DOUBLE) AS item_count,
+          CAST(COALESCE(SUM(discount_cents), 0) AS SIGNED) AS discount_cents,
+          CAST(COALESCE(SUM(service_charge_cents), 0) AS SIGNED) AS service_charge_cents,
+          CAST(COALESCE(SUM(tip_cents), 0) AS SIGNED) AS tip_cents
+        FROM (
+          SELECT
+            {period} AS period,
+            COALESCE(o.total_cents,
+                     (SELECT SUM(oi.line_total_cents) FROM order_items oi WHERE oi.order_id = o.id),
+                     0) AS total_cents,
+            (SELECT SUM(oi.quantity) FROM order_items oi WHERE oi.order_id = o.id) AS item_count,
+            (SELECT SUM(d.amount_cents) FROM order_discounts d WHERE d.order_id = o.id) AS discount_cents,
+            o.service_charge_cents,
+            o.tip_cents
+          FROM orders o
+          WHERE o.status <> 'voided' AND ({windows})
+        ) report_orders
+        GROUP BY period
+        ORDER BY period
+        "#,
+        period = period_expr("o", &spans, bucket),
+        windows = window_condition("o", windows)
+    );
+    let query = bind_spans(sqlx::query(&sql), &spans);
+    let rows = bind_windows(query, windows).fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportOrderTotals {
+            period: row.get("period"),
+            order_count: row.get("order_count"),
+            total_cents: row.get("total_cents"),
+            item_count: row.get("item_count"),
+            discount_cents: row.get("discount_cents"),
+            service_charge_cents: row.get("service_charge_cents"),
+            tip_cents: row.get("tip_cents"),
+        })
+        .collect())
+}
+
+/// Refunds made in one period.
+#[derive(Debug, Clone)]
+pub struct ReportRefundTotals {
+    /// See [`ReportBucket`].
+    pub period: String,
+    /// Positive amount refunded.
+    pub amount_cents: i64,
+}
+
+/// Refunds made in the windows (by refund time, not order time), totalled per period.
+pub async fn list_report_refund_totals(
+    pool: &MySqlPool,
+    windows: &[ReportWindow],
+    bucket: ReportBucket,
+) -> Result<Vec<ReportRefundTotals>, sqlx::Error> {
+    if windows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let spans = report_spans(windows, bucket);
+    let sql = format!(
+        r#"
+        SELECT period, CAST(COALESCE(SUM(amount_cents), 0) AS SIGNED) AS amount_cents
+        FROM (
+          SELECT {period} AS period, r.amount_cents
+          FROM order_refunds r
+          WHERE {windows}
+        ) report_refunds
+        GROUP BY period
+        ORDER BY period
+        "#,
+        period = period_expr("r", &spans, bucket),
+        windows = window_condition("r", windows)
+    );
+    let query = bind_spans(sqlx::query(&sql), &spans);
+    let rows = bind_windows(query, windows).fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportRefundTotals {
+            period: row.get("period"),
+            amount_cents: row.get("amount_cents"),
+        })
+        .collect())
+}
+
+/// Sales of one menu item (or unmatched product_ref) across the windows.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReportItemSales {
+    pub item: String,
+    pub category: String,
+    pub quantity: f64,
+    pub sales_cents: i64,
+    /// Orders the item appeared on.
+    pub order_count: i64,
+}
+
+/// Item sales in the windows, best selling (by sales) first. Voided and fully refunded orders are
+/// left out; refunds are not itemised, so partially refunded orders count in full. Lines are
+/// matched to the store's menu (`store_menu_items`: its template menu, else its canonical
+/// device's), whichever till took the order; unmatched lines are reported under their
+/// product_ref as "Uncategorised".
+pub async fn list_report_item_sales(
+    pool: &MySqlPool,
+    windows: &[ReportWindow],
+) -> Result<Vec<ReportItemSales>, sqlx::Error> {
+    if windows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        r#"
+        SELECT
+          COALESCE(mi.name, oi.product_ref, 'Unknown item') AS item,
+          COALESCE(mi.category_name, 'Uncategorised') AS category,
+          CAST(SUM(oi.quantity) AS DOUBLE) AS quantity,
+          CAST(COALESCE(SUM(COALESCE(oi.line_total_cents, ROUND(oi.unit_price_cents * oi.quantity))), 0) AS SIGNED) AS sales_cents,
+          COUNT(DISTINCT o.id) AS order_count
+        FROM order_items oi
+        JOIN orders o ON o.id = oi.order_id
+        LEFT JOIN store_menu_items mi ON mi.store_id = o.store_id AND mi.local_item_id = oi.product_ref
+        WHERE o.status NOT IN ('voided', 'refunded') AND ({})
+        GROUP BY item, category
+        ORDER BY sales_cents DESC, item
+        "#,
+        window_condition("o", windows)
+    );
+    let rows = bind_windows(sqlx::query(&sql), windows).fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportItemSales {
+            item: row.get("item"),
+            category: row.get("category"),
+            quantity: row.get("quantity"),
+            sales_cents: row.get("sales_cents"),
+            order_count: row.get("order_count"),
+        })
+        .collect())
+}
+
+/// Takings for one payment kind (transactions.kind, e.g. "card", "cash").
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ReportPaymentTotal {
+    pub kind: String,
+    /// Payments, not counting refunds.
+    pub transaction_count: i64,
+    pub refund_count: i64,
+    /// Refunded to this kind (positive).
+    pub refund_cents: i64,
+    /// Net of refunds.
+    pub amount_cents: i64,
+}
+
+/// Transactions in the windows grouped by kind, largest net amount first. Refunds are the
+/// negative rows.
+pub async fn list_report_payment_totals(
+    pool: &MySqlPool,
+    windows: &[ReportWindow],
+) -> Result<Vec<ReportPaymentTotal>, sqlx::Error> {
+    if windows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let sql = format!(
+        r#"
+        SELECT
+          t.kind,
+          CAST(SUM(t.amount_cents >= 0) AS SIGNED) AS transaction_count,
+          CAST(SUM(t.amount_cents < 0) AS SIGNED) AS refund_count,
+          CAST(COALESCE(SUM(CASE WHEN t.amount_cents < 0 THEN -t.amount_cents ELSE 0 END), 0) AS SIGNED) AS refund_cents,
+          CAST(COALESCE(SUM(t.amount_cents), 0) AS SIGNED) AS amount_cents
+        FROM transactions t
+        WHERE {}
+        GROUP BY t.kind
+        ORDER BY amount_cents DESC, t.kind
+        "#,
+        window_condition("t", windows)
+    );
+    let rows = bind_windows(sqlx::query(&sql), windows).fetch_all(pool).await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportPaymentTotal {
+            kind: row.get("kind"),
+            transaction_count: row.get("transaction_count"),
+            refund_count: row.get("refund_count"),
+            refund_cents: row.get("refund_cents"),
+            amount_cents: row.get("amount_cents"),
+        })
+        .collect())
+}
+
+/// Sales at one VAT rate on one order, or the share of a refund made against it (negative).
+#[derive(Debug, Clone)]
+pub struct ReportVatLine {
+    pub store_id: String,
+    /// Order time for sales, refund time for refunds.
+    pub occurred_at: chrono::NaiveDateTime,
+    /// eat_in, takeaway, delivery; None when the POS did not say.
+    pub service_type: Option<String>,
+    /// Rate name from the tax line ("standard", or what the POS sent).
+    pub name: Option<String>,
+    pub rate_percent: f64,
+    pub net_cents: i64,
+    pub tax_cents: i64,
+}
+
+/// Tax lines of non-voided orders taken in the windows, then refunds made in the windows split
+/// across the refunded order's tax lines in proportion to their gross. A tax line without a net
+/// amount is grossed down from its tax.
+pub async fn list_report_vat_lines(
+    pool: &MySqlPool,
+    windows: &[ReportWindow],
+) -> Result<Vec<ReportVatLine>, sqlx::Error> {
+    if windows.is_empty() {
+        return Ok(Vec::new());
+    }
+    let line_net = |a: &str| {
+        format!(
+            "COALESCE({a}.net_cents, IF({a}.rate_percent > 0, ROUND({a}.tax_cents * 100 / {a}.rate_percent), 0))",
+            a = a
+        )
+    };
+    let sales_sql = format!(
+        r#"
+        SELECT o.store_id, o.occurred_at, o.service_type, t.name, CAST(t.rate_percent AS DOUBLE) AS rate_percent,
+               CAST({net} AS SIGNED) AS net_cents, t.tax_cents
+        FROM order_taxes t
+        JOIN orders o ON o.id = t.order_id
+        WHERE o.status <> 'voided' AND ({windows})
+        "#,
+        net = line_net("t"),
+        windows = window_condition("o", windows)
+    );
+    let refund_sql = format!(
+        r#"
+        SELECT store_id, occurred_at, service_type, name, rate_percent,
+               CAST(-ROUND(amount_cents * net_cents / gross_cents) AS SIGNED) AS net_cents,
+               CAST(-ROUND(amount_cents * tax_cents / gross_cents) AS SIGNED) AS tax_cents
+        FROM (
+          SELECT r.store_id, r.occurred_at, o.service_type, t.name, CAST(t.rate_percent AS DOUBLE) AS rate_percent,
+                 r.amount_cents, {net} AS net_cents, t.tax_cents,
+                 (SELECT SUM({net2} + t2.tax_cents) FROM order_taxes t2 WHERE t2.order_id = r.order_id) AS gross_cents
+          FROM order_refunds r
+          JOIN orders o ON o.id = r.order_id
+          JOIN order_taxes t ON t.order_id = r.order_id
+          WHERE o.status <> 'voided' AND ({windows})
+        ) refund_taxes
+        WHERE gross_cents > 0
+        "#,
+        net = line_net("t"),
+        net2 = line_net("t2"),
+        windows = window_condition("r", windows)
+    );
+    let mut rows = bind_windows(sqlx::query(&sales_sql), windows).fetch_all(pool).await?;
+    rows.extend(bind_windows(sqlx::query(&refund_sql), windows).fetch_all(pool).await?);
+    Ok(rows
+        .into_iter()
+        .map(|row| ReportVatLine {
+            store_id: row.get("store_id"),
+            occurred_at: row.get("occurred_at"),
+            service_type: row.get("service_type"),
+            name: row.get("name"),
+            rate_percent: row.get("rate_percent"),
+            net_cents: row.get("net_cents"),
+            tax_cents: row.get("tax_cents"),
+        })
+        .collect())
+}
+
+/// Part of a window over which its stores' UTC offset is fixed, so the local period of a stored
+/// UTC time is that time plus `shift_secs`.
+struct ReportSpan<'a> {
+    store_ids: &'a [String],
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+    shift_secs: i64,
+}
+
+fn report_spans(windows: &[ReportWindow], bucket: ReportBucket) -> Vec<ReportSpan<'_>> {
+    windows
+        .iter()
+        .flat_map(|w| {
+            // Trading days start at the cutoff, so shift it back to midnight.
+            let cutoff_secs = match bucket {
+                ReportBucket::LocalHour => 0,
+                ReportBucket::TradingDay => w.clock.cutoff_secs(),
+            };
+            w.clock
+                .offset_spans(w.start.and_utc(), w.end.and_utc())
+                .into_iter()
+                .map(move |(start, end, offset)| ReportSpan {
+                    store_ids: &w.store_ids,
+                    start: start.naive_utc(),
+                    end: end.naive_utc(),
+                    shift_secs: offset - cutoff_secs,
+                })
+        })
+        .collect()
+}
+
+/// `CASE WHEN <span> THEN DATE_FORMAT(<local time>, ..) ... END`: the period of `alias.occurred_at`.
+fn period_expr(alias: &str, spans: &[ReportSpan<'_>], bucket: ReportBucket) -> String {
+    let format = match bucket {
+        ReportBucket::LocalHour => "%H:00",
+        ReportBucket::TradingDay => "%Y-%m-%d",
+    };
+    let whens: String = spans
+        .iter()
+        .map(|span| {
+            format!(
+                " WHEN {a}.store_id IN ({}) AND {a}.occurred_at >= ? AND {a}.occurred_at < ? \
+                 THEN DATE_FORMAT(DATE_ADD({a}.occurred_at, INTERVAL ? SECOND), '{}')",
+                vec!["?"; span.store_ids.len()].join(", "),
+                format,
+                a = alias
+            )
+        })
+        .collect();
+    format!("CASE{} END", whens)
+}
+
+fn bind_spans<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    spans: &'q [ReportSpan<'q>],
+) -> Query<'q, MySql, MySqlArguments> {
+    for span in spans {
+        for id in span.store_ids {
+            query = query.bind(id);
+        }
+        query = query.bind(span.start).bind(span.end).bind(span.shift_secs);
+    }
+    query
+}
+
+/// `(t.store_id IN (?, ..) AND t.occurred_at >= ? AND t.occurred_at < ?) OR ...` for `alias`.
+fn window_condition(alias: &str, windows: &[ReportWindow]) -> String {
+    windows
+        .iter()
+        .map(|w| {
+            format!(
+                "({a}.store_id IN ({}) AND {a}.occurred_at >= ? AND {a}.occurred_at < ?)",
+                vec!["?"; w.store_ids.len()].join(", "),
+                a = alias
+            )
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+fn bind_windows<'q>(
+    mut query: Query<'q, MySql, MySqlArguments>,
+    windows: &'q [ReportWindow],
+) -> Query<'q, MySql, MySqlArguments> {
+    for w in windows {
+        for id in &w.store_ids {
+            query = query.bind(id);
+        }
+        query = query.bind(w.start).bind(w.end);
+    }
+    query
+}
@@ -1,68 +1,329 @@
-//! Sync: idempotent event insert, device_sync_state update, command fetch/ack.
+//! Sync: idempotent (batch) event insert, device_sync_state seq tracking, command fetch/ack.
 
-use sqlx::MySqlPool;
+use std::collections::HashSet;
+
+use sqlx::{MySql, MySqlConnection, MySqlExecutor, MySqlPool, QueryBuilder};
 use uuid::Uuid;
 
-/// Insert event idempotently (ignore duplicate on (device_id, event_id)).
-/// Returns whether the row was actually inserted (true) or duplicate (false).
-pub async fn insert_event_idempotent(
-    pool: &MySqlPool,
+use crate::command_results::{log_command_status, CommandEntity};
+use crate::menu_schedule::apply_menu_dayparts;
+use crate::menu_versions::{record_menu_version, MenuPublish};
+
+/// Rows per multi-row INSERT into device_event_log (keeps statements well under max_allowed_packet).
+const EVENT_INSERT_CHUNK: usize = 200;
+
+/// Event accepted by POST /sync/events, ready for the batch insert into device_event_log.
+#[derive(Debug)]
+pub struct NewDeviceEvent<'a> {
+    pub event_id: Uuid,
+    pub seq: Option<i64>,
+    pub event_type: &'a str,
+    pub event_body: &'a serde_json::Value,
+    pub schema_version: u32,
+    /// As reported by the device.
+    pub occurred_at: chrono::DateTime<chrono::Utc>,
+    /// Set (with clock_skew_ms) when the device clock is off by CLOCK_SKEW_THRESHOLD_SECS or more.
+    pub corrected_occurred_at: Option<chrono::DateTime<chrono::Utc>>,
+    pub clock_skew_ms: Option<i64>,
+}
+
+/// Return the subset of event_ids already present in device_event_log for this device.
+pub async fn find_existing_event_ids(
+    conn: &mut MySqlConnection,
+    device_id: Uuid,
+    event_ids: &[Uuid],
+) -> Result<HashSet<Uuid>, sqlx::Error> {
+    let mut existing = HashSet::new();
+    for chunk in event_ids.chunks(EVENT_INSERT_CHUNK) {
+        let mut qb: QueryBuilder<MySql> =
+            QueryBuilder::new("SELECT event_id FROM device_event_log WHERE device_id = ");
+        qb.push_bind(device_id.to_string());
+        qb.push(" AND event_id IN (");
+        let mut sep = qb.separated(", ");
+        for id in chunk {
+            sep.push_bind(id.to_string());
+        }
+        qb.push(")");
+        let rows: Vec<(String,)> = qb.build_query_as().fetch_all(&mut *conn).await?;
+        existing.extend(rows.into_iter().filter_map(|(s,)| Uuid::parse_str(&s).ok()));
+    }
+    Ok(existing)
+}
+
+/// Insert a batch of events with multi-row INSERTs (duplicates on (device_id, event_id) are ignored).
+/// Returns the number of rows actually inserted.
+pub async fn insert_events_batch(
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
-    event_id: Uuid,
-    seq: Option<i64>,
-    event_type: &str,
-    event_body: &serde_json::Value,
-    occurred_at: chrono::DateTime<chrono::Utc>,
-) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query(
+    events: &[NewDeviceEvent<'_>],
+) -> Result<u64, sqlx::Error> {
+    let mut inserted = 0u64;
+    for chunk in events.chunks(EVENT_INSERT_CHUNK) {
+        let mut qb: QueryBuilder<MySql> = QueryBuilder::new(
+            "INSERT IGNORE INTO device_event_log (org_id, store_id, device_id, event_id, seq, event_type, event_body, schema_version, occurred_at, corrected_occurred_at, clock_skew_ms) ",
+        );
+        qb.push_values(chunk, |mut row, e| {
+            row.push_bind(org_id.to_string())
+                .push_bind(store_id.to_string())
+                .push_bind(device_id.to_string())
+                .push_bind(e.event_id.to_string())
+                .push_bind(e.seq)
+                .push_bind(e.event_type)
+                .push_bind(e.event_body)
+                .push_bind(e.schema_version)
+                .push_bind(e.occurred_at)
+                .push_bind(e.corrected_occurred_at)
+                .push_bind(e.clock_skew_ms);
+        });
+        inserted += qb.build().execute(&mut *conn).await?.rows_affected();
+    }
+    Ok(inserted)
+}
+
+/// Received seq ranges for a device: inclusive (start, end) pairs, sorted and non-overlapping.
+pub type SeqRanges = Vec<(i64, i64)>;
+
+/// First seq a device sends.
+pub const FIRST_SEQ: i64 = 1;
+
+/// Contiguity of the seqs received from a device.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SeqProgress {
+    /// Every seq from FIRST_SEQ up to here is stored; None while FIRST_SEQ itself is missing.
+    pub ack_seq: Option<i64>,
+    /// Highest seq received at all.
+    pub highest_seq: Option<i64>,
+    /// Holes before and between received ranges, inclusive (start, end).
+    pub missing: SeqRanges,
+}
+
+impl SeqProgress {
+    pub fn from_ranges(ranges: &[(i64, i64)]) -> Self {
+        let first = ranges.first();
+        let leading = first
+            .filter(|r| r.0 > FIRST_SEQ)
+            .map(|r| (FIRST_SEQ, r.0 - 1));
+        Self {
+            ack_seq: first.filter(|r| r.0 <= FIRST_SEQ).map(|r| r.1),
+            highest_seq: ranges.last().map(|r| r.1),
+            missing: leading
+                .into_iter()
+                .chain(ranges.windows(2).map(|w| (w[0].1 + 1, w[1].0 - 1)))
+                .collect(),
+        }
+    }
+}
+
+/// Merge seqs into sorted, non-overlapping ranges (adjacent ranges are joined).
+pub fn merge_seq_ranges(ranges: &mut SeqRanges, seqs: impl IntoIterator<Item = i64>) {
+    ranges.extend(seqs.into_iter().map(|s| (s, s)));
+    ranges.sort_unstable();
+    let mut merged: SeqRanges = Vec::with_capacity(ranges.len());
+    for &(start, end) in ranges.iter() {
+        match merged.last_mut() {
+            Some(last) if start <= last.1.saturating_add(1) => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    *ranges = merged;
+}
+
+/// Compute a device's received seq ranges from device_event_log (used when seq_ranges is NULL).
+async fn seq_ranges_from_log(
+    conn: &mut MySqlConnection,
+    device_id: Uuid,
+) -> Result<SeqRanges, sqlx::Error> {
+    let rows: Vec<(i64,)> = sqlx::query_as(
+        "SELECT DISTINCT seq FROM device_event_log WHERE device_id = ? AND seq IS NOT NULL ORDER BY seq",
+    )
+    .bind(device_id.to_string())
+    .fetch_all(&mut *conn)
+    .await?;
+    let mut ranges = SeqRanges::new();
+    merge_seq_ranges(&mut ranges, rows.into_iter().map(|(s,)| s));
+    Ok(ranges)
+}
+
+fn parse_seq_ranges(value: Option<serde_json::Value>) -> Option<SeqRanges> {
+    value.and_then(|v| serde_json::from_value(v).ok())
+}
+
+/// Lock the device's device_sync_state row (creating it if needed) for the rest of the
+/// transaction. Event ingest takes it before writing to device_event_log, so one device's batches
+/// commit in log_pos order: log_pos is assigned at insert, and the projection worker moves a
+/// device's cursor past every position it has seen, so a lower position committed later would be
+/// skipped.
+pub async fn lock_device_sync_state(
+    conn: &mut MySqlConnection,
+    device_id: Uuid,
+    org_id: Uuid,
+    store_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
         r#"
-        INSERT IGNORE INTO device_event_log (org_id, store_id, device_id, event_id, seq, event_type, event_body, occurred_at)
-        VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+        INSERT IGNORE INTO device_sync_state (device_id, org_id, store_id, last_ack_seq)
+        VALUES (?, ?, ?, NULL)
         "#,
     )
+    .bind(device_id.to_string())
     .bind(org_id.to_string())
     .bind(store_id.to_string())
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("SELECT device_id FROM device_sync_state WHERE device_id = ? FOR UPDATE")
+        .bind(device_id.to_string())
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Record the seqs stored for a device in this batch (accepted and duplicate events) and update
+/// device_sync_state.seq_ranges and last_ack_seq (= highest contiguous seq). The row is locked for
+/// the rest of the transaction so concurrent batches from the same device merge correctly.
+pub async fn record_received_seqs(
+    conn: &mut MySqlConnection,
+    device_id: Uuid,
+    org_id: Uuid,
+    store_id: Uuid,
+    seqs: &[i64],
+) -> Result<SeqProgress, sqlx::Error> {
+    lock_device_sync_state(&mut *conn, device_id, org_id, store_id).await?;
+
+    let (stored,): (Option<serde_json::Value>,) =
+        sqlx::query_as("SELECT seq_ranges FROM device_sync_state WHERE device_id = ? FOR UPDATE")
+            .bind(device_id.to_string())
+            .fetch_one(&mut *conn)
+            .await?;
+    let mut ranges = match parse_seq_ranges(stored) {
+        Some(ranges) => ranges,
+        // The log already includes this batch, so merging it below is a no-op.
+        None => seq_ranges_from_log(&mut *conn, device_id).await?,
+    };
+    merge_seq_ranges(&mut ranges, seqs.iter().copied());
+    let progress = SeqProgress::from_ranges(&ranges);
+
+    sqlx::query(
+        r#"
+        UPDATE device_sync_state
+        SET seq_ranges = ?, last_ack_seq = ?, updated_at = NOW()
+        WHERE device_id = ?
+        "#,
+    )
+    .bind(serde_json::json!(ranges))
+    .bind(progress.ack_seq)
     .bind(device_id.to_string())
-    .bind(event_id.to_string())
-    .bind(seq)
-    .bind(event_type)
-    .bind(event_body)
-    .bind(occurred_at)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
-    Ok(result.rows_affected() > 0)
+    Ok(progress)
 }
 
-/// Update device_sync_state.last_ack_seq for device (only if new value is greater or null).
-pub async fn update_device_sync_state_ack_seq(
-    pool: &MySqlPool,
+/// Device clocks off by less than this are trusted as-is (the difference is mostly network
+/// latency); beyond it, event timestamps are corrected and flagged.
+pub const CLOCK_SKEW_THRESHOLD_SECS: i64 = 120;
+
+/// Only events recorded within this long of the skew measurement (by the device clock) are
+/// corrected: the skew measured now says nothing about the clock when older events, e.g. ones
+/// queued offline, were recorded.
+pub const CLOCK_SKEW_CORRECTION_WINDOW_SECS: i64 = 3600;
+
+/// Store the device's latest measured clock skew (device minus cloud, ms).
+pub async fn record_device_clock_skew(
+    executor: impl MySqlExecutor<'_>,
     device_id: Uuid,
     org_id: Uuid,
     store_id: Uuid,
-    last_ack_seq: Option<i64>,
+    skew_ms: i64,
 ) -> Result<(), sqlx::Error> {
-    let seq_val = last_ack_seq.unwrap_or(-1);
     sqlx::query(
         r#"
-        INSERT INTO device_sync_state (device_id, org_id, store_id, last_ack_seq, updated_at)
-        VALUES (?, ?, ?, ?, NOW())
-        ON DUPLICATE KEY UPDATE
-          last_ack_seq = GREATEST(COALESCE(device_sync_state.last_ack_seq, -1), ?),
-          updated_at = NOW()
+        INSERT INTO device_sync_state (device_id, org_id, store_id, last_ack_seq, clock_skew_ms, clock_checked_at)
+        VALUES (?, ?, ?, NULL, ?, NOW(3))
+        ON DUPLICATE KEY UPDATE clock_skew_ms = VALUES(clock_skew_ms), clock_checked_at = NOW(3)
         "#,
     )
     .bind(device_id.to_string())
     .bind(org_id.to_string())
     .bind(store_id.to_string())
-    .bind(last_ack_seq)
-    .bind(seq_val)
-    .execute(pool)
+    .bind(skew_ms)
+    .execute(executor)
     .await?;
     Ok(())
 }
 
+/// Last measured clock skew for the device, if any.
+pub async fn get_device_clock_skew(
+    executor: impl MySqlExecutor<'_>,
+    device_id: Uuid,
+) -> Result<Option<i64>, sqlx::Error> {
+    let row: Option<(Option<i64>,)> =
+        sqlx::query_as("SELECT clock_skew_ms FROM device_sync_state WHERE device_id = ?")
+            .bind(device_id.to_string())
+            .fetch_optional(executor)
+            .await?;
+    Ok(row.and_then(|(skew,)| skew))
+}
+
+/// Current seq contiguity for a device (portal view). Falls back to device_event_log when the
+/// ranges have not been recorded yet.
+pub async fn get_device_seq_progress(
+    pool: &MySqlPool,
+    device_id: Uuid,
+) -> Result<SeqProgress, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    let stored: Option<(Option<serde_json::Value>,)> =
+        sqlx::query_as("SELECT seq_ranges FROM device_sync_state WHERE device_id = ?")
+            .bind(device_id.to_string())
+            .fetch_optional(&mut *conn)
+            .await?;
+    let ranges = match parse_seq_ranges(stored.and_then(|(v,)| v)) {
+        Some(ranges) => ranges,
+        None => seq_ranges_from_log(&mut conn, device_id).await?,
+    };
+    Ok(SeqProgress::from_ranges(&ranges))
+}
+
+/// A delivered but unacked command is handed out again after this long (the device may have
+/// crashed before acking). Each hand-out counts as a delivery attempt.
+pub const COMMAND_REDELIVERY_SECS: i64 = 60;
+
+/// Default lifetime and delivery budget for a command type.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandPolicy {
+    /// Seconds from enqueue until the command expires if not acked.
+    pub ttl_secs: i64,
+    /// Hand-outs without an ack before the command expires.
+    pub max_attempts: u32,
+}
+
+/// Per-type defaults. A delivery order is useless once the customer has given up; a menu push is
+/// superseded by the next one; voids and refunds stay relevant for the trading day and a bit.
+pub fn command_policy(command_type: &str) -> CommandPolicy {
+    match command_type {
+        "delivery_order" => CommandPolicy {
+            ttl_secs: 30 * 60,
+            max_attempts: 10,
+        },
+        "apply_menu" => CommandPolicy {
+            ttl_secs: 24 * 60 * 60,
+            max_attempts: 20,
+        },
+        "void_order" | "refund_order" => CommandPolicy {
+            ttl_secs: 48 * 60 * 60,
+            max_attempts: 20,
+        },
+        _ => CommandPolicy {
+            ttl_secs: 72 * 60 * 60,
+            max_attempts: 20,
+        },
+    }
+}
+
+/// Why the sweeper expired a command (stored in device_command_queue.expired_reason).
+pub const EXPIRED_TTL: &str = "ttl_elapsed";
+pub const EXPIRED_MAX_ATTEMPTS: &str = "max_attempts_reached";
+
 /// Row for a deliverable command (queued or delivered, not acked/failed/expired). command_id decoded as String from CHAR(36).
 #[derive(Debug, sqlx::FromRow)]
 pub struct CommandRow {
@@ -72,7 +333,9 @@ pub struct CommandRow {
     pub sensitive: bool,
 }
 
-/// Fetch deliverable commands for device (status in queued, delivered), ordered by created_at.
+/// Fetch deliverable commands for device, ordered by created_at: queued commands, plus delivered
+/// ones whose redelivery delay has passed. Commands past expires_at or out of delivery attempts
+/// are skipped even before the sweeper marks them expired.
 pub async fn fetch_deliverable_commands(
     pool: &MySqlPool,
     device_id: Uuid,
@@ -82,34 +345,51 @@ pub async fn fetch_deliverable_commands(
         r#"
         SELECT command_id, command_type, command_body, sensitive
         FROM device_command_queue
-        WHERE device_id = ? AND status IN ('queued', 'delivered')
+        WHERE device_id = ?
+          AND (status = 'queued'
+               OR (status = 'delivered' AND (delivered_at IS NULL OR delivered_at <= NOW(3) - INTERVAL ? SECOND)))
+          AND (expires_at IS NULL OR expires_at > NOW(3))
+          AND (max_attempts IS NULL OR delivery_attempts < max_attempts)
         ORDER BY created_at
         LIMIT ?
         "#,
     )
     .bind(device_id.to_string())
+    .bind(COMMAND_REDELIVERY_SECS)
     .bind(limit)
     .fetch_all(pool)
     .await?;
     Ok(rows)
 }
 
-/// Mark command as delivered (optional: set delivered_at).
+/// Mark command as delivered and count the delivery attempt.
 pub async fn mark_command_delivered(pool: &MySqlPool, command_id: Uuid) -> Result<(), sqlx::Error> {
-    sqlx::query(
+    let mut tx = pool.begin().await?;
+    let result = sqlx::query(
         r#"
         UPDATE device_command_queue
-        SET status = 'delivered', delivered_at = NOW()
-        WHERE command_id = ? AND status = 'queued'
+        SET status = 'delivered', delivered_at = NOW(3), delivery_attempts = delivery_attempts + 1
+        WHERE command_id = ? AND status IN ('queued', 'delivered')
         "#,
     )
     .bind(command_id.to_string())
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
+    if result.rows_affected() > 0 {
+        let (attempts,): (u32,) =
+            sqlx::query_as("SELECT delivery_attempts FROM device_command_queue WHERE command_id = ?")
+                .bind(command_id.to_string())
+                .fetch_one(&mut *tx)
+                .await?;
+        let detail = format!("attempt {}", attempts);
+        log_command_status(&mut tx, command_id, "delivered", Some(&detail)).await?;
+    }
+    tx.commit().await?;
     Ok(())
 }
 
-/// Ack or fail command: set status and ack_result. Only updates if command belongs to device (tenant-safe).
+/// Ack or fail command: set status, ack_result and acked_at/failed_at and log the change. Only
+/// updates if command belongs to device (tenant-safe). Expired commands cannot be acked.
 pub async fn ack_command(
     pool: &MySqlPool,
     device_id: Uuid,
@@ -117,88 +397,294 @@ pub async fn ack_command(
     status: &str,
     ack_result: Option<&serde_json::Value>,
 ) -> Result<bool, sqlx::Error> {
-    let result = sqlx::query(
+    let mut tx = pool.begin().await?;
+    let row: Option<(String,)> = sqlx::query_as(
         r#"
-        UPDATE device_command_queue
-        SET status = ?, ack_result = ?
+        SELECT command_id
+        FROM device_command_queue
         WHERE command_id = ? AND device_id = ? AND status IN ('queued', 'delivered')
+        FOR UPDATE
+        "#,
+    )
+    .bind(command_id.to_string())
+    .bind(device_id.to_string())
+    .fetch_optional(&mut *tx)
+    .await?;
+    if row.is_none() {
+        return Ok(false);
+    }
+
+    sqlx::query(
+        r#"
+        UPDATE device_command_queue
+        SET status = ?, ack_result = ?,
+            acked_at = IF(? = 'acked', NOW(3), acked_at),
+            failed_at = IF(? = 'failed', NOW(3), failed_at)
+        WHERE command_id = ?
         "#,
     )
     .bind(status)
     .bind(ack_result)
+    .bind(status)
+    .bind(status)
     .bind(command_id.to_string())
-    .bind(device_id.to_string())
-    .execute(pool)
+    .execute(&mut *tx)
     .await?;
-    Ok(result.rows_affected() > 0)
+    // A failure's message (if the device sent one) is the most useful detail on the timeline.
+    let detail = ack_result
+        .and_then(|r| r.get("error").or_else(|| r.get("message")))
+        .and_then(|v| v.as_str())
+        .map(|m| m.chars().take(255).collect::<String>());
+    log_command_status(&mut tx, command_id, status, detail.as_deref()).await?;
+    tx.commit().await?;
+    Ok(true)
 }
 
-/// Enqueue apply_menu command to every device in the store (so cloud menu edits reach all devices).
-pub async fn enqueue_apply_menu_for_store(
+/// Command to queue for one device (see `enqueue_device_command`).
+#[derive(Debug)]
+pub struct NewDeviceCommand<'a> {
+    pub org_id: Uuid,
+    pub store_id: Uuid,
+    pub device_id: Uuid,
+    pub command_type: &'a str,
+    pub command_body: &'a serde_json::Value,
+    /// Sensitive commands start in `pending_approval` and are only delivered once approved (see
+    /// approvals.rs).
+    pub sensitive: bool,
+    /// Overrides the type's default TTL (see `command_policy`).
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Entity the command acts on; order commands show on the order detail.
+    pub target: Option<CommandEntity>,
+    /// Portal user who requested the command; they cannot approve it themselves.
+    pub requested_by_user_id: Option<&'a str>,
+}
+
+/// Queue a command for one device. Returns the new command_id. Non-sensitive commands are
+/// deliverable immediately: callers should then wake the device's long-poll/SSE listeners
+/// (cloud_api CommandNotifier) once the insert is committed.
+pub async fn enqueue_device_command(
     pool: &MySqlPool,
-    store_id: Uuid,
-) -> Result<u64, sqlx::Error> {
-    use crate::read_model::get_store_menu_for_sync;
+    command: &NewDeviceCommand<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let command_id = insert_device_command(&mut tx, command).await?;
+    tx.commit().await?;
+    Ok(command_id)
+}
 
-    let (org_id, device_ids): (String, Vec<String>) = {
-        let row: Option<(String,)> =
-            sqlx::query_as("SELECT org_id FROM stores WHERE id = ?")
-                .bind(store_id.to_string())
-                .fetch_optional(pool)
-                .await?;
-        let org_id = match row {
-            Some((id,)) => id,
-            None => return Ok(0),
-        };
-        let rows: Vec<(String,)> = sqlx::query_as(
-            "SELECT device_id FROM device_sync_state WHERE store_id = ?",
+/// Queue several commands in one transaction, so either all of them are queued or none is (e.g. a
+/// store- or franchise-wide send). Returns the new command_ids in order; wake listeners as for
+/// `enqueue_device_command` after it returns.
+pub async fn enqueue_device_commands(
+    pool: &MySqlPool,
+    commands: &[NewDeviceCommand<'_>],
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let mut command_ids = Vec::with_capacity(commands.len());
+    for command in commands {
+        command_ids.push(insert_device_command(&mut tx, command).await?);
+    }
+    tx.commit().await?;
+    Ok(command_ids)
+}
+
+/// Insert a command and its first timeline entry in the caller's transaction.
+async fn insert_device_command(
+    conn: &mut MySqlConnection,
+    command: &NewDeviceCommand<'_>,
+) -> Result<Uuid, sqlx::Error> {
+    let policy = command_policy(command.command_type);
+    let expires_at = command
+        .expires_at
+        .unwrap_or_else(|| chrono::Utc::now() + chrono::Duration::seconds(policy.ttl_secs));
+    let (status, required_approvals) = if command.sensitive {
+        ("pending_approval", crate::approvals::SENSITIVE_COMMAND_APPROVALS)
+    } else {
+        ("queued", 0)
+    };
+    let order_id = match command.target {
+        Some(CommandEntity::Order(id)) => Some(id.to_string()),
+        _ => None,
+    };
+    let command_id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO device_command_queue (command_id, org_id, store_id, device_id, order_id, target_type, target_id, command_type, command_body, status, sensitive, requested_by_user_id, required_approvals, created_at, expires_at, max_attempts)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, CURRENT_TIMESTAMP(3), ?, ?)
+        "#,
+    )
+    .bind(command_id.to_string())
+    .bind(command.org_id.to_string())
+    .bind(command.store_id.to_string())
+    .bind(command.device_id.to_string())
+    .bind(order_id)
+    .bind(command.target.map(|t| t.target_type()))
+    .bind(command.target.map(|t| t.target_id().to_string()))
+    .bind(command.command_type)
+    .bind(command.command_body)
+    .bind(status)
+    .bind(command.sensitive)
+    .bind(command.requested_by_user_id)
+    .bind(required_approvals)
+    .bind(expires_at)
+    .bind(policy.max_attempts)
+    .execute(&mut *conn)
+    .await?;
+    log_command_status(&mut *conn, command_id, status, None).await?;
+    Ok(command_id)
+}
+
+/// Counts from one sweeper pass.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ExpiredCommands {
+    pub ttl_elapsed: u64,
+    pub max_attempts_reached: u64,
+}
+
+/// Move stale commands to 'expired' and record why: commands past expires_at (including ones
+/// still awaiting approval), and delivered
+/// commands that used up their delivery attempts (once the last attempt's redelivery delay has
+/// passed without an ack).
+pub async fn expire_stale_commands(pool: &MySqlPool) -> Result<ExpiredCommands, sqlx::Error> {
+    let ttl_elapsed = expire_commands_where(
+        pool,
+        EXPIRED_TTL,
+        r#"status IN ('pending_approval', 'queued', 'delivered')
+          AND expires_at IS NOT NULL AND expires_at <= NOW(3)"#,
+        None,
+    )
+    .await?;
+    let max_attempts_reached = expire_commands_where(
+        pool,
+        EXPIRED_MAX_ATTEMPTS,
+        r#"status = 'delivered'
+          AND max_attempts IS NOT NULL AND delivery_attempts >= max_attempts
+          AND delivered_at <= NOW(3) - INTERVAL ? SECOND"#,
+        Some(COMMAND_REDELIVERY_SECS),
+    )
+    .await?;
+    Ok(ExpiredCommands {
+        ttl_elapsed,
+        max_attempts_reached,
+    })
+}
+
+/// Expire the commands matching `condition` (binding `param` if given) and log the change. The
+/// locking read keeps the log and the update on the same rows.
+async fn expire_commands_where(
+    pool: &MySqlPool,
+    reason: &str,
+    condition: &str,
+    param: Option<i64>,
+) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let select = format!("SELECT command_id FROM device_command_queue WHERE {} FOR UPDATE", condition);
+    let mut query = sqlx::query_as::<_, (String,)>(&select);
+    if let Some(param) = param {
+        query = query.bind(param);
+    }
+    let ids: Vec<Uuid> = query
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .filter_map(|(id,)| Uuid::parse_str(&id).ok())
+        .collect();
+    for command_id in &ids {
+        sqlx::query(
+            "UPDATE device_command_queue SET status = 'expired', expired_at = NOW(3), expired_reason = ? WHERE command_id = ?",
         )
+        .bind(reason)
+        .bind(command_id.to_string())
+        .execute(&mut *tx)
+        .await?;
+        log_command_status(&mut tx, *command_id, "expired", Some(reason)).await?;
+    }
+    tx.commit().await?;
+    Ok(ids.len() as u64)
+}
+
+/// command_body for apply_menu: the store's current cloud menu (same shape as GET /sync/menu)
+/// plus the menu version it was recorded as, with the store's open dayparts applied. None if the
+/// store has no menu yet.
+pub async fn apply_menu_command_body(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    publish: &MenuPublish<'_>,
+) -> Result<Option<serde_json::Value>, sqlx::Error> {
+    let Some((version, mut menu)) = record_menu_version(pool, store_id, publish).await? else {
+        return Ok(None);
+    };
+    apply_menu_dayparts(pool, store_id, &mut menu.items, chrono::Utc::now()).await?;
+    Ok(Some(serde_json::json!({
+        "version": version,
+        "categories": menu.categories,
+        "items": menu.items,
+    })))
+}
+
+/// Enqueue apply_menu command to every device in the store (so cloud menu edits reach all devices),
+/// in one transaction so either every device gets the new menu or none does. Returns the devices a
+/// command was queued for.
+pub async fn enqueue_apply_menu_for_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    publish: &MenuPublish<'_>,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT org_id FROM stores WHERE id = ?")
         .bind(store_id.to_string())
-        .fetch_all(pool)
+        .fetch_optional(pool)
         .await?;
-        let device_ids: Vec<String> = rows.into_iter().map(|(d,)| d).collect();
-        (org_id, device_ids)
+    let Some(org_id) = row.and_then(|(id,)| Uuid::parse_str(&id).ok()) else {
+        return Ok(Vec::new());
     };
+    let rows: Vec<(String,)> = sqlx::query_as(
+        "SELECT device_id FROM device_sync_state WHERE store_id = ?",
+    )
+    .bind(store_id.to_string())
+    .fetch_all(pool)
+    .await?;
+    let device_ids: Vec<Uuid> = rows
+        .into_iter()
+        .filter_map(|(d,)| Uuid::parse_str(&d).ok())
+        .collect();
 
-    let menu = match get_store_menu_for_sync(pool, store_id).await? {
-        Some(m) => m,
-        None => return Ok(0),
+    let Some(body) = apply_menu_command_body(pool, store_id, publish).await? else {
+        return Ok(Vec::new());
     };
-    let body = serde_json::json!({
-        "categories": menu.0,
-        "items": menu.1,
-    });
 
-    let mut count = 0u64;
+    let mut tx = pool.begin().await?;
     for device_id in &device_ids {
-        let command_id = Uuid::new_v4();
-        let r = sqlx::query(
-            r#"
-            INSERT INTO device_command_queue (command_id, org_id, store_id, device_id, command_type, command_body, status, sensitive, created_at)
-            VALUES (?, ?, ?, ?, 'apply_menu', ?, 'queued', 0, CURRENT_TIMESTAMP(3))
-            "#,
+        insert_device_command(
+            &mut tx,
+            &NewDeviceCommand {
+                org_id,
+                store_id,
+                device_id: *device_id,
+                command_type: "apply_menu",
+                command_body: &body,
+                sensitive: false,
+                expires_at: None,
+                target: Some(CommandEntity::Menu(store_id)),
+                requested_by_user_id: publish.published_by_user_id,
+            },
         )
-        .bind(command_id.to_string())
-        .bind(&org_id)
-        .bind(store_id.to_string())
-        .bind(device_id)
-        .bind(&body)
-        .execute(pool)
         .await?;
-        count += r.rows_affected();
     }
-    Ok(count)
+    tx.commit().await?;
+    Ok(device_ids)
 }
 
 /// Enqueue a delivery_order command for the canonical device of the store (or first active device).
+/// Returns the device the command was queued for, if any.
 pub async fn enqueue_delivery_order_command(
     pool: &MySqlPool,
     org_id: Uuid,
     store_id: Uuid,
+    delivery_order_id: Option<Uuid>,
     payload: &serde_json::Value,
-) -> Result<(), sqlx::Error> {
+) -> Result<Option<Uuid>, sqlx::Error> {
     // Prefer canonical device if set.
-    let device_row: Option<(String,)> = sqlx::query_as(
+    let device_row: Option<(Option<String>,)> = sqlx::query_as(
         r#"
         SELECT
           COALESCE(
@@ -212,43 +698,36 @@ pub async fn enqueue_delivery_order_command(
     .fetch_optional(pool)
     .await?;
 
-    let Some((device_id,)) = device_row else {
-        return Ok(());
+    let Some(device_id) = device_row
+        .and_then(|(d,)| d)
+        .and_then(|d| Uuid::parse_str(&d).ok())
+    else {
+        return Ok(None);
     };
 
-    let command_id = Uuid::new_v4();
-    sqlx::query(
-        r#"
-        INSERT INTO device_command_queue (
-          command_id,
-          org_id,
-          store_id,
-          device_id,
-          command_type,
-          command_body,
-          status,
-          sensitive,
-          created_at
-        )
-        VALUES (?, ?, ?, ?, 'delivery_order', ?, 'queued', 0, CURRENT_TIMESTAMP(3))
-        "#,
+    enqueue_device_command(
+        pool,
+        &NewDeviceCommand {
+            org_id,
+            store_id,
+            device_id,
+            command_type: "delivery_order",
+            command_body: payload,
+            sensitive: false,
+            expires_at: None,
+            target: delivery_order_id.map(CommandEntity::DeliveryOrder),
+            requested_by_user_id: None,
+        },
     )
-    .bind(command_id.to_string())
-    .bind(org_id.to_string())
-    .bind(store_id.to_string())
-    .bind(&device_id)
-    .bind(payload)
-    .execute(pool)
     .await?;
-
-    Ok(())
+    Ok(Some(device_id))
 }
 
 /// Record an alert when a non-canonical (non-primary) device attempts to change
 /// menu or configuration state. These rows can be surfaced in the portal for
 /// investigation or used to trigger downstream notifications.
 pub async fn insert_device_config_alert(
-    pool: &MySqlPool,
+    conn: &mut MySqlConnection,
     org_id: Uuid,
     store_id: Uuid,
     device_id: Uuid,
@@ -266,7 +745,60 @@ pub async fn insert_device_config_alert(
     .bind(device_id.to_string())
     .bind(event_type)
     .bind(details)
-    .execute(pool)
+    .execute(&mut *conn)
     .await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(seqs: &[i64]) -> SeqRanges {
+        let mut ranges = SeqRanges::new();
+        merge_seq_ranges(&mut ranges, seqs.iter().copied());
+        ranges
+    }
+
+    #[test]
+    fn merge_joins_adjacent_and_duplicate_seqs() {
+        assert_eq!(merged(&[3, 1, 2, 2, 7, 5, 6, 10]), vec![(1, 3), (5, 7), (10, 10)]);
+        assert_eq!(merged(&[]), SeqRanges::new());
+    }
+
+    #[test]
+    fn merge_extends_existing_ranges() {
+        let mut ranges = vec![(1, 10), (15, 20)];
+        merge_seq_ranges(&mut ranges, [11, 12, 13, 14, 21]);
+        assert_eq!(ranges, vec![(1, 21)]);
+
+        let mut ranges = vec![(5, 8)];
+        merge_seq_ranges(&mut ranges, [6, 9, 2]);
+        assert_eq!(ranges, vec![(2, 2), (5, 9)]);
+    }
+
+    #[test]
+    fn progress_reports_holes_between_ranges() {
+        let progress = SeqProgress::from_ranges(&[(1, 10), (15, 20), (22, 22)]);
+        assert_eq!(progress.ack_seq, Some(10));
+        assert_eq!(progress.highest_seq, Some(22));
+        assert_eq!(progress.missing, vec![(11, 14), (21, 21)]);
+    }
+
+    #[test]
+    fn progress_reports_seqs_missing_before_the_first_received() {
+        let progress = SeqProgress::from_ranges(&[(5, 9)]);
+        assert_eq!(progress.ack_seq, None);
+        assert_eq!(progress.highest_seq, Some(9));
+        assert_eq!(progress.missing, vec![(1, 4)]);
+
+        let progress = SeqProgress::from_ranges(&[(2, 3), (6, 6)]);
+        assert_eq!(progress.ack_seq, None);
+        assert_eq!(progress.missing, vec![(1, 1), (4, 5)]);
+    }
+
+    #[test]
+    fn progress_of_nothing_received() {
+        assert_eq!(SeqProgress::from_ranges(&[]), SeqProgress::default());
+    }
+}
@@ -1,5 +1,8 @@
 //! Tenancy: organizations and stores (for admin activation key creation).
 
+use std::collections::HashMap;
+
+use domain::StoreClock;
 use sqlx::MySqlPool;
 use uuid::Uuid;
 
@@ -70,3 +73,87 @@ pub async fn get_first_store_id_for_org(
     .await?;
     Ok(row.and_then(|(s,)| Uuid::parse_str(&s).ok()))
 }
+
+/// Org and franchise (if any) of a store.
+pub async fn get_store_org_and_franchise(
+    pool: &MySqlPool,
+    store_id: Uuid,
+) -> Result<Option<(Uuid, Option<Uuid>)>, sqlx::Error> {
+    let row: Option<(String, Option<String>)> =
+        sqlx::query_as("SELECT org_id, franchise_id FROM stores WHERE id = ?")
+            .bind(store_id.to_string())
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.and_then(|(org_id, franchise_id)| {
+        Some((
+            Uuid::parse_str(&org_id).ok()?,
+            franchise_id.and_then(|f| Uuid::parse_str(&f).ok()),
+        ))
+    }))
+}
+
+/// Org a franchise belongs to.
+pub async fn get_franchise_org_id(pool: &MySqlPool, franchise_id: Uuid) -> Result<Option<Uuid>, sqlx::Error> {
+    let row: Option<(String,)> = sqlx::query_as("SELECT org_id FROM franchises WHERE id = ?")
+        .bind(franchise_id.to_string())
+        .fetch_optional(pool)
+        .await?;
+    Ok(row.and_then(|(s,)| Uuid::parse_str(&s).ok()))
+}
+
+/// Active store ids in a franchise.
+pub async fn list_store_ids_for_franchise(
+    pool: &MySqlPool,
+    franchise_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let rows: Vec<(String,)> = sqlx::query_as("SELECT id FROM stores WHERE franchise_id = ? AND status = 'active' ORDER BY name")
+        .bind(franchise_id.to_string())
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.into_iter().filter_map(|(s,)| Uuid::parse_str(&s).ok()).collect())
+}
+
+/// Reporting clock (timezone and trading-day cutoff) of a store.
+pub async fn get_store_clock(pool: &MySqlPool, store_id: Uuid) -> Result<Option<StoreClock>, sqlx::Error> {
+    let row: Option<(String, chrono::NaiveTime)> =
+        sqlx::query_as("SELECT timezone, trading_day_cutoff FROM stores WHERE id = ?")
+            .bind(store_id.to_string())
+            .fetch_optional(pool)
+            .await?;
+    Ok(row.map(|(timezone, cutoff)| StoreClock::new(&timezone, cutoff)))
+}
+
+/// Reporting clocks of all stores, by store id.
+pub async fn list_store_clocks(pool: &MySqlPool) -> Result<HashMap<String, StoreClock>, sqlx::Error> {
+    let rows: Vec<(String, String, chrono::NaiveTime)> =
+        sqlx::query_as("SELECT id, timezone, trading_day_cutoff FROM stores")
+            .fetch_all(pool)
+            .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, timezone, cutoff)| (id, StoreClock::new(&timezone, cutoff)))
+        .collect())
+}
+
+/// Set a store's reporting timezone and/or trading-day cutoff. The caller validates the timezone
+/// name (domain::parse_timezone).
+pub async fn update_store_clock(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    timezone: Option<&str>,
+    trading_day_cutoff: Option<chrono::NaiveTime>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE stores
+        SET timezone = COALESCE(?, timezone), trading_day_cutoff = COALESCE(?, trading_day_cutoff)
+        WHERE id = ?
+        "#,
+    )
+    .bind(timezone)
+    .bind(trading_day_cutoff)
+    .bind(store_id.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
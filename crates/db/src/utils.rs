@@ -137,3 +137,49 @@ pub async fn user_can_access_store(
 
     Ok(has_org_membership != 0)
 }
+
+/// Ids of every store the user can access, by the same rules as [`user_can_access_store`]:
+/// all stores for a super_admin, otherwise stores with an active store_memberships row plus
+/// the stores of orgs with an active org_memberships row.
+pub async fn list_accessible_store_ids(
+    pool: &MySqlPool,
+    user_id: &str,
+) -> Result<Vec<String>, sqlx::Error> {
+    let (is_super_admin,): (i64,) = sqlx::query_as(
+        r#"
+        SELECT EXISTS(
+          SELECT 1
+          FROM org_memberships om
+          JOIN cloud_roles r ON r.id = om.role_id
+          WHERE om.user_id = ? AND r.code = 'super_admin'
+        ) AS has_role
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+    if is_super_admin != 0 {
+        let rows: Vec<(String,)> = sqlx::query_as("SELECT id FROM stores")
+            .fetch_all(pool)
+            .await?;
+        return Ok(rows.into_iter().map(|(id,)| id).collect());
+    }
+
+    let rows: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT sm.store_id
+        FROM store_memberships sm
+        WHERE sm.user_id = ? AND sm.status = 'active'
+        UNION
+        SELECT s.id
+        FROM stores s
+        JOIN org_memberships om ON om.org_id = s.org_id
+        WHERE om.user_id = ? AND om.status = 'active'
+        "#,
+    )
+    .bind(user_id)
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(id,)| id).collect())
+}
@@ -0,0 +1,651 @@
+//! End-of-day Z-reports: per store and trading day, computed from orders, transactions and
+//! receipts once the day has closed and then kept unchanged (z_reports), plus the totals devices
+//! report with z_report_closed events and their reconciliation against the cloud figures
+//! (z_report_device_closes).
+
+use domain::{StoreClock, ZReportClosed};
+use sqlx::{MySqlConnection, MySqlPool, Row};
+use uuid::Uuid;
+
+/// Past trading days the closer looks at for reports still to be written.
+pub const Z_REPORT_LOOKBACK_DAYS: i64 = 7;
+
+/// Time after a trading day ends before it is closed, so devices that were briefly offline can
+/// sync the day's last sales first.
+pub const Z_REPORT_CLOSE_GRACE_MINUTES: i64 = 120;
+
+/// Takings for one payment kind.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct ZReportTenderTotal {
+    pub kind: String,
+    pub transaction_count: i64,
+    pub amount_cents: i64,
+}
+
+/// Z-report figures for a store (or one device) over a trading day.
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct ZReportFigures {
+    /// All orders, including voided and refunded ones.
+    pub order_count: i64,
+    pub gross_cents: i64,
+    pub void_count: i64,
+    pub void_cents: i64,
+    /// Refunds made during the day (order_refunds), whichever day the order was taken.
+    pub refund_count: i64,
+    pub refund_cents: i64,
+    /// gross - voids - refunds.
+    pub net_cents: i64,
+    pub tenders: Vec<ZReportTenderTotal>,
+    pub receipt_count: i64,
+    pub first_receipt_id: Option<String>,
+    pub first_receipt_at: Option<chrono::NaiveDateTime>,
+    pub last_receipt_id: Option<String>,
+    pub last_receipt_at: Option<chrono::NaiveDateTime>,
+}
+
+impl ZReportFigures {
+    fn is_empty(&self) -> bool {
+        self.order_count == 0 && self.tenders.is_empty() && self.receipt_count == 0
+    }
+}
+
+/// A stored Z-report.
+#[derive(Debug, Clone)]
+pub struct ZReport {
+    pub id: String,
+    pub store_id: String,
+    /// None for the whole-store report.
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    pub trading_day: chrono::NaiveDate,
+    /// Store clock when the day was closed.
+    pub clock: StoreClock,
+    pub period_start: chrono::NaiveDateTime,
+    pub period_end: chrono::NaiveDateTime,
+    pub figures: ZReportFigures,
+    pub closed_at: chrono::NaiveDateTime,
+    /// Device closes for the day that disagree with the cloud figures (whole-store reports).
+    pub mismatch_count: i64,
+}
+
+/// A device's own Z-report totals (z_report_closed) and how they compare with the cloud.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct DeviceZReportClose {
+    pub device_id: String,
+    pub device_name: Option<String>,
+    pub local_z_report_id: String,
+    pub order_count: Option<i64>,
+    pub gross_cents: Option<i64>,
+    pub void_cents: Option<i64>,
+    pub refund_cents: Option<i64>,
+    pub net_cents: Option<i64>,
+    pub tenders: Option<serde_json::Value>,
+    pub closed_at: String,
+    /// None until the cloud report for the day exists.
+    pub mismatch: Option<bool>,
+    /// [{ field, device, cloud }] for each total that differs.
+    pub mismatches: Option<serde_json::Value>,
+}
+
+/// Compute Z-report figures over the UTC range [start, end) for a store, or one of its devices.
+pub async fn compute_z_report(
+    conn: &mut MySqlConnection,
+    store_id: Uuid,
+    device_id: Option<Uuid>,
+    start: chrono::NaiveDateTime,
+    end: chrono::NaiveDateTime,
+) -> Result<ZReportFigures, sqlx::Error> {
+    let store = store_id.to_string();
+    let device = device_id.map(|d| d.to_string());
+
+    let row = sqlx::query(
+        r#"
+        SELECT
+          COUNT(*) AS order_count,
+          CAST(COALESCE(SUM(total), 0) AS SIGNED) AS gross_cents,
+          CAST(COALESCE(SUM(status = 'voided'), 0) AS SIGNED) AS void_count,
+          CAST(COALESCE(SUM(IF(status = 'voided', total, 0)), 0) AS SIGNED) AS void_cents
+        FROM (
+          SELECT o.status,
+                 COALESCE(o.total_cents,
+                          (SELECT SUM(oi.line_total_cents) FROM order_items oi WHERE oi.order_id = o.id),
+                          0) AS total
+          FROM orders o
+          WHERE o.store_id = ? AND (? IS NULL OR o.device_id = ?)
+            AND o.occurred_at >= ? AND o.occurred_at < ?
+        ) day_orders
+        "#,
+    )
+    .bind(&store)
+    .bind(&device)
+    .bind(&device)
+    .bind(start)
+    .bind(end)
+    .fetch_one(&mut *conn)
+    .await?;
+    let mut figures = ZReportFigures {
+        order_count: row.get("order_count"),
+        gross_cents: row.get("gross_cents"),
+        void_count: row.get("void_count"),
+        void_cents: row.get("void_cents"),
+        ..Default::default()
+    };
+
+    // Refunds count on the day they were made, whichever day the order was taken.
+    let (refund_count, refund_cents): (i64, i64) = sqlx::query_as(
+        r#"
+        SELECT COUNT(*), CAST(COALESCE(SUM(amount_cents), 0) AS SIGNED)
+        FROM order_refunds
+        WHERE store_id = ? AND (? IS NULL OR device_id = ?)
+          AND occurred_at >= ? AND occurred_at < ?
+        "#,
+    )
+    .bind(&store)
+    .bind(&device)
+    .bind(&device)
+    .bind(start)
+    .bind(end)
+    .fetch_one(&mut *conn)
+    .await?;
+    figures.refund_count = refund_count;
+    figures.refund_cents = refund_cents;
+    figures.net_cents = figures.gross_cents - figures.void_cents - figures.refund_cents;
+
+    let tender_rows = sqlx::query(
+        r#"
+        SELECT kind, COUNT(*) AS transaction_count, CAST(SUM(amount_cents) AS SIGNED) AS amount_cents
+        FROM transactions
+        WHERE store_id = ? AND (? IS NULL OR device_id = ?)
+          AND occurred_at >= ? AND occurred_at < ?
+        GROUP BY kind
+        ORDER BY kind
+        "#,
+    )
+    .bind(&store)
+    .bind(&device)
+    .bind(&device)
+    .bind(start)
+    .bind(end)
+    .fetch_all(&mut *conn)
+    .await?;
+    figures.tenders = tender_rows
+        .into_iter()
+        .map(|row| ZReportTenderTotal {
+            kind: row.get("kind"),
+            transaction_count: row.get("transaction_count"),
+            amount_cents: row.get("amount_cents"),
+        })
+        .collect();
+
+    let receipts: Vec<(String, chrono::NaiveDateTime)> = sqlx::query_as(
+        r#"
+        SELECT local_receipt_id, occurred_at
+        FROM receipts
+        WHERE store_id = ? AND (? IS NULL OR device_id = ?)
+          AND occurred_at >= ? AND occurred_at < ?
+        ORDER BY occurred_at, local_receipt_id
+        "#,
+    )
+    .bind(&store)
+    .bind(&device)
+    .bind(&device)
+    .bind(start)
+    .bind(end)
+    .fetch_all(&mut *conn)
+    .await?;
+    figures.receipt_count = receipts.len() as i64;
+    if let Some((id, at)) = receipts.first() {
+        figures.first_receipt_id = Some(id.clone());
+        figures.first_receipt_at = Some(*at);
+    }
+    if let Some((id, at)) = receipts.last() {
+        figures.last_receipt_id = Some(id.clone());
+        figures.last_receipt_at = Some(*at);
+    }
+    Ok(figures)
+}
+
+/// Counts from a close_due_z_reports pass.
+#[derive(Debug, Default)]
+pub struct ClosedZReports {
+    /// Store days closed.
+    pub days: u64,
+    /// Reports written (whole-store plus per device).
+    pub reports: u64,
+}
+
+/// Write Z-reports for every store trading day in the lookback window that has ended (plus the
+/// grace period), had activity and has no report yet.
+pub async fn close_due_z_reports(pool: &MySqlPool) -> Result<ClosedZReports, sqlx::Error> {
+    let mut closed = ClosedZReports::default();
+    let stores: Vec<(String, String, String, chrono::NaiveTime)> =
+        sqlx::query_as("SELECT id, org_id, timezone, trading_day_cutoff FROM stores")
+            .fetch_all(pool)
+            .await?;
+    let now = chrono::Utc::now();
+    for (store_id, org_id, timezone, cutoff) in stores {
+        let (Ok(store_uuid), Ok(org_uuid)) = (Uuid::parse_str(&store_id), Uuid::parse_str(&org_id)) else {
+            continue;
+        };
+        let clock = StoreClock::new(&timezone, cutoff);
+        let today = clock.today();
+        let first_day = today - chrono::Duration::days(Z_REPORT_LOOKBACK_DAYS);
+        let closed_days: Vec<(chrono::NaiveDate,)> = sqlx::query_as(
+            "SELECT trading_day FROM z_reports WHERE store_id = ? AND device_id IS NULL AND trading_day >= ?",
+        )
+        .bind(&store_id)
+        .bind(first_day)
+        .fetch_all(pool)
+        .await?;
+
+        let mut day = first_day;
+        while day < today {
+            let (_, end) = clock.day_bounds(day);
+            let due = end + chrono::Duration::minutes(Z_REPORT_CLOSE_GRACE_MINUTES) <= now;
+            if due && !closed_days.iter().any(|(d,)| *d == day) {
+                let written = close_z_report_day(pool, org_uuid, store_uuid, clock, day).await?;
+                if written > 0 {
+                    closed.days += 1;
+                    closed.reports += written;
+                }
+            }
+            day += chrono::Duration::days(1);
+        }
+    }
+    Ok(closed)
+}
+
+/// Compute and store the whole-store and per-device Z-reports for one trading day, then
+/// reconcile the day's device closes. Returns the number of reports written (0 when the day had
+/// no activity or was already closed).
+pub async fn close_z_report_day(
+    pool: &MySqlPool,
+    org_id: Uuid,
+    store_id: Uuid,
+    clock: StoreClock,
+    trading_day: chrono::NaiveDate,
+) -> Result<u64, sqlx::Error> {
+    let (start, end) = clock.day_bounds(trading_day);
+    let (start, end) = (start.naive_utc(), end.naive_utc());
+    let mut tx = pool.begin().await?;
+
+    let store_figures = compute_z_report(&mut tx, store_id, None, start, end).await?;
+    if store_figures.is_empty() {
+        return Ok(0);
+    }
+    let device_ids: Vec<(String,)> = sqlx::query_as(
+        r#"
+        SELECT device_id FROM orders WHERE store_id = ? AND occurred_at >= ? AND occurred_at < ?
+        UNION
+        SELECT device_id FROM transactions WHERE store_id = ? AND occurred_at >= ? AND occurred_at < ?
+        UNION
+        SELECT device_id FROM receipts WHERE store_id = ? AND occurred_at >= ? AND occurred_at < ?
+        "#,
+    )
+    .bind(store_id.to_string())
+    .bind(start)
+    .bind(end)
+    .bind(store_id.to_string())
+    .bind(start)
+    .bind(end)
+    .bind(store_id.to_string())
+    .bind(start)
+    .bind(end)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut written = 0;
+    if !insert_z_report(&mut tx, org_id, store_id, None, clock, trading_day, &store_figures).await? {
+        // Another closer got there first.
+        tx.rollback().await?;
+        return Ok(0);
+    }
+    written += 1;
+    for (device_id,) in device_ids {
+        let Ok(device_uuid) = Uuid::parse_str(&device_id) else {
+            continue;
+        };
+        let figures = compute_z_report(&mut tx, store_id, Some(device_uuid), start, end).await?;
+        if insert_z_report(&mut tx, org_id, store_id, Some(device_uuid), clock, trading_day, &figures).await? {
+            written += 1;
+        }
+    }
+    reconcile_device_closes(&mut tx, store_id, trading_day).await?;
+    tx.commit().await?;
+    Ok(written)
+}
+
+/// Insert a Z-report unless one already exists for the store, day and device. Returns whether
+/// it was inserted.
+async fn insert_z_report(
+    conn: &mut MySqlConnection,
+    org_id: Uuid,
+    store_id: Uuid,
+    device_id: Option<Uuid>,
+    clock: StoreClock,
+    trading_day: chrono::NaiveDate,
+    figures: &ZReportFigures,
+) -> Result<bool, sqlx::Error> {
+    let (period_start, period_end) = clock.day_bounds(trading_day);
+    let tenders = serde_json::to_value(&figures.tenders).unwrap_or_default();
+    let result = sqlx::query(
+        r#"
+        INSERT IGNORE INTO z_reports (
+          id, org_id, store_id, device_id, trading_day, timezone, trading_day_cutoff, period_start, period_end,
+          order_count, gross_cents, void_count, void_cents, refund_count, refund_cents, net_cents, tenders,
+          receipt_count, first_receipt_id, first_receipt_at, last_receipt_id, last_receipt_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(org_id.to_string())
+    .bind(store_id.to_string())
+    .bind(device_id.map(|d| d.to_string()))
+    .bind(trading_day)
+    .bind(clock.timezone.name())
+    .bind(clock.trading_day_cutoff)
+    .bind(period_start.naive_utc())
+    .bind(period_end.naive_utc())
+    .bind(figures.order_count)
+    .bind(figures.gross_cents)
+    .bind(figures.void_count)
+    .bind(figures.void_cents)
+    .bind(figures.refund_count)
+    .bind(figures.refund_cents)
+    .bind(figures.net_cents)
+    .bind(tenders)
+    .bind(figures.receipt_count)
+    .bind(&figures.first_receipt_id)
+    .bind(figures.first_receipt_at)
+    .bind(&figures.last_receipt_id)
+    .bind(figures.last_receipt_at)
+    .execute(&mut *conn)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+const Z_REPORT_COLUMNS: &str = r#"
+    z.id, z.store_id, z.device_id, d.device_name, z.trading_day, z.timezone, z.trading_day_cutoff,
+    z.period_start, z.period_end, z.order_count, z.gross_cents, z.void_count, z.void_cents,
+    z.refund_count, z.refund_cents, z.net_cents, z.tenders, z.receipt_count, z.first_receipt_id,
+    z.first_receipt_at, z.last_receipt_id, z.last_receipt_at, z.closed_at,
+    (SELECT COUNT(*) FROM z_report_device_closes c
+     WHERE c.store_id = z.store_id AND c.trading_day = z.trading_day AND c.mismatch = 1
+       AND (z.device_id IS NULL OR c.device_id = z.device_id)) AS mismatch_count
+"#;
+
+fn z_report_from_row(row: &sqlx::mysql::MySqlRow) -> ZReport {
+    let tenders: serde_json::Value = row.get("tenders");
+    ZReport {
+        id: row.get("id"),
+        store_id: row.get("store_id"),
+        device_id: row.get("device_id"),
+        device_name: row.get("device_name"),
+        trading_day: row.get("trading_day"),
+        clock: StoreClock::new(&row.get::<String, _>("timezone"), row.get("trading_day_cutoff")),
+        period_start: row.get("period_start"),
+        period_end: row.get("period_end"),
+        figures: ZReportFigures {
+            order_count: row.get::<i32, _>("order_count") as i64,
+            gross_cents: row.get("gross_cents"),
+            void_count: row.get::<i32, _>("void_count") as i64,
+            void_cents: row.get("void_cents"),
+            refund_count: row.get::<i32, _>("refund_count") as i64,
+            refund_cents: row.get("refund_cents"),
+            net_cents: row.get("net_cents"),
+            tenders: serde_json::from_value(tenders).unwrap_or_default(),
+            receipt_count: row.get::<i32, _>("receipt_count") as i64,
+            first_receipt_id: row.get("first_receipt_id"),
+            first_receipt_at: row.get("first_receipt_at"),
+            last_receipt_id: row.get("last_receipt_id"),
+            last_receipt_at: row.get("last_receipt_at"),
+        },
+        closed_at: row.get("closed_at"),
+        mismatch_count: row.get("mismatch_count"),
+    }
+}
+
+/// Whole-store Z-reports for a store between two trading days (inclusive), newest first.
+pub async fn list_z_reports_for_store(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    from: chrono::NaiveDate,
+    to: chrono::NaiveDate,
+) -> Result<Vec<ZReport>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM z_reports z
+        LEFT JOIN devices d ON d.id = z.device_id
+        WHERE z.store_id = ? AND z.device_id IS NULL AND z.trading_day >= ? AND z.trading_day <= ?
+        ORDER BY z.trading_day DESC
+        "#,
+        Z_REPORT_COLUMNS
+    );
+    let rows = sqlx::query(&sql)
+        .bind(store_id.to_string())
+        .bind(from)
+        .bind(to)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.iter().map(z_report_from_row).collect())
+}
+
+/// Z-reports for one store trading day: the whole-store report first, then one per device.
+/// Empty when the day has not been closed.
+pub async fn get_z_reports_for_day(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    trading_day: chrono::NaiveDate,
+) -> Result<Vec<ZReport>, sqlx::Error> {
+    let sql = format!(
+        r#"
+        SELECT {}
+        FROM z_reports z
+        LEFT JOIN devices d ON d.id = z.device_id
+        WHERE z.store_id = ? AND z.trading_day = ?
+        ORDER BY z.device_id IS NOT NULL, d.device_name, z.device_id
+        "#,
+        Z_REPORT_COLUMNS
+    );
+    let rows = sqlx::query(&sql)
+        .bind(store_id.to_string())
+        .bind(trading_day)
+        .fetch_all(pool)
+        .await?;
+    Ok(rows.iter().map(z_report_from_row).collect())
+}
+
+/// Device closes (z_report_closed) for one store trading day.
+pub async fn list_device_z_report_closes(
+    pool: &MySqlPool,
+    store_id: Uuid,
+    trading_day: chrono::NaiveDate,
+) -> Result<Vec<DeviceZReportClose>, sqlx::Error> {
+    let rows = sqlx::query(
+        r#"
+        SELECT c.device_id, d.device_name, c.local_z_report_id, c.order_count, c.gross_cents,
+               c.void_cents, c.refund_cents, c.net_cents, c.tenders, c.closed_at, c.mismatch, c.mismatches
+        FROM z_report_device_closes c
+        LEFT JOIN devices d ON d.id = c.device_id
+        WHERE c.store_id = ? AND c.trading_day = ?
+        ORDER BY d.device_name, c.device_id
+        "#,
+    )
+    .bind(store_id.to_string())
+    .bind(trading_day)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| DeviceZReportClose {
+            device_id: row.get("device_id"),
+            device_name: row.get("device_name"),
+            local_z_report_id: row.get("local_z_report_id"),
+            order_count: row.get::<Option<i32>, _>("order_count").map(i64::from),
+            gross_cents: row.get("gross_cents"),
+            void_cents: row.get("void_cents"),
+            refund_cents: row.get("refund_cents"),
+            net_cents: row.get("net_cents"),
+            tenders: row.get("tenders"),
+            closed_at: row
+                .get::<chrono::NaiveDateTime, _>("closed_at")
+                .and_utc()
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            mismatch: row.get::<Option<i8>, _>("mismatch").map(|m| m != 0),
+            mismatches: row.get("mismatches"),
+        })
+        .collect())
+}
+
+/// Project a z_report_closed event: record the device's totals and reconcile them if the cloud
+/// report for the day exists.
+pub(crate) async fn record_device_z_report_close(
+    conn: &mut MySqlConnection,
+    org_id: Uuid,
+    store_id: Uuid,
+    device_id: Uuid,
+    event: &ZReportClosed,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+) -> Result<(), sqlx::Error> {
+    let trading_day = match event.trading_day {
+        Some(day) => day,
+        None => {
+            let clock: Option<(String, chrono::NaiveTime)> =
+                sqlx::query_as("SELECT timezone, trading_day_cutoff FROM stores WHERE id = ?")
+                    .bind(store_id.to_string())
+                    .fetch_optional(&mut *conn)
+                    .await?;
+            let clock = clock
+                .map(|(timezone, cutoff)| StoreClock::new(&timezone, cutoff))
+                .unwrap_or_default();
+            clock.trading_day(occurred_at)
+        }
+    };
+    let tenders = event
+        .tenders
+        .as_ref()
+        .map(|t| serde_json::to_value(t).unwrap_or_default());
+    sqlx::query(
+        r#"
+        INSERT INTO z_report_device_closes (
+          id, org_id, store_id, device_id, trading_day, local_z_report_id, order_count, gross_cents,
+          void_cents, refund_cents, net_cents, tenders, closed_at
+        )
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON DUPLICATE KEY UPDATE
+          local_z_report_id = VALUES(local_z_report_id), order_count = VALUES(order_count),
+          gross_cents = VALUES(gross_cents), void_cents = VALUES(void_cents),
+          refund_cents = VALUES(refund_cents), net_cents = VALUES(net_cents), tenders = VALUES(tenders),
+          closed_at = VALUES(closed_at), reconciled_at = NULL, mismatch = NULL, mismatches = NULL
+        "#,
+    )
+    .bind(Uuid::new_v4().to_string())
+    .bind(org_id.to_string())
+    .bind(store_id.to_string())
+    .bind(device_id.to_string())
+    .bind(trading_day)
+    .bind(event.z_report_id.as_str())
+    .bind(event.order_count)
+    .bind(event.gross_cents)
+    .bind(event.void_cents)
+    .bind(event.refund_cents)
+    .bind(event.net_cents)
+    .bind(tenders)
+    .bind(occurred_at)
+    .execute(&mut *conn)
+    .await?;
+    reconcile_device_closes(conn, store_id, trading_day).await
+}
+
+/// Compare unreconciled device closes for a store day with the cloud's per-device reports. A
+/// device with no report of its own on a closed day is compared against zero takings. Does
+/// nothing until the day is closed.
+async fn reconcile_device_closes(
+    conn: &mut MySqlConnection,
+    store_id: Uuid,
+    trading_day: chrono::NaiveDate,
+) -> Result<(), sqlx::Error> {
+    let (day_closed,): (i64,) = sqlx::query_as(
+        "SELECT EXISTS(SELECT 1 FROM z_reports WHERE store_id = ? AND trading_day = ? AND device_id IS NULL)",
+    )
+    .bind(store_id.to_string())
+    .bind(trading_day)
+    .fetch_one(&mut *conn)
+    .await?;
+    if day_closed == 0 {
+        return Ok(());
+    }
+
+    let closes = sqlx::query(
+        r#"
+        SELECT c.id, c.order_count, c.gross_cents, c.void_cents, c.refund_cents, c.net_cents, c.tenders,
+               z.order_count AS cloud_order_count, z.gross_cents AS cloud_gross_cents,
+               z.void_cents AS cloud_void_cents, z.refund_cents AS cloud_refund_cents,
+               z.net_cents AS cloud_net_cents, z.tenders AS cloud_tenders
+        FROM z_report_device_closes c
+        LEFT JOIN z_reports z
+          ON z.store_id = c.store_id AND z.trading_day = c.trading_day AND z.device_id = c.device_id
+        WHERE c.store_id = ? AND c.trading_day = ? AND c.reconciled_at IS NULL
+        "#,
+    )
+    .bind(store_id.to_string())
+    .bind(trading_day)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for row in closes {
+        let mut mismatches = Vec::new();
+        let mut compare = |field: &str, device: Option<i64>, cloud: i64| {
+            if let Some(device) = device {
+                if device != cloud {
+                    mismatches.push(serde_json::json!({ "field": field, "device": device, "cloud": cloud }));
+                }
+            }
+        };
+        compare(
+            "order_count",
+            row.get::<Option<i32>, _>("order_count").map(i64::from),
+            row.get::<Option<i32>, _>("cloud_order_count").map(i64::from).unwrap_or(0),
+        );
+        for field in ["gross_cents", "void_cents", "refund_cents", "net_cents"] {
+            compare(
+                field,
+                row.get::<Option<i64>, _>(field),
+                row.get::<Option<i64>, _>(format!("cloud_{}", field).as_str()).unwrap_or(0),
+            );
+        }
+
+        if let Some(device_tenders) = row.get::<Option<serde_json::Value>, _>("tenders") {
+            let device_tenders: Vec<domain::ZReportTender> =
+                serde_json::from_value(device_tenders).unwrap_or_default();
+            let cloud_tenders: Vec<ZReportTenderTotal> = row
+                .get::<Option<serde_json::Value>, _>("cloud_tenders")
+                .and_then(|t| serde_json::from_value(t).ok())
+                .unwrap_or_default();
+            let mut kinds: Vec<&str> = device_tenders
+                .iter()
+                .map(|t| t.kind.as_str())
+                .chain(cloud_tenders.iter().map(|t| t.kind.as_str()))
+                .collect();
+            kinds.sort_unstable();
+            kinds.dedup();
+            for kind in kinds {
+                let device: i64 = device_tenders.iter().filter(|t| t.kind == kind).map(|t| t.amount_cents).sum();
+                let cloud: i64 = cloud_tenders.iter().filter(|t| t.kind == kind).map(|t| t.amount_cents).sum();
+                compare(&format!("tender:{}", kind), Some(device), cloud);
+            }
+        }
+
+        sqlx::query(
+            "UPDATE z_report_device_closes SET reconciled_at = NOW(3), mismatch = ?, mismatches = ? WHERE id = ?",
+        )
+        .bind(!mismatches.is_empty())
+        .bind(serde_json::Value::Array(mismatches))
+        .bind(row.get::<String, _>("id"))
+        .execute(&mut *conn)
+        .await?;
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+//! Registry of device command types the portal may send, with a JSON Schema for each
+//! `command_body`. The cloud validates bodies against these schemas before queuing; POS builds can
+//! use the same schemas to know what to expect.
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+/// Where a command type may be sent from the portal command-center API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CommandTargeting {
+    /// One device, every device in a store, or every store in a franchise.
+    Any,
+    /// Only through the order it acts on (POST /portal/orders/:id/commands).
+    Order,
+}
+
+/// One registered command type.
+#[derive(Debug, Clone, Serialize)]
+pub struct CommandTypeSpec {
+    pub command_type: &'static str,
+    pub description: &'static str,
+    /// Needs two-person approval before delivery.
+    pub sensitive: bool,
+    pub targeting: CommandTargeting,
+    /// The cloud fills command_body itself (the portal sends `{}`).
+    pub body_from_cloud: bool,
+    /// JSON Schema (draft 7) for command_body.
+    pub body_schema: Value,
+}
+
+/// All command types, in display order.
+pub fn command_types() -> Vec<CommandTypeSpec> {
+    vec![
+        CommandTypeSpec {
+            command_type: "apply_menu",
+            description: "Push the store's current cloud menu to the device.",
+            sensitive: false,
+            targeting: CommandTargeting::Any,
+            body_from_cloud: true,
+            body_schema: json!({
+                "type": "object",
+                "properties": {
+                    "version": { "type": "integer", "minimum": 1 },
+                    "categories": { "type": "array" },
+                    "items": { "type": "array" }
+                },
+                "additionalProperties": false
+            }),
+        },
+        CommandTypeSpec {
+            command_type: "refresh_config",
+            description: "Re-download store and device configuration.",
+            sensitive: false,
+            targeting: CommandTargeting::Any,
+            body_from_cloud: false,
+            body_schema: json!({
+                "type": "object",
+                "properties": {
+                    "sections": {
+                        "type": "array",
+                        "items": { "enum": ["store", "device", "menu", "printers"] },
+                        "uniqueItems": true
+                    }
+                },
+                "additionalProperties": false
+            }),
+        },
+        CommandTypeSpec {
+            command_type: "print_test_receipt",
+            description: "Print a test receipt on the device's (or a named) printer.",
+            sensitive: false,
+            targeting: CommandTargeting::Any,
+            body_from_cloud: false,
+            body_schema: json!({
+                "type": "object",
+                "properties": {
+                    "printer": { "type": "string", "minLength": 1, "maxLength": 100 },
+                    "message": { "type": "string", "maxLength": 500 }
+                },
+                "additionalProperties": false
+            }),
+        },
+        CommandTypeSpec {
+            command_type: "logout_staff",
+            description: "Sign out one staff member, or everyone if staff_id is omitted.",
+            sensitive: false,
+            targeting: CommandTargeting::Any,
+            body_from_cloud: false,
+            body_schema: json!({
+                "type": "object",
+                "properties": {
+                    "staff_id": { "type": "string", "minLength": 1, "maxLength": 100 },
+                    "reason": { "type": "string", "maxLength": 255 }
+                },
+                "additionalProperties": false
+            }),
+        },
+        CommandTypeSpec {
+            command_type: "update_dish_yield",
+            description: "Set a dish's yield counters (same fields as the dish_yield_upserted event).",
+            sensitive: false,
+            targeting: CommandTargeting::Any,
+            body_from_cloud: false,
+            body_schema: json!({
+                "type": "object",
+                "required": ["menu_item_id"],
+                "properties": {
+                    "menu_item_id": { "type": "string", "minLength": 1 },
+                    "estimated_total": { "type": "number", "minimum": 0 },
+                    "remaining": { "type": "number", "minimum": 0 },
+                    "warning_threshold": { "type": "number", "minimum": 0 }
+                },
+                "additionalProperties": false
+            }),
+        },
+        CommandTypeSpec {
+            command_type: "void_order",
+            description: "Void an order on the device that took it.",
+            sensitive: true,
+            targeting: CommandTargeting::Order,
+            body_from_cloud: true,
+            body_schema: order_command_schema(),
+        },
+        CommandTypeSpec {
+            command_type: "refund_order",
+            description: "Refund an order on the device that took it.",
+            sensitive: true,
+            targeting: CommandTargeting::Order,
+            body_from_cloud: true,
+            body_schema: order_command_schema(),
+        },
+    ]
+}
+
+/// Look up a registered command type.
+pub fn find_command_type(command_type: &str) -> Option<CommandTypeSpec> {
+    command_types().into_iter().find(|t| t.command_type == command_type)
+}
+
+fn order_command_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "local_order_id": { "type": "string", "minLength": 1 },
+            "order_id": { "type": "string", "minLength": 1 },
+            "global_order_id": { "type": "string", "minLength": 1, "maxLength": 64 }
+        },
+        "anyOf": [{ "required": ["local_order_id"] }, { "required": ["order_id"] }]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A body each schema should accept and one it should reject.
+    fn samples(command_type: &str) -> (Value, Value) {
+        match command_type {
+            "apply_menu" => (
+                json!({ "version": 3, "categories": [], "items": [] }),
+                json!({ "version": 0 }),
+            ),
+            "refresh_config" => (json!({ "sections": ["menu", "printers"] }), json!({ "sections": ["till"] })),
+            "print_test_receipt" => (json!({ "printer": "Kitchen", "message": "hello" }), json!({ "printer": "" })),
+            "logout_staff" => (json!({}), json!({ "staff_id": 7 })),
+            "update_dish_yield" => (
+                json!({ "menu_item_id": "42", "remaining": 5 }),
+                json!({ "remaining": 5 }),
+            ),
+            "void_order" | "refund_order" => (
+                json!({ "local_order_id": "1042", "global_order_id": "g-1" }),
+                json!({ "global_order_id": "g-1" }),
+            ),
+            other => panic!("no sample bodies for {}", other),
+        }
+    }
+
+    #[test]
+    fn every_schema_compiles_and_checks_bodies() {
+        for spec in command_types() {
+            let schema = jsonschema::JSONSchema::compile(&spec.body_schema)
+                .unwrap_or_else(|e| panic!("{} schema does not compile: {}", spec.command_type, e));
+            let (valid, invalid) = samples(spec.command_type);
+            assert!(schema.is_valid(&valid), "{} rejected {}", spec.command_type, valid);
+            assert!(!schema.is_valid(&invalid), "{} accepted {}", spec.command_type, invalid);
+            assert!(
+                !schema.is_valid(&json!({ "unexpected": true })),
+                "{} accepted an unknown field",
+                spec.command_type
+            );
+        }
+    }
+
+    #[test]
+    fn find_command_type_by_name() {
+        assert!(find_command_type("void_order").unwrap().sensitive);
+        assert!(find_command_type("reboot").is_none());
+    }
+}
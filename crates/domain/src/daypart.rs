@@ -0,0 +1,106 @@
+//! Menu dayparts: store-local time windows (breakfast 06:00–11:30, late night 22:00–02:00) on
+//! chosen weekdays, outside which the categories and items assigned to them are unavailable.
+
+use chrono::{Datelike, Duration, NaiveDateTime, NaiveTime, Weekday};
+
+/// A daypart's window in store-local time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaypartWindow {
+    pub start: NaiveTime,
+    /// End (exclusive); before `start` the window runs past midnight, equal to `start` is all day.
+    pub end: NaiveTime,
+    /// Weekdays the window starts on; empty = every day.
+    pub days: Vec<Weekday>,
+}
+
+impl DaypartWindow {
+    /// Whether the window is open at a store-local time. A window past midnight belongs to the
+    /// day it started (a Friday 22:00–02:00 window is open at 01:00 on Saturday).
+    pub fn is_open(&self, local: NaiveDateTime) -> bool {
+        let time = local.time();
+        let started_on = if self.start == self.end {
+            Some(local.date())
+        } else if self.start < self.end {
+            (time >= self.start && time < self.end).then(|| local.date())
+        } else if time >= self.start {
+            Some(local.date())
+        } else if time < self.end {
+            Some(local.date() - Duration::days(1))
+        } else {
+            None
+        };
+        started_on.is_some_and(|day| self.days.is_empty() || self.days.contains(&day.weekday()))
+    }
+}
+
+/// Parse a weekday as stored and sent by the API ("mon" … "sun"; full names accepted).
+pub fn parse_weekday(value: &str) -> Option<Weekday> {
+    value.trim().parse().ok()
+}
+
+pub fn weekday_str(day: Weekday) -> &'static str {
+    match day {
+        Weekday::Mon => "mon",
+        Weekday::Tue => "tue",
+        Weekday::Wed => "wed",
+        Weekday::Thu => "thu",
+        Weekday::Fri => "fri",
+        Weekday::Sat => "sat",
+        Weekday::Sun => "sun",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(start: &str, end: &str, days: &[Weekday]) -> DaypartWindow {
+        DaypartWindow {
+            start: NaiveTime::parse_from_str(start, "%H:%M").unwrap(),
+            end: NaiveTime::parse_from_str(end, "%H:%M").unwrap(),
+            days: days.to_vec(),
+        }
+    }
+
+    /// 2026-10-16 is a Friday.
+    fn at(local: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(local, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn same_day_window_includes_start_and_excludes_end() {
+        let breakfast = window("06:00", "11:30", &[]);
+        assert!(!breakfast.is_open(at("2026-10-16 05:59")));
+        assert!(breakfast.is_open(at("2026-10-16 06:00")));
+        assert!(breakfast.is_open(at("2026-10-16 11:29")));
+        assert!(!breakfast.is_open(at("2026-10-16 11:30")));
+    }
+
+    #[test]
+    fn window_past_midnight_belongs_to_the_day_it_started() {
+        let late = window("22:00", "02:00", &[Weekday::Fri]);
+        assert!(!late.is_open(at("2026-10-16 21:59")));
+        assert!(late.is_open(at("2026-10-16 22:00")));
+        assert!(late.is_open(at("2026-10-17 01:00")));
+        assert!(!late.is_open(at("2026-10-17 02:00")));
+        // Friday early hours are Thursday's window, and Saturday night is not Friday's.
+        assert!(!late.is_open(at("2026-10-16 01:00")));
+        assert!(!late.is_open(at("2026-10-17 23:00")));
+    }
+
+    #[test]
+    fn equal_start_and_end_is_open_all_day() {
+        let all_day = window("05:00", "05:00", &[Weekday::Sat, Weekday::Sun]);
+        assert!(all_day.is_open(at("2026-10-17 00:00")));
+        assert!(all_day.is_open(at("2026-10-18 23:59")));
+        assert!(!all_day.is_open(at("2026-10-16 12:00")));
+    }
+
+    #[test]
+    fn weekdays_parse_and_format() {
+        assert_eq!(parse_weekday("mon"), Some(Weekday::Mon));
+        assert_eq!(parse_weekday(" Friday "), Some(Weekday::Fri));
+        assert_eq!(parse_weekday("someday"), None);
+        assert_eq!(weekday_str(Weekday::Sun), "sun");
+    }
+}
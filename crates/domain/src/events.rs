@@ -0,0 +1,996 @@
+//! Typed device events: the `event_type` + `event_body` pairs a POS sends to POST /sync/events.
+//!
+//! Bodies are validated at ingest; an event whose body does not match its type is rejected
+//! instead of being stored and projected with empty strings and zeros. Field names older POS
+//! builds still send are accepted as explicit legacy aliases (see the `*Wire` structs), with
+//! the same precedence the projections used before the schema existed. Unknown extra fields
+//! are ignored. Event types not listed here are stored but not projected.
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::order_identity::GlobalOrderId;
+use crate::vat::ServiceType;
+
+/// Current event schema version. Devices may send `schema_version` per event (default 1).
+pub const DEVICE_EVENT_SCHEMA_VERSION: u32 = 1;
+
+/// Non-empty POS local id (order_id, item_id, ...). Older POS builds send some ids as numbers.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct LocalId(String);
+
+impl LocalId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for LocalId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for LocalId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let id = match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::String(s) => s,
+            serde_json::Value::Number(n) if n.is_i64() || n.is_u64() => n.to_string(),
+            other => {
+                return Err(serde::de::Error::custom(format!(
+                    "expected id string or integer, got {}",
+                    other
+                )))
+            }
+        };
+        if id.trim().is_empty() {
+            return Err(serde::de::Error::custom("id must not be empty"));
+        }
+        Ok(LocalId(id))
+    }
+}
+
+/// Legacy bodies send "" for absent optional ids.
+fn non_empty(value: Option<String>) -> Option<String> {
+    value.filter(|s| !s.trim().is_empty())
+}
+
+/// Amount given in major units (e.g. pounds) as a JSON number; converted to minor units.
+fn major_to_minor(n: &serde_json::Number) -> Option<i64> {
+    n.as_f64().map(|p| (p * 100.0).round() as i64)
+}
+
+/// Amount that is minor units when an integer and major units when fractional (legacy fields).
+fn int_minor_or_fractional_major(n: &serde_json::Number) -> Option<i64> {
+    n.as_i64().or_else(|| major_to_minor(n))
+}
+
+// Lenient readers for the legacy `*Wire` fields. The projections read these with
+// `as_str` / `as_i64` / `as_f64` before the schema existed, so a value of an unexpected type was
+// ignored rather than failing the whole event; these keep that, and also accept the numeric ids
+// older POS builds send.
+
+/// String, or a number sent as an id; anything else is treated as absent.
+fn lenient_string<'de, D: Deserializer<'de>>(d: D) -> Result<Option<String>, D::Error> {
+    Ok(match serde_json::Value::deserialize(d)? {
+        serde_json::Value::String(s) => Some(s),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    })
+}
+
+/// Any JSON number; anything else is treated as absent.
+fn lenient_number<'de, D: Deserializer<'de>>(d: D) -> Result<Option<serde_json::Number>, D::Error> {
+    Ok(match serde_json::Value::deserialize(d)? {
+        serde_json::Value::Number(n) => Some(n),
+        _ => None,
+    })
+}
+
+/// Minor units: an integer, or a whole float such as `1250.0`; anything else is treated as absent.
+fn lenient_minor<'de, D: Deserializer<'de>>(d: D) -> Result<Option<i64>, D::Error> {
+    Ok(lenient_number(d)?.and_then(|n| {
+        n.as_i64()
+            .or_else(|| n.as_f64().filter(|f| f.fract() == 0.0).map(|f| f as i64))
+    }))
+}
+
+/// Legacy amount: integer minor units, or fractional major units (see
+/// `int_minor_or_fractional_major`).
+fn lenient_minor_or_major<'de, D: Deserializer<'de>>(d: D) -> Result<Option<i64>, D::Error> {
+    Ok(lenient_number(d)?.as_ref().and_then(int_minor_or_fractional_major))
+}
+
+fn lenient_f64<'de, D: Deserializer<'de>>(d: D) -> Result<Option<f64>, D::Error> {
+    Ok(lenient_number(d)?.and_then(|n| n.as_f64()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "event_type", content = "event_body", rename_all = "snake_case")]
+pub enum DeviceEvent {
+    StoreUpdated(StoreUpdated),
+    MenuCategoryCreated(MenuCategoryCreated),
+    MenuCategoryRenamed(MenuCategoryRenamed),
+    MenuCategoryImage(MenuCategoryImage),
+    MenuItemCreated(MenuItemCreated),
+    MenuItemDeleted(MenuItemDeleted),
+    MenuItemVisibility(MenuItemVisibility),
+    MenuItemImage(MenuItemImage),
+    MenuItemModifiersSet(MenuItemModifiersSet),
+    DishYieldUpserted(DishYieldUpserted),
+    DishYieldAdjusted(DishYieldAdjusted),
+    DeviceUpdated(DeviceUpdated),
+    OrderCreated(OrderPayload),
+    OrderUpdated(OrderPayload),
+    TransactionCompleted(TransactionCompleted),
+    ReceiptCreated(ReceiptCreated),
+    OrderVoided(OrderVoided),
+    OrderRefunded(OrderRefunded),
+    TransactionRefunded(TransactionRefunded),
+    OrderSentToKitchen(OrderSentToKitchen),
+    ItemStarted(KitchenItemEvent),
+    ItemBumped(KitchenItemEvent),
+    OrderReady(OrderReady),
+    ZReportClosed(ZReportClosed),
+}
+
+/// Why an event body was rejected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEventError {
+    UnsupportedVersion(u32),
+    InvalidBody { event_type: String, message: String },
+}
+
+impl fmt::Display for DeviceEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceEventError::UnsupportedVersion(v) => write!(
+                f,
+                "unsupported schema_version {} (max {})",
+                v, DEVICE_EVENT_SCHEMA_VERSION
+            ),
+            DeviceEventError::InvalidBody {
+                event_type,
+                message,
+            } => write!(f, "invalid {} body: {}", event_type, message),
+        }
+    }
+}
+
+impl std::error::Error for DeviceEventError {}
+
+impl DeviceEvent {
+    /// Parse an event from its wire form. Returns Ok(None) for event types without a schema
+    /// (stored but not projected).
+    pub fn parse(
+        schema_version: u32,
+        event_type: &str,
+        event_body: &serde_json::Value,
+    ) -> Result<Option<Self>, DeviceEventError> {
+        if schema_version == 0 || schema_version > DEVICE_EVENT_SCHEMA_VERSION {
+            return Err(DeviceEventError::UnsupportedVersion(schema_version));
+        }
+        fn body<T: serde::de::DeserializeOwned>(
+            event_type: &str,
+            event_body: &serde_json::Value,
+        ) -> Result<T, DeviceEventError> {
+            T::deserialize(event_body).map_err(|e| DeviceEventError::InvalidBody {
+                event_type: event_type.to_string(),
+                message: e.to_string(),
+            })
+        }
+        let event = match event_type {
+            "store_updated" => DeviceEvent::StoreUpdated(body(event_type, event_body)?),
+            "menu_category_created" => DeviceEvent::MenuCategoryCreated(body(event_type, event_body)?),
+            "menu_category_renamed" => DeviceEvent::MenuCategoryRenamed(body(event_type, event_body)?),
+            "menu_category_image" => DeviceEvent::MenuCategoryImage(body(event_type, event_body)?),
+            "menu_item_created" => DeviceEvent::MenuItemCreated(body(event_type, event_body)?),
+            "menu_item_deleted" => DeviceEvent::MenuItemDeleted(body(event_type, event_body)?),
+            "menu_item_visibility" => DeviceEvent::MenuItemVisibility(body(event_type, event_body)?),
+            "menu_item_image" => DeviceEvent::MenuItemImage(body(event_type, event_body)?),
+            "menu_item_modifiers_set" => DeviceEvent::MenuItemModifiersSet(body(event_type, event_body)?),
+            "dish_yield_upserted" => DeviceEvent::DishYieldUpserted(body(event_type, event_body)?),
+            "dish_yield_adjusted" => DeviceEvent::DishYieldAdjusted(body(event_type, event_body)?),
+            "device_updated" => DeviceEvent::DeviceUpdated(body(event_type, event_body)?),
+            "order_created" => DeviceEvent::OrderCreated(body(event_type, event_body)?),
+            "order_updated" => DeviceEvent::OrderUpdated(body(event_type, event_body)?),
+            "transaction_completed" => DeviceEvent::TransactionCompleted(body(event_type, event_body)?),
+            "receipt_created" => DeviceEvent::ReceiptCreated(body(event_type, event_body)?),
+            "order_voided" => DeviceEvent::OrderVoided(body(event_type, event_body)?),
+            "order_refunded" => DeviceEvent::OrderRefunded(body(event_type, event_body)?),
+            "transaction_refunded" => DeviceEvent::TransactionRefunded(body(event_type, event_body)?),
+            "order_sent_to_kitchen" => DeviceEvent::OrderSentToKitchen(body(event_type, event_body)?),
+            "item_started" => DeviceEvent::ItemStarted(body(event_type, event_body)?),
+            "item_bumped" => DeviceEvent::ItemBumped(body(event_type, event_body)?),
+            "order_ready" => DeviceEvent::OrderReady(body(event_type, event_body)?),
+            "z_report_closed" => DeviceEvent::ZReportClosed(body(event_type, event_body)?),
+            _ => return Ok(None),
+        };
+        Ok(Some(event))
+    }
+
+    /// Wire name of the event type.
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            DeviceEvent::StoreUpdated(_) => "store_updated",
+            DeviceEvent::MenuCategoryCreated(_) => "menu_category_created",
+            DeviceEvent::MenuCategoryRenamed(_) => "menu_category_renamed",
+            DeviceEvent::MenuCategoryImage(_) => "menu_category_image",
+            DeviceEvent::MenuItemCreated(_) => "menu_item_created",
+            DeviceEvent::MenuItemDeleted(_) => "menu_item_deleted",
+            DeviceEvent::MenuItemVisibility(_) => "menu_item_visibility",
+            DeviceEvent::MenuItemImage(_) => "menu_item_image",
+            DeviceEvent::MenuItemModifiersSet(_) => "menu_item_modifiers_set",
+            DeviceEvent::DishYieldUpserted(_) => "dish_yield_upserted",
+            DeviceEvent::DishYieldAdjusted(_) => "dish_yield_adjusted",
+            DeviceEvent::DeviceUpdated(_) => "device_updated",
+            DeviceEvent::OrderCreated(_) => "order_created",
+            DeviceEvent::OrderUpdated(_) => "order_updated",
+            DeviceEvent::TransactionCompleted(_) => "transaction_completed",
+            DeviceEvent::ReceiptCreated(_) => "receipt_created",
+            DeviceEvent::OrderVoided(_) => "order_voided",
+            DeviceEvent::OrderRefunded(_) => "order_refunded",
+            DeviceEvent::TransactionRefunded(_) => "transaction_refunded",
+            DeviceEvent::OrderSentToKitchen(_) => "order_sent_to_kitchen",
+            DeviceEvent::ItemStarted(_) => "item_started",
+            DeviceEvent::ItemBumped(_) => "item_bumped",
+            DeviceEvent::OrderReady(_) => "order_ready",
+            DeviceEvent::ZReportClosed(_) => "z_report_closed",
+        }
+    }
+
+    /// Menu/configuration events, which only the store's canonical device may send.
+    pub fn is_config_event(&self) -> bool {
+        !matches!(
+            self,
+            DeviceEvent::DeviceUpdated(_)
+                | DeviceEvent::OrderCreated(_)
+                | DeviceEvent::OrderUpdated(_)
+                | DeviceEvent::TransactionCompleted(_)
+                | DeviceEvent::ReceiptCreated(_)
+                | DeviceEvent::OrderVoided(_)
+                | DeviceEvent::OrderRefunded(_)
+                | DeviceEvent::TransactionRefunded(_)
+                | DeviceEvent::OrderSentToKitchen(_)
+                | DeviceEvent::ItemStarted(_)
+                | DeviceEvent::ItemBumped(_)
+                | DeviceEvent::OrderReady(_)
+                | DeviceEvent::ZReportClosed(_)
+        )
+    }
+}
+
+// --- Store / device ---
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StoreUpdated {
+    /// POS local store id.
+    pub store_id: LocalId,
+    pub name: String,
+    #[serde(default = "default_timezone")]
+    pub timezone: String,
+}
+
+fn default_timezone() -> String {
+    "Europe/London".to_string()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceUpdated {
+    #[serde(default)]
+    pub device_name: Option<String>,
+    #[serde(default)]
+    pub is_primary: bool,
+}
+
+// --- Menu ---
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuCategoryCreated {
+    pub category_id: LocalId,
+    /// Empty or missing means the "default" menu.
+    #[serde(default)]
+    pub menu_id: Option<String>,
+    pub name: String,
+    #[serde(default)]
+    pub position: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuCategoryRenamed {
+    pub category_id: LocalId,
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuCategoryImage {
+    pub category_id: LocalId,
+    pub image_path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "MenuItemCreatedWire")]
+pub struct MenuItemCreated {
+    pub item_id: LocalId,
+    pub store_id: Option<String>,
+    pub category_id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub price_pence: Option<i64>,
+    pub active: bool,
+    pub image_path: Option<String>,
+    pub customer_editable: bool,
+}
+
+/// Legacy: `price` (integer pence, or fractional pounds) when `price_pence` is absent.
+#[derive(Deserialize)]
+struct MenuItemCreatedWire {
+    item_id: LocalId,
+    #[serde(default)]
+    store_id: Option<String>,
+    #[serde(default)]
+    category_id: Option<String>,
+    name: String,
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    price_pence: Option<i64>,
+    #[serde(default)]
+    price: Option<serde_json::Number>,
+    #[serde(default = "default_true")]
+    active: bool,
+    #[serde(default)]
+    image_path: Option<String>,
+    #[serde(default)]
+    customer_editable: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl From<MenuItemCreatedWire> for MenuItemCreated {
+    fn from(w: MenuItemCreatedWire) -> Self {
+        Self {
+            item_id: w.item_id,
+            store_id: w.store_id,
+            category_id: w.category_id,
+            name: w.name,
+            description: w.description,
+            price_pence: w
+                .price_pence
+                .or_else(|| w.price.as_ref().and_then(int_minor_or_fractional_major)),
+            active: w.active,
+            image_path: w.image_path,
+            customer_editable: w.customer_editable,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItemDeleted {
+    pub item_id: LocalId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItemVisibility {
+    pub item_id: LocalId,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItemImage {
+    pub item_id: LocalId,
+    pub image_path: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItemModifiersSet {
+    pub menu_item_id: LocalId,
+    /// Replaces every modifier on the item; empty clears them.
+    #[serde(default)]
+    pub modifiers: Vec<MenuItemModifier>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MenuItemModifier {
+    pub name: String,
+    #[serde(default)]
+    pub price_delta_pence: i32,
+    /// Defaults to the modifier's index in the list.
+    #[serde(default)]
+    pub position: Option<i32>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DishYieldUpserted {
+    pub menu_item_id: LocalId,
+    #[serde(default)]
+    pub estimated_total: Option<f64>,
+    #[serde(default)]
+    pub remaining: Option<f64>,
+    #[serde(default)]
+    pub warning_threshold: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DishYieldAdjusted {
+    pub menu_item_id: LocalId,
+    #[serde(default)]
+    pub remaining: Option<f64>,
+}
+
+// --- Orders / payments ---
+
+/// Body of order_created and order_updated.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "OrderPayloadWire")]
+pub struct OrderPayload {
+    pub order_id: LocalId,
+    /// Store-scoped identity shared across devices (see [`crate::order_identity`]).
+    pub global_order_id: Option<GlobalOrderId>,
+    pub total_cents: Option<i64>,
+    /// None when the event carries no line list (as opposed to an empty one).
+    pub items: Option<Vec<OrderLine>>,
+    /// Order-level discounts; None leaves the recorded ones unchanged.
+    pub discounts: Option<Vec<OrderDiscount>>,
+    /// Tax lines by rate; None leaves the recorded ones unchanged.
+    pub taxes: Option<Vec<OrderTax>>,
+    pub tip_cents: Option<i64>,
+    pub service_charge_cents: Option<i64>,
+    /// Eat-in, takeaway or delivery; None when not sent or not recognised.
+    pub service_type: Option<ServiceType>,
+    /// Delivery platform the order came from (e.g. "uber_eats"), with the platform's order id.
+    pub delivery_provider: Option<String>,
+    pub external_order_id: Option<String>,
+}
+
+/// Legacy: `total` for `total_cents` (integer minor units, or fractional major units),
+/// `line_items` for `items`, `order_type` / `dining_option` for `service_type`, `provider` for
+/// `delivery_provider`.
+#[derive(Deserialize)]
+struct OrderPayloadWire {
+    order_id: LocalId,
+    #[serde(default)]
+    global_order_id: Option<GlobalOrderId>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    total_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_minor_or_major")]
+    total: Option<i64>,
+    #[serde(default)]
+    items: Option<Vec<OrderLine>>,
+    #[serde(default)]
+    line_items: Option<Vec<OrderLine>>,
+    #[serde(default)]
+    discounts: Option<Vec<OrderDiscount>>,
+    #[serde(default)]
+    taxes: Option<Vec<OrderTax>>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    tip_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    service_charge_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    service_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    order_type: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    dining_option: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    delivery_provider: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    provider: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    external_order_id: Option<String>,
+}
+
+impl From<OrderPayloadWire> for OrderPayload {
+    fn from(w: OrderPayloadWire) -> Self {
+        let delivery_provider = w.delivery_provider.or(w.provider).filter(|p| !p.is_empty());
+        let service_type = w
+            .service_type
+            .or(w.order_type)
+            .or(w.dining_option)
+            .as_deref()
+            .and_then(ServiceType::parse)
+            // Platform orders are delivery orders unless the POS says otherwise.
+            .or(delivery_provider.as_ref().map(|_| ServiceType::Delivery));
+        Self {
+            order_id: w.order_id,
+            global_order_id: w.global_order_id,
+            total_cents: w.total_cents.or(w.total),
+            items: w.items.or(w.line_items),
+            discounts: w.discounts,
+            taxes: w.taxes,
+            tip_cents: w.tip_cents,
+            service_charge_cents: w.service_charge_cents,
+            service_type,
+            delivery_provider,
+            external_order_id: w.external_order_id.filter(|id| !id.is_empty()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "OrderLineWire")]
+pub struct OrderLine {
+    /// POS local line id.
+    pub local_item_id: Option<String>,
+    /// Menu item reference (menu item id, or the product name on older builds).
+    pub product_ref: Option<String>,
+    pub quantity: f64,
+    pub unit_price_cents: Option<i64>,
+    pub line_total_cents: Option<i64>,
+    pub modifiers: Vec<OrderLineModifier>,
+    /// Discounts on this line only.
+    pub discounts: Vec<OrderDiscount>,
+}
+
+/// Legacy aliases, first present wins:
+/// - quantity: `qty`; default 1
+/// - unit_price_cents: `price_pence`, `unit_price` (minor units), then `price` (major units)
+/// - line_total_cents: `line_total` (integer minor units, or fractional major units)
+/// - local_item_id: `id`, `item_id`, `local_item_id`
+/// - product_ref: `product_ref`, `product_id`, `menu_item_id`, `name`, `product_name`
+#[derive(Deserialize)]
+struct OrderLineWire {
+    #[serde(default, deserialize_with = "lenient_f64")]
+    quantity: Option<f64>,
+    #[serde(default, deserialize_with = "lenient_f64")]
+    qty: Option<f64>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    unit_price_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    price_pence: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    unit_price: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_number")]
+    price: Option<serde_json::Number>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    line_total_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_number")]
+    line_total: Option<serde_json::Number>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    item_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    local_item_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    product_ref: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    product_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    menu_item_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    name: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    product_name: Option<String>,
+    #[serde(default)]
+    modifiers: Vec<OrderLineModifier>,
+    #[serde(default)]
+    discounts: Vec<OrderDiscount>,
+}
+
+impl From<OrderLineWire> for OrderLine {
+    fn from(w: OrderLineWire) -> Self {
+        Self {
+            local_item_id: non_empty(w.id)
+                .or(non_empty(w.item_id))
+                .or(non_empty(w.local_item_id)),
+            product_ref: w
+                .product_ref
+                .or(w.product_id)
+                .or(w.menu_item_id)
+                .or(w.name)
+                .or(w.product_name),
+            quantity: w.quantity.or(w.qty).unwrap_or(1.0),
+            unit_price_cents: w
+                .unit_price_cents
+                .or(w.price_pence)
+                .or(w.unit_price)
+                .or_else(|| w.price.as_ref().and_then(major_to_minor)),
+            line_total_cents: w
+                .line_total_cents
+                .or_else(|| w.line_total.as_ref().and_then(int_minor_or_fractional_major)),
+            modifiers: w.modifiers,
+            discounts: w.discounts,
+        }
+    }
+}
+
+/// A modifier chosen on an order line. Matched to the menu's modifiers (pos_menu_item_modifiers)
+/// by the line's product_ref and the modifier name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderLineModifier {
+    pub name: String,
+    #[serde(default = "default_quantity")]
+    pub quantity: f64,
+    /// Price change per unit of the line, in minor units (`price_delta_pence` on older builds).
+    #[serde(default, alias = "price_delta_pence")]
+    pub price_delta_cents: i64,
+}
+
+fn default_quantity() -> f64 {
+    1.0
+}
+
+/// A discount on an order or one of its lines.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderDiscount {
+    /// POS local discount id.
+    #[serde(default, alias = "id")]
+    pub discount_id: Option<String>,
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Promotion or voucher code.
+    #[serde(default)]
+    pub code: Option<String>,
+    /// Amount taken off, in minor units (the sign is ignored).
+    pub amount_cents: i64,
+    /// Percentage the POS applied, for display.
+    #[serde(default)]
+    pub percent: Option<f64>,
+}
+
+/// Tax charged on an order at one rate.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderTax {
+    /// Rate name, e.g. "standard", "reduced", "zero".
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Rate in percent, e.g. 20.0.
+    pub rate_percent: f64,
+    /// Sales taxed at this rate, excluding the tax.
+    #[serde(default)]
+    pub net_cents: Option<i64>,
+    pub tax_cents: i64,
+    /// Tax is included in the prices (VAT) rather than added on top.
+    #[serde(default = "default_true")]
+    pub included: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "TransactionCompletedWire")]
+pub struct TransactionCompleted {
+    pub order_id: LocalId,
+    pub global_order_id: Option<GlobalOrderId>,
+    pub transaction_id: String,
+    pub amount_cents: i64,
+    /// Payment kind or method (e.g. "card", "cash"); "payment" when not given.
+    pub kind: String,
+}
+
+/// Legacy: `local_transaction_id`, `amount` (integer minor units, or fractional major units),
+/// `payment_method` / `provider`.
+#[derive(Deserialize)]
+struct TransactionCompletedWire {
+    order_id: LocalId,
+    #[serde(default)]
+    global_order_id: Option<GlobalOrderId>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    transaction_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    local_transaction_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_minor")]
+    amount_cents: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_minor_or_major")]
+    amount: Option<i64>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    kind: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    payment_method: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    provider: Option<String>,
+}
+
+impl TryFrom<TransactionCompletedWire> for TransactionCompleted {
+    type Error = &'static str;
+
+    fn try_from(w: TransactionCompletedWire) -> Result<Self, Self::Error> {
+        Ok(Self {
+            order_id: w.order_id,
+            global_order_id: w.global_order_id,
+            transaction_id: non_empty(w.transaction_id)
+                .or(non_empty(w.local_transaction_id))
+                .ok_or("missing field `transaction_id`")?,
+            amount_cents: w
+                .amount_cents
+                .or(w.amount)
+                .ok_or("missing field `amount_cents`")?,
+            kind: non_empty(w.kind)
+                .or(non_empty(w.payment_method))
+                .or(non_empty(w.provider))
+                .unwrap_or_else(|| "payment".to_string()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "ReceiptCreatedWire")]
+pub struct ReceiptCreated {
+    pub order_id: LocalId,
+    pub global_order_id: Option<GlobalOrderId>,
+    pub receipt_id: String,
+    pub transaction_id: Option<String>,
+}
+
+/// Legacy: `local_receipt_id`, `local_transaction_id`.
+#[derive(Deserialize)]
+struct ReceiptCreatedWire {
+    order_id: LocalId,
+    #[serde(default)]
+    global_order_id: Option<GlobalOrderId>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    receipt_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    local_receipt_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    transaction_id: Option<String>,
+    #[serde(default, deserialize_with = "lenient_string")]
+    local_transaction_id: Option<String>,
+}
+
+impl TryFrom<ReceiptCreatedWire> for ReceiptCreated {
+    type Error = &'static str;
+
+    fn try_from(w: ReceiptCreatedWire) -> Result<Self, Self::Error> {
+        Ok(Self {
+            order_id: w.order_id,
+            global_order_id: w.global_order_id,
+            receipt_id: non_empty(w.receipt_id)
+                .or(non_empty(w.local_receipt_id))
+                .ok_or("missing field `receipt_id`")?,
+            transaction_id: non_empty(w.transaction_id).or(non_empty(w.local_transaction_id)),
+        })
+    }
+}
+
+// --- Voids / refunds ---
+
+/// Body of order_voided.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderVoided {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// Staff member who voided the order (POS staff id and display name).
+    #[serde(default)]
+    pub staff_id: Option<String>,
+    #[serde(default)]
+    pub staff_name: Option<String>,
+}
+
+/// Body of order_refunded: a refund against the order as a whole. Without `amount_cents` the
+/// rest of the order total is refunded. Send a `refund_id` per refund when an order can be
+/// refunded more than once.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderRefunded {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+    #[serde(default)]
+    pub refund_id: Option<String>,
+    /// Refunded amount in minor units (the sign is ignored).
+    #[serde(default)]
+    pub amount_cents: Option<i64>,
+    /// Payment kind refunded to (e.g. "card"); defaults to the order's first payment kind.
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub staff_id: Option<String>,
+    #[serde(default)]
+    pub staff_name: Option<String>,
+}
+
+/// Body of transaction_refunded: a full or partial refund of one payment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TransactionRefunded {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+    /// POS local id of the payment being refunded.
+    pub transaction_id: String,
+    pub refund_id: String,
+    /// Refunded amount in minor units (the sign is ignored).
+    pub amount_cents: i64,
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(default)]
+    pub staff_id: Option<String>,
+    #[serde(default)]
+    pub staff_name: Option<String>,
+}
+
+// --- Kitchen ---
+
+/// Body of order_sent_to_kitchen: the order, or some of its lines, went to the kitchen. An
+/// order can be sent more than once (e.g. courses, or lines added later).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderSentToKitchen {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+    /// Lines sent (their local_item_id); every line of the order when absent.
+    #[serde(default)]
+    pub item_ids: Option<Vec<String>>,
+    /// Kitchen station, e.g. "grill".
+    #[serde(default)]
+    pub station: Option<String>,
+}
+
+/// Body of item_started (preparation began) and item_bumped (the line is done).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct KitchenItemEvent {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+    /// The order line's local_item_id.
+    #[serde(alias = "local_item_id")]
+    pub item_id: LocalId,
+    #[serde(default)]
+    pub station: Option<String>,
+}
+
+/// Body of order_ready: the whole ticket is ready for service or collection.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrderReady {
+    pub order_id: LocalId,
+    #[serde(default)]
+    pub global_order_id: Option<GlobalOrderId>,
+}
+
+// --- End of day ---
+
+/// Body of z_report_closed: the totals a device printed when it closed its day. Reconciled
+/// against the cloud's Z-report for the same device and trading day. Totals the device does not
+/// send are not compared.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZReportClosed {
+    /// POS local Z-report id (or number).
+    pub z_report_id: LocalId,
+    /// Business day the report closes (YYYY-MM-DD); when absent, the store's trading day at
+    /// occurred_at.
+    #[serde(default)]
+    pub trading_day: Option<chrono::NaiveDate>,
+    #[serde(default)]
+    pub order_count: Option<i64>,
+    #[serde(default)]
+    pub gross_cents: Option<i64>,
+    #[serde(default)]
+    pub void_cents: Option<i64>,
+    #[serde(default)]
+    pub refund_cents: Option<i64>,
+    #[serde(default)]
+    pub net_cents: Option<i64>,
+    /// Takings per payment kind; None when the device sends no tender breakdown.
+    #[serde(default)]
+    pub tenders: Option<Vec<ZReportTender>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ZReportTender {
+    pub kind: String,
+    pub amount_cents: i64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn parse(event_type: &str, body: serde_json::Value) -> DeviceEvent {
+        DeviceEvent::parse(1, event_type, &body)
+            .expect("valid body")
+            .expect("projected event type")
+    }
+
+    #[test]
+    fn legacy_order_with_numeric_ids_and_float_prices() {
+        let event = parse(
+            "order_created",
+            json!({
+                "order_id": 1042,
+                "total": 12.5,
+                "order_type": "takeaway",
+                "line_items": [
+                    { "id": 7, "product_id": 311, "qty": 2, "price": 3.25, "line_total": 6.5 },
+                    { "item_id": "8", "name": "Chips", "unit_price": 600.0, "line_total": 600 }
+                ]
+            }),
+        );
+        let DeviceEvent::OrderCreated(order) = event else {
+            panic!("expected order_created");
+        };
+        assert_eq!(order.order_id.as_str(), "1042");
+        assert_eq!(order.total_cents, Some(1250));
+        assert_eq!(order.service_type, Some(ServiceType::Takeaway));
+        let items = order.items.expect("line_items");
+        assert_eq!(items[0].local_item_id.as_deref(), Some("7"));
+        assert_eq!(items[0].product_ref.as_deref(), Some("311"));
+        assert_eq!(items[0].quantity, 2.0);
+        assert_eq!(items[0].unit_price_cents, Some(325));
+        assert_eq!(items[0].line_total_cents, Some(650));
+        assert_eq!(items[1].local_item_id.as_deref(), Some("8"));
+        assert_eq!(items[1].product_ref.as_deref(), Some("Chips"));
+        assert_eq!(items[1].unit_price_cents, Some(600));
+        assert_eq!(items[1].line_total_cents, Some(600));
+    }
+
+    #[test]
+    fn legacy_order_ignores_values_of_unexpected_type() {
+        let event = parse(
+            "order_updated",
+            json!({
+                "order_id": "A1",
+                "total_cents": "n/a",
+                "tip_cents": null,
+                "provider": false,
+                "items": [{ "product_ref": ["x"], "quantity": "two", "price": "3.00" }]
+            }),
+        );
+        let DeviceEvent::OrderUpdated(order) = event else {
+            panic!("expected order_updated");
+        };
+        assert_eq!(order.total_cents, None);
+        assert_eq!(order.tip_cents, None);
+        assert_eq!(order.delivery_provider, None);
+        let items = order.items.expect("items");
+        assert_eq!(items[0].product_ref, None);
+        assert_eq!(items[0].quantity, 1.0);
+        assert_eq!(items[0].unit_price_cents, None);
+    }
+
+    #[test]
+    fn legacy_transaction_with_numeric_id_and_float_amount() {
+        let event = parse(
+            "transaction_completed",
+            json!({
+                "order_id": 1042,
+                "local_transaction_id": 99,
+                "amount": 12.5,
+                "payment_method": "card"
+            }),
+        );
+        let DeviceEvent::TransactionCompleted(tx) = event else {
+            panic!("expected transaction_completed");
+        };
+        assert_eq!(tx.transaction_id, "99");
+        assert_eq!(tx.amount_cents, 1250);
+        assert_eq!(tx.kind, "card");
+
+        let event = parse(
+            "transaction_completed",
+            json!({ "order_id": "1042", "transaction_id": "T1", "amount_cents": 1250.0 }),
+        );
+        let DeviceEvent::TransactionCompleted(tx) = event else {
+            panic!("expected transaction_completed");
+        };
+        assert_eq!(tx.amount_cents, 1250);
+        assert_eq!(tx.kind, "payment");
+    }
+
+    #[test]
+    fn legacy_receipt_with_numeric_ids() {
+        let event = parse(
+            "receipt_created",
+            json!({ "order_id": 1042, "local_receipt_id": 5, "local_transaction_id": 99 }),
+        );
+        let DeviceEvent::ReceiptCreated(receipt) = event else {
+            panic!("expected receipt_created");
+        };
+        assert_eq!(receipt.receipt_id, "5");
+        assert_eq!(receipt.transaction_id.as_deref(), Some("99"));
+    }
+
+    #[test]
+    fn transaction_without_amount_is_rejected() {
+        let body = json!({ "order_id": "1042", "transaction_id": "T1", "amount": "12.50" });
+        assert!(DeviceEvent::parse(1, "transaction_completed", &body).is_err());
+    }
+}
@@ -2,6 +2,22 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+mod commands;
+mod daypart;
+mod events;
+mod order_identity;
+mod sync_channel;
+mod trading_day;
+mod vat;
+
+pub use commands::*;
+pub use daypart::*;
+pub use events::*;
+pub use order_identity::*;
+pub use sync_channel::*;
+pub use trading_day::*;
+pub use vat::*;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ActivateDeviceRequest {
     pub local_device_id: String,
@@ -31,17 +47,62 @@ pub struct DeviceEventIn {
     pub event_type: String,
     pub occurred_at: String,
     pub event_body: serde_json::Value,
+    /// Event schema version (see DeviceEvent); older devices omit it (= 1).
+    #[serde(default)]
+    pub schema_version: Option<u32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncEventsRequest {
     pub last_ack_seq: Option<i64>,
     pub events: Vec<DeviceEventIn>,
+    /// Device clock when the batch was sent (RFC3339), for clock-skew detection. Over REST the
+    /// X-Device-Time header may be sent instead.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_time: Option<String>,
+}
+
+/// Outcome for one event in a POST /sync/events batch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncEventStatus {
+    /// Stored in the event log and queued for projection (the projection worker applies it).
+    Accepted,
+    /// Already stored (same device_id + event_id); safe to drop on the device.
+    Duplicate,
+    /// Not stored; the device should keep the event and inspect `reason`.
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncEventResult {
+    pub event_id: Uuid,
+    pub status: SyncEventStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// Inclusive range of event seqs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SeqRange {
+    pub start: i64,
+    pub end: i64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SyncEventsResponse {
+    /// Highest contiguous seq the cloud has stored; the device may drop events up to here.
     pub ack_seq: Option<i64>,
+    /// Seqs above ack_seq the cloud has never received (lowest first); the device should resend them.
+    #[serde(default)]
+    pub missing_seq_ranges: Vec<SeqRange>,
+    /// One entry per request event, in request order.
+    #[serde(default)]
+    pub results: Vec<SyncEventResult>,
+    /// Device clock minus cloud clock, in milliseconds, when it is beyond the skew threshold. The
+    /// events' timestamps were corrected by this amount; the device should fix its clock.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clock_skew_ms: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,155 @@
+//! Cross-device order identity and the rules for merging one order's events from several
+//! devices (an order opened on Till 1 and paid on Till 2, or bumped by a kitchen screen).
+//!
+//! **Identity.** An order is identified within its store by a [`GlobalOrderId`]. The device that
+//! opens an order generates one (a UUID is recommended) and every device that touches the order
+//! sends it with its events, alongside its own `order_id` (the device's local id). Orders opened
+//! without one get the cloud order id as their global id, which the portal and void/refund
+//! commands expose so other devices can adopt it. Events without a global id keep the per-device
+//! identity (store, device, local order id).
+//!
+//! **Device links.** Each device's local order id maps to exactly one order. The first event
+//! from a device carrying a global id links its local id to the order; later events from that
+//! device may send the local id alone.
+//!
+//! **Opening.** Whichever event for a global id reaches the cloud first opens the order, so
+//! devices may sync in any order. An order event for an unknown local id without a global id is
+//! dropped, as before, except kitchen events: their local id is matched against the store's
+//! orders from every device (a kitchen screen echoes the till's id), and they are dropped only
+//! when no single order matches. Kitchen screens should send the global id so their events are
+//! kept even when they are projected before the order's.
+//!
+//! **Owner.** The owning device (orders.device_id / local_order_id, where void and refund
+//! commands are sent) is the device whose `order_created` has the earliest occurred_at; until an
+//! `order_created` arrives it is the device that opened the order. The order's time is the
+//! earliest `order_created` time.
+//!
+//! **Concurrent edits.** Order content (lines, total, discounts, taxes, tip, service charge,
+//! service type) is replaced whole by each `order_created` / `order_updated`, last writer wins by
+//! [`OrderRevision`]: the later occurred_at, ties broken by device id. An edit older than the one
+//! applied is still recorded in the order's history but does not change the order, so the result
+//! does not depend on which device syncs first.
+//!
+//! **Voids.** A void is final: it wins over edits from any device, and edits to a voided order are
+//! recorded but not applied.
+//!
+//! **Payments, receipts and refunds** are additive and idempotent per device (by their local
+//! ids), so they never conflict; all of them attach to the merged order.
+
+use std::fmt;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Deserializer, Serialize};
+use uuid::Uuid;
+
+/// Longest global order id accepted.
+pub const MAX_GLOBAL_ORDER_ID_LEN: usize = 64;
+
+/// Store-scoped order identity shared by every device that touches the order.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(transparent)]
+pub struct GlobalOrderId(String);
+
+impl GlobalOrderId {
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s.is_empty() || s.len() > MAX_GLOBAL_ORDER_ID_LEN {
+            return None;
+        }
+        Some(GlobalOrderId(s.to_string()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for GlobalOrderId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for GlobalOrderId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        GlobalOrderId::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "global_order_id must be 1 to {} characters",
+                MAX_GLOBAL_ORDER_ID_LEN
+            ))
+        })
+    }
+}
+
+/// When and where an order's content was written. Ordered by time, then device id.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct OrderRevision {
+    pub occurred_at: DateTime<Utc>,
+    pub device_id: Uuid,
+}
+
+impl OrderRevision {
+    /// Whether an edit at this revision replaces content last written at `current`. The same
+    /// revision does, so a re-sent event applies again instead of being dropped.
+    pub fn supersedes(&self, current: Option<&OrderRevision>) -> bool {
+        current.is_none_or(|current| self >= current)
+    }
+}
+
+/// Whether an edit may change an order in this status (voids are final).
+pub fn order_accepts_edits(status: &str) -> bool {
+    status != "voided"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn revision(at: &str, device: u128) -> OrderRevision {
+        OrderRevision {
+            occurred_at: DateTime::parse_from_rfc3339(at).unwrap().with_timezone(&Utc),
+            device_id: Uuid::from_u128(device),
+        }
+    }
+
+    #[test]
+    fn later_edit_wins_whatever_the_device() {
+        let current = revision("2026-10-17T12:00:00Z", 2);
+        assert!(revision("2026-10-17T12:00:01Z", 1).supersedes(Some(&current)));
+        assert!(!revision("2026-10-17T11:59:59Z", 3).supersedes(Some(&current)));
+    }
+
+    #[test]
+    fn same_time_is_broken_by_device_id() {
+        let current = revision("2026-10-17T12:00:00Z", 2);
+        assert!(revision("2026-10-17T12:00:00Z", 3).supersedes(Some(&current)));
+        assert!(!revision("2026-10-17T12:00:00Z", 1).supersedes(Some(&current)));
+        // Applying the edits in either order ends on the same one.
+        let (a, b) = (revision("2026-10-17T12:00:00Z", 1), revision("2026-10-17T12:00:00Z", 3));
+        assert_eq!(a.max(b), b.max(a));
+    }
+
+    #[test]
+    fn same_revision_and_first_edit_apply() {
+        let current = revision("2026-10-17T12:00:00Z", 2);
+        assert!(current.supersedes(Some(&current)));
+        assert!(current.supersedes(None));
+    }
+
+    #[test]
+    fn voided_orders_refuse_edits() {
+        assert!(!order_accepts_edits("voided"));
+        assert!(order_accepts_edits("open"));
+        assert!(order_accepts_edits("refunded"));
+    }
+
+    #[test]
+    fn global_order_id_is_trimmed_and_bounded() {
+        assert_eq!(GlobalOrderId::parse("  abc ").unwrap().as_str(), "abc");
+        assert!(GlobalOrderId::parse("   ").is_none());
+        assert!(GlobalOrderId::parse(&"x".repeat(MAX_GLOBAL_ORDER_ID_LEN)).is_some());
+        assert!(GlobalOrderId::parse(&"x".repeat(MAX_GLOBAL_ORDER_ID_LEN + 1)).is_none());
+        assert!(serde_json::from_str::<GlobalOrderId>("\"\"").is_err());
+    }
+}
@@ -0,0 +1,85 @@
+//! Message framing for the device WebSocket at GET /sync/ws.
+//!
+//! One JSON object per text frame, tagged by `type`. The socket multiplexes what the REST trio
+//! does: the device pushes event batches and command acks, the cloud pushes commands as soon as
+//! they are queued. Requests may carry a `request_id` chosen by the device; the matching reply
+//! echoes it so a device can have several requests in flight.
+
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{CommandAckRequest, DeviceCommandOut, SyncEventsRequest, SyncEventsResponse};
+
+/// Device → cloud.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncClientMessage {
+    /// Same fields as the POST /sync/events body. Answered with `events_result`.
+    Events {
+        #[serde(default)]
+        request_id: Option<String>,
+        #[serde(flatten)]
+        batch: SyncEventsRequest,
+    },
+    /// Same fields as the POST /sync/commands/ack body. Answered with `command_ack_result`.
+    CommandAck {
+        #[serde(default)]
+        request_id: Option<String>,
+        #[serde(flatten)]
+        ack: CommandAckRequest,
+    },
+    /// Application-level keep-alive for clients that cannot send WebSocket ping frames. Carrying
+    /// the device clock (RFC3339) keeps its measured clock skew current.
+    Ping {
+        #[serde(default)]
+        request_id: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        device_time: Option<String>,
+    },
+}
+
+/// Cloud → device.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SyncServerMessage {
+    /// First frame after the upgrade.
+    Hello {
+        device_id: Uuid,
+        /// RFC3339 server time.
+        server_time: String,
+        /// The server pings at this interval and closes the socket after two silent intervals.
+        heartbeat_seconds: u32,
+    },
+    /// Reply to `events`; same fields as the POST /sync/events response.
+    EventsResult {
+        #[serde(default)]
+        request_id: Option<String>,
+        #[serde(flatten)]
+        response: SyncEventsResponse,
+    },
+    /// A deliverable command (sent once per connection; ack with `command_ack`).
+    Command {
+        #[serde(flatten)]
+        command: DeviceCommandOut,
+    },
+    /// Reply to `command_ack`.
+    CommandAckResult {
+        #[serde(default)]
+        request_id: Option<String>,
+        command_id: Uuid,
+        ok: bool,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    Pong {
+        #[serde(default)]
+        request_id: Option<String>,
+    },
+    /// A request failed (or a frame could not be parsed). `status` mirrors the REST status code.
+    Error {
+        #[serde(default)]
+        request_id: Option<String>,
+        status: u16,
+        message: String,
+    },
+}
@@ -0,0 +1,257 @@
+//! Store-local reporting time: a store's timezone and trading-day cutoff decide which business
+//! day an order belongs to (a 04:00 cutoff puts a 01:30 Saturday sale on Friday's takings), and
+//! timestamps are shown with the store's UTC offset.
+
+use chrono::{
+    DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, NaiveTime, Offset, SecondsFormat, TimeZone, Utc,
+};
+use chrono_tz::Tz;
+
+/// Timezone used when a store's timezone is not a valid IANA name (same as the stores default).
+pub const DEFAULT_STORE_TIMEZONE: Tz = chrono_tz::Europe::London;
+
+/// Parse an IANA timezone name (e.g. "Europe/Dublin").
+pub fn parse_timezone(name: &str) -> Option<Tz> {
+    name.trim().parse().ok()
+}
+
+/// A store's reporting clock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct StoreClock {
+    pub timezone: Tz,
+    /// Local time the trading day starts (00:00 = calendar days).
+    pub trading_day_cutoff: NaiveTime,
+}
+
+impl StoreClock {
+    /// From stored settings; an unknown timezone name falls back to DEFAULT_STORE_TIMEZONE.
+    pub fn new(timezone: &str, trading_day_cutoff: NaiveTime) -> Self {
+        Self {
+            timezone: parse_timezone(timezone).unwrap_or(DEFAULT_STORE_TIMEZONE),
+            trading_day_cutoff,
+        }
+    }
+
+    /// Business day `at` falls in.
+    pub fn trading_day(&self, at: DateTime<Utc>) -> NaiveDate {
+        let local = at.with_timezone(&self.timezone).naive_local();
+        (local - self.cutoff_offset()).date()
+    }
+
+    /// Current business day.
+    pub fn today(&self) -> NaiveDate {
+        self.trading_day(Utc::now())
+    }
+
+    /// UTC range [start, end) of a business day, for filtering stored UTC timestamps.
+    pub fn day_bounds(&self, day: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        self.range_bounds(day, day)
+    }
+
+    /// UTC range [start, end) covering business days `from` through `to` inclusive.
+    pub fn range_bounds(&self, from: NaiveDate, to: NaiveDate) -> (DateTime<Utc>, DateTime<Utc>) {
+        let start = self.local_to_utc(from.and_time(self.trading_day_cutoff));
+        let end = self.local_to_utc((to + Duration::days(1)).and_time(self.trading_day_cutoff));
+        (start, end)
+    }
+
+    /// RFC3339 in store-local time with its offset, e.g. "2026-03-28T23:15:00+00:00".
+    pub fn format_local(&self, at: DateTime<Utc>) -> String {
+        at.with_timezone(&self.timezone)
+            .to_rfc3339_opts(SecondsFormat::Secs, false)
+    }
+
+    /// Same as `format_local` for a naive UTC value read from a DATETIME column.
+    pub fn format_local_naive(&self, at: NaiveDateTime) -> String {
+        self.format_local(Utc.from_utc_datetime(&at))
+    }
+
+    /// Store-local wall-clock time of a UTC instant.
+    pub fn local_time(&self, at: DateTime<Utc>) -> NaiveDateTime {
+        at.with_timezone(&self.timezone).naive_local()
+    }
+
+    /// Seconds from the start of a local day to the trading-day cutoff.
+    pub fn cutoff_secs(&self) -> i64 {
+        self.cutoff_offset().num_seconds()
+    }
+
+    /// UTC offset (local minus UTC) in force at `at`, in seconds.
+    pub fn utc_offset_secs(&self, at: DateTime<Utc>) -> i64 {
+        i64::from(self.timezone.offset_from_utc_datetime(&at.naive_utc()).fix().local_minus_utc())
+    }
+
+    /// Split [start, end) at the clock's UTC offset changes: (span start, span end, offset in
+    /// seconds), in order. Within a span, local time is UTC plus the offset, so it can be computed
+    /// in SQL.
+    pub fn offset_spans(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Vec<(DateTime<Utc>, DateTime<Utc>, i64)> {
+        let mut spans = Vec::new();
+        let mut span_start = start;
+        let mut offset = self.utc_offset_secs(start);
+        let mut at = start;
+        while at < end {
+            let next = (at + Duration::hours(1)).min(end);
+            if next < end && self.utc_offset_secs(next) != offset {
+                // Offsets change at most once an hour; find the first second of the new one.
+                let (mut lo, mut hi) = (at, next);
+                while hi - lo > Duration::seconds(1) {
+                    let mid = lo + (hi - lo) / 2;
+                    if self.utc_offset_secs(mid) == offset {
+                        lo = mid;
+                    } else {
+                        hi = mid;
+                    }
+                }
+                spans.push((span_start, hi, offset));
+                span_start = hi;
+                offset = self.utc_offset_secs(hi);
+            }
+            at = next;
+        }
+        if span_start < end {
+            spans.push((span_start, end, offset));
+        }
+        spans
+    }
+
+    fn cutoff_offset(&self) -> Duration {
+        self.trading_day_cutoff - NaiveTime::MIN
+    }
+
+    /// A local wall-clock time as UTC. Ambiguous times (clocks going back) take the first
+    /// occurrence; times skipped by a DST jump move forward to the first valid instant.
+    pub fn local_to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        let mut candidate = local;
+        for _ in 0..4 {
+            match self.timezone.from_local_datetime(&candidate) {
+                LocalResult::Single(dt) | LocalResult::Ambiguous(dt, _) => return dt.with_timezone(&Utc),
+                LocalResult::None => candidate += Duration::minutes(30),
+            }
+        }
+        Utc.from_utc_datetime(&local)
+    }
+}
+
+impl Default for StoreClock {
+    fn default() -> Self {
+        Self {
+            timezone: DEFAULT_STORE_TIMEZONE,
+            trading_day_cutoff: NaiveTime::MIN,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn london(cutoff: &str) -> StoreClock {
+        StoreClock::new("Europe/London", NaiveTime::parse_from_str(cutoff, "%H:%M").unwrap())
+    }
+
+    fn utc(at: &str) -> DateTime<Utc> {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M").unwrap().and_utc()
+    }
+
+    fn local(at: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(at, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn day(d: &str) -> NaiveDate {
+        NaiveDate::parse_from_str(d, "%Y-%m-%d").unwrap()
+    }
+
+    #[test]
+    fn local_to_utc_moves_skipped_times_forward() {
+        let clock = london("00:00");
+        // 01:00-02:00 does not exist on 29 March 2026; the first valid instant is 02:00 BST.
+        assert_eq!(clock.local_to_utc(local("2026-03-29 01:30")), utc("2026-03-29 01:00"));
+        assert_eq!(clock.local_to_utc(local("2026-03-29 00:59")), utc("2026-03-29 00:59"));
+        assert_eq!(clock.local_to_utc(local("2026-03-29 02:00")), utc("2026-03-29 01:00"));
+    }
+
+    #[test]
+    fn local_to_utc_takes_the_first_of_repeated_times() {
+        let clock = london("00:00");
+        // 01:00-02:00 happens twice on 25 October 2026: first in BST, then in GMT.
+        assert_eq!(clock.local_to_utc(local("2026-10-25 01:30")), utc("2026-10-25 00:30"));
+        assert_eq!(clock.local_to_utc(local("2026-10-25 02:00")), utc("2026-10-25 02:00"));
+    }
+
+    #[test]
+    fn day_bounds_follow_dst() {
+        let clock = london("04:00");
+        // The day the clocks go forward is 23 hours long, the day they go back 25.
+        assert_eq!(
+            clock.day_bounds(day("2026-03-28")),
+            (utc("2026-03-28 04:00"), utc("2026-03-29 03:00"))
+        );
+        assert_eq!(
+            clock.day_bounds(day("2026-10-24")),
+            (utc("2026-10-24 03:00"), utc("2026-10-25 04:00"))
+        );
+        assert_eq!(
+            clock.range_bounds(day("2026-03-28"), day("2026-10-24")),
+            (utc("2026-03-28 04:00"), utc("2026-10-25 04:00"))
+        );
+    }
+
+    #[test]
+    fn day_bounds_with_a_cutoff_in_the_dst_gap() {
+        let clock = london("01:30");
+        assert_eq!(
+            clock.day_bounds(day("2026-03-29")),
+            (utc("2026-03-29 01:00"), utc("2026-03-30 00:30"))
+        );
+        // Calendar days start at local midnight.
+        let clock = london("00:00");
+        assert_eq!(
+            clock.day_bounds(day("2026-07-04")),
+            (utc("2026-07-03 23:00"), utc("2026-07-04 23:00"))
+        );
+    }
+
+    #[test]
+    fn trading_day_before_the_cutoff_is_the_previous_day() {
+        let clock = london("04:00");
+        assert_eq!(clock.trading_day(utc("2026-03-28 01:30")), day("2026-03-27"));
+        assert_eq!(clock.trading_day(utc("2026-03-28 03:59")), day("2026-03-27"));
+        assert_eq!(clock.trading_day(utc("2026-03-28 04:00")), day("2026-03-28"));
+        // 03:30 BST is still before the cutoff; 03:00 UTC is 04:00 BST.
+        assert_eq!(clock.trading_day(utc("2026-07-04 02:30")), day("2026-07-03"));
+        assert_eq!(clock.trading_day(utc("2026-07-04 03:00")), day("2026-07-04"));
+        assert_eq!(london("00:00").trading_day(utc("2026-07-04 23:30")), day("2026-07-05"));
+    }
+
+    #[test]
+    fn unknown_timezone_falls_back_to_the_default() {
+        let clock = StoreClock::new("Not/AZone", NaiveTime::MIN);
+        assert_eq!(clock.timezone, DEFAULT_STORE_TIMEZONE);
+        assert_eq!(
+            clock.format_local(utc("2026-07-04 12:00")),
+            "2026-07-04T13:00:00+01:00"
+        );
+    }
+
+    #[test]
+    fn offset_spans_split_at_dst_changes() {
+        let clock = london("00:00");
+        let spans = clock.offset_spans(utc("2026-03-28 00:00"), utc("2026-10-26 00:00"));
+        assert_eq!(
+            spans,
+            vec![
+                (utc("2026-03-28 00:00"), utc("2026-03-29 01:00"), 0),
+                (utc("2026-03-29 01:00"), utc("2026-10-25 01:00"), 3600),
+                (utc("2026-10-25 01:00"), utc("2026-10-26 00:00"), 0),
+            ]
+        );
+    }
+
+    #[test]
+    fn offset_spans_without_a_change() {
+        let clock = StoreClock::new("Asia/Kolkata", NaiveTime::MIN);
+        let (start, end) = (utc("2026-03-01 00:00"), utc("2026-04-01 00:00"));
+        assert_eq!(clock.offset_spans(start, end), vec![(start, end, 19800)]);
+        assert!(clock.offset_spans(end, start).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! UK VAT: the rate bands menu items are configured with, and whether an order is eaten in or
+//! taken away (hot and eat-in food is standard rated; much cold takeaway food is zero rated, so
+//! an item can have a different takeaway rate).
+
+use serde::{Deserialize, Serialize};
+
+/// A UK VAT rate band.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VatRate {
+    Standard,
+    Reduced,
+    Zero,
+}
+
+impl VatRate {
+    pub const ALL: [VatRate; 3] = [VatRate::Standard, VatRate::Reduced, VatRate::Zero];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "standard" => Some(VatRate::Standard),
+            "reduced" => Some(VatRate::Reduced),
+            "zero" => Some(VatRate::Zero),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            VatRate::Standard => "standard",
+            VatRate::Reduced => "reduced",
+            VatRate::Zero => "zero",
+        }
+    }
+
+    /// Current rate in percent.
+    pub fn percent(&self) -> f64 {
+        match self {
+            VatRate::Standard => 20.0,
+            VatRate::Reduced => 5.0,
+            VatRate::Zero => 0.0,
+        }
+    }
+
+    /// The band with this percentage, if any (for tax lines sent by the POS).
+    pub fn from_percent(percent: f64) -> Option<Self> {
+        Self::ALL.into_iter().find(|r| (r.percent() - percent).abs() < 0.001)
+    }
+
+    /// VAT contained in a VAT-inclusive amount, rounded to the nearest minor unit.
+    pub fn included_tax(&self, gross_cents: i64) -> i64 {
+        let percent = self.percent();
+        (gross_cents as f64 * percent / (100.0 + percent)).round() as i64
+    }
+}
+
+/// How an order is served, which decides the VAT rate of items with a takeaway rate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ServiceType {
+    EatIn,
+    Takeaway,
+    Delivery,
+}
+
+impl ServiceType {
+    /// Accepts the spellings POS builds use ("dine_in", "take-away", "collection", ...).
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().replace(['-', ' '], "_").as_str() {
+            "eat_in" | "eatin" | "dine_in" | "table" => Some(ServiceType::EatIn),
+            "takeaway" | "take_away" | "takeout" | "to_go" | "collection" => Some(ServiceType::Takeaway),
+            "delivery" => Some(ServiceType::Delivery),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ServiceType::EatIn => "eat_in",
+            ServiceType::Takeaway => "takeaway",
+            ServiceType::Delivery => "delivery",
+        }
+    }
+
+    /// Takeaway and delivery orders use an item's takeaway VAT rate.
+    pub fn is_takeaway(&self) -> bool {
+        !matches!(self, ServiceType::EatIn)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn included_tax_rounds_to_the_nearest_minor_unit() {
+        assert_eq!(VatRate::Standard.included_tax(1200), 200);
+        assert_eq!(VatRate::Standard.included_tax(1000), 167);
+        assert_eq!(VatRate::Standard.included_tax(995), 166);
+        // Exactly half a penny rounds away from zero.
+        assert_eq!(VatRate::Standard.included_tax(999), 167);
+        assert_eq!(VatRate::Standard.included_tax(1), 0);
+        assert_eq!(VatRate::Reduced.included_tax(1050), 50);
+        assert_eq!(VatRate::Reduced.included_tax(399), 19);
+        assert_eq!(VatRate::Zero.included_tax(1234), 0);
+    }
+
+    #[test]
+    fn included_tax_of_refunds_is_negative() {
+        assert_eq!(VatRate::Standard.included_tax(-1200), -200);
+        assert_eq!(VatRate::Standard.included_tax(-999), -167);
+        assert_eq!(VatRate::Standard.included_tax(0), 0);
+    }
+
+    #[test]
+    fn from_percent_matches_bands_only() {
+        assert_eq!(VatRate::from_percent(20.0), Some(VatRate::Standard));
+        assert_eq!(VatRate::from_percent(5.0), Some(VatRate::Reduced));
+        assert_eq!(VatRate::from_percent(0.0), Some(VatRate::Zero));
+        // Tolerates rounding from DECIMAL columns sent as floats.
+        assert_eq!(VatRate::from_percent(19.9999), Some(VatRate::Standard));
+        assert_eq!(VatRate::from_percent(12.5), None);
+        assert_eq!(VatRate::from_percent(20.01), None);
+    }
+
+    #[test]
+    fn parse_rates_and_service_types() {
+        for rate in VatRate::ALL {
+            assert_eq!(VatRate::parse(rate.as_str()), Some(rate));
+        }
+        assert_eq!(VatRate::parse(" Standard "), Some(VatRate::Standard));
+        assert_eq!(VatRate::parse("exempt"), None);
+
+        assert_eq!(ServiceType::parse("Dine-In"), Some(ServiceType::EatIn));
+        assert_eq!(ServiceType::parse("take away"), Some(ServiceType::Takeaway));
+        assert_eq!(ServiceType::parse("collection"), Some(ServiceType::Takeaway));
+        assert_eq!(ServiceType::parse("drive_thru"), None);
+        assert!(ServiceType::Delivery.is_takeaway());
+        assert!(!ServiceType::EatIn.is_takeaway());
+    }
+}
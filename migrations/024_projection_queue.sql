@@ -0,0 +1,56 @@
+-- Durable projection queue: device_event_log is projected asynchronously by a background worker.
+-- log_pos gives every logged event a monotonic position; each device has a cursor recording the
+-- last position projected plus retry state. Events that keep failing are parked in
+-- projection_dead_letters so the portal can list and replay them.
+
+ALTER TABLE device_event_log
+  ADD COLUMN log_pos BIGINT NOT NULL AUTO_INCREMENT,
+  ADD UNIQUE KEY uq_device_event_log_pos (log_pos);
+
+CREATE INDEX idx_device_event_log_device_pos ON device_event_log(device_id, log_pos);
+
+CREATE TABLE device_projection_cursors (
+  device_id CHAR(36) PRIMARY KEY,
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  last_projected_pos BIGINT NOT NULL DEFAULT 0,
+  attempts INT NOT NULL DEFAULT 0,
+  last_error TEXT NULL,
+  next_attempt_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE,
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_device_projection_cursors_store ON device_projection_cursors(store_id);
+CREATE INDEX idx_device_projection_cursors_next ON device_projection_cursors(next_attempt_at);
+
+-- Events logged before this migration were already projected inline by POST /sync/events.
+INSERT INTO device_projection_cursors (device_id, org_id, store_id, last_projected_pos)
+SELECT device_id, MAX(org_id), MAX(store_id), MAX(log_pos)
+FROM device_event_log
+GROUP BY device_id;
+
+CREATE TABLE projection_dead_letters (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  device_id CHAR(36) NOT NULL,
+  event_log_id CHAR(36) NOT NULL,
+  event_id CHAR(36) NOT NULL,
+  event_type VARCHAR(100) NOT NULL,
+  attempts INT NOT NULL,
+  last_error TEXT NOT NULL,
+  replay_count INT NOT NULL DEFAULT 0,
+  resolved_at DATETIME(3) NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  UNIQUE KEY uq_projection_dead_letters_event_log (event_log_id),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE,
+  FOREIGN KEY (event_log_id) REFERENCES device_event_log(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_projection_dead_letters_store ON projection_dead_letters(store_id, resolved_at);
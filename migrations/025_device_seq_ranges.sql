@@ -0,0 +1,10 @@
+-- Seq gap detection: device_sync_state keeps the merged ranges of event seqs received from each
+-- device, as a JSON array of inclusive [start, end] pairs sorted by start. last_ack_seq becomes the
+-- end of the first range (highest contiguous seq); the holes between ranges are reported back to
+-- the device as missing ranges. NULL means not computed yet: it is rebuilt from device_event_log
+-- on first use.
+
+ALTER TABLE device_sync_state
+  ADD COLUMN seq_ranges JSON NULL AFTER last_ack_seq;
+
+CREATE INDEX idx_device_event_log_device_seq ON device_event_log(device_id, seq);
@@ -0,0 +1,4 @@
+-- Typed device events: record the event schema version each row was validated against at ingest
+-- so projections parse it with the matching rules (see domain::DeviceEvent).
+ALTER TABLE device_event_log
+  ADD COLUMN schema_version INT UNSIGNED NOT NULL DEFAULT 1 AFTER event_body;
@@ -0,0 +1,6 @@
+-- WebSocket sync channel: devices.online_since is set while a device holds an open /sync/ws
+-- connection; last_seen_at is refreshed on every heartbeat. A device counts as online when
+-- online_since is set and last_seen_at is recent (guards against instances that died without
+-- clearing it).
+ALTER TABLE devices
+  ADD COLUMN online_since DATETIME(3) NULL AFTER last_seen_at;
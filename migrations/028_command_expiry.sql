@@ -0,0 +1,27 @@
+-- Command expiry and redelivery policy. expires_at (added in 007) is now filled at enqueue from
+-- the command type's TTL; delivery_attempts counts hand-outs to the device; the sweeper moves
+-- commands past expires_at or max_attempts to 'expired' and records why.
+ALTER TABLE device_command_queue
+  ADD COLUMN delivery_attempts INT UNSIGNED NOT NULL DEFAULT 0 AFTER delivered_at,
+  ADD COLUMN max_attempts INT UNSIGNED NULL AFTER delivery_attempts,
+  ADD COLUMN expired_at DATETIME(3) NULL AFTER ack_result,
+  ADD COLUMN expired_reason VARCHAR(255) NULL AFTER expired_at;
+
+CREATE INDEX idx_device_command_queue_status_expires
+  ON device_command_queue(status, expires_at);
+
+-- Commands still waiting for delivery get the TTL and attempt cap their type has at enqueue (keep
+-- in sync with db::command_policy), counted from when they were queued; ones already handed out
+-- count as one attempt.
+UPDATE device_command_queue
+SET expires_at = COALESCE(expires_at, TIMESTAMPADD(SECOND,
+      CASE command_type
+        WHEN 'delivery_order' THEN 30 * 60
+        WHEN 'apply_menu' THEN 24 * 60 * 60
+        WHEN 'void_order' THEN 48 * 60 * 60
+        WHEN 'refund_order' THEN 48 * 60 * 60
+        ELSE 72 * 60 * 60
+      END, created_at)),
+    max_attempts = CASE command_type WHEN 'delivery_order' THEN 10 ELSE 20 END,
+    delivery_attempts = IF(delivered_at IS NULL, 0, 1)
+WHERE status IN ('queued', 'delivered');
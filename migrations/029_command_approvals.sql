@@ -0,0 +1,29 @@
+-- Two-person approval for sensitive commands. A sensitive command is created in
+-- 'pending_approval' with required_approvals set; it becomes 'queued' (deliverable) once that many
+-- users other than the requester have approved it in `approvals`, or 'rejected' on the first
+-- rejection. order_id links order commands (void/refund) to the orders read model.
+ALTER TABLE device_command_queue
+  DROP CHECK chk_device_command_queue_status;
+
+ALTER TABLE device_command_queue
+  ADD COLUMN order_id CHAR(36) NULL AFTER device_id,
+  ADD COLUMN requested_by_user_id CHAR(36) NULL AFTER `sensitive`,
+  ADD COLUMN required_approvals INT UNSIGNED NOT NULL DEFAULT 0 AFTER requested_by_user_id,
+  ADD COLUMN approved_at DATETIME(3) NULL AFTER required_approvals,
+  ADD COLUMN rejected_at DATETIME(3) NULL AFTER approved_at,
+  ADD CONSTRAINT chk_device_command_queue_status
+    CHECK (status IN ('pending_approval', 'queued', 'delivered', 'acked', 'failed', 'expired', 'rejected')),
+  ADD CONSTRAINT fk_device_command_queue_requested_by
+    FOREIGN KEY (requested_by_user_id) REFERENCES cloud_users(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_device_command_queue_order
+  ON device_command_queue(order_id);
+
+ALTER TABLE approvals
+  ADD COLUMN comment VARCHAR(500) NULL AFTER decision;
+
+-- Voids and refunds queued before approvals existed wait for an approval like new ones instead
+-- of being delivered unapproved. Commands already handed to a device are left to finish.
+UPDATE device_command_queue
+SET status = 'pending_approval', `sensitive` = 1, required_approvals = 1
+WHERE status = 'queued' AND command_type IN ('void_order', 'refund_order');
@@ -0,0 +1,60 @@
+-- Command outcomes. target_type/target_id name the entity a command acts on ('order' -> orders.id,
+-- 'menu' -> stores.id, 'delivery_order' -> delivery_orders.id) so the portal can list a target's
+-- commands; an ack does not change the target (the device reports what it did in its own events).
+-- acked_at/failed_at record when the device answered. device_command_status_log keeps every
+-- status change (queued, approved, delivered per attempt, acked/failed/expired/rejected) for the
+-- per-command timeline.
+ALTER TABLE device_command_queue
+  ADD COLUMN target_type VARCHAR(32) NULL AFTER order_id,
+  ADD COLUMN target_id CHAR(36) NULL AFTER target_type,
+  ADD COLUMN acked_at DATETIME(3) NULL AFTER ack_result,
+  ADD COLUMN failed_at DATETIME(3) NULL AFTER acked_at;
+
+CREATE INDEX idx_device_command_queue_target
+  ON device_command_queue(target_type, target_id);
+
+CREATE TABLE device_command_status_log (
+  id BIGINT UNSIGNED AUTO_INCREMENT PRIMARY KEY,
+  command_id CHAR(36) NOT NULL,
+  status VARCHAR(50) NOT NULL,
+  detail VARCHAR(255) NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  KEY idx_device_command_status_log_command (command_id, id),
+  FOREIGN KEY (command_id) REFERENCES device_command_queue(command_id) ON DELETE CASCADE
+);
+
+-- Link void/refund commands queued before order_id was recorded (matched by POS local order id).
+UPDATE device_command_queue q
+JOIN orders o
+  ON o.device_id = q.device_id
+ AND o.local_order_id = COALESCE(JSON_UNQUOTE(JSON_EXTRACT(q.command_body, '$.local_order_id')),
+                                 JSON_UNQUOTE(JSON_EXTRACT(q.command_body, '$.order_id')))
+SET q.order_id = o.id
+WHERE q.order_id IS NULL AND q.command_type IN ('void_order', 'refund_order');
+
+UPDATE device_command_queue SET target_type = 'order', target_id = order_id
+WHERE order_id IS NOT NULL;
+
+UPDATE device_command_queue SET target_type = 'menu', target_id = store_id
+WHERE command_type = 'apply_menu';
+
+-- Seed timelines from the timestamps recorded so far.
+INSERT INTO device_command_status_log (command_id, status, detail, created_at)
+SELECT command_id, IF(required_approvals > 0, 'pending_approval', 'queued'), NULL, created_at
+FROM device_command_queue;
+
+INSERT INTO device_command_status_log (command_id, status, detail, created_at)
+SELECT command_id, 'queued', 'approved', approved_at
+FROM device_command_queue WHERE approved_at IS NOT NULL;
+
+INSERT INTO device_command_status_log (command_id, status, detail, created_at)
+SELECT command_id, 'rejected', NULL, rejected_at
+FROM device_command_queue WHERE rejected_at IS NOT NULL;
+
+INSERT INTO device_command_status_log (command_id, status, detail, created_at)
+SELECT command_id, 'delivered', CONCAT('attempt ', delivery_attempts), delivered_at
+FROM device_command_queue WHERE delivered_at IS NOT NULL;
+
+INSERT INTO device_command_status_log (command_id, status, detail, created_at)
+SELECT command_id, 'expired', expired_reason, expired_at
+FROM device_command_queue WHERE expired_at IS NOT NULL;
@@ -0,0 +1,12 @@
+-- Device clock skew. Devices send their clock with sync calls (X-Device-Time header, or
+-- device_time in the events batch / WebSocket ping); device_sync_state keeps the latest measured
+-- offset (device minus cloud, ms). Events from a device whose skew is beyond the threshold are
+-- flagged and get corrected_occurred_at = occurred_at - skew; projections use the corrected
+-- time when set. occurred_at always keeps what the device reported.
+ALTER TABLE device_sync_state
+  ADD COLUMN clock_skew_ms BIGINT NULL AFTER seq_ranges,
+  ADD COLUMN clock_checked_at DATETIME(3) NULL AFTER clock_skew_ms;
+
+ALTER TABLE device_event_log
+  ADD COLUMN corrected_occurred_at DATETIME(3) NULL AFTER occurred_at,
+  ADD COLUMN clock_skew_ms BIGINT NULL AFTER corrected_occurred_at;
@@ -0,0 +1,5 @@
+-- Store-local reporting: business days run from trading_day_cutoff to the same local time the next
+-- day, in stores.timezone (e.g. 04:00 for late-night venues, so sales after midnight count towards
+-- the evening they belong to).
+ALTER TABLE stores
+  ADD COLUMN trading_day_cutoff TIME NOT NULL DEFAULT '00:00:00' AFTER timezone;
@@ -0,0 +1,3 @@
+-- Sales reports filter orders and transactions by store and a UTC time range.
+CREATE INDEX idx_orders_store_occurred ON orders(store_id, occurred_at);
+CREATE INDEX idx_transactions_store_occurred ON transactions(store_id, occurred_at);
@@ -0,0 +1,62 @@
+-- End-of-day Z-reports. Once a store's trading day has closed the cloud computes a Z-report from
+-- orders, transactions and receipts: one for the whole store (device_id NULL) and one per device
+-- that traded. Rows are written once and never updated, so later syncs or rebuilds do not change
+-- a closed day; the store's clock at closing is kept with the report.
+CREATE TABLE z_reports (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  device_id CHAR(36) NULL,
+  device_key CHAR(36) AS (COALESCE(device_id, '')) STORED,
+  trading_day DATE NOT NULL,
+  timezone VARCHAR(64) NOT NULL,
+  trading_day_cutoff TIME NOT NULL,
+  period_start DATETIME(3) NOT NULL,
+  period_end DATETIME(3) NOT NULL,
+  order_count INT NOT NULL,
+  gross_cents BIGINT NOT NULL,
+  void_count INT NOT NULL,
+  void_cents BIGINT NOT NULL,
+  refund_count INT NOT NULL,
+  refund_cents BIGINT NOT NULL,
+  net_cents BIGINT NOT NULL,
+  tenders JSON NOT NULL,
+  receipt_count INT NOT NULL,
+  first_receipt_id VARCHAR(255) NULL,
+  first_receipt_at DATETIME(3) NULL,
+  last_receipt_id VARCHAR(255) NULL,
+  last_receipt_at DATETIME(3) NULL,
+  closed_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  UNIQUE KEY uq_z_reports_store_day_device (store_id, trading_day, device_key),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+-- Totals devices report with z_report_closed events (orders read model; cleared and replayed by
+-- a rebuild). Reconciled against the cloud's per-device Z-report once both exist: mismatch and
+-- mismatches ([{ field, device, cloud }]) are set at reconciled_at.
+CREATE TABLE z_report_device_closes (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  device_id CHAR(36) NOT NULL,
+  trading_day DATE NOT NULL,
+  local_z_report_id VARCHAR(255) NOT NULL,
+  order_count INT NULL,
+  gross_cents BIGINT NULL,
+  void_cents BIGINT NULL,
+  refund_cents BIGINT NULL,
+  net_cents BIGINT NULL,
+  tenders JSON NULL,
+  closed_at DATETIME(3) NOT NULL,
+  reconciled_at DATETIME(3) NULL,
+  mismatch TINYINT(1) NULL,
+  mismatches JSON NULL,
+  UNIQUE KEY uq_z_report_device_closes_device_day (store_id, device_id, trading_day),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_receipts_store_occurred ON receipts(store_id, occurred_at);
@@ -0,0 +1,41 @@
+-- Voids and refunds projected from order_voided, order_refunded and transaction_refunded.
+-- A void marks the order (who, when, why). Each refund is an order_refunds row plus a negative
+-- transactions row (local_transaction_id 'refund:<local_refund_id>', refund_of_transaction_id set
+-- for refunds of one payment), so summing transactions gives net takings. orders.refunded_cents
+-- is the sum of the order's refunds; status becomes 'partially_refunded' or 'refunded'.
+ALTER TABLE orders
+  ADD COLUMN refunded_cents BIGINT NOT NULL DEFAULT 0 AFTER total_cents,
+  ADD COLUMN voided_at DATETIME(3) NULL AFTER occurred_at,
+  ADD COLUMN void_reason VARCHAR(255) NULL AFTER voided_at,
+  ADD COLUMN voided_by_staff_id VARCHAR(255) NULL AFTER void_reason,
+  ADD COLUMN voided_by_staff_name VARCHAR(255) NULL AFTER voided_by_staff_id;
+
+ALTER TABLE transactions
+  ADD COLUMN refund_of_transaction_id CHAR(36) NULL AFTER order_id;
+
+CREATE TABLE order_refunds (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  device_id CHAR(36) NOT NULL,
+  order_id CHAR(36) NULL,
+  transaction_id CHAR(36) NULL,
+  refunded_transaction_id CHAR(36) NULL,
+  local_refund_id VARCHAR(255) NOT NULL,
+  kind VARCHAR(100) NOT NULL,
+  amount_cents BIGINT NOT NULL,
+  reason VARCHAR(255) NULL,
+  staff_id VARCHAR(255) NULL,
+  staff_name VARCHAR(255) NULL,
+  occurred_at DATETIME(3) NOT NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  UNIQUE KEY uq_order_refunds_store_device_local (store_id, device_id, local_refund_id),
+  KEY idx_order_refunds_order (order_id),
+  KEY idx_order_refunds_store_occurred (store_id, occurred_at),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE,
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+  FOREIGN KEY (transaction_id) REFERENCES transactions(id) ON DELETE SET NULL,
+  FOREIGN KEY (refunded_transaction_id) REFERENCES transactions(id) ON DELETE SET NULL
+);
@@ -0,0 +1,14 @@
+-- order_updated carries the order's full line set and is reconciled against order_items by
+-- local_item_id (see crates/db/src/orders.rs). Earlier builds appended every line again on each
+-- update: keep only the latest copy of each (order_id, local_item_id) before making it unique.
+-- Lines without a local_item_id are not keyed (NULLs do not collide).
+DELETE oi
+FROM order_items oi
+JOIN order_items newer
+  ON newer.order_id = oi.order_id
+ AND newer.local_item_id = oi.local_item_id
+ AND (newer.created_at > oi.created_at OR (newer.created_at = oi.created_at AND newer.id > oi.id));
+
+ALTER TABLE order_items
+  ADD COLUMN updated_at DATETIME(3) NULL AFTER created_at,
+  ADD UNIQUE KEY uq_order_items_order_local (order_id, local_item_id);
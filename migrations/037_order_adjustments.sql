@@ -0,0 +1,50 @@
+-- Structured order detail from order_created / order_updated: line modifiers (matched to
+-- pos_menu_item_modifiers by the line's product_ref and the modifier name), order- and
+-- line-level discounts, tax lines by rate, and tip / service charge on the order.
+ALTER TABLE orders
+  ADD COLUMN tip_cents BIGINT NULL AFTER refunded_cents,
+  ADD COLUMN service_charge_cents BIGINT NULL AFTER tip_cents;
+
+CREATE TABLE order_item_modifiers (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  order_id CHAR(36) NOT NULL,
+  order_item_id CHAR(36) NOT NULL,
+  name VARCHAR(255) NOT NULL,
+  quantity DECIMAL(18,4) NOT NULL DEFAULT 1,
+  price_delta_cents BIGINT NOT NULL DEFAULT 0,
+  position INT NOT NULL DEFAULT 0,
+  KEY idx_order_item_modifiers_item (order_item_id),
+  KEY idx_order_item_modifiers_order (order_id),
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+  FOREIGN KEY (order_item_id) REFERENCES order_items(id) ON DELETE CASCADE
+);
+
+-- order_item_id NULL: a discount on the whole order.
+CREATE TABLE order_discounts (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  order_id CHAR(36) NOT NULL,
+  order_item_id CHAR(36) NULL,
+  local_discount_id VARCHAR(255) NULL,
+  name VARCHAR(255) NULL,
+  code VARCHAR(100) NULL,
+  amount_cents BIGINT NOT NULL,
+  percent DECIMAL(7,3) NULL,
+  position INT NOT NULL DEFAULT 0,
+  KEY idx_order_discounts_order (order_id),
+  KEY idx_order_discounts_item (order_item_id),
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE,
+  FOREIGN KEY (order_item_id) REFERENCES order_items(id) ON DELETE CASCADE
+);
+
+CREATE TABLE order_taxes (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  order_id CHAR(36) NOT NULL,
+  name VARCHAR(100) NULL,
+  rate_percent DECIMAL(6,3) NOT NULL,
+  net_cents BIGINT NULL,
+  tax_cents BIGINT NOT NULL,
+  included TINYINT(1) NOT NULL DEFAULT 1,
+  position INT NOT NULL DEFAULT 0,
+  KEY idx_order_taxes_order (order_id),
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
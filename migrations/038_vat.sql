@@ -0,0 +1,13 @@
+-- UK VAT. Menu items carry a VAT rate band (standard / reduced / zero; NULL = standard) and an
+-- optional takeaway band used for takeaway and delivery orders. Orders record how they were
+-- served. When the POS sends no tax lines, order_taxes rows are computed from the menu rates
+-- (source 'computed'); tax lines the POS sends are kept as they are (source 'pos').
+ALTER TABLE pos_menu_items
+  ADD COLUMN vat_rate VARCHAR(20) NULL AFTER price_pence,
+  ADD COLUMN takeaway_vat_rate VARCHAR(20) NULL AFTER vat_rate;
+
+ALTER TABLE orders
+  ADD COLUMN service_type VARCHAR(20) NULL AFTER status;
+
+ALTER TABLE order_taxes
+  ADD COLUMN source VARCHAR(20) NOT NULL DEFAULT 'pos' AFTER included;
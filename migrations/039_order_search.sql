@@ -0,0 +1,12 @@
+-- Order search. Orders taken from a delivery platform record the provider and the platform's
+-- order id (sent by the POS with the order, from the delivery_order command it accepted), so
+-- they can be found by provider and matched back to delivery_orders. Indexes cover the
+-- reference lookups (local order id, receipt id, platform order id) and amount sorts.
+ALTER TABLE orders
+  ADD COLUMN delivery_provider VARCHAR(50) NULL AFTER service_type,
+  ADD COLUMN external_order_id VARCHAR(255) NULL AFTER delivery_provider;
+
+CREATE INDEX idx_orders_local_order ON orders(local_order_id);
+CREATE INDEX idx_orders_external_order ON orders(external_order_id);
+CREATE INDEX idx_orders_store_total ON orders(store_id, total_cents);
+CREATE INDEX idx_receipts_local_receipt ON receipts(local_receipt_id);
@@ -0,0 +1,38 @@
+-- Cross-device order identity. An order has a store-scoped global_order_id that every device
+-- touching it sends with its events (devices generate it when they open the order; otherwise the
+-- cloud order id is used), and order_device_refs maps each device's local order id to the one
+-- merged order. orders.device_id / local_order_id stay the owning device (the one whose
+-- order_created is earliest). Content edits from several devices are last-writer-wins by
+-- (occurred_at, device_id), recorded in content_revision_at / content_revision_device_id.
+-- See domain::order_identity for the merge rules.
+ALTER TABLE orders
+  ADD COLUMN global_order_id VARCHAR(64) NULL AFTER local_order_id,
+  ADD COLUMN created_event_at DATETIME(3) NULL AFTER occurred_at,
+  ADD COLUMN content_revision_at DATETIME(3) NULL AFTER created_event_at,
+  ADD COLUMN content_revision_device_id CHAR(36) NULL AFTER content_revision_at;
+
+UPDATE orders SET global_order_id = id, created_event_at = occurred_at;
+
+ALTER TABLE orders
+  MODIFY COLUMN global_order_id VARCHAR(64) NOT NULL,
+  ADD UNIQUE KEY uq_orders_store_global (store_id, global_order_id);
+
+CREATE TABLE order_device_refs (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  device_id CHAR(36) NOT NULL,
+  order_id CHAR(36) NOT NULL,
+  local_order_id VARCHAR(255) NOT NULL,
+  first_seen_at DATETIME(3) NOT NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  UNIQUE KEY uq_order_device_refs_local (store_id, device_id, local_order_id),
+  KEY idx_order_device_refs_order (order_id),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (device_id) REFERENCES devices(id) ON DELETE CASCADE,
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
+
+INSERT INTO order_device_refs (id, org_id, store_id, device_id, order_id, local_order_id, first_seen_at)
+SELECT UUID(), org_id, store_id, device_id, id, local_order_id, occurred_at FROM orders;
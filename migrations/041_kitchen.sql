@@ -0,0 +1,26 @@
+-- Kitchen display timings. Orders record when they first went to the kitchen and when the
+-- ticket was ready; order_kitchen_items records each line's kitchen times, keyed by the line's
+-- local_item_id (kept apart from order_items so timings survive line edits and can arrive before
+-- the lines do).
+ALTER TABLE orders
+  ADD COLUMN kitchen_sent_at DATETIME(3) NULL AFTER content_revision_device_id,
+  ADD COLUMN kitchen_ready_at DATETIME(3) NULL AFTER kitchen_sent_at;
+
+CREATE INDEX idx_orders_store_kitchen_sent ON orders(store_id, kitchen_sent_at);
+
+CREATE TABLE order_kitchen_items (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  store_id CHAR(36) NOT NULL,
+  order_id CHAR(36) NOT NULL,
+  local_item_id VARCHAR(255) NOT NULL,
+  station VARCHAR(100) NULL,
+  sent_at DATETIME(3) NULL,
+  started_at DATETIME(3) NULL,
+  bumped_at DATETIME(3) NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  UNIQUE KEY uq_order_kitchen_items_line (order_id, local_item_id),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (order_id) REFERENCES orders(id) ON DELETE CASCADE
+);
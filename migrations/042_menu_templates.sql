@@ -0,0 +1,74 @@
+-- Head-office menu templates. An org (or one of its franchises) keeps a master menu that is
+-- published to selected stores; each store keeps the menu it was last published (menu_body, the
+-- same shape as GET /sync/menu) until the next publish, so a template can be rolled out store by
+-- store. Stores on a template get that menu, with their own price and availability overrides
+-- layered on top, instead of their canonical device's menu.
+CREATE TABLE menu_templates (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  org_id CHAR(36) NOT NULL,
+  franchise_id CHAR(36) NULL,
+  name VARCHAR(255) NOT NULL,
+  created_by_user_id CHAR(36) NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  FOREIGN KEY (org_id) REFERENCES organizations(id) ON DELETE CASCADE,
+  FOREIGN KEY (franchise_id) REFERENCES franchises(id) ON DELETE CASCADE
+);
+
+-- Template categories and items carry the local ids devices see (copied from a store's menu, or
+-- hq-<uuid> for ones created in the portal).
+CREATE TABLE menu_template_categories (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  template_id CHAR(36) NOT NULL,
+  local_category_id VARCHAR(255) NOT NULL,
+  name VARCHAR(255) NOT NULL,
+  position INT NOT NULL DEFAULT 0,
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  UNIQUE KEY uq_menu_template_categories_local (template_id, local_category_id),
+  FOREIGN KEY (template_id) REFERENCES menu_templates(id) ON DELETE CASCADE
+);
+
+CREATE TABLE menu_template_items (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  template_id CHAR(36) NOT NULL,
+  local_item_id VARCHAR(255) NOT NULL,
+  local_category_id VARCHAR(255) NULL,
+  name VARCHAR(255) NOT NULL,
+  description TEXT NULL,
+  price_pence BIGINT NULL,
+  vat_rate VARCHAR(20) NULL,
+  takeaway_vat_rate VARCHAR(20) NULL,
+  active TINYINT(1) NOT NULL DEFAULT 1,
+  image_path VARCHAR(512) NULL,
+  customer_editable TINYINT(1) NOT NULL DEFAULT 0,
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  UNIQUE KEY uq_menu_template_items_local (template_id, local_item_id),
+  FOREIGN KEY (template_id) REFERENCES menu_templates(id) ON DELETE CASCADE
+);
+
+-- The template a store is on and the menu it was last published.
+CREATE TABLE menu_template_stores (
+  store_id CHAR(36) PRIMARY KEY,
+  template_id CHAR(36) NOT NULL,
+  menu_body JSON NOT NULL,
+  published_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  published_by_user_id CHAR(36) NULL,
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (template_id) REFERENCES menu_templates(id) ON DELETE CASCADE
+);
+
+-- Per-store price and availability over the template menu (NULL = the template's value).
+CREATE TABLE store_menu_overrides (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  store_id CHAR(36) NOT NULL,
+  local_item_id VARCHAR(255) NOT NULL,
+  price_pence BIGINT NULL,
+  active TINYINT(1) NULL,
+  updated_by_user_id CHAR(36) NULL,
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  UNIQUE KEY uq_store_menu_overrides_item (store_id, local_item_id),
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_menu_templates_org ON menu_templates(org_id);
+CREATE INDEX idx_menu_template_stores_template ON menu_template_stores(template_id);
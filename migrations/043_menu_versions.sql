@@ -0,0 +1,20 @@
+-- Menu versions. Every time a store's menu is published to its devices (apply_menu after a
+-- portal edit, a template publish, an override change, a rollback or a command-center send) the
+-- menu sent is kept as an immutable version, numbered 1, 2, ... per store, with who published it
+-- and why. template_id / template_body record the head-office template menu (before the store's
+-- overrides) when the store was on a template, so a rollback can restore it.
+CREATE TABLE menu_publishes (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  store_id CHAR(36) NOT NULL,
+  version INT NOT NULL,
+  menu_body JSON NOT NULL,
+  template_id CHAR(36) NULL,
+  template_body JSON NULL,
+  source VARCHAR(30) NOT NULL,
+  restored_version INT NULL,
+  published_by_user_id CHAR(36) NULL,
+  published_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  UNIQUE KEY uq_menu_publishes_store_version (store_id, version),
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE,
+  FOREIGN KEY (template_id) REFERENCES menu_templates(id) ON DELETE SET NULL
+);
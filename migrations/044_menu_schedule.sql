@@ -0,0 +1,57 @@
+-- Scheduled menu changes and dayparts. A scheduled change makes a menu version (see
+-- menu_publishes) or a set of item prices live at a store-local time (run_at is wall-clock time
+-- in the store's timezone, so it follows the store's clock); the menu scheduler applies it and
+-- queues apply_menu. Dayparts are store-local windows; categories and items assigned to dayparts
+-- are sent inactive outside all of them, and the scheduler re-sends the menu when the set of open
+-- dayparts changes (menu_daypart_state).
+CREATE TABLE scheduled_menu_changes (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  store_id CHAR(36) NOT NULL,
+  run_at DATETIME NOT NULL,
+  -- Exactly one of: a menu version to restore, or [{ local_item_id, price_pence }].
+  version INT NULL,
+  prices JSON NULL,
+  -- pending, applying (claimed by the scheduler), applied, failed or cancelled.
+  status VARCHAR(20) NOT NULL DEFAULT 'pending',
+  error VARCHAR(512) NULL,
+  applied_at DATETIME(3) NULL,
+  applied_version INT NULL,
+  created_by_user_id CHAR(36) NOT NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE
+);
+
+CREATE TABLE menu_dayparts (
+  id CHAR(36) PRIMARY KEY DEFAULT (UUID()),
+  store_id CHAR(36) NOT NULL,
+  name VARCHAR(100) NOT NULL,
+  start_time TIME NOT NULL,
+  -- Before start_time = runs past midnight; equal = all day.
+  end_time TIME NOT NULL,
+  -- Comma-separated weekdays the window starts on (mon..sun); NULL = every day.
+  days VARCHAR(30) NULL,
+  created_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)),
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE
+);
+
+-- Categories (entry_type 'category', local_category_id) and items ('item', local_item_id) in a
+-- daypart.
+CREATE TABLE menu_daypart_entries (
+  daypart_id CHAR(36) NOT NULL,
+  entry_type VARCHAR(10) NOT NULL,
+  local_id VARCHAR(255) NOT NULL,
+  PRIMARY KEY (daypart_id, entry_type, local_id),
+  FOREIGN KEY (daypart_id) REFERENCES menu_dayparts(id) ON DELETE CASCADE
+);
+
+-- Dayparts open when the store's menu was last sent for them.
+CREATE TABLE menu_daypart_state (
+  store_id CHAR(36) PRIMARY KEY,
+  open_daypart_ids TEXT NOT NULL,
+  updated_at DATETIME(3) NOT NULL DEFAULT (CURRENT_TIMESTAMP(3)) ON UPDATE CURRENT_TIMESTAMP(3),
+  FOREIGN KEY (store_id) REFERENCES stores(id) ON DELETE CASCADE
+);
+
+CREATE INDEX idx_scheduled_menu_changes_due ON scheduled_menu_changes(status, run_at);
+CREATE INDEX idx_menu_dayparts_store ON menu_dayparts(store_id);
@@ -0,0 +1,6 @@
+-- Read-model rebuilds replay a store in batches, one transaction each. While a rebuild runs it
+-- holds a lease on the store's projection cursors (rebuild_until, extended every batch); the
+-- background worker skips leased cursors, and a second rebuild of the store is refused. A
+-- rebuild that dies leaves the lease to run out, after which the worker carries on.
+ALTER TABLE device_projection_cursors
+  ADD COLUMN rebuild_until DATETIME(3) NULL AFTER next_attempt_at;
@@ -0,0 +1,39 @@
+-- A store's effective menu, for matching order lines (order_items.product_ref) to menu items
+-- whatever till took the order. Only the canonical device's menu config is projected, and stores
+-- on a head-office template get the template menu instead (see get_store_menu_for_sync).
+
+-- The device whose projected menu is the store's: the canonical device, else the device that
+-- synced last (as get_device_id_for_store).
+CREATE VIEW store_menu_devices AS
+SELECT
+  s.id AS store_id,
+  COALESCE(s.canonical_device_id,
+           (SELECT ds.device_id FROM device_sync_state ds
+            WHERE ds.store_id = s.id ORDER BY ds.updated_at DESC LIMIT 1)) AS device_id
+FROM stores s;
+
+-- Items on each store's menu with their category name and VAT bands: the menu the store was last
+-- published when it is on a template, otherwise its menu device's items. Store overrides only
+-- change prices and availability, so they are not needed here.
+CREATE VIEW store_menu_items AS
+SELECT ts.store_id, i.local_item_id, i.local_category_id, i.name, c.name AS category_name,
+       i.vat_rate, i.takeaway_vat_rate
+FROM menu_template_stores ts
+CROSS JOIN JSON_TABLE(ts.menu_body, '$.items[*]' COLUMNS (
+  local_item_id VARCHAR(255) PATH '$.local_item_id',
+  local_category_id VARCHAR(255) PATH '$.local_category_id',
+  name VARCHAR(255) PATH '$.name',
+  vat_rate VARCHAR(20) PATH '$.vat_rate',
+  takeaway_vat_rate VARCHAR(20) PATH '$.takeaway_vat_rate'
+)) AS i
+LEFT JOIN JSON_TABLE(ts.menu_body, '$.categories[*]' COLUMNS (
+  local_category_id VARCHAR(255) PATH '$.local_category_id',
+  name VARCHAR(255) PATH '$.name'
+)) AS c ON c.local_category_id = i.local_category_id
+UNION ALL
+SELECT d.store_id, mi.local_item_id, mi.local_category_id, mi.name, mc.name AS category_name,
+       mi.vat_rate, mi.takeaway_vat_rate
+FROM store_menu_devices d
+JOIN pos_menu_items mi ON mi.device_id = d.device_id
+LEFT JOIN pos_menu_categories mc ON mc.device_id = mi.device_id AND mc.local_category_id = mi.local_category_id
+WHERE NOT EXISTS (SELECT 1 FROM menu_template_stores ts WHERE ts.store_id = d.store_id);